@@ -0,0 +1,375 @@
+//! Adding entries to the timesheets from the command line, or from an interactive prompt.
+
+use crate::clock;
+use crate::color::*;
+use crate::config::Config;
+use crate::data::{DateSelector, DuplicateDates, Entry, LoadOptions, TimeData};
+use crate::diagnostics;
+use crate::import::{month_file, write_entries, ImportedEntry};
+use crate::parse::{parse_date, parse_line};
+use crate::quiet;
+use crate::undo::{self, Mutation};
+use chrono::NaiveDate;
+use colored::Color;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Descriptions and tags used in existing entries, most frequently used first.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Suggestions {
+    pub descriptions: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl Suggestions {
+    /// Ranks the descriptions and `#tags` of the time and cost entries by how often they are
+    /// used, and then by how recently.
+    pub fn from_time_data(time_data: &TimeData) -> Self {
+        let mut descriptions: HashMap<String, (usize, NaiveDate)> = HashMap::new();
+        let mut tags: HashMap<String, (usize, NaiveDate)> = HashMap::new();
+        for (date, entries) in &time_data.entries {
+            for entry in entries {
+                if let Entry::Note(_) = entry {
+                    continue;
+                }
+                let text = entry.text().trim();
+                if text.is_empty() {
+                    continue;
+                }
+                count(&mut descriptions, text, *date);
+                for tag in text.split_whitespace().filter(|w| w.len() > 1 && w.starts_with('#')) {
+                    count(&mut tags, tag, *date);
+                }
+            }
+        }
+        Suggestions { descriptions: ranked(descriptions), tags: ranked(tags) }
+    }
+
+    /// Returns where the completed text starts in `line`, and the candidates for it.
+    ///
+    /// A word starting with `#` completes to a tag, anything else completes the whole line to
+    /// a description.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let (start, candidates) = if line[start..pos].starts_with('#') {
+            (start, &self.tags)
+        } else {
+            (0, &self.descriptions)
+        };
+        let prefix = line[start..pos].to_lowercase();
+        let found = candidates.iter()
+            .filter(|c| c.len() > prefix.len() && c.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect();
+        (start, found)
+    }
+}
+
+fn count(counts: &mut HashMap<String, (usize, NaiveDate)>, text: &str, date: NaiveDate) {
+    let (uses, last) = counts.entry(text.to_string()).or_insert((0, date));
+    *uses += 1;
+    *last = (*last).max(date);
+}
+
+fn ranked(counts: HashMap<String, (usize, NaiveDate)>) -> Vec<String> {
+    let mut ranked: Vec<(String, (usize, NaiveDate))> = counts.into_iter().collect();
+    ranked.sort_by(|(a, (a_uses, a_last)), (b, (b_uses, b_last))| {
+        b_uses.cmp(a_uses).then(b_last.cmp(a_last)).then(a.cmp(b))
+    });
+    ranked.into_iter().map(|(text, _)| text).collect()
+}
+
+/// Splits the words of an entry into its time and description.
+///
+/// A first word naming one of the `[snippets]` is replaced by the snippet, and the other
+/// words are added to its description.
+pub fn expand(words: &[String], snippets: &HashMap<String, String>) -> (String, String) {
+    let (first, rest) = match words.split_first() {
+        Some((first, rest)) => (first.as_str(), rest.join(" ")),
+        None => return (String::new(), String::new()),
+    };
+    match snippets.get(first).and_then(|snippet| snippet.split_once('=')) {
+        Some((spec, description)) => {
+            let description = [description.trim(), rest.as_str()].join(" ");
+            (spec.trim().to_string(), description.trim().to_string())
+        }
+        None => (first.to_string(), rest),
+    }
+}
+
+/// Checks that a time specification, like `2h`, `9-12:30` or `$50`, can be logged.
+pub fn check_spec(spec: &str) -> Result<(), String> {
+    if spec.trim().is_empty() || spec.contains('=') {
+        return Err("Expected a time, like 2h or 9-12:30".to_string());
+    }
+    parse_line(&format!("{} = -", spec)).map(|_| ())
+}
+
+// The value being asked for by the interactive prompt.
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Date,
+    Time,
+    Description,
+}
+
+// Completes, hints, and checks the input of the interactive prompt.
+struct AddHelper {
+    field: Field,
+    suggestions: Suggestions,
+    snippets: HashMap<String, String>,
+}
+
+impl AddHelper {
+    fn check(&self, line: &str) -> Result<(), String> {
+        match self.field {
+            Field::Date if !line.trim().is_empty() && parse_date(line.trim()).is_none() => {
+                Err("Expected a date, like 2025.03.14".to_string())
+            }
+            Field::Time if self.snippets.contains_key(line.trim()) => Ok(()),
+            Field::Time => check_spec(line),
+            Field::Description if line.trim().is_empty() => Err("Expected a description".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Completer for AddHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        if self.field != Field::Description {
+            return Ok((pos, Vec::new()));
+        }
+        Ok(self.suggestions.complete(line, pos))
+    }
+}
+
+impl Hinter for AddHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if self.field != Field::Description || line.is_empty() || pos < line.len() {
+            return None;
+        }
+        let (start, candidates) = self.suggestions.complete(line, pos);
+        candidates.first().map(|c| c[pos - start..].to_string())
+    }
+}
+
+impl Highlighter for AddHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.field == Field::Time && !line.is_empty() {
+            let color = if self.check(line).is_ok() { theme().hours } else { theme().error };
+            return Cow::Owned(line.out_colored(color).to_string());
+        }
+        Cow::Borrowed(line)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.out_colored(Color::BrightBlack).to_string())
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        self.field == Field::Time
+    }
+}
+
+impl Validator for AddHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match self.check(ctx.input()) {
+            Ok(()) => ValidationResult::Valid(None),
+            Err(err) => ValidationResult::Invalid(Some(format!("  ({})", err))),
+        })
+    }
+}
+
+impl Helper for AddHelper {}
+
+// Asks for one field, starting from `initial`, returning `None` if the prompt was cancelled.
+fn ask(editor: &mut Editor<AddHelper, DefaultHistory>, field: Field, prompt: &str, initial: &str) -> Option<String> {
+    if let Some(helper) = editor.helper_mut() {
+        helper.field = field;
+    }
+    match editor.readline_with_initial(prompt, (initial, "")) {
+        Ok(line) => Some(line.trim().to_string()),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => None,
+        Err(err) => {
+            tracing::error!("Failed to read input: {}", err);
+            diagnostics::exit(1);
+        }
+    }
+}
+
+// Prompts for the date, time, and description of an entry.
+fn prompt_entry(directory: &str, options: &LoadOptions, today: NaiveDate) -> Option<ImportedEntry> {
+    let suggestions = TimeData::load(directory, &DateSelector::new(), options)
+        .map(|time_data| Suggestions::from_time_data(&time_data))
+        .unwrap_or_default();
+    let mut editor = Editor::new().unwrap_or_else(|err| {
+        tracing::error!("Failed to start the prompt: {}", err);
+        diagnostics::exit(1);
+    });
+    let snippets = options.snippets.clone();
+    editor.set_helper(Some(AddHelper { field: Field::Date, suggestions, snippets: snippets.clone() }));
+
+    let date = ask(&mut editor, Field::Date, &format!("date [{}]: ", today.format("%Y.%m.%d")), "")?;
+    let date = if date.is_empty() { today } else { parse_date(&date)? };
+    let time = ask(&mut editor, Field::Time, "time or snippet: ", "")?;
+    let (spec, description) = expand(&[time], &snippets);
+    let description = ask(&mut editor, Field::Description, "description: ", &description)?;
+    Some(ImportedEntry { date, spec, description })
+}
+
+/// Writes an entry to the timesheet of its month, and records it for `undo`.
+///
+/// # Returns
+///
+/// `false` if the same entry is already logged on its date.
+pub fn write_entry(directory: &str, entry: &ImportedEntry, options: &LoadOptions) -> io::Result<bool> {
+    let added = write_entries(Path::new(directory), std::slice::from_ref(entry), options)? > 0;
+    if added {
+        let file = month_file(Path::new(directory), entry.date, options)?;
+        undo::record(directory, Mutation::Entry {
+            file: file.strip_prefix(directory).unwrap_or(&file).display().to_string(),
+            date: entry.date.format("%Y.%m.%d").to_string(),
+            line: entry.to_line(),
+        });
+    }
+    Ok(added)
+}
+
+/// Adds an entry to the timesheet of its month, or prompts for one when `entry` is empty.
+///
+/// The first word of `entry` is the time, like `2h` or `9-12:30`, or the name of a snippet,
+/// and the rest is the description.
+pub fn run(
+    date: Option<String>,
+    entry: Vec<String>,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot add entries in read-only mode");
+        diagnostics::exit(1);
+    }
+    let today = clock::today(config.as_ref());
+    let options = LoadOptions { duplicate_dates: DuplicateDates::Merge, ..LoadOptions::from_config(config.as_ref()) };
+
+    let entry = if entry.is_empty() {
+        if !atty::is(atty::Stream::Stdin) {
+            tracing::error!("Nothing to add, give a time and description like: add 2h fixed the build");
+            diagnostics::exit(1);
+        }
+        match prompt_entry(directory, &options, today) {
+            Some(entry) => entry,
+            None => return,
+        }
+    } else {
+        let date = match date {
+            Some(date) => parse_date(&date).unwrap_or_else(|| {
+                tracing::error!("Invalid date: {}", date);
+                diagnostics::exit(1);
+            }),
+            None => today,
+        };
+        let (spec, description) = expand(&entry, &options.snippets);
+        ImportedEntry { date, spec, description }
+    };
+
+    if let Err(err) = check_spec(&entry.spec) {
+        tracing::error!("{}: {}", err, entry.spec);
+        diagnostics::exit(1);
+    }
+    if entry.description.is_empty() {
+        tracing::error!("Missing a description for {}", entry.spec);
+        diagnostics::exit(1);
+    }
+
+    match write_entry(directory, &entry, &options) {
+        Ok(false) => tracing::warn!("Already logged on {}: {}", entry.date.format("%Y.%m.%d"), entry.to_line()),
+        Ok(true) => tracing::info!("Added to {}: {}", entry.date.format("%Y.%m.%d"), entry.to_line()),
+        Err(err) => {
+            tracing::error!("Failed to write entry: {}", err);
+            diagnostics::exit(1);
+        }
+    }
+    quiet::summary("added", &[
+        ("date", entry.date.format("%Y.%m.%d").to_string()),
+        ("entry", entry.to_line()),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestions() -> Suggestions {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "\
+2025.03.03
+    2h = standup #bnl
+    4h = repair lamp spire #bnl
+    - a note #ignored
+2025.03.04
+    2h = standup #bnl
+    3h = review #axiom
+2025.03.05
+    1h = repair lamp spire #bnl
+    $20 = parking
+").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        Suggestions::from_time_data(&time_data)
+    }
+
+    #[test]
+    fn test_suggestions_ranking() {
+        let suggestions = suggestions();
+        assert_eq!(suggestions.descriptions, vec![
+            "repair lamp spire #bnl", "standup #bnl", "parking", "review #axiom",
+        ]);
+        assert_eq!(suggestions.tags, vec!["#bnl", "#axiom"]);
+    }
+
+    #[test]
+    fn test_suggestions_complete() {
+        let suggestions = suggestions();
+        assert_eq!(suggestions.complete("Re", 2), (0, vec![
+            "repair lamp spire #bnl".to_string(), "review #axiom".to_string(),
+        ]));
+        assert_eq!(suggestions.complete("fixed #a", 8), (6, vec!["#axiom".to_string()]));
+        assert_eq!(suggestions.complete("standup #bnl", 12), (8, vec![]));
+    }
+
+    #[test]
+    fn test_expand() {
+        let snippets: HashMap<String, String> = [("standup".to_string(), "0.25h = daily standup #meetings".to_string())].into();
+        let words = |text: &str| text.split_whitespace().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(expand(&words("standup"), &snippets), ("0.25h".to_string(), "daily standup #meetings".to_string()));
+        assert_eq!(expand(&words("standup ran long"), &snippets), ("0.25h".to_string(), "daily standup #meetings ran long".to_string()));
+        assert_eq!(expand(&words("2h fixed it"), &snippets), ("2h".to_string(), "fixed it".to_string()));
+    }
+
+    #[test]
+    fn test_check_spec() {
+        assert!(check_spec("2h").is_ok());
+        assert!(check_spec("9-12:30").is_ok());
+        assert!(check_spec("1h, 10-11").is_ok());
+        assert!(check_spec("$50").is_ok());
+        assert!(check_spec("2x").is_err());
+        assert!(check_spec("").is_err());
+        assert!(check_spec("2h = x").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! User-defined command aliases, from the `[alias]` table of the configuration.
+
+use crate::config::Config;
+use crate::project;
+use clap::Command;
+use std::collections::HashMap;
+
+/// Reads the aliases in `[alias]`, like `month = "log -f month"`.
+pub fn aliases(config: &Config) -> HashMap<String, String> {
+    config.get_table("alias").into_iter().flatten()
+        .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
+/// Where the subcommand is in the arguments, and the global options needed to find the
+/// configuration.
+#[derive(Debug, Default, PartialEq)]
+pub struct Scan {
+    pub position: Option<usize>,
+    pub config: Option<String>,
+    pub directory: Option<String>,
+    pub project: Option<String>,
+}
+
+/// Skips over the global options of `command`, and their values, to find the subcommand.
+///
+/// `args` start with the program name, like `std::env::args`.
+pub fn scan(args: &[String], command: &Command) -> Scan {
+    let takes_value = |arg: Option<&clap::Arg>| arg.is_some_and(|a| a.get_action().takes_values());
+    let mut scan = Scan::default();
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let (id, value) = if let Some(long) = arg.strip_prefix("--") {
+            if long.is_empty() {
+                break;
+            }
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let option = command.get_arguments().find(|a| a.get_long() == Some(name));
+            let value = match inline {
+                Some(value) => Some(value),
+                None if takes_value(option) => {
+                    i += 1;
+                    args.get(i).cloned()
+                }
+                None => None,
+            };
+            (option.map(|a| a.get_id().to_string()), value)
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // Flags can be combined, like `-qd dir`, until one that takes a value.
+            let mut found = (None, None);
+            for (n, c) in shorts.char_indices() {
+                let option = command.get_arguments().find(|a| a.get_short() == Some(c));
+                if takes_value(option) {
+                    let rest = &shorts[n + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        i += 1;
+                        args.get(i).cloned()
+                    } else {
+                        Some(rest.trim_start_matches('=').to_string())
+                    };
+                    found = (option.map(|a| a.get_id().to_string()), value);
+                    break;
+                }
+            }
+            found
+        } else {
+            scan.position = Some(i);
+            break;
+        };
+        match id.as_deref() {
+            Some("config") => scan.config = value,
+            Some("directory") if scan.directory.is_none() => scan.directory = value,
+            Some("project") => scan.project = value,
+            _ => {}
+        }
+        i += 1;
+    }
+    scan
+}
+
+// Replaces the alias at `position` with its expansion, repeatedly, since an alias can
+// expand to another one.
+fn expand_at(
+    mut args: Vec<String>,
+    position: usize,
+    aliases: &HashMap<String, String>,
+    is_command: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let mut seen: Vec<String> = Vec::new();
+    while let Some(name) = args.get(position).filter(|name| !is_command(name)).cloned() {
+        let Some(expansion) = aliases.get(&name) else {
+            break;
+        };
+        if seen.contains(&name) {
+            return Err(format!("Alias {} expands to itself: {}", name, seen.join(" -> ")));
+        }
+        let words = shlex::split(expansion)
+            .filter(|words| !words.is_empty())
+            .ok_or(format!("Invalid alias {} = \"{}\"", name, expansion))?;
+        seen.push(name);
+        args.splice(position..=position, words);
+    }
+    Ok(args)
+}
+
+/// Expands a user-defined alias in place of the subcommand, before the arguments are parsed.
+///
+/// The aliases come from the configuration selected by `--config` and `--directory`, or
+/// `--project`, and never replace a built-in subcommand.
+///
+/// # Errors
+///
+/// Returns a `String` error if an alias cannot be split into words, or expands to itself.
+pub fn expand(args: Vec<String>, command: &Command) -> Result<Vec<String>, String> {
+    let scan = scan(&args, command);
+    let Some(position) = scan.position else {
+        return Ok(args);
+    };
+    let is_command = |name: &str| name == "help" || command.find_subcommand(name).is_some();
+    if is_command(&args[position]) {
+        return Ok(args);
+    }
+    let (mut directory, mut config_file) = (scan.directory, scan.config);
+    // A project that cannot be found is reported once the arguments are parsed.
+    if let Some(project) = scan.project.as_deref().and_then(|name| project::lookup(&project::registry_path()?, name).ok()) {
+        directory = Some(project.directory);
+        config_file = config_file.or(project.config);
+    }
+    let config = Config::new(config_file.as_deref(), Some(directory.as_deref().unwrap_or(".")));
+    let aliases = config.map(|config| aliases(&config)).unwrap_or_default();
+    expand_at(args, position, &aliases, is_command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(|w| w.to_string()).collect()
+    }
+
+    fn command() -> Command {
+        Command::new("clinvoice")
+            .arg(Arg::new("config").short('c').long("config"))
+            .arg(Arg::new("directory").short('d').long("directory").action(ArgAction::Append))
+            .arg(Arg::new("quiet").short('q').long("quiet").action(ArgAction::SetTrue))
+            .arg(Arg::new("project").short('p').long("project"))
+            .subcommand(Command::new("log"))
+    }
+
+    #[test]
+    fn test_scan() {
+        let command = command();
+        assert_eq!(scan(&args("clinvoice -q -c a.toml -d dir bill 2025"), &command),
+            Scan { position: Some(6), config: Some("a.toml".to_string()), directory: Some("dir".to_string()), project: None });
+        assert_eq!(scan(&args("clinvoice --config=a.toml -qddir -d other bill"), &command),
+            Scan { position: Some(5), config: Some("a.toml".to_string()), directory: Some("dir".to_string()), project: None });
+        assert_eq!(scan(&args("clinvoice --project acme -q bill"), &command),
+            Scan { position: Some(4), project: Some("acme".to_string()), ..Scan::default() });
+        assert_eq!(scan(&args("clinvoice -q"), &command).position, None);
+    }
+
+    #[test]
+    fn test_expand_at() {
+        let aliases: HashMap<String, String> = [
+            ("month".to_string(), "log -f month".to_string()),
+            ("m".to_string(), "month".to_string()),
+            ("log".to_string(), "generate".to_string()),
+            ("loop".to_string(), "again".to_string()),
+            ("again".to_string(), "loop".to_string()),
+        ].into();
+        let is_command = |name: &str| name == "log";
+        assert_eq!(expand_at(args("clinvoice -q m 2025"), 2, &aliases, is_command).unwrap(),
+            args("clinvoice -q log -f month 2025"));
+        assert_eq!(expand_at(args("clinvoice log"), 1, &aliases, is_command).unwrap(), args("clinvoice log"));
+        assert_eq!(expand_at(args("clinvoice other"), 1, &aliases, is_command).unwrap(), args("clinvoice other"));
+        assert!(expand_at(args("clinvoice loop"), 1, &aliases, is_command).is_err());
+    }
+}
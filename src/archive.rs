@@ -0,0 +1,340 @@
+//! Moving old entries out of the data directory, into its `archive/` subdirectory.
+//!
+//! Timesheets are never read from `archive/`, so archived entries no longer slow down
+//! loading.  The date sections before a cutoff move to the file of the same path in
+//! `archive/`, like `archive/2024/05.cli` for `2024/05.cli`, which can still be read with
+//! `--directory archive`.
+//!
+//! The index stays where it is, so that sequence numbers go on.  The sequences of archived
+//! invoices are copied to the index of the archive, so that generating them again there
+//! gives the same numbers.
+
+use crate::backup;
+use crate::config::Config;
+use crate::data::{read_dates, timesheet_files, LoadOptions, Selection};
+use crate::index::{index_file_path, Index};
+use crate::parse::{parse_date, parse_date_line};
+use crate::quiet;
+use chrono::NaiveDate;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The subdirectory of the data directory holding archived timesheets.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// A timesheet split at a cutoff date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    /// The lines before the first date, like the format directive and comments.
+    pub preamble: Vec<String>,
+    /// The date sections before the cutoff.
+    pub before: Vec<String>,
+    /// The date sections from the cutoff on.
+    pub after: Vec<String>,
+    /// The number of dates before the cutoff.
+    pub dates: usize,
+}
+
+/// Splits the content of a `.cli` timesheet into the date sections before `cutoff`, and the
+/// others.  Each section runs from its date line to the next date line.
+pub fn split(content: &str, cutoff: NaiveDate) -> Split {
+    let mut split = Split { preamble: Vec::new(), before: Vec::new(), after: Vec::new(), dates: 0 };
+    let mut current: Option<bool> = None;
+    for line in content.lines() {
+        if let Some(Ok(date)) = parse_date_line(line.trim()) {
+            current = Some(date < cutoff);
+            split.dates += usize::from(date < cutoff);
+        }
+        match current {
+            None => split.preamble.push(line.to_string()),
+            Some(true) => split.before.push(line.to_string()),
+            Some(false) => split.after.push(line.to_string()),
+        }
+    }
+    split
+}
+
+// Joins lines into file content, without trailing blank lines.
+fn join(lines: &[String]) -> String {
+    let mut content = lines.join("\n").trim_end().to_string();
+    content.push('\n');
+    content
+}
+
+/// Returns the content of the archived file, after adding the sections of `split` before
+/// the cutoff to its `existing` content.
+pub fn archived(existing: Option<&str>, split: &Split) -> String {
+    let mut lines: Vec<String> = match existing {
+        Some(content) => content.lines().map(|l| l.to_string()).collect(),
+        None => split.preamble.clone(),
+    };
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    if !lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines.extend(split.before.iter().cloned());
+    join(&lines)
+}
+
+/// Returns the content left in the data directory, or `None` if no dates are left.
+pub fn remaining(split: &Split) -> Option<String> {
+    if split.after.is_empty() {
+        return None;
+    }
+    Some(join(&[split.preamble.clone(), split.after.clone()].concat()))
+}
+
+/// Sorts the sequences of the index by their dates: those entirely before `cutoff`, to copy
+/// to the archive.
+///
+/// # Errors
+///
+/// Returns a `String` error if the cutoff falls within the dates of an invoice, which would
+/// leave it split between the data directory and the archive.
+pub fn archived_sequences(sequences: &[(u32, Vec<String>)], cutoff: NaiveDate) -> Result<Vec<u32>, String> {
+    let mut archived = Vec::new();
+    for (sequence, dates) in sequences.iter().filter(|(_, dates)| !dates.is_empty()) {
+        let Ok(selector) = Selection::from_key(dates).selector() else {
+            tracing::warn!("Skipping sequence {} with invalid dates {}", sequence, dates.join(" "));
+            continue;
+        };
+        let start = selector.ranges.iter().map(|r| r.start).min();
+        let end = selector.ranges.iter().map(|r| r.end).max();
+        match start.zip(end) {
+            Some((_, end)) if end < cutoff => archived.push(*sequence),
+            Some((start, _)) if start < cutoff => {
+                return Err(format!("The cutoff {} falls within invoice {} ({}), pick a date between invoices",
+                    cutoff.format("%Y.%m.%d"), sequence, dates.join(" ")));
+            }
+            _ => {}
+        }
+    }
+    Ok(archived)
+}
+
+// A change to the data directory.
+enum Move {
+    // A `.cli` file split at the cutoff.
+    Split { path: PathBuf, target: PathBuf, archived: String, remaining: Option<String>, dates: usize },
+    // A timesheet that cannot be split, like a `.tsv`, with all of its dates before the cutoff.
+    Whole { path: PathBuf, target: PathBuf, dates: usize },
+}
+
+// Works out the moves of the timesheets of `directory` with entries before `cutoff`, to the
+// same paths under `archive/`.
+fn plan(directory: &str, options: &LoadOptions, cutoff: NaiveDate) -> Result<Vec<Move>, String> {
+    let files = timesheet_files(directory, options).map_err(|err| format!("Failed to list timesheets: {}", err))?;
+    let archive_dir = Path::new(directory).join(ARCHIVE_DIR);
+    let mut moves = Vec::new();
+    for path in files {
+        let Ok(relative) = path.strip_prefix(directory) else {
+            tracing::warn!("Skipping {}, outside of {}", path.display(), directory);
+            continue;
+        };
+        let target = archive_dir.join(relative);
+        if path.extension().is_some_and(|ext| ext == "cli") {
+            let content = fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+            let split = split(&content, cutoff);
+            if split.dates == 0 {
+                continue;
+            }
+            let existing = fs::read_to_string(&target).ok();
+            moves.push(Move::Split {
+                archived: archived(existing.as_deref(), &split),
+                remaining: remaining(&split),
+                dates: split.dates,
+                path,
+                target,
+            });
+            continue;
+        }
+        let dates = read_dates(&path, options).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let old = dates.iter().filter(|(_, date)| *date < cutoff).count();
+        if old == 0 {
+            continue;
+        }
+        if old < dates.len() || target.exists() {
+            tracing::warn!("Cannot split {}, archive its entries before {} by hand", path.display(), cutoff.format("%Y.%m.%d"));
+            continue;
+        }
+        moves.push(Move::Whole { path, target, dates: old });
+    }
+    Ok(moves)
+}
+
+/// Moves the entries dated before `before` into the archive.
+///
+/// The files changed are saved in a snapshot first, so that `restore` can undo it.
+pub fn run(
+    before: String,
+    dry_run: bool,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    if read_only && !dry_run {
+        tracing::error!("Cannot archive in read-only mode, use --dry-run to see what would move");
+        std::process::exit(1);
+    }
+    let cutoff = parse_date(&before).unwrap_or_else(|| {
+        tracing::error!("Invalid date: {}", before);
+        std::process::exit(1);
+    });
+
+    let index_path = index_file_path(config.as_ref(), directory);
+    let index = Index::open_read_only(&index_path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read index {}: {}", index_path.display(), err);
+        std::process::exit(1);
+    });
+    let sequences = archived_sequences(&index.sequences(), cutoff).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        std::process::exit(1);
+    });
+
+    let options = LoadOptions::from_config(config.as_ref());
+    let moves = plan(directory, &options, cutoff).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        std::process::exit(1);
+    });
+
+    let mut total = 0;
+    for change in &moves {
+        let (Move::Split { path, target, dates, .. } | Move::Whole { path, target, dates }) = change;
+        total += dates;
+        if !quiet::enabled() {
+            println!("{}: {} dates to {}", path.display(), dates, target.display());
+        }
+    }
+    if !dry_run && !moves.is_empty() {
+        write(directory, config.as_ref(), &moves, &index, &sequences, cutoff);
+        tracing::info!("Archived {} dates from {} files", total, moves.len());
+    }
+    quiet::summary("archived", &[
+        ("files", moves.len().to_string()),
+        ("dates", total.to_string()),
+        ("sequences", sequences.len().to_string()),
+        ("dry_run", dry_run.to_string()),
+    ]);
+}
+
+// Writes the moves, and copies the archived sequences to the index of the archive.
+fn write(directory: &str, config: Option<&Config>, moves: &[Move], index: &Index, sequences: &[u32], cutoff: NaiveDate) {
+    let archive_dir = Path::new(directory).join(ARCHIVE_DIR);
+    let archive_index = index_file_path(config, &archive_dir.display().to_string());
+    let same_index = archive_index == index_file_path(config, directory);
+    let mut paths: Vec<PathBuf> = moves.iter().flat_map(|change| match change {
+        Move::Split { path, target, .. } | Move::Whole { path, target, .. } => [path.clone(), target.clone()],
+    }).collect();
+    if !same_index {
+        paths.push(archive_index.clone());
+    }
+    if let Err(err) = backup::snapshot(directory, &paths, &format!("archive before {}", cutoff.format("%Y.%m.%d"))) {
+        tracing::error!("Failed to save a snapshot before archiving: {}", err);
+        std::process::exit(1);
+    }
+    let mut dirs: Vec<&Path> = moves.iter().filter_map(|change| match change {
+        Move::Split { target, .. } | Move::Whole { target, .. } => target.parent(),
+    }).collect();
+    dirs.push(&archive_dir);
+    for dir in dirs {
+        if let Err(err) = fs::create_dir_all(dir) {
+            tracing::error!("Failed to create {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    }
+
+    for change in moves {
+        let result = match change {
+            Move::Split { path, target, archived, remaining, .. } => fs::write(target, archived).and_then(|_| match remaining {
+                Some(content) => fs::write(path, content),
+                None => fs::remove_file(path),
+            }),
+            Move::Whole { path, target, .. } => fs::rename(path, target),
+        };
+        if let Err(err) = result {
+            let (Move::Split { path, .. } | Move::Whole { path, .. }) = change;
+            tracing::error!("Failed to archive {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+
+    if same_index || sequences.is_empty() {
+        return;
+    }
+    let mut archived = Index::create(&archive_index).unwrap_or_else(|err| {
+        tracing::error!("Failed to open index {}: {}", archive_index.display(), err);
+        std::process::exit(1);
+    });
+    for sequence in sequences {
+        let state = index.state(*sequence).map(|state| state.to_string());
+        archived.restore(*sequence, index.dates(*sequence).map(|dates| dates.to_vec()), state);
+    }
+    if let Err(err) = archived.save() {
+        tracing::error!("Failed to save index {}: {}", archive_index.display(), err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    const CONTENT: &str = "# clinvoice-format: 2\n# March\n\n2025.03.03\n    2h = review\n\n2025.03.10\n    1h = fix\n";
+
+    #[test]
+    fn test_split() {
+        let split = split(CONTENT, date(5));
+        assert_eq!(split.dates, 1);
+        assert_eq!(archived(None, &split), "# clinvoice-format: 2\n# March\n\n2025.03.03\n    2h = review\n");
+        assert_eq!(remaining(&split).unwrap(), "# clinvoice-format: 2\n# March\n\n2025.03.10\n    1h = fix\n");
+        assert_eq!(archived(Some("# clinvoice-format: 2\n2025.03.01\n    1h = a\n\n"), &split),
+            "# clinvoice-format: 2\n2025.03.01\n    1h = a\n\n2025.03.03\n    2h = review\n");
+        assert_eq!(remaining(&super::split(CONTENT, date(11))), None);
+    }
+
+    #[test]
+    fn test_plan_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        fs::create_dir_all(dir.path().join("2025")).unwrap();
+        fs::write(dir.path().join("2025/03.cli"), CONTENT).unwrap();
+        fs::write(dir.path().join("top.cli"), "2025.03.12\n    1h = later\n").unwrap();
+        let options = LoadOptions { depth: 1, ..LoadOptions::default() };
+        let moves = plan(directory, &options, date(5)).unwrap();
+        assert_eq!(moves.len(), 1);
+        let Move::Split { path, target, dates, .. } = &moves[0] else {
+            panic!("expected a split");
+        };
+        assert_eq!(path, &dir.path().join("2025/03.cli"));
+        assert_eq!(target, &dir.path().join("archive/2025/03.cli"));
+        assert_eq!(*dates, 1);
+
+        // The top of the data directory only, without data.recursive.
+        assert!(plan(directory, &LoadOptions::default(), date(5)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_archived_sequences() {
+        let sequences = vec![
+            (1, vec!["2025.02".to_string()]),
+            (2, vec!["2025.03.01-2025.03.15".to_string()]),
+            (3, Vec::new()),
+            (4, vec!["2025.03.16-2025.03.31".to_string()]),
+            (5, vec!["2025.01".to_string(), "!2025.01.06".to_string(), "@weekdays".to_string(), "#acme".to_string()]),
+        ];
+        assert_eq!(archived_sequences(&sequences, date(16)).unwrap(), vec![1, 2, 5]);
+        assert!(archived_sequences(&sequences, date(10)).unwrap_err().contains("invoice 2"));
+
+        let sequences = vec![(6, vec!["2025.03".to_string(), "@weekday=mon".to_string(), "@file=2025-03.cli".to_string()])];
+        assert!(archived_sequences(&sequences, date(10)).unwrap_err().contains("invoice 6"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Snapshots of files taken before operations that rewrite them, and restoring them.
+
+use crate::config::Config;
+use crate::quiet;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where snapshots are kept, inside the data directory.
+pub const BACKUPS_DIR: &str = ".clinvoice/backups";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// A file in a snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotFile {
+    pub path: PathBuf,          // Where the file lives.
+    pub copy: Option<String>,   // The copy in the snapshot, None if the file did not exist.
+}
+
+/// The files saved by a snapshot, and why it was taken.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub reason: String,
+    pub files: Vec<SnapshotFile>,
+}
+
+/// Returns the directory holding the snapshots of a data directory.
+pub fn backups_dir(directory: &str) -> PathBuf {
+    Path::new(directory).join(BACKUPS_DIR)
+}
+
+/// Copies `files` into a new `.clinvoice/backups/<timestamp>/` snapshot before they are changed.
+///
+/// Files that do not exist yet are recorded too, so that `restore` removes them.
+///
+/// # Returns
+///
+/// The snapshot directory.
+pub fn snapshot(directory: &str, files: &[PathBuf], reason: &str) -> io::Result<PathBuf> {
+    let backups = backups_dir(directory);
+    let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut snapshot_dir = backups.join(&stamp);
+    let mut attempt = 1;
+    while snapshot_dir.exists() {
+        attempt += 1;
+        snapshot_dir = backups.join(format!("{}-{}", stamp, attempt));
+    }
+    fs::create_dir_all(&snapshot_dir)?;
+
+    let mut manifest = Manifest { reason: reason.to_string(), files: Vec::new() };
+    for (n, file) in files.iter().enumerate() {
+        let path = std::path::absolute(file)?;
+        let copy = if path.exists() {
+            let name = format!("{}-{}", n, path.file_name().map_or("file".into(), |f| f.to_string_lossy()));
+            fs::copy(&path, snapshot_dir.join(&name))?;
+            Some(name)
+        } else {
+            None
+        };
+        manifest.files.push(SnapshotFile { path, copy });
+    }
+    let json = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+    fs::write(snapshot_dir.join(MANIFEST_FILE), json)?;
+    tracing::info!("Saved a snapshot of {} files in {}", files.len(), snapshot_dir.display());
+    Ok(snapshot_dir)
+}
+
+/// Returns the names of the snapshots of a data directory, oldest first, with their manifests.
+pub fn snapshots(directory: &str) -> io::Result<Vec<(String, Manifest)>> {
+    let backups = backups_dir(directory);
+    if !backups.exists() {
+        return Ok(Vec::new());
+    }
+    let mut found = Vec::new();
+    for entry in fs::read_dir(&backups)? {
+        let path = entry?.path();
+        let manifest_path = path.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            continue;
+        }
+        let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)
+            .map_err(io::Error::other)?;
+        found.push((path.file_name().unwrap().to_string_lossy().to_string(), manifest));
+    }
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(found)
+}
+
+/// Puts the files of a snapshot back, removing those that did not exist when it was taken.
+pub fn restore(directory: &str, name: &str, manifest: &Manifest) -> io::Result<()> {
+    let snapshot_dir = backups_dir(directory).join(name);
+    for file in &manifest.files {
+        match &file.copy {
+            Some(copy) => {
+                if let Some(parent) = file.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(snapshot_dir.join(copy), &file.path)?;
+            }
+            None if file.path.exists() => fs::remove_file(&file.path)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Lists the snapshots, or restores one, the latest by default.
+///
+/// The files are saved in a new snapshot before they are restored, so a restore can be
+/// restored too.
+pub fn run(
+    name: Option<String>,
+    list: bool,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    let snapshots = snapshots(directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to read snapshots: {}", err);
+        std::process::exit(1);
+    });
+
+    if list {
+        if quiet::enabled() {
+            quiet::summary("snapshots", &[("count", snapshots.len().to_string())]);
+            return;
+        }
+        for (name, manifest) in &snapshots {
+            println!("{}  {} ({} files)", name, manifest.reason, manifest.files.len());
+        }
+        return;
+    }
+
+    if read_only {
+        tracing::error!("Cannot restore in read-only mode");
+        std::process::exit(1);
+    }
+    let found = match &name {
+        Some(name) => snapshots.iter().find(|(n, _)| n == name),
+        None => snapshots.last(),
+    };
+    let Some((name, manifest)) = found else {
+        tracing::error!("No snapshot {}found in {}", name.map_or(String::new(), |n| n + " "), backups_dir(directory).display());
+        std::process::exit(1);
+    };
+
+    let files: Vec<PathBuf> = manifest.files.iter().map(|f| f.path.clone()).collect();
+    if let Err(err) = snapshot(directory, &files, &format!("restore {}", name)) {
+        tracing::error!("Failed to save the files before restoring: {}", err);
+        std::process::exit(1);
+    }
+    if let Err(err) = restore(directory, name, manifest) {
+        tracing::error!("Failed to restore {}: {}", name, err);
+        std::process::exit(1);
+    }
+    tracing::info!("Restored {} files from {} ({})", files.len(), name, manifest.reason);
+    quiet::summary("restored", &[
+        ("snapshot", name.clone()),
+        ("files", files.len().to_string()),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let existing = dir.path().join("2025-03.cli");
+        let created = dir.path().join("2025-04.cli");
+        fs::write(&existing, "2025.03.03\n    1h = a\n").unwrap();
+
+        snapshot(directory, &[existing.clone(), created.clone()], "import").unwrap();
+        fs::write(&existing, "changed\n").unwrap();
+        fs::write(&created, "new\n").unwrap();
+
+        let found = snapshots(directory).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.reason, "import");
+        restore(directory, &found[0].0, &found[0].1).unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "2025.03.03\n    1h = a\n");
+        assert!(!created.exists());
+    }
+
+    #[test]
+    fn test_snapshot_names_are_unique() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let first = snapshot(directory, &[], "first").unwrap();
+        let second = snapshot(directory, &[], "second").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(snapshots(directory).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,273 @@
+//! Payments received for invoices, and the balance still owed.
+//!
+//! `pay` records payments in `.clinvoice/payments.json`, next to the records of invoices
+//! that `generate` keeps.  `balance` puts the two together: the amount billed by each
+//! invoice in the index, what was paid, what is left, and how late it is, counting
+//! `contract.payment_days` from the day the invoice was generated.
+
+use crate::clock;
+use crate::color::*;
+use crate::config::Config;
+use crate::diff::{self, Record};
+use crate::index::{index_file_path, Index, ISSUED};
+use crate::parse::parse_date;
+use crate::quiet;
+use chrono::{NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where payments are recorded, inside the data directory.
+pub const PAYMENTS_FILE: &str = ".clinvoice/payments.json";
+
+// Balances smaller than this are settled.
+const EPSILON: f64 = 0.005;
+
+/// A payment received for an invoice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Payment {
+    pub sequence: u32,
+    pub date: String,
+    pub amount: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+/// Returns the path of the payments of a data directory.
+pub fn payments_path(directory: &str) -> PathBuf {
+    Path::new(directory).join(PAYMENTS_FILE)
+}
+
+/// Loads the payments recorded, none if the file does not exist.
+pub fn load_payments(directory: &str) -> io::Result<Vec<Payment>> {
+    match fs::read_to_string(payments_path(directory)) {
+        Ok(json) => serde_json::from_str(&json).map_err(io::Error::other),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Saves the payments, replacing those recorded before.
+pub fn save_payments(directory: &str, payments: &[Payment]) -> io::Result<()> {
+    let path = payments_path(directory);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(payments).map_err(io::Error::other)?;
+    fs::write(path, json + "\n")
+}
+
+/// The balance of one invoice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Balance {
+    pub sequence: u32,
+    pub dates: Vec<String>,
+    pub issued: NaiveDate,
+    pub due: NaiveDate,
+    pub billed: f64,
+    pub paid: f64,
+}
+
+impl Balance {
+    /// The amount still owed, negative when overpaid.
+    pub fn outstanding(&self) -> f64 {
+        self.billed - self.paid
+    }
+
+    /// Days past the due date on `today`, 0 when settled or not due yet.
+    pub fn overdue(&self, today: NaiveDate) -> i64 {
+        if self.outstanding() <= EPSILON {
+            return 0;
+        }
+        (today - self.due).num_days().max(0)
+    }
+}
+
+/// Works out the balance of each recorded invoice, with the payments made for it.
+pub fn balances(records: &[Record], payments: &[Payment], payment_days: i64) -> Vec<Balance> {
+    records.iter().filter_map(|record| {
+        let Some(issued) = record.generated.split_whitespace().next().and_then(parse_date) else {
+            tracing::warn!("Invoice {} has an invalid generation date {}", record.sequence, record.generated);
+            return None;
+        };
+        let paid = payments.iter()
+            .filter(|payment| payment.sequence == record.sequence)
+            .fold(0.0, |paid, payment| paid + payment.amount);
+        Some(Balance {
+            sequence: record.sequence,
+            dates: record.dates.clone(),
+            issued,
+            due: issued + chrono::Duration::days(payment_days),
+            billed: record.total_amount,
+            paid,
+        })
+    }).collect()
+}
+
+fn load_config(directory: &str, config_file: &Option<String>) -> Option<Config> {
+    Config::new(config_file.as_deref(), Some(directory)).ok()
+}
+
+/// Shows the billed, paid, and outstanding amounts of the issued invoices, as of `as_of`,
+/// today by default.
+pub fn run(as_of: Option<String>, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = load_config(directory, config_file);
+    let today = match as_of {
+        Some(date) => parse_date(&date).unwrap_or_else(|| {
+            tracing::error!("Invalid date: {}", date);
+            std::process::exit(1);
+        }),
+        None => clock::today(config.as_ref()),
+    };
+    let index_path = index_file_path(config.as_ref(), directory);
+    let index = Index::open_read_only(&index_path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read index {}: {}", index_path.display(), err);
+        std::process::exit(1);
+    });
+    let payments = load_payments(directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to read {}: {}", payments_path(directory).display(), err);
+        std::process::exit(1);
+    });
+
+    let mut records = Vec::new();
+    for (sequence, _) in index.sequences() {
+        if index.state(sequence) != Some(ISSUED) {
+            continue;
+        }
+        match diff::load(directory, sequence) {
+            Ok(record) => records.push(record),
+            Err(err) => tracing::warn!("No record of invoice {}: {}, generate it again to record it", sequence, err),
+        }
+    }
+    let payment_days = config.as_ref().and_then(|c| c.get_i64("contract.payment_days")).unwrap_or(30);
+    let balances = balances(&records, &payments, payment_days);
+
+    let billed = balances.iter().fold(0.0, |sum, balance| sum + balance.billed);
+    let paid = balances.iter().fold(0.0, |sum, balance| sum + balance.paid);
+    let outstanding = balances.iter().fold(0.0, |sum, balance| sum + balance.outstanding().max(0.0));
+    let overdue = balances.iter().filter(|balance| balance.overdue(today) > 0).count();
+    if quiet::enabled() {
+        quiet::summary("balance", &[
+            ("invoices", balances.len().to_string()),
+            ("billed", format!("{:.2}", billed)),
+            ("paid", format!("{:.2}", paid)),
+            ("outstanding", format!("{:.2}", outstanding)),
+            ("overdue", overdue.to_string()),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    println!("{:>5}  {:<14} {:<10}  {:>10} {:>10} {:>10}  Overdue", "Seq", "Dates", "Due", "Billed", "Paid", "Balance");
+    for balance in &balances {
+        let days = balance.overdue(today);
+        let late = match days {
+            0 => String::new(),
+            1 => "1 day".to_string(),
+            n => format!("{} days", n),
+        }.out_colored(theme.warning);
+        println!("{:>5}  {:<14} {}  {:>10.2} {:>10.2} {}  {}",
+            balance.sequence, balance.dates.join(" "), balance.due.format("%Y-%m-%d").to_string().out_colored(theme.period),
+            balance.billed, balance.paid, format!("{:>10.2}", balance.outstanding()).out_colored(theme.hours), late);
+    }
+    println!("{:>5}  {:<14} {:<10}  {:>10.2} {:>10.2} {}", "Total", "", "", billed, paid,
+        format!("{:>10.2}", outstanding).out_colored(theme.total));
+}
+
+/// Records a payment for invoice `sequence`, on `date`, today by default.
+pub fn pay(
+    sequence: u32,
+    amount: f64,
+    date: Option<String>,
+    note: Option<String>,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = load_config(directory, config_file);
+    if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot record payments in read-only mode");
+        std::process::exit(1);
+    }
+    let date = match date {
+        Some(date) => parse_date(&date).unwrap_or_else(|| {
+            tracing::error!("Invalid date: {}", date);
+            std::process::exit(1);
+        }),
+        None => clock::today(config.as_ref()),
+    };
+    let index_path = index_file_path(config.as_ref(), directory);
+    let index = Index::open_read_only(&index_path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read index {}: {}", index_path.display(), err);
+        std::process::exit(1);
+    });
+    if index.state(sequence) != Some(ISSUED) {
+        tracing::error!("Sequence {} is not an issued invoice in {}", sequence, index_path.display());
+        std::process::exit(1);
+    }
+
+    let mut payments = load_payments(directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to read {}: {}", payments_path(directory).display(), err);
+        std::process::exit(1);
+    });
+    payments.push(Payment { sequence, date: date.format("%Y-%m-%d").to_string(), amount, note: note.unwrap_or_default() });
+    if let Err(err) = save_payments(directory, &payments) {
+        tracing::error!("Failed to write {}: {}", payments_path(directory).display(), err);
+        std::process::exit(1);
+    }
+    tracing::info!("Recorded a payment of {:.2} for invoice {}", amount, sequence);
+    quiet::summary("paid", &[
+        ("seq", sequence.to_string()),
+        ("amount", format!("{:.2}", amount)),
+        ("date", date.format("%Y-%m-%d").to_string()),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sequence: u32, generated: &str, total_amount: f64) -> Record {
+        Record {
+            sequence,
+            dates: vec![format!("2025.0{}", sequence)],
+            generated: generated.to_string(),
+            days: Vec::new(),
+            total_hours: 0.0,
+            total_amount,
+            files: Vec::new(),
+            generator: None,
+        }
+    }
+
+    fn payment(sequence: u32, amount: f64) -> Payment {
+        Payment { sequence, date: "2025-04-10".to_string(), amount, note: String::new() }
+    }
+
+    #[test]
+    fn test_balances() {
+        let records = vec![record(1, "2025-02-01 10:00", 1000.0), record(2, "2025-03-01 10:00", 500.0)];
+        let payments = vec![payment(1, 400.0), payment(1, 600.0), payment(2, 200.0)];
+        let balances = balances(&records, &payments, 30);
+        let today = NaiveDate::from_ymd_opt(2025, 4, 10).unwrap();
+        assert_eq!(balances[0].outstanding(), 0.0);
+        assert_eq!(balances[0].overdue(today), 0);
+        assert_eq!(balances[1].due, NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+        assert_eq!(balances[1].outstanding(), 300.0);
+        assert_eq!(balances[1].overdue(today), 10);
+        assert_eq!(balances[1].overdue(NaiveDate::from_ymd_opt(2025, 3, 20).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_payments_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        assert!(load_payments(directory).unwrap().is_empty());
+        let payments = vec![payment(1, 400.0), Payment { note: "wire".to_string(), ..payment(2, 200.0) }];
+        save_payments(directory, &payments).unwrap();
+        assert_eq!(load_payments(directory).unwrap(), payments);
+    }
+}
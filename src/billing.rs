@@ -0,0 +1,208 @@
+//! Custom billing rules, written in [Rhai](https://rhai.rs) in a `billing.rhai` file.
+//!
+//! The script can define either of these functions, which `compute` calls:
+//!
+//! - `day_hours(day)` returns the hours to bill for a day.  `day` is a map with `date`
+//!   (`YYYY-MM-DD`), `weekday` (`Mon`..`Sun`), `hours` (after `contract.cap_hours_per_day`),
+//!   `hours_worked`, and `entries`, each a map with `kind` (`time`, `cost`, or `note`),
+//!   `hours` or `amount`, `text`, and `tags` (without the `#`).
+//! - `billed_amount(invoice)` returns the amount billed for the hours, before fixed costs and
+//!   tax.  `invoice` is a map with `hours` (after `contract.cap_hours_per_invoice`),
+//!   `hours_worked`, `rate`, `amount` (hours times rate), `period_start`, `period_end`, and
+//!   `months`, each a map with `label` (`YYYY-MM`), `hours`, and `amount`.
+
+use crate::config::Config;
+use crate::data::{expand_home, Entry};
+use chrono::NaiveDate;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The script read from the data directory, unless `billing.script` names another.
+pub const SCRIPT_FILE: &str = "billing.rhai";
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled billing script.
+pub struct BillingRules {
+    engine: Engine,
+    ast: AST,
+    path: PathBuf,
+}
+
+impl BillingRules {
+    /// Loads the billing script, `billing.script` or `billing.rhai` in the data directory.
+    ///
+    /// # Returns
+    ///
+    /// `None` if there is no script.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the script cannot be read or compiled, or `billing.script`
+    /// names a missing file.
+    pub fn load(config: Option<&Config>, directory: &str) -> Result<Option<Self>, String> {
+        let path = match config.and_then(|c| c.get_string("billing.script")) {
+            Some(script) => Path::new(directory).join(expand_home(&script)),
+            None => {
+                let path = Path::new(directory).join(SCRIPT_FILE);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+        let script = fs::read_to_string(&path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let rules = Self::from_script(&script)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        tracing::info!("Billing rules {}", path.display());
+        Ok(Some(BillingRules { path, ..rules }))
+    }
+
+    /// Compiles billing rules from a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the script does not compile.
+    pub fn from_script(script: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(script).map_err(|err| err.to_string())?;
+        Ok(BillingRules { engine, ast, path: PathBuf::from("<script>") })
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name && f.params.len() == 1)
+    }
+
+    // Calls a function of the script, which must return a number.
+    fn call(&self, name: &str, arg: Map) -> Result<Option<f64>, String> {
+        if !self.defines(name) {
+            return Ok(None);
+        }
+        let result: Dynamic = self.engine.call_fn(&mut Scope::new(), &self.ast, name, (arg,))
+            .map_err(|err| format!("{}: {}: {}", self.path.display(), name, err))?;
+        let number = result.as_float().ok().or_else(|| result.as_int().ok().map(|i| i as f64))
+            .ok_or(format!("{}: {} returned {}, not a number", self.path.display(), name, result.type_name()))?;
+        Ok(Some(number))
+    }
+
+    /// Returns the hours to bill for a day, from `day_hours(day)`, or `hours` if the script
+    /// does not define it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the script fails, or does not return a number.
+    pub fn day_hours(&self, date: NaiveDate, hours: f64, hours_worked: f64, entries: &[Entry]) -> Result<f64, String> {
+        let entries: Array = entries.iter().map(|entry| {
+            let mut map = Map::new();
+            let (kind, value) = match entry {
+                Entry::Time(h, ..) => ("time", Some(("hours", *h as f64))),
+                Entry::FixedCost(c, _) => ("cost", Some(("amount", *c as f64))),
+                Entry::Note(_) => ("note", None),
+                Entry::NonBillable(h, _) => ("nonbillable", Some(("hours", *h as f64))),
+                Entry::Units(..) => ("units", Some(("amount", entry.cost()))),
+            };
+            map.insert("kind".into(), kind.into());
+            if let Some((key, value)) = value {
+                map.insert(key.into(), value.into());
+            }
+            map.insert("text".into(), entry.text().into());
+            let tags: Array = entry.tags().into_iter().map(|tag| tag.into()).collect();
+            map.insert("tags".into(), tags.into());
+            Dynamic::from_map(map)
+        }).collect();
+
+        let mut day = Map::new();
+        day.insert("date".into(), date.format("%Y-%m-%d").to_string().into());
+        day.insert("weekday".into(), date.format("%a").to_string().into());
+        day.insert("hours".into(), hours.into());
+        day.insert("hours_worked".into(), hours_worked.into());
+        day.insert("entries".into(), entries.into());
+        Ok(self.call("day_hours", day)?.unwrap_or(hours))
+    }
+
+    /// Returns the amount billed for the hours, from `billed_amount(invoice)`, or `amount` if
+    /// the script does not define it.
+    ///
+    /// `months` are the label, hours, and amount of each month.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the script fails, or does not return a number.
+    #[allow(clippy::too_many_arguments)]
+    pub fn billed_amount(
+        &self,
+        hours: f64,
+        hours_worked: f64,
+        rate: f64,
+        amount: f64,
+        period: (Option<NaiveDate>, Option<NaiveDate>),
+        months: &[(String, f64, f64)],
+    ) -> Result<f64, String> {
+        let date = |date: Option<NaiveDate>| date.map_or(Dynamic::UNIT, |d| d.format("%Y-%m-%d").to_string().into());
+        let months: Array = months.iter().map(|(label, hours, amount)| {
+            let mut map = Map::new();
+            map.insert("label".into(), label.clone().into());
+            map.insert("hours".into(), (*hours).into());
+            map.insert("amount".into(), (*amount).into());
+            Dynamic::from_map(map)
+        }).collect();
+
+        let mut invoice = Map::new();
+        invoice.insert("hours".into(), hours.into());
+        invoice.insert("hours_worked".into(), hours_worked.into());
+        invoice.insert("rate".into(), rate.into());
+        invoice.insert("amount".into(), amount.into());
+        invoice.insert("period_start".into(), date(period.0));
+        invoice.insert("period_end".into(), date(period.1));
+        invoice.insert("months".into(), months.into());
+        Ok(self.call("billed_amount", invoice)?.unwrap_or(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_hours() {
+        let rules = BillingRules::from_script(r#"
+            fn day_hours(day) {
+                if day.weekday == "Sat" { return 0; }
+                let support = 0.0;
+                for entry in day.entries {
+                    if entry.kind == "time" && "support" in entry.tags { support += entry.hours; }
+                }
+                day.hours - support + min(support, 1.0)
+            }
+        "#).unwrap();
+        let entries = vec![
+            Entry::Time(3.0, "call #support".to_string(), Vec::new()),
+            Entry::Time(2.0, "code".to_string(), Vec::new()),
+            Entry::Note("note".to_string()),
+        ];
+        let monday = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let saturday = NaiveDate::from_ymd_opt(2025, 3, 8).unwrap();
+        assert_eq!(rules.day_hours(monday, 5.0, 5.0, &entries).unwrap(), 3.0);
+        assert_eq!(rules.day_hours(saturday, 5.0, 5.0, &entries).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_billed_amount() {
+        let tiered = BillingRules::from_script(r#"
+            fn billed_amount(invoice) {
+                if invoice.hours <= 10.0 { return invoice.amount; }
+                10.0 * invoice.rate + (invoice.hours - 10.0) * invoice.rate * 0.5
+            }
+        "#).unwrap();
+        let months = vec![("2025-03".to_string(), 20.0, 2000.0)];
+        assert_eq!(tiered.billed_amount(20.0, 20.0, 100.0, 2000.0, (None, None), &months).unwrap(), 1500.0);
+        assert_eq!(tiered.billed_amount(8.0, 8.0, 100.0, 800.0, (None, None), &months).unwrap(), 800.0);
+
+        let none = BillingRules::from_script("let x = 1;").unwrap();
+        assert_eq!(none.billed_amount(8.0, 8.0, 100.0, 800.0, (None, None), &months).unwrap(), 800.0);
+        let broken = BillingRules::from_script(r#"fn billed_amount(invoice) { "text" }"#).unwrap();
+        assert!(broken.billed_amount(8.0, 8.0, 100.0, 800.0, (None, None), &months).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! The current time, in the `timezone` of the configuration.
+//!
+//! Invoices are dated by the billing day where the work is done, not by the clock of the
+//! machine generating them, which on a server is often UTC.  `timezone` is an IANA name like
+//! `America/Toronto`; without one, the system's local time is used.
+
+use crate::config::Config;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+/// Parses an IANA timezone name, like `Europe/Berlin`.
+///
+/// # Errors
+///
+/// Returns a `String` error for names that are not in the timezone database.
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|_| format!("Unknown timezone '{}', expected a name like America/Toronto", name))
+}
+
+/// Returns the configured timezone, `None` for local time.
+pub fn timezone(config: Option<&Config>) -> Option<Tz> {
+    let name = config?.get_string("timezone")?;
+    parse_timezone(&name).map_err(|err| tracing::warn!("{}, using local time", err)).ok()
+}
+
+/// Returns `utc` in `timezone`, or in local time without one.
+pub fn at(timezone: Option<Tz>, utc: DateTime<Utc>) -> DateTime<FixedOffset> {
+    match timezone {
+        Some(timezone) => utc.with_timezone(&timezone).fixed_offset(),
+        None => utc.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Returns the current time in the configured timezone.
+pub fn now(config: Option<&Config>) -> DateTime<FixedOffset> {
+    at(timezone(config), Utc::now())
+}
+
+/// Returns the current date in the configured timezone.
+pub fn today(config: Option<&Config>) -> NaiveDate {
+    now(config).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config(text: &str) -> Config {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("clinvoice.toml"), text).unwrap();
+        Config::new(None, Some(dir.path().to_str().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_at() {
+        let utc = Utc.with_ymd_and_hms(2025, 3, 31, 23, 30, 0).unwrap();
+        let toronto = at(timezone(Some(&config("timezone = \"America/Toronto\"\n"))), utc);
+        assert_eq!(toronto.to_rfc3339(), "2025-03-31T19:30:00-04:00");
+        let tokyo = at(timezone(Some(&config("timezone = \"Asia/Tokyo\"\n"))), utc);
+        assert_eq!(tokyo.date_naive(), NaiveDate::from_ymd_opt(2025, 4, 1).unwrap());
+        assert_eq!(timezone(Some(&config("timezone = \"Mars/Olympus\"\n"))), None);
+        assert_eq!(at(None, utc), utc.with_timezone(&Local).fixed_offset());
+        assert!(parse_timezone("Mars/Olympus").unwrap_err().contains("Unknown timezone"));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::config::Config;
+use std::env;
 use std::sync::OnceLock;
 use clap::ValueEnum;
 use colored::*;
@@ -19,23 +21,128 @@ pub struct ColorEnable {
 
 impl ColorEnable {
     /// Creates a new `ColorEnable` instance based on the provided `ColorOption`.
+    ///
+    /// In `Auto` mode, a non-empty `NO_COLOR` disables color, and `CLICOLOR_FORCE` (other
+    /// than `0`) enables it even when not writing to a terminal.
     pub fn new(color_option: &ColorOption) -> Self {
+        let no_color = env::var("NO_COLOR").ok();
+        let force = env::var("CLICOLOR_FORCE").ok();
+
         let use_color_stdout = match color_option {
             ColorOption::Always => true,
             ColorOption::Never => false,
-            ColorOption::Auto => atty::is(atty::Stream::Stdout),
+            ColorOption::Auto => auto_enabled(atty::is(atty::Stream::Stdout), no_color.as_deref(), force.as_deref()),
         };
 
         let use_color_stderr = match color_option {
             ColorOption::Always => true,
             ColorOption::Never => false,
-            ColorOption::Auto => atty::is(atty::Stream::Stderr),
+            ColorOption::Auto => auto_enabled(atty::is(atty::Stream::Stderr), no_color.as_deref(), force.as_deref()),
         };
 
         ColorEnable { stdout: use_color_stdout, stderr: use_color_stderr }
     }
 }
 
+// Decides if color is used in `Auto` mode, given the `NO_COLOR` and `CLICOLOR_FORCE` variables.
+fn auto_enabled(is_tty: bool, no_color: Option<&str>, force: Option<&str>) -> bool {
+    if no_color.is_some_and(|v| !v.is_empty()) {
+        false
+    } else if force.is_some_and(|v| !v.is_empty() && v != "0") {
+        true
+    } else {
+        is_tty
+    }
+}
+
+/// Colors used for the different kinds of output, configurable in `[colors]`.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub period: Color,      // Dates and periods in `log`.
+    pub hours: Color,       // Hours in `log`.
+    pub count: Color,       // Day counts in `log`.
+    pub total: Color,       // The total label in `log`.
+    pub warning: Color,     // Warnings, like problems in the index or timesheets.
+    pub error: Color,       // Errors.
+    pub build: Color,       // Build command output.
+    pub build_error: Color, // Build command output that looks like an error.
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            period: Color::Blue,
+            hours: Color::Green,
+            count: Color::Yellow,
+            total: Color::Red,
+            warning: Color::Yellow,
+            error: Color::Red,
+            build: Color::BrightBlack,
+            build_error: Color::BrightRed,
+        }
+    }
+}
+
+impl Theme {
+    /// Creates a `Theme` from the `[colors]` section of the configuration, if any.
+    ///
+    /// Colors are names like `blue` or `bright black`, or `#rrggbb`.
+    pub fn from_config(config: Option<&Config>) -> Self {
+        let mut theme = Theme::default();
+        let Some(config) = config else {
+            return theme;
+        };
+        let slots: [(&str, &mut Color); 8] = [
+            ("period", &mut theme.period),
+            ("hours", &mut theme.hours),
+            ("count", &mut theme.count),
+            ("total", &mut theme.total),
+            ("warning", &mut theme.warning),
+            ("error", &mut theme.error),
+            ("build", &mut theme.build),
+            ("build_error", &mut theme.build_error),
+        ];
+        for (key, slot) in slots {
+            if let Some(name) = config.get_string(&format!("colors.{}", key)) {
+                match parse_color(&name) {
+                    Ok(color) => *slot = color,
+                    Err(err) => tracing::warn!("colors.{}: {}", key, err),
+                }
+            }
+        }
+        theme
+    }
+}
+
+/// Parses a color name, like `blue`, `bright_black` or `#ff8800`.
+pub fn parse_color(name: &str) -> Result<Color, String> {
+    let name = name.trim();
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                return Ok(Color::TrueColor { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8 });
+            }
+        }
+        return Err(format!("invalid color {}", name));
+    }
+    name.replace(['_', '-'], " ").parse::<Color>()
+        .map_err(|_| format!("invalid color {}", name))
+}
+
+static G_THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Initializes the global color theme.
+///
+/// This function should be called at most once; the default theme is used otherwise.
+pub fn init_theme(config: Option<&Config>) {
+    G_THEME.set(Theme::from_config(config)).expect("init_theme called multiple times");
+}
+
+/// Returns the current color theme.
+pub fn theme() -> Theme {
+    G_THEME.get().cloned().unwrap_or_default()
+}
+
 static G_COLOR_ENABLED: OnceLock<ColorEnable> = OnceLock::new();
 
 /// Initializes the global color enable state.
@@ -47,9 +154,9 @@ pub fn init(color_option: &ColorOption) {
 
 /// Returns the current color enable state.
 ///
-/// Panics if `init` has not been called.
+/// Color is disabled if `init` has not been called, as when used as a library.
 pub fn color_enabled() -> ColorEnable {
-    G_COLOR_ENABLED.get().expect("init not called").clone()
+    G_COLOR_ENABLED.get().cloned().unwrap_or(ColorEnable { stdout: false, stderr: false })
 }
 
 /// Trait for dynamically applying colors to strings based on output stream.
@@ -113,3 +220,29 @@ impl DynamicColorize for String {
         self.as_str().err_colored(color)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_enabled() {
+        assert!(auto_enabled(true, None, None));
+        assert!(!auto_enabled(false, None, None));
+        assert!(!auto_enabled(true, Some("1"), None));
+        assert!(auto_enabled(true, Some(""), None));
+        assert!(auto_enabled(false, None, Some("1")));
+        assert!(!auto_enabled(false, None, Some("0")));
+        assert!(!auto_enabled(false, Some("1"), Some("1")));
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("blue"), Ok(Color::Blue));
+        assert_eq!(parse_color("bright_black"), Ok(Color::BrightBlack));
+        assert_eq!(parse_color("Bright Red"), Ok(Color::BrightRed));
+        assert_eq!(parse_color("#ff8800"), Ok(Color::TrueColor { r: 255, g: 136, b: 0 }));
+        assert!(parse_color("#ff88").is_err());
+        assert!(parse_color("mauve").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Shell completion: static scripts from the command definition, with dates and generator
+//! names suggested at completion time by the hidden `__complete` subcommand.
+
+use crate::alias;
+use crate::config::Config;
+use crate::data::{DateSelector, LoadOptions, TimeData};
+use clap::Command;
+use clap_complete::Shell;
+use std::collections::BTreeSet;
+use std::io;
+
+// Wraps the generated bash completion, asking `__complete` first.
+const BASH_DYNAMIC: &str = r#"
+_clinvoice_dynamic() {
+    local IFS=$'\n'
+    local candidates=($(clinvoice __complete -- "${COMP_WORDS[@]:0:COMP_CWORD+1}" 2>/dev/null))
+    if [[ ${#candidates[@]} -gt 0 ]]; then
+        COMPREPLY=("${candidates[@]}")
+        return 0
+    fi
+    _clinvoice "$@"
+}
+complete -F _clinvoice_dynamic -o bashdefault -o default clinvoice
+"#;
+
+// Wraps the generated zsh completion, asking `__complete` first.
+const ZSH_DYNAMIC: &str = r#"
+_clinvoice_dynamic() {
+    local -a candidates
+    candidates=(${(f)"$(clinvoice __complete -- "${(@)words[1,CURRENT]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -a candidates
+    else
+        _clinvoice "$@"
+    fi
+}
+compdef _clinvoice_dynamic clinvoice
+"#;
+
+/// Writes the completion script for a shell.  The bash and zsh scripts also complete dates
+/// and generator names from the data directory and configuration.
+pub fn generate(shell: Shell, command: &mut Command, out: &mut dyn io::Write) -> io::Result<()> {
+    clap_complete::generate(shell, command, "clinvoice", out);
+    match shell {
+        Shell::Bash => out.write_all(BASH_DYNAMIC.as_bytes()),
+        Shell::Zsh => out.write_all(ZSH_DYNAMIC.as_bytes()),
+        _ => Ok(()),
+    }
+}
+
+/// What a word being completed stands for.
+#[derive(Debug, PartialEq)]
+pub enum Expected {
+    Generator,
+    Date,
+    Other,
+}
+
+/// Works out what the last of `words`, the command line up to the cursor, stands for.
+pub fn expected(words: &[String], command: &Command) -> Expected {
+    let Some((current, before)) = words.split_last() else {
+        return Expected::Other;
+    };
+    let Some(position) = alias::scan(before, command).position else {
+        return Expected::Other;
+    };
+    let Some(subcommand) = command.find_subcommand(&before[position]) else {
+        return Expected::Other;
+    };
+
+    // The value of an option, like `--generator pdf`.
+    let previous = before.last().filter(|_| before.len() > position + 1);
+    if let Some(option) = previous.filter(|word| word.starts_with('-')) {
+        let arg = subcommand.get_arguments().find(|arg| match option.strip_prefix("--") {
+            Some(long) => arg.get_long() == Some(long),
+            None => option.len() == 2 && arg.get_short() == option.chars().nth(1),
+        });
+        if let Some(arg) = arg.filter(|arg| arg.get_action().takes_values()) {
+            return match arg.get_id().as_str() {
+                "generator" => Expected::Generator,
+                "exclude" => Expected::Date,
+                _ => Expected::Other,
+            };
+        }
+    }
+    if !current.starts_with('-') && subcommand.get_arguments().any(|arg| arg.get_id() == "dates") {
+        return Expected::Date;
+    }
+    Expected::Other
+}
+
+/// Returns the generators defined in the configuration.
+pub fn generators(config: &Config) -> Vec<String> {
+    config.get_table("generator").into_iter().flatten()
+        .filter(|(_, value)| value.is_table())
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Returns the years and months with entries, like `2025` and `2025.03`.
+pub fn dates(time_data: &TimeData) -> Vec<String> {
+    let mut dates = BTreeSet::new();
+    for date in time_data.entries.keys() {
+        dates.insert(date.format("%Y").to_string());
+        dates.insert(date.format("%Y.%m").to_string());
+    }
+    dates.into_iter().collect()
+}
+
+/// Prints the suggestions for the last of `words`, one per line, for the completion scripts.
+///
+/// Nothing is printed when the static completion knows better, and errors are ignored, so
+/// completion never gets in the way.
+pub fn run(words: &[String], command: &Command) {
+    let Some(current) = words.last() else {
+        return;
+    };
+    let expected = expected(words, command);
+    if expected == Expected::Other {
+        return;
+    }
+
+    let scan = alias::scan(&words[..words.len() - 1], command);
+    let directory = scan.directory.as_deref().unwrap_or(".");
+    let config = Config::new(scan.config.as_deref(), Some(directory)).ok();
+    let candidates = match expected {
+        Expected::Generator => config.as_ref().map(generators).unwrap_or_default(),
+        Expected::Date => TimeData::load(directory, &DateSelector::new(), &LoadOptions::from_config(config.as_ref()))
+            .map(|time_data| dates(&time_data))
+            .unwrap_or_default(),
+        Expected::Other => Vec::new(),
+    };
+    for candidate in candidates.iter().filter(|c| c.starts_with(current.as_str())) {
+        println!("{}", candidate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn words(text: &str) -> Vec<String> {
+        let mut words: Vec<String> = text.split_whitespace().map(|w| w.to_string()).collect();
+        if text.ends_with(' ') {
+            words.push(String::new());
+        }
+        words
+    }
+
+    fn command() -> Command {
+        let dates = || Arg::new("dates").action(ArgAction::Append);
+        Command::new("clinvoice")
+            .arg(Arg::new("directory").short('d').long("directory"))
+            .subcommand(Command::new("log").arg(Arg::new("format").short('f').long("format")).arg(dates()))
+            .subcommand(Command::new("generate")
+                .arg(Arg::new("generator").short('g').long("generator"))
+                .arg(Arg::new("exclude").short('x').long("exclude"))
+                .arg(dates()))
+            .subcommand(Command::new("undo"))
+    }
+
+    #[test]
+    fn test_expected() {
+        let command = command();
+        assert_eq!(expected(&words("clinvoice -d dir log 20"), &command), Expected::Date);
+        assert_eq!(expected(&words("clinvoice log "), &command), Expected::Date);
+        assert_eq!(expected(&words("clinvoice log -f "), &command), Expected::Other);
+        assert_eq!(expected(&words("clinvoice log --f"), &command), Expected::Other);
+        assert_eq!(expected(&words("clinvoice generate -g "), &command), Expected::Generator);
+        assert_eq!(expected(&words("clinvoice generate --generator p"), &command), Expected::Generator);
+        assert_eq!(expected(&words("clinvoice generate -x 2025"), &command), Expected::Date);
+        assert_eq!(expected(&words("clinvoice undo "), &command), Expected::Other);
+        assert_eq!(expected(&words("clinvoice lo"), &command), Expected::Other);
+    }
+
+    #[test]
+    fn test_generate_wraps_bash_and_zsh() {
+        let mut command = command();
+        let mut out = Vec::new();
+        generate(Shell::Bash, &mut command, &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("_clinvoice()"));
+        assert!(script.trim_end().ends_with("complete -F _clinvoice_dynamic -o bashdefault -o default clinvoice"));
+
+        let mut out = Vec::new();
+        generate(Shell::Fish, &mut command, &mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("__complete"));
+    }
+}
@@ -1,13 +1,18 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use toml::Value;
+use crate::schema::Kind;
 use std::fs;
 use std::env;
+use std::sync::OnceLock;
+
+static G_CLIENT: OnceLock<String> = OnceLock::new();
 
 /// Represents the application's configuration loaded from a TOML file.
 #[allow(dead_code)]
 pub struct Config {
     value: Value,
+    directory: Option<PathBuf>,
 }
 
 impl Config {
@@ -29,11 +34,16 @@ impl Config {
         let content = fs::read_to_string(&config_path)?;
         let value: Value = toml::from_str(&content)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        Ok(Config { value })
+        let directory = config_path.parent().map(Path::to_path_buf);
+        match G_CLIENT.get() {
+            Some(client) => Ok(Config { value: with_client(value, client), directory }),
+            None => Ok(Config { value, directory }),
+        }
     }
 
-    // Attempts to find the configuration file based on provided path or predefined locations.
-    fn find_config_path(config_file: Option<&str>, data_directory: Option<&str>) -> Result<PathBuf, std::io::Error> {
+    /// Finds the configuration file, the given one, or `clinvoice.toml` in the data directory,
+    /// the current directory, or `~/.config/clinvoice`.
+    pub fn find_config_path(config_file: Option<&str>, data_directory: Option<&str>) -> Result<PathBuf, std::io::Error> {
         if let Some(path) = config_file {
             let path = PathBuf::from(path);
             if path.exists() {
@@ -69,6 +79,25 @@ impl Config {
         Err(std::io::Error::new(std::io::ErrorKind::NotFound, "No config file found in searched locations"))
     }
 
+    /// Returns a copy of the configuration, with the settings of the `defaults` TOML for the
+    /// keys it does not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if `defaults` is not valid TOML.
+    pub fn with_defaults(&self, defaults: &str) -> Result<Config, String> {
+        let mut value: Value = toml::from_str(defaults).map_err(|err| err.to_string())?;
+        if let (Some(base), Some(layer)) = (value.as_table_mut(), self.value.as_table()) {
+            merge(base, layer);
+        }
+        Ok(Config { value, directory: self.directory.clone() })
+    }
+
+    /// Returns the directory holding the configuration file, if it was read from one.
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
     /// Checks if a key exists in the configuration.
     #[allow(dead_code)]
     pub fn has(&self, key: &str) -> bool {
@@ -126,6 +155,20 @@ impl Config {
         })
     }
 
+    /// Retrieves an array of strings for a given key, or `None` if any element is not a string.
+    #[allow(dead_code)]
+    pub fn get_string_array(&self, key: &str) -> Option<Vec<String>> {
+        self.get_value(key)
+            .and_then(|v| v.as_array())
+            .and_then(|a| a.iter().map(|v| v.as_str().map(|s| s.to_string())).collect())
+    }
+
+    /// Retrieves a `bool` value for a given key.
+    #[allow(dead_code)]
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get_value(key).and_then(|v| v.as_bool())
+    }
+
     /// Retrieves an `i64` value for a given key.
     #[allow(dead_code)]
     pub fn get_i64(&self, key: &str) -> Option<i64> {
@@ -186,6 +229,60 @@ impl Config {
     }
 }
 
+// Lays the settings of `[clients.<client>]` over the others, merging tables.
+// Lays the keys of `layer` over `base`, merging the tables they both have.
+fn merge(base: &mut toml::map::Map<String, Value>, layer: &toml::map::Map<String, Value>) {
+    for (key, value) in layer {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn with_client(mut value: Value, client: &str) -> Value {
+    let Some(mut layer) = value.get("clients").and_then(|c| c.get(client)).and_then(|c| c.as_table()).cloned() else {
+        return value;
+    };
+    layer.remove("directory");
+    if let Some(table) = value.as_table_mut() {
+        merge(table, &layer);
+    }
+    value
+}
+
+/// Selects a client from the `[clients.<name>]` sections of the configuration.  Its settings
+/// are laid over the others by every `Config` loaded afterwards, and its data live in the
+/// `directory` of the section, or a subdirectory named after the client, in the data
+/// directory.
+///
+/// # Returns
+///
+/// The data directory of the client, and the configuration file, which stays the one found
+/// for the data directory.
+///
+/// # Errors
+///
+/// Returns a `String` error if there is no configuration, or the client is not in it.
+pub fn select_client(client: &str, directory: &str, config_file: Option<&str>) -> Result<(String, String), String> {
+    let path = Config::find_config_path(config_file, Some(directory))
+        .map_err(|e| format!("Failed to find the configuration: {}", e))?;
+    let config = Config::new(path.to_str(), None).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+    if config.get_table(&format!("clients.{}", client)).is_none() {
+        let clients: Vec<String> = config.get_table("clients").map(|t| t.keys().cloned().collect()).unwrap_or_default();
+        return Err(format!("No [clients.{}] in {}, known clients: {}", client, path.display(),
+            if clients.is_empty() { "none".to_string() } else { clients.join(", ") }));
+    }
+    let subdirectory = config.get_string(&format!("clients.{}.directory", client)).unwrap_or(client.to_string());
+    let client_directory = Path::new(directory).join(subdirectory);
+    if G_CLIENT.set(client.to_string()).is_err() {
+        return Err("A client was already selected".to_string());
+    }
+    Ok((client_directory.display().to_string(), path.display().to_string()))
+}
+
 /// A trait for converting a `toml::Value` into another type.
 #[allow(dead_code)]
 pub trait FromValue {
@@ -216,6 +313,167 @@ impl FromValue for f64 {
     }
 }
 
+/// Actions on the configuration.
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print a JSON Schema of clinvoice.toml, for editors to validate and complete it
+    Schema,
+    /// Print the value of a setting, like contract.hourly_rate
+    Get {
+        key: String,
+    },
+    /// Change a setting in clinvoice.toml, keeping its comments and layout
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Print all the settings, as key = value lines
+    List,
+}
+
+/// Converts the `text` given for the setting `key` to a TOML value, by the kind of the
+/// setting.  Values of unknown settings are read as TOML, or as a string.
+///
+/// # Errors
+///
+/// Returns a `String` error if the text is not a valid value for the setting.
+pub fn parse_setting(key: &str, text: &str) -> Result<toml_edit::Value, String> {
+    let literal = text.parse::<toml_edit::Value>().ok();
+    let Some(known) = crate::schema::find(key) else {
+        tracing::warn!("{} is not a known setting", key);
+        return Ok(literal.unwrap_or_else(|| text.into()));
+    };
+    let invalid = || format!("Invalid value for {}, expected {}: {}", key, known.kind.describe(), text);
+    let value = match known.kind {
+        Kind::String => text.into(),
+        Kind::StringOrArray if !text.trim_start().starts_with('[') => text.into(),
+        Kind::Number => match literal {
+            Some(value @ (toml_edit::Value::Float(_) | toml_edit::Value::Integer(_))) => value,
+            _ => return Err(invalid()),
+        },
+        Kind::Integer => literal.filter(|v| v.is_integer()).ok_or_else(invalid)?,
+        Kind::Boolean => literal.filter(|v| v.is_bool()).ok_or_else(invalid)?,
+        Kind::StringArray | Kind::StringOrArray => literal
+            .filter(|v| v.as_array().is_some_and(|a| a.iter().all(|v| v.is_str())))
+            .ok_or_else(invalid)?,
+        Kind::Table => return Err(format!("{} is a table, set its keys one by one", key)),
+    };
+    if !known.values.is_empty() && !value.as_str().is_some_and(|v| known.values.contains(&v)) {
+        return Err(format!("Invalid value for {}, expected one of {}: {}", key, known.values.join(", "), text));
+    }
+    Ok(value)
+}
+
+/// Sets the dotted `key` to `value` in the TOML `content`, creating the tables on the way,
+/// and keeping comments and formatting.  The comment after a replaced value is kept too.
+///
+/// # Errors
+///
+/// Returns a `String` error if the content is not valid TOML, or a part of the key is not a
+/// table.
+pub fn set_value(content: &str, key: &str, value: toml_edit::Value) -> Result<String, String> {
+    let mut document = content.parse::<toml_edit::DocumentMut>().map_err(|e| e.to_string())?;
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, parents) = parts.split_last().filter(|(last, _)| !last.is_empty()).ok_or("Empty key")?;
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for (n, part) in parents.iter().enumerate() {
+        let item = table.entry(part).or_insert_with(|| {
+            let mut new = toml_edit::Table::new();
+            new.set_implicit(true);
+            toml_edit::Item::Table(new)
+        });
+        table = item.as_table_like_mut()
+            .ok_or_else(|| format!("{} is not a table", parts[..=n].join(".")))?;
+    }
+    let mut value = value;
+    match table.get_mut(last) {
+        Some(toml_edit::Item::Value(old)) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        Some(item) if item.is_table_like() => return Err(format!("{} is a table", key)),
+        _ => {
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+    }
+    Ok(document.to_string())
+}
+
+// Formats a value for `get`: strings without quotes, so that scripts can use them.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Table(_) => toml::to_string(value).unwrap_or_default().trim_end().to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Runs a configuration action, on the configuration of the data directory.
+pub fn run(action: ConfigAction, read_only: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let load = || Config::new(config_file.as_deref(), Some(directory)).unwrap_or_else(|err| {
+        tracing::error!("Failed to load configuration: {}", err);
+        std::process::exit(1);
+    });
+    match action {
+        ConfigAction::Schema => {
+            println!("{}", serde_json::to_string_pretty(&crate::schema::schema()).unwrap());
+        }
+        ConfigAction::Get { key } => match load().get(&key) {
+            Some(value) => println!("{}", format_value(value)),
+            None => {
+                tracing::error!("{} is not set", key);
+                std::process::exit(1);
+            }
+        },
+        ConfigAction::List => {
+            let mut values: Vec<(String, Value)> = load().get_flattened_values(".").into_iter().collect();
+            values.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, value) in values {
+                println!("{} = {}", key, value);
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            let read_only = read_only || Config::new(config_file.as_deref(), Some(directory)).ok()
+                .and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+            if read_only {
+                tracing::error!("Cannot change the configuration in read-only mode");
+                std::process::exit(1);
+            }
+            // Without a configuration, one is started in the data directory.
+            let path = Config::find_config_path(config_file.as_deref(), Some(directory))
+                .unwrap_or_else(|_| Path::new(directory).join("clinvoice.toml"));
+            let content = if path.exists() {
+                fs::read_to_string(&path).unwrap_or_else(|err| {
+                    tracing::error!("Failed to read {}: {}", path.display(), err);
+                    std::process::exit(1);
+                })
+            } else {
+                String::new()
+            };
+            let updated = parse_setting(&key, &value).and_then(|v| set_value(&content, &key, v))
+                .unwrap_or_else(|err| {
+                    tracing::error!("{}: {}", path.display(), err);
+                    std::process::exit(1);
+                });
+            if updated == content {
+                tracing::info!("{} is already {}", key, value);
+                return;
+            }
+            if let Err(err) = crate::backup::snapshot(directory, std::slice::from_ref(&path), &format!("config set {}", key)) {
+                tracing::error!("Failed to save a snapshot: {}", err);
+                std::process::exit(1);
+            }
+            if let Err(err) = fs::write(&path, updated) {
+                tracing::error!("Failed to write {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+            tracing::info!("Set {} in {}", key, path.display());
+            crate::quiet::summary("config", &[("set", key), ("file", path.display().to_string())]);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -479,4 +737,54 @@ mod tests {
         env::set_current_dir(&original_dir)?;
         Ok(())
     }
+
+    #[test]
+    fn test_set_value() {
+        let content = "# settings\n[contract]\n    hourly_rate = 50.0 # per hour\n    payment_days = 30\n";
+        let updated = set_value(content, "contract.hourly_rate", parse_setting("contract.hourly_rate", "75").unwrap()).unwrap();
+        assert_eq!(updated, "# settings\n[contract]\n    hourly_rate = 75 # per hour\n    payment_days = 30\n");
+        let updated = set_value(&updated, "generator.pdf.build", parse_setting("generator.pdf.build", "make pdf").unwrap()).unwrap();
+        assert!(updated.ends_with("[generator.pdf]\nbuild = \"make pdf\"\n"), "{}", updated);
+        assert!(set_value(&updated, "contract.hourly_rate.usd", 1.into()).is_err());
+        assert!(set_value(&updated, "contract", 1.into()).is_err());
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(parse_setting("read_only", "true").unwrap().as_bool(), Some(true));
+        assert!(parse_setting("read_only", "yes").is_err());
+        assert!(parse_setting("contract.hourly_rate", "fifty").is_err());
+        assert_eq!(parse_setting("contract.currency", "true").unwrap().as_str(), Some("true"));
+        assert_eq!(parse_setting("custom.count", "3").unwrap().as_integer(), Some(3));
+        assert_eq!(parse_setting("custom.name", "Acme Inc").unwrap().as_str(), Some("Acme Inc"));
+    }
+
+    #[test]
+    fn test_with_client() {
+        let value: Value = toml::from_str(r#"
+            [contract]
+            hourly_rate = 100.0
+            payment_days = 30
+            [clients.acme]
+            directory = "acme-inc"
+            contract = { hourly_rate = 120.0 }
+            client = { company = "Acme Inc" }
+        "#).unwrap();
+        let config = Config { value: with_client(value.clone(), "acme"), directory: None };
+        assert_eq!(config.get_f64("contract.hourly_rate"), Some(120.0));
+        assert_eq!(config.get_i64("contract.payment_days"), Some(30));
+        assert_eq!(config.get_string("client.company"), Some("Acme Inc".to_string()));
+        assert!(!config.has("directory"));
+        let config = Config { value: with_client(value, "globex"), directory: None };
+        assert_eq!(config.get_f64("contract.hourly_rate"), Some(100.0));
+    }
+
+    #[test]
+    fn test_with_defaults() {
+        let config = Config { value: toml::from_str("[contract]\nhourly_rate = 100.0\n").unwrap(), directory: None };
+        let config = config.with_defaults("[contract]\nhourly_rate = 50.0\npayment_days = 15\n").unwrap();
+        assert_eq!(config.get_f64("contract.hourly_rate"), Some(100.0));
+        assert_eq!(config.get_i64("contract.payment_days"), Some(15));
+        assert!(config.with_defaults("[contract").is_err());
+    }
 }
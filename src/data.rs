@@ -1,21 +1,123 @@
-use crate::parse::{parse_date, parse_line};
-use crate::color::*;
-use chrono::{NaiveDate};
-use std::collections::HashMap;
+use crate::parse::{looks_like_date, parse_date_line, parse_format_directive, parse_line_at, parse_project_directive, FORMAT_VERSION};
+use crate::archive::ARCHIVE_DIR;
+use crate::clock;
+use crate::config::Config;
+use crate::diagnostics::{self, Diagnostic};
+use crate::quiet;
+use crate::recurring::{self, Recurring};
+use crate::tsv::{self, Columns};
+use chrono::{Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use clap::Args;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
 use std::fs;
-use std::path::Path;
-use colored::Color;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// A span of time worked, from a time range like `9:00-12:30`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub start: NaiveTime,
+    /// The end, `00:00` for a range ending at midnight.
+    pub end: NaiveTime,
+}
+
+impl Interval {
+    /// Returns the start and end in minutes since midnight, with an end at midnight as 24:00.
+    pub fn minutes(&self) -> (i64, i64) {
+        let minutes = |time: NaiveTime| (time - NaiveTime::MIN).num_minutes();
+        let end = match minutes(self.end) {
+            0 => 24 * 60,
+            end => end,
+        };
+        (minutes(self.start), end)
+    }
+}
 
 /// Represents a single entry in a timesheet, which can be time worked, a fixed cost, or a note.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Entry {
-    Time(f32, String),
+    /// Hours worked, with the intervals of the time ranges they were written as, if any.
+    Time(f32, String, Vec<Interval>),
     FixedCost(f32, String),
     Note(String),
+    /// Time worked but not billed, written `~2h = internal meeting`.
+    NonBillable(f32, String),
+    /// A quantity at a unit price, written `3 x $50 = licenses`.
+    Units(f32, f32, String),
+}
+
+impl Entry {
+    /// Returns the text of the entry, the description or the note.
+    pub fn text(&self) -> &str {
+        match self {
+            Entry::Time(_, text, _) | Entry::FixedCost(_, text) | Entry::Note(text) | Entry::NonBillable(_, text)
+            | Entry::Units(_, _, text) => text,
+        }
+    }
+
+    /// Returns the hours worked, billable or not, 0 for fixed costs and notes.
+    pub fn hours(&self) -> f32 {
+        match self {
+            Entry::Time(hours, ..) | Entry::NonBillable(hours, _) => *hours,
+            Entry::FixedCost(..) | Entry::Note(_) | Entry::Units(..) => 0.0,
+        }
+    }
+
+    /// Returns the fixed cost, or the quantity at the unit price, 0 for time and notes.
+    pub fn cost(&self) -> f64 {
+        match self {
+            Entry::FixedCost(cost, _) => *cost as f64,
+            Entry::Units(quantity, price, _) => *quantity as f64 * *price as f64,
+            Entry::Time(..) | Entry::Note(_) | Entry::NonBillable(..) => 0.0,
+        }
+    }
+
+    /// Appends a continuation line to the text, separated by a space.
+    pub fn append_text(&mut self, more: &str) {
+        let (Entry::Time(_, text, _) | Entry::FixedCost(_, text) | Entry::Note(text) | Entry::NonBillable(_, text)
+            | Entry::Units(_, _, text)) = self;
+        if !text.is_empty() && !more.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(more);
+    }
+
+    /// Returns the `#tags` of the entry text, without the `#` or trailing punctuation, like
+    /// `backend` for `3h = code review #backend`.
+    pub fn tags(&self) -> Vec<String> {
+        self.text().split_whitespace()
+            .filter_map(|word| word.strip_prefix('#'))
+            .map(|word| word.trim_end_matches(|c: char| !c.is_alphanumeric()).to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// Returns the first `@project` marker of the entry text, without the `@` or trailing
+    /// punctuation, like `acme` for `3h = code review @acme`.  A timesheet line starting
+    /// with `@` is a snippet instead, replaced before it is parsed.
+    pub fn project(&self) -> Option<String> {
+        self.text().split_whitespace()
+            .filter_map(|word| word.strip_prefix('@'))
+            .map(|word| word.trim_end_matches(|c: char| !c.is_alphanumeric()).to_string())
+            .find(|project| !project.is_empty())
+    }
+
+    /// Checks if the entry text carries the `#tag`, given with or without the `#`.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim_start_matches('#');
+        self.tags().iter().any(|word| word.eq_ignore_ascii_case(tag))
+    }
 }
 
 /// Represents a range of dates, inclusive of start and end dates.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct DateRange {
     pub start: NaiveDate,
     pub end: NaiveDate,
@@ -25,6 +127,12 @@ pub struct DateRange {
 #[derive(Debug)]
 pub struct DateSelector {
     pub ranges: Vec<DateRange>,
+    /// Ranges carved out of the selection, even if they are within `ranges`.
+    pub excludes: Vec<DateRange>,
+    /// Leaves out Saturdays and Sundays.
+    pub weekdays_only: bool,
+    /// Days of the week to select, all days if empty.
+    pub weekdays: Vec<Weekday>,
 }
 
 impl Default for DateSelector {
@@ -36,7 +144,7 @@ impl Default for DateSelector {
 impl DateSelector {
     /// Creates a new, empty `DateSelector`.
     pub fn new() -> Self {
-        DateSelector { ranges: Vec::new() }
+        DateSelector { ranges: Vec::new(), excludes: Vec::new(), weekdays_only: false, weekdays: Vec::new() }
     }
 
     /// Creates a `DateSelector` from a list of date argument strings.
@@ -68,11 +176,29 @@ impl DateSelector {
         self.ranges.push(range);
     }
 
+    /// Excludes the dates given as date argument strings, like `2025.02.10-2025.02.14`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if any date argument is invalid.
+    pub fn exclude_dates(&mut self, dates: &[String]) -> Result<(), String> {
+        for date_arg in dates {
+            let range = crate::parse::parse_date_arg(date_arg)
+                .map_err(|err| format!("Invalid exclude argument: {} - {}", date_arg, err))?;
+            self.excludes.push(range);
+        }
+        Ok(())
+    }
+
     /// Checks if a given date falls within any of the selected date ranges.
     ///
-    /// If no ranges are specified, all dates are considered selected.
+    /// If no ranges are specified, all dates are considered selected, except for excluded ones.
     pub fn selected(&self, date: &NaiveDate) -> bool {
-        if self.ranges.is_empty() {
+        let weekend = self.weekdays_only && matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        let other_day = !self.weekdays.is_empty() && !self.weekdays.contains(&date.weekday());
+        if weekend || other_day || self.excludes.iter().any(|range| date >= &range.start && date <= &range.end) {
+            false
+        } else if self.ranges.is_empty() {
             true
         } else {
             self.ranges.iter().any(|range| date >= &range.start && date <= &range.end)
@@ -80,10 +206,621 @@ impl DateSelector {
     }
 }
 
+// Index key items recording `--weekdays-only`, `--weekday` and `--file`.
+const WEEKDAYS_ONLY_KEY: &str = "@weekdays";
+const WEEKDAY_KEY_PREFIX: &str = "@weekday=";
+const FILE_KEY_PREFIX: &str = "@file=";
+const DIRECTORY_KEY_PREFIX: &str = "@directory=";
+
+/// Date selection arguments shared by the commands that read timesheets.
+#[derive(Args, Debug, Default, Clone)]
+pub struct Selection {
+    /// Dates or date ranges to select, all dates if none are given.
+    #[clap(value_parser)]
+    pub dates: Vec<String>,
+    /// Dates or date ranges to leave out, can be repeated.
+    #[clap(short = 'x', long, value_name = "DATES")]
+    pub exclude: Vec<String>,
+    /// Leave out entries logged on Saturdays and Sundays.
+    #[clap(long)]
+    pub weekdays_only: bool,
+    /// Days of the week to select, like `mon,tue` or `mon-wed`.
+    #[clap(short, long, value_delimiter = ',', value_name = "DAYS")]
+    pub weekday: Vec<String>,
+    /// Read only this timesheet file instead of the whole directory, can be repeated and
+    /// use glob patterns.
+    #[clap(long, value_name = "FILE")]
+    pub file: Vec<String>,
+    /// Stop on any problem in the timesheets instead of leaving out what cannot be read.
+    #[clap(long)]
+    pub strict: bool,
+    /// Tags whose entries are billed alone, as `generate --tag` does, without the `#`.
+    #[clap(skip)]
+    pub tags: Vec<String>,
+    /// Directories whose timesheets are read in addition to the data directory, from
+    /// repeated `--directory` arguments.
+    #[clap(skip)]
+    pub directories: Vec<String>,
+}
+
+impl Selection {
+    /// Creates a `Selection` from a key made by `key`, as stored in the index.
+    pub fn from_key(key: &[String]) -> Self {
+        let mut selection = Selection::default();
+        for item in key {
+            if item == WEEKDAYS_ONLY_KEY {
+                selection.weekdays_only = true;
+                continue;
+            }
+            if let Some(days) = item.strip_prefix(WEEKDAY_KEY_PREFIX) {
+                selection.weekday.extend(days.split(',').map(|d| d.to_string()));
+                continue;
+            }
+            if let Some(file) = item.strip_prefix(FILE_KEY_PREFIX) {
+                selection.file.push(file.to_string());
+                continue;
+            }
+            if let Some(directory) = item.strip_prefix(DIRECTORY_KEY_PREFIX) {
+                selection.directories.push(directory.to_string());
+                continue;
+            }
+            if let Some(tag) = item.strip_prefix('#') {
+                selection.tags.push(tag.to_string());
+                continue;
+            }
+            match item.strip_prefix('!') {
+                Some(exclude) => selection.exclude.push(exclude.to_string()),
+                None => selection.dates.push(item.clone()),
+            }
+        }
+        selection
+    }
+
+    /// Returns the dates followed by the excludes prefixed with `!`, the weekday filter,
+    /// the files and the `#tags`, identifying the selection in the index.
+    pub fn key(&self) -> Vec<String> {
+        self.dates.iter().cloned()
+            .chain(self.exclude.iter().map(|e| format!("!{}", e)))
+            .chain(self.weekdays_only.then(|| WEEKDAYS_ONLY_KEY.to_string()))
+            .chain((!self.weekday.is_empty()).then(|| format!("{}{}", WEEKDAY_KEY_PREFIX, self.weekday.join(","))))
+            .chain(self.file.iter().map(|f| format!("{}{}", FILE_KEY_PREFIX, f)))
+            .chain(self.directories.iter().map(|d| format!("{}{}", DIRECTORY_KEY_PREFIX, d)))
+            .chain(self.tags.iter().map(|t| format!("#{}", t)))
+            .collect()
+    }
+
+    /// Returns the `LoadOptions` from the configuration, reading only the selected files and
+    /// the extra directories, and strictly with `--strict` or `parse.strict`.
+    pub fn load_options(&self, config: Option<&Config>) -> LoadOptions {
+        let strict = self.strict || config.and_then(|c| c.get_bool("parse.strict")).unwrap_or(false);
+        LoadOptions {
+            files: self.file.clone(),
+            extra_directories: self.directories.clone(),
+            strict,
+            ..LoadOptions::from_config(config)
+        }
+    }
+
+    /// Returns the `load_options`, stopping on dates written in several files when
+    /// `parse.duplicate_dates` is `"error"`, for the commands that show or bill the entries:
+    /// `log`, `generate` and `heatmap`.  Others only report them, so that they can be fixed.
+    pub fn checked_load_options(&self, config: Option<&Config>) -> LoadOptions {
+        let mut options = self.load_options(config);
+        if config.and_then(|c| c.get_string("parse.duplicate_dates")).as_deref() == Some("error") {
+            options.duplicate_dates = DuplicateDates::Error;
+        }
+        options
+    }
+
+    /// Creates the `DateSelector` for this selection.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if any date or weekday argument is invalid.
+    pub fn selector(&self) -> Result<DateSelector, String> {
+        let mut selector = DateSelector::from_dates(&self.dates)?;
+        selector.exclude_dates(&self.exclude)?;
+        selector.weekdays_only = self.weekdays_only;
+        for day in &self.weekday {
+            selector.weekdays.extend(crate::parse::parse_weekdays(day)?);
+        }
+        Ok(selector)
+    }
+}
+
+/// Options controlling how `TimeData` reads timesheet files.
+#[derive(Debug, Default, Clone)]
+pub struct LoadOptions {
+    /// Identity file passed to `age` when decrypting `.cli.age` files.
+    pub age_identity: Option<PathBuf>,
+    /// Command used to decrypt `.cli.age` files, `age` by default.
+    pub age_command: Option<String>,
+    /// Command used to decrypt `.cli.gpg` files, `gpg` by default.
+    pub gpg_command: Option<String>,
+    /// Show a progress bar while loading, if stderr is a terminal.
+    pub progress: bool,
+    /// Files or glob patterns to read, relative to the data directory, instead of every
+    /// timesheet in it.
+    pub files: Vec<String>,
+    /// Directories whose timesheets are read in addition to the data directory.
+    pub extra_directories: Vec<String>,
+    /// Entries from `[snippets]`, written as `@name` lines in timesheets.
+    pub snippets: HashMap<String, String>,
+    /// The columns of `.tsv` timesheets without a header row, from `tsv.columns`.
+    pub tsv_columns: Columns,
+    /// The timezone of open-ended time ranges running until now, from `timezone`, local
+    /// time by default.
+    pub timezone: Option<Tz>,
+    /// Entries from `[recurring]`, added to the dates they happen on.
+    pub recurring: Vec<Recurring>,
+    /// Fail with the problems found, instead of leaving out the lines that cannot be read.
+    pub strict: bool,
+    /// What to do with a date written in several files, from `parse.duplicate_dates`, where
+    /// `error` is only kept by `Selection::checked_load_options`.
+    pub duplicate_dates: DuplicateDates,
+    /// How many levels of subdirectories are searched for timesheets, 0 for none, from
+    /// `data.recursive` and `data.max_depth`.
+    pub depth: usize,
+}
+
+/// What to do with a date written in several timesheet files.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DuplicateDates {
+    /// Merge the entries, as if they were written in one file.
+    #[default]
+    Merge,
+    /// Merge the entries, and report the date and its files as a problem.
+    Warn,
+    /// Stop loading, reporting the date and its files.
+    Error,
+}
+
+impl DuplicateDates {
+    /// Parses a policy name, `merge`, `warn` or `error`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error for other names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "merge" => Ok(DuplicateDates::Merge),
+            "warn" => Ok(DuplicateDates::Warn),
+            "error" => Ok(DuplicateDates::Error),
+            other => Err(format!("unknown parse.duplicate_dates '{}', expected merge, warn or error", other)),
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Creates `LoadOptions` from the `[encryption]`, `[snippets]`, and `[tsv]` sections of
+    /// the configuration, if any.
+    pub fn from_config(config: Option<&Config>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        LoadOptions {
+            age_identity: config.get_string("encryption.age_identity").map(|p| expand_home(&p)),
+            age_command: config.get_string("encryption.age_command"),
+            gpg_command: config.get_string("encryption.gpg_command"),
+            progress: false,
+            files: Vec::new(),
+            extra_directories: Vec::new(),
+            snippets: snippets(config),
+            tsv_columns: Columns::from_config(config).unwrap_or_else(|err| {
+                tracing::warn!("{}, using {}", err, tsv::COLUMNS.join(", "));
+                Columns::default()
+            }),
+            timezone: clock::timezone(Some(config)),
+            recurring: recurring::from_config(config).unwrap_or_else(|err| {
+                tracing::warn!("{}, leaving out recurring entries", err);
+                Vec::new()
+            }),
+            strict: false,
+            duplicate_dates: match config.get_string("parse.duplicate_dates").map(|name| DuplicateDates::parse(&name)) {
+                // Only the commands showing or billing the entries stop, see `checked_load_options`.
+                Some(Ok(DuplicateDates::Error)) => DuplicateDates::Warn,
+                Some(Ok(policy)) => policy,
+                Some(Err(err)) => {
+                    tracing::warn!("{}, merging them", err);
+                    DuplicateDates::Merge
+                }
+                None => DuplicateDates::Merge,
+            },
+            depth: match config.get_bool("data.recursive") {
+                Some(true) => config.get_i64("data.max_depth").map_or(usize::MAX, |depth| depth.max(0) as usize),
+                _ => 0,
+            },
+        }
+    }
+}
+
+/// Reads the entries defined in `[snippets]`, like `standup = "0.25h = daily standup"`.
+pub fn snippets(config: &Config) -> HashMap<String, String> {
+    config.get_table("snippets").into_iter().flatten()
+        .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Checks if a path names a timesheet file: `.cli`, an encrypted `.cli.age` or `.cli.gpg`,
+/// or `.tsv`.
+pub fn is_timesheet(path: &Path) -> bool {
+    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    name.ends_with(".cli") || name.ends_with(".cli.age") || name.ends_with(".cli.gpg") || tsv::is_tsv(path)
+}
+
+/// The name of the file listing files to skip in a data directory, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".clinvoiceignore";
+
+// Reads the `.clinvoiceignore` file of a directory, if there is one.
+fn ignore_rules(dir: &Path) -> Result<Gitignore, Error> {
+    let path = dir.join(IGNORE_FILE);
+    if !path.is_file() {
+        return Ok(Gitignore::empty());
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(err) = builder.add(&path) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), err)));
+    }
+    builder.build().map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+// Lists the timesheet files in a directory, and in its subdirectories down to `depth` levels,
+// except those matched by its `.clinvoiceignore`, sorted by path.
+fn timesheets_in(dir: &Path, depth: usize) -> Result<Vec<PathBuf>, Error> {
+    let ignore = ignore_rules(dir)?;
+    let mut files = Vec::new();
+    collect_timesheets(dir, dir, depth, &ignore, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+// Adds the timesheet files of `dir` to `files`, and those of its subdirectories while `depth`
+// allows.  Hidden subdirectories, symbolic links to directories, and the `archive/` of the
+// data directory are not entered.
+fn collect_timesheets(root: &Path, dir: &Path, depth: usize, ignore: &Gitignore, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if depth == 0 || name.starts_with('.') || (dir == root && name == ARCHIVE_DIR) {
+                continue;
+            }
+            if ignore.matched(&path, true).is_ignore() {
+                tracing::debug!("ignoring {}", path.display());
+                continue;
+            }
+            collect_timesheets(root, &path, depth - 1, ignore, files)?;
+        } else if path.is_file() && is_timesheet(&path) {
+            if ignore.matched_path_or_any_parents(&path, false).is_ignore() {
+                tracing::debug!("ignoring {}", path.display());
+                continue;
+            }
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Lists the timesheet files that `TimeData::load` reads with the given `LoadOptions`.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if a directory cannot be read, or `options.files` names a
+/// missing file or a pattern matching nothing.
+pub fn timesheet_files(dir_path: &str, options: &LoadOptions) -> Result<Vec<PathBuf>, Error> {
+    let path = Path::new(dir_path);
+    let mut files = if options.files.is_empty() {
+        timesheets_in(path, options.depth)?
+    } else {
+        matching_files(path, &options.files)?
+    };
+    for directory in &options.extra_directories {
+        files.extend(timesheets_in(Path::new(directory), options.depth)?);
+    }
+    Ok(files)
+}
+
+/// Reads the date lines of a timesheet file, with their 1-based line numbers.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the file cannot be read or decrypted.
+pub fn read_dates(path: &Path, options: &LoadOptions) -> Result<Vec<(usize, NaiveDate)>, Error> {
+    let (reader, child) = open_timesheet(path, options)?;
+    let mut dates = Vec::new();
+    if tsv::is_tsv(path) {
+        for row in read_tsv(path, reader, options)? {
+            dates.extend(row.date.ok().map(|date| (row.line, date)));
+        }
+        finish_timesheet(path, child)?;
+        return Ok(dates);
+    }
+    for (line_number, line) in reader.lines().enumerate() {
+        if let Some(Ok(date)) = parse_date_line(line?.trim()) {
+            dates.push((line_number + 1, date));
+        }
+    }
+    finish_timesheet(path, child)?;
+    Ok(dates)
+}
+
+// Finds the files named by `patterns`, relative to `dir` unless absolute.
+//
+// A pattern without glob characters names a file that must exist, while glob patterns only
+// match timesheet files that are not ignored, and must match at least one.
+fn matching_files(dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let ignore = ignore_rules(dir)?;
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let full = dir.join(pattern);
+        if !pattern.contains(['*', '?', '[']) {
+            if !full.is_file() {
+                return Err(Error::new(ErrorKind::NotFound, format!("{}: no such file", full.display())));
+            }
+            files.push(full);
+            continue;
+        }
+        let paths = glob::glob(&full.to_string_lossy())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", pattern, e)))?;
+        let before = files.len();
+        for path in paths {
+            let path = path.map_err(Error::from)?;
+            if path.is_file() && is_timesheet(&path) && !ignore.matched_path_or_any_parents(&path, false).is_ignore() {
+                files.push(path);
+            }
+        }
+        if files.len() == before {
+            return Err(Error::new(ErrorKind::NotFound, format!("{}: no timesheet files match", pattern)));
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// Opens a timesheet file for reading line by line, decrypting it with an external tool if needed.
+//
+// When a decryption tool is used, its `Decryption` is returned as well, and must be passed to
+// `finish_timesheet` once all lines have been read.
+fn open_timesheet(path: &Path, options: &LoadOptions) -> Result<(Box<dyn BufRead>, Option<Decryption>), Error> {
+    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    let mut command = if name.ends_with(".age") {
+        let mut command = Command::new(options.age_command.as_deref().unwrap_or("age"));
+        command.arg("--decrypt");
+        match &options.age_identity {
+            Some(identity) => command.arg("--identity").arg(identity),
+            None => return Err(Error::new(ErrorKind::InvalidInput,
+                format!("{}: encryption.age_identity is not set in config", path.display()))),
+        };
+        command
+    } else if name.ends_with(".gpg") {
+        let mut command = Command::new(options.gpg_command.as_deref().unwrap_or("gpg"));
+        command.arg("--quiet").arg("--batch").arg("--decrypt");
+        command
+    } else {
+        return Ok((Box::new(BufReader::new(File::open(path)?)), None));
+    };
+
+    tracing::debug!("decrypting {}", path.display());
+    let mut child = command.arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::new(e.kind(), format!("{}: failed to run {:?}: {}", path.display(), command.get_program(), e)))?;
+    let stdout = child.stdout.take().unwrap();
+    // Read on a thread, a tool filling the stderr pipe would otherwise stop writing stdout.
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr = thread::spawn(move || {
+        let mut text = String::new();
+        let _ = stderr.read_to_string(&mut text);
+        text
+    });
+    Ok((Box::new(BufReader::new(stdout)), Some(Decryption { child, stderr })))
+}
+
+// A decryption tool started by `open_timesheet`, and the thread reading its error messages.
+struct Decryption {
+    child: Child,
+    stderr: JoinHandle<String>,
+}
+
+// Reads the rows of a `.tsv` timesheet.
+fn read_tsv(path: &Path, reader: Box<dyn BufRead>, options: &LoadOptions) -> Result<Vec<tsv::Row>, Error> {
+    tsv::read_rows(reader, &options.tsv_columns)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+// Waits for the decryption tool started by `open_timesheet`, if any, and checks that it succeeded.
+fn finish_timesheet(path: &Path, decryption: Option<Decryption>) -> Result<(), Error> {
+    let Some(Decryption { mut child, stderr }) = decryption else {
+        return Ok(());
+    };
+    let status = child.wait()?;
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(Error::other(format!("{}: decryption failed: {}", path.display(), stderr.trim())));
+    }
+    Ok(())
+}
+
+// Creates a progress bar for loading files, hidden unless requested and stderr is a terminal.
+fn progress_bar(options: &LoadOptions, files: usize) -> ProgressBar {
+    if !options.progress || quiet::enabled() || !atty::is(atty::Stream::Stderr) {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(files as u64);
+    bar.set_style(ProgressStyle::with_template("{spinner} loading {pos}/{len} files {wide_bar}")
+        .unwrap());
+    bar
+}
+
+// The entry that continuation lines are appended to, and the date it was kept under, if it
+// was selected and parsed.
+struct Continued {
+    indent: usize,
+    backslash: bool,
+    date: Option<NaiveDate>,
+}
+
+/// The file and 1-based line number an entry was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub file: PathBuf,
+    pub line: usize,
+    /// The project of the entry: its `@project` marker, or the `# clinvoice-project:` above
+    /// it in its file.  None for the source of a date.
+    pub project: Option<String>,
+}
+
+impl Source {
+    /// The source of an entry of `[recurring.<name>]` in the configuration, which has no
+    /// file and is at line 0.
+    pub fn recurring(name: &str) -> Self {
+        Source { file: PathBuf::from(format!("[recurring.{}]", name)), line: 0, project: None }
+    }
+
+    /// Checks if the entry was added from `[recurring]` rather than read from a timesheet.
+    pub fn is_recurring(&self) -> bool {
+        self.line == 0
+    }
+}
+
+// Remembers the first line of each file where a date is written.
+fn note_date(date_files: &mut HashMap<NaiveDate, Vec<Source>>, date: NaiveDate, file: &Path, line: usize) {
+    let sources = date_files.entry(date).or_default();
+    if !sources.iter().any(|source| source.file == file) {
+        sources.push(Source { file: file.to_path_buf(), line, project: None });
+    }
+}
+
+// Reports the dates written in several files, at each file after the first, unless they are
+// to be merged quietly.  Returns the number of such dates.
+fn check_duplicate_dates(
+    date_files: &HashMap<NaiveDate, Vec<Source>>,
+    policy: DuplicateDates,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> usize {
+    let mut dates: Vec<_> = date_files.iter().filter(|(_, sources)| sources.len() > 1).collect();
+    dates.sort_by_key(|(date, _)| **date);
+    if policy != DuplicateDates::Merge {
+        for (date, sources) in &dates {
+            let files: Vec<String> = sources.iter().map(|source| format!("{}:{}", source.file.display(), source.line)).collect();
+            let err = format!("Date written in several files, {}:", files.join(", "));
+            for source in &sources[1..] {
+                tracing::debug!("{}:{}: {}", source.file.display(), source.line, err);
+                diagnostics.push(Diagnostic::warning(source.file.clone(), source.line, &err, &date.format("%Y.%m.%d").to_string()));
+            }
+        }
+    }
+    dates.len()
+}
+
+// Formats minutes like the time specifications, as `2h`, `45m` or `1h30m`.
+fn format_minutes(minutes: i64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (hours, 0) => format!("{}h", hours),
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h{}m", hours, minutes),
+    }
+}
+
+// Warns about the time ranges of a day that overlap, like `9-12` and `11:30-13`, naming the
+// entry that starts first and how long they overlap, at the one that starts later.
+fn check_overlaps(
+    entries: &HashMap<NaiveDate, Vec<Entry>>,
+    sources: &HashMap<NaiveDate, Vec<Source>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut dates: Vec<_> = entries.keys().collect();
+    dates.sort();
+    let clock = |minutes: i64| format!("{:02}:{:02}", minutes / 60, minutes % 60);
+    for date in dates {
+        let Some(day_sources) = sources.get(date) else {
+            continue;
+        };
+        let mut spans = Vec::new();
+        for (i, entry) in entries[date].iter().enumerate() {
+            if let Entry::Time(_, _, intervals) = entry {
+                spans.extend(intervals.iter().map(|interval| (interval.minutes(), i)));
+            }
+        }
+        spans.sort_by_key(|((start, end), ..)| (*start, *end));
+        for (n, ((_, first_end), i)) in spans.iter().enumerate() {
+            for ((second_start, second_end), j) in &spans[n + 1..] {
+                if second_start >= first_end {
+                    break;
+                }
+                let (first, second) = (&day_sources[*i], &day_sources[*j]);
+                let err = format!("Overlaps '{}' at {}:{} by {}:", entries[date][*i].text(), first.file.display(), first.line,
+                    format_minutes(first_end.min(second_end) - second_start));
+                let text = format!("{}-{} = {}", clock(*second_start), clock(*second_end), entries[date][*j].text());
+                tracing::debug!("{}:{}: {}", second.file.display(), second.line, err);
+                diagnostics.push(Diagnostic::warning(second.file.clone(), second.line, &err, &text));
+            }
+        }
+    }
+}
+
+// Adds the recurring entries to the selected dates, or to the dates from the first to the
+// last one written when all are selected.  An entry already written on the day is not added
+// again.  Their source is the `[recurring.<name>]` table, at line 0.
+fn add_recurring(
+    selector: &DateSelector,
+    recurring: &[Recurring],
+    entries: &mut HashMap<NaiveDate, Vec<Entry>>,
+    sources: &mut HashMap<NaiveDate, Vec<Source>>,
+    dates: &HashMap<NaiveDate, Source>,
+) {
+    if recurring.is_empty() {
+        return;
+    }
+    let ranges = if selector.ranges.is_empty() {
+        match (dates.keys().min(), dates.keys().max()) {
+            (Some(start), Some(end)) => vec![DateRange { start: *start, end: *end }],
+            _ => return,
+        }
+    } else {
+        selector.ranges.clone()
+    };
+    let days: BTreeSet<NaiveDate> = ranges.iter()
+        .flat_map(|range| range.start.iter_days().take_while(move |date| *date <= range.end))
+        .filter(|date| selector.selected(date))
+        .collect();
+    for date in days {
+        for item in recurring.iter().filter(|item| item.occurs(date)) {
+            let entry = item.entry();
+            let day = entries.entry(date).or_default();
+            if day.contains(&entry) {
+                continue;
+            }
+            day.push(entry);
+            sources.entry(date).or_default().push(Source::recurring(&item.name));
+        }
+    }
+    entries.retain(|_, day| !day.is_empty());
+}
+
 /// Stores time entries organized by date.
 #[derive(Debug)]
 pub struct TimeData {
     pub entries: HashMap<NaiveDate, Vec<Entry>>,
+    /// Where each entry was read from, in the same order as `entries`.
+    pub sources: HashMap<NaiveDate, Vec<Source>>,
+    /// Where each selected date was first written, whether or not it has entries.
+    pub dates: HashMap<NaiveDate, Source>,
+    /// Problems found while reading the files, also recorded for the end of run report.
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl TimeData {
@@ -95,58 +832,214 @@ impl TimeData {
     ///
     /// * `dir_path` - The path to the directory containing .cli files.
     /// * `selector` - A `DateSelector` to filter entries by date.
+    /// * `options` - How the files are found and read.
     ///
     /// # Errors
     ///
-    /// Returns an `std::io::Error` if the directory cannot be read or files cannot be parsed.
-    pub fn new(dir_path: &str, selector: &DateSelector) -> Result<Self, std::io::Error> {
-        let mut entries = HashMap::new();
-        let path = Path::new(dir_path);
+    /// Returns an `std::io::Error` if the directory cannot be read, or files cannot be read or decrypted.
+    pub fn load(dir_path: &str, selector: &DateSelector, options: &LoadOptions) -> Result<Self, std::io::Error> {
+        let mut entries: HashMap<NaiveDate, Vec<Entry>> = HashMap::new();
+        let mut sources = HashMap::new();
+        let mut dates = HashMap::new();
+        let mut diagnostics = Vec::new();
+        let mut date_files: HashMap<NaiveDate, Vec<Source>> = HashMap::new();
+        let files = timesheet_files(dir_path, options)?;
+
+        let progress = progress_bar(options, files.len());
+        for file_path in files {
+            progress.inc(1);
+            tracing::trace!("FILE  {}", file_path.display());
+
+            let (reader, child) = open_timesheet(&file_path, options)?;
+            if tsv::is_tsv(&file_path) {
+                for row in read_tsv(&file_path, reader, options)? {
+                    if let Some(date) = row.date.as_ref().ok().filter(|date| selector.selected(date)) {
+                        dates.entry(*date).or_insert_with(|| Source { file: file_path.clone(), line: row.line, project: None });
+                        note_date(&mut date_files, *date, &file_path, row.line);
+                    }
+                    let err = match (row.date, row.entry) {
+                        (Ok(date), _) if !selector.selected(&date) => continue,
+                        (Ok(date), Ok(entry)) => {
+                            entries.entry(date).or_default().push(entry);
+                            sources.entry(date).or_insert_with(Vec::new)
+                                .push(Source { file: file_path.clone(), line: row.line, project: None });
+                            continue;
+                        }
+                        (Err(err), _) | (Ok(_), Err(err)) => err,
+                    };
+                    tracing::debug!("{}:{}: {}", file_path.display(), row.line, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), row.line, &err, &row.text));
+                }
+                finish_timesheet(&file_path, child)?;
+                continue;
+            }
+            let mut current_date: Option<NaiveDate> = None;
+            let mut project: Option<String> = None;
+            let mut skipping = false;
+            let mut continued: Option<Continued> = None;
+            let now = clock::at(options.timezone, Utc::now());
+
+            for (line_number, line) in reader.lines().enumerate() {
+                let line = line.map_err(|e| Error::new(e.kind(),
+                    format!("{}:{}: {}", file_path.display(), line_number + 1, e)))?;
+                let indent = line.len() - line.trim_start().len();
+                let line = line.trim();
+                if let Some(version) = parse_format_directive(line).filter(|v| *v > FORMAT_VERSION) {
+                    let err = format!("Written for format version {}, newer than {}, upgrade clinvoice", version, FORMAT_VERSION);
+                    tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, &err, line));
+                }
+                if let Some(name) = parse_project_directive(line) {
+                    project = Some(name).filter(|name| !name.is_empty());
+                }
+                if line.is_empty() {
+                    continued = None;
+                    continue;
+                }
 
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_path = entry.path();
-            if file_path.is_file() && file_path.extension().and_then(|s| s.to_str()) == Some("cli") {
-                tracing::trace!("FILE  {}", file_path.display());
+                // A line following a trailing `\` continues the entry's description, and so does a
+                // line indented deeper than the entry that is not an entry itself.  A `#tag` is
+                // kept there, other `#` lines are comments.
+                let backslash = line.ends_with('\\');
+                let text = line.strip_suffix('\\').map_or(line, str::trim_end);
+                let tagged = text.strip_prefix('#').is_some_and(|tag| tag.starts_with(char::is_alphanumeric));
+                let comment = line.starts_with("//") || line.starts_with('#') && !tagged;
+                let deeper = !comment && continued.as_ref().is_some_and(|c| !c.backslash && indent > c.indent);
+                let entry = deeper && !tagged
+                    && parse_line_at(text, current_date.filter(|date| *date == now.date_naive()).map(|_| now.time())).is_ok();
+                if entry && current_date.is_some_and(|date| selector.selected(&date)) {
+                    let err = "Indented like a continuation line, but read as an entry:";
+                    tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, err, line));
+                }
+                let continues = !comment && !entry && continued.as_ref().is_some_and(|c| c.backslash || deeper);
+                if comment || tagged && !continues {
+                    continue;
+                }
 
-                let content = fs::read_to_string(&file_path)?;
-                let mut current_date: Option<NaiveDate> = None;
+                tracing::trace!("LINE {}  {}", line_number+1, line);
 
-                for (line_number, line) in content.lines().enumerate() {
-                    let line = line.trim();
-                    if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
-                        continue;
+                let line = text;
+                if let Some(entry) = continued.as_mut().filter(|_| continues) {
+                    if let Some(date) = entry.date {
+                        if let Some(last) = entries.get_mut(&date).and_then(|day| day.last_mut()) {
+                            last.append_text(line);
+                        }
                     }
+                    entry.backslash = backslash;
+                    continue;
+                }
+                continued = None;
 
-                    tracing::trace!("LINE {}  {}", line_number+1, line);
-
-                    if let Some(date) = parse_date(line) {
-                        current_date = Some(date);
-                    } else if let Some(date) = current_date {
-                        if selector.selected(&date) {
-                            match parse_line(line) {
-                                Ok(entry) => {
-                                    entries.entry(date).or_insert_with(Vec::new).push(entry);
-                                }
-                                Err(err) => {
-                                    let path_line = format!("{}:{}", file_path.display(), line_number + 1);
-                                    tracing::warn!("{}\n\t{}: {}",
-                                        err.err_colored(Color::Yellow),
-                                        path_line, line);
-                                }
-                            }
+                let line = match line.strip_prefix('@') {
+                    Some(name) => match options.snippets.get(name.trim()) {
+                        Some(snippet) => snippet.as_str(),
+                        None => {
+                            let err = "Unknown snippet:";
+                            tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                            diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, err, line));
+                            continue;
                         }
-                    } else {
-                        let path_line = format!("{}:{}", file_path.display(), line_number + 1);
+                    },
+                    None => line,
+                };
 
-                        let err = "Expected date, found:";
-                        tracing::warn!("{}\n\t{}: {}",
-                            err.err_colored(Color::Yellow),
-                            path_line, line);
+                if let Some(Err(err)) = parse_date_line(line) {
+                    let err = format!("{}, skipping its entries:", err);
+                    tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, &err, line));
+                    current_date = None;
+                    skipping = true;
+                } else if let Some(Ok(date)) = parse_date_line(line) {
+                    if selector.selected(&date) {
+                        dates.entry(date).or_insert_with(|| Source { file: file_path.clone(), line: line_number + 1, project: None });
+                        note_date(&mut date_files, date, &file_path, line_number + 1);
+                    }
+                    current_date = Some(date);
+                    skipping = false;
+                } else if looks_like_date(line) {
+                    let err = "Invalid date, skipping its entries:";
+                    tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, err, line));
+                    current_date = None;
+                    skipping = true;
+                } else if let Some(date) = current_date {
+                    continued = Some(Continued { indent, backslash, date: None });
+                    if selector.selected(&date) {
+                        // An open-ended range like `9:00-` runs until now, on today only.
+                        match parse_line_at(line, (date == now.date_naive()).then(|| now.time())) {
+                            Ok(entry) => {
+                                entries.entry(date).or_default().push(entry);
+                                sources.entry(date).or_insert_with(Vec::new)
+                                    .push(Source { file: file_path.clone(), line: line_number + 1, project: project.clone() });
+                                continued = Some(Continued { indent, backslash, date: Some(date) });
+                            }
+                            Err(err) => {
+                                tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                                diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, &err, line));
+                            }
+                        }
                     }
+                } else if !skipping {
+                    let err = "Expected date, found:";
+                    tracing::debug!("{}:{}: {}", file_path.display(), line_number + 1, err);
+                    diagnostics.push(Diagnostic::warning(file_path.clone(), line_number + 1, err, line));
                 }
             }
+            finish_timesheet(&file_path, child)?;
         }
-        Ok(TimeData { entries })
+        progress.finish_and_clear();
+        add_recurring(selector, &options.recurring, &mut entries, &mut sources, &dates);
+        check_overlaps(&entries, &sources, &mut diagnostics);
+        let duplicates = check_duplicate_dates(&date_files, options.duplicate_dates, &mut diagnostics);
+        diagnostics::record(&diagnostics);
+        if options.strict && !diagnostics.is_empty() {
+            diagnostics::report();
+            let count = diagnostics.len();
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("{} problem{} found in the timesheets, stopping in strict mode", count, if count == 1 { "" } else { "s" })));
+        }
+        if options.duplicate_dates == DuplicateDates::Error && duplicates > 0 {
+            diagnostics::report();
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("{} date{} written in several files, and parse.duplicate_dates is \"error\"", duplicates, if duplicates == 1 { " is" } else { "s are" })));
+        }
+        // Markers are read once continuation lines are appended, and win over the file's project.
+        for (date, day) in &entries {
+            for (entry, source) in day.iter().zip(sources.entry(*date).or_default().iter_mut()) {
+                if let Some(own) = entry.project() {
+                    source.project = Some(own);
+                }
+            }
+        }
+        Ok(TimeData { entries, sources, dates, diagnostics })
+    }
+
+    /// Keeps only the entries carrying at least one of the `tags`, and drops the days left
+    /// without time or fixed cost entries.  Does nothing if `tags` is empty.
+    pub fn retain_tagged(&mut self, tags: &[String]) {
+        if tags.is_empty() {
+            return;
+        }
+        for (date, entries) in self.entries.iter_mut() {
+            let keep: Vec<bool> = entries.iter().map(|entry| tags.iter().any(|tag| entry.has_tag(tag))).collect();
+            let mut keep_entries = keep.iter();
+            entries.retain(|_| *keep_entries.next().unwrap());
+            if let Some(sources) = self.sources.get_mut(date) {
+                let mut keep_sources = keep.iter();
+                sources.retain(|_| *keep_sources.next().unwrap());
+            }
+        }
+        self.entries.retain(|_, entries| entries.iter().any(|entry| !matches!(entry, Entry::Note(_))));
+        self.sources.retain(|date, _| self.entries.contains_key(date));
+        self.dates.retain(|date, _| self.entries.contains_key(date));
+    }
+
+    /// Returns the directories that entries were read from, leaving out recurring entries.
+    pub fn source_directories(&self) -> BTreeSet<PathBuf> {
+        self.sources.values().flatten()
+            .filter(|source| !source.is_recurring())
+            .filter_map(|source| source.file.parent().map(|dir| dir.to_path_buf()))
+            .collect()
     }
 }
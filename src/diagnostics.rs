@@ -0,0 +1,154 @@
+use crate::color::*;
+use crate::quiet;
+use clap::ValueEnum;
+use colored::Color;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+/// Selects how diagnostics are reported at the end of a run.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DiagnosticsFormat {
+    #[default]
+    Text, // Human readable summary on stderr.
+    Json, // A single JSON object on stderr.
+    None, // Do not report diagnostics.
+}
+
+/// A problem found while reading a timesheet file.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+    pub text: String,
+}
+
+impl Diagnostic {
+    /// Creates a warning for the given file, 1-based line number (0 for the whole file), and line text.
+    pub fn warning(file: PathBuf, line: usize, message: &str, text: &str) -> Self {
+        Diagnostic { file, line, message: message.to_string(), text: text.to_string() }
+    }
+}
+
+/// A summary of the diagnostics collected during a run.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Report {
+    pub count: usize,
+    pub files: BTreeMap<String, usize>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+static G_FORMAT: OnceLock<DiagnosticsFormat> = OnceLock::new();
+static G_DIAGNOSTICS: Mutex<BTreeSet<Diagnostic>> = Mutex::new(BTreeSet::new());
+static G_REPORTED: Mutex<BTreeSet<Diagnostic>> = Mutex::new(BTreeSet::new());
+
+/// Initializes the global diagnostics format.
+///
+/// This function should be called once at the application startup.
+pub fn init(format: DiagnosticsFormat) {
+    G_FORMAT.set(format).expect("init called multiple times");
+}
+
+/// Records diagnostics for the end of run report.
+///
+/// The same diagnostic is only recorded once, even if the data is loaded again.
+pub fn record(diagnostics: &[Diagnostic]) {
+    let mut all = G_DIAGNOSTICS.lock().unwrap();
+    all.extend(diagnostics.iter().cloned());
+}
+
+// Takes the recorded diagnostics not reported yet, marking them as reported.
+fn unreported() -> Vec<Diagnostic> {
+    let recorded = std::mem::take(&mut *G_DIAGNOSTICS.lock().unwrap());
+    let mut reported = G_REPORTED.lock().unwrap();
+    recorded.into_iter().filter(|diagnostic| reported.insert(diagnostic.clone())).collect()
+}
+
+/// Reports the recorded diagnostics and exits with `code`, so that the problems found in the
+/// timesheets are not lost when a command fails after loading them.
+pub fn exit(code: i32) -> ! {
+    report();
+    std::process::exit(code)
+}
+
+/// Builds a `Report` from a list of diagnostics.
+pub fn summarize(diagnostics: &[Diagnostic]) -> Report {
+    let mut files = BTreeMap::new();
+    for diagnostic in diagnostics {
+        *files.entry(diagnostic.file.display().to_string()).or_insert(0) += 1;
+    }
+    Report { count: diagnostics.len(), files, diagnostics: diagnostics.to_vec() }
+}
+
+/// Prints all recorded diagnostics to stderr, in the format selected by `init`.
+///
+/// They are only reported once, later calls print what was recorded since, without the
+/// diagnostics already reported when the data is loaded again.
+pub fn report() {
+    let diagnostics = unreported();
+    let format = G_FORMAT.get().copied().unwrap_or_default();
+    if diagnostics.is_empty() || format == DiagnosticsFormat::None {
+        return;
+    }
+
+    let report = summarize(&diagnostics);
+    if format == DiagnosticsFormat::Json {
+        eprintln!("{}", serde_json::to_string(&report).unwrap());
+        return;
+    }
+    if quiet::enabled() {
+        return;
+    }
+
+    let heading = format!("{} problem{} found in {} file{}:",
+        report.count, if report.count == 1 { "" } else { "s" },
+        report.files.len(), if report.files.len() == 1 { "" } else { "s" });
+    eprintln!("{}", heading.err_colored(theme().warning));
+    for (file, count) in &report.files {
+        eprintln!("  {} ({})", file, count);
+        for diagnostic in report.diagnostics.iter().filter(|d| d.file.display().to_string() == *file) {
+            let location = match diagnostic.line {
+                0 => diagnostic.file.display().to_string(),
+                line => format!("{}:{}", diagnostic.file.display(), line),
+            };
+            eprintln!("    {}: {}\n\t{}",
+                location.err_colored(Color::BrightBlack),
+                diagnostic.message.err_colored(theme().warning),
+                diagnostic.text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let diagnostics = vec![
+            Diagnostic::warning(PathBuf::from("a.cli"), 3, "Expected date, found:", "8h = x"),
+            Diagnostic::warning(PathBuf::from("a.cli"), 7, "Invalid hour format", "xh = y"),
+            Diagnostic::warning(PathBuf::from("b.cli"), 1, "Invalid hour format", "zh = z"),
+        ];
+        let report = summarize(&diagnostics);
+        assert_eq!(report.count, 3);
+        assert_eq!(report.files.get("a.cli"), Some(&2));
+        assert_eq!(report.files.get("b.cli"), Some(&1));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["diagnostics"][0]["message"], "Expected date, found:");
+        assert_eq!(json["diagnostics"][0]["line"], 3);
+    }
+
+    #[test]
+    fn test_unreported() {
+        let diagnostic = Diagnostic::warning(PathBuf::from("unreported.cli"), 2, "Invalid hour format", "xh = y");
+        record(std::slice::from_ref(&diagnostic));
+        unreported();
+        assert!(G_REPORTED.lock().unwrap().contains(&diagnostic));
+        record(std::slice::from_ref(&diagnostic));
+        assert!(!unreported().contains(&diagnostic));
+    }
+}
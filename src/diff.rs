@@ -0,0 +1,242 @@
+//! Records of the days billed by each invoice, and comparing them to the timesheets.
+//!
+//! `generate` records the hours and cost of each day of an invoice in
+//! `.clinvoice/invoices/<sequence>.json`.  `diff` computes the invoice again from the
+//! current timesheets, and shows the days that changed since.
+
+use crate::billing::BillingRules;
+use crate::config::Config;
+use crate::data::{LoadOptions, Selection, TimeData};
+use crate::diagnostics;
+use crate::generate::{compute, DayOptions, Invoice};
+use crate::index::{index_file_path, Index};
+use crate::quiet;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the records of invoices are kept, inside the data directory.
+pub const RECORDS_DIR: &str = ".clinvoice/invoices";
+
+// Hours and amounts closer than this are the same.
+const EPSILON: f64 = 0.005;
+
+/// A day of a recorded invoice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedDay {
+    pub date: String,
+    pub hours: f64,
+    pub cost: f64,
+}
+
+/// The days billed by an invoice when it was generated.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    pub sequence: u32,
+    pub dates: Vec<String>,
+    pub generated: String,
+    pub days: Vec<RecordedDay>,
+    pub total_hours: f64,
+    pub total_amount: f64,
+    /// The finished files of each generator, to send, relative to the data directory unless
+    /// written outside of it.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// The generator whose day options the days were computed with, the defaults if none.
+    #[serde(default)]
+    pub generator: Option<String>,
+}
+
+impl Record {
+    /// Creates the record of an invoice generated at `now`, in the configured timezone.
+    pub fn new(sequence: u32, dates: &[String], invoice: &Invoice, now: DateTime<FixedOffset>) -> Self {
+        Record {
+            sequence,
+            dates: dates.to_vec(),
+            generated: now.format("%Y-%m-%d %H:%M").to_string(),
+            days: invoice.days.iter()
+                .map(|day| RecordedDay { date: day.date.clone(), hours: day.hours as f64, cost: day.cost })
+                .collect(),
+            total_hours: invoice.total_hours_billed,
+            total_amount: invoice.total_amount,
+            files: Vec::new(),
+            generator: None,
+        }
+    }
+}
+
+/// Returns the path of the record of an invoice.
+pub fn record_path(directory: &str, sequence: u32) -> PathBuf {
+    Path::new(directory).join(RECORDS_DIR).join(format!("{}.json", sequence))
+}
+
+/// Saves the record of an invoice, replacing the one of an earlier generation.
+pub fn save(directory: &str, record: &Record) -> io::Result<()> {
+    let path = record_path(directory, record.sequence);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(record).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+/// Loads the record of an invoice.
+pub fn load(directory: &str, sequence: u32) -> io::Result<Record> {
+    let json = fs::read_to_string(record_path(directory, sequence))?;
+    serde_json::from_str(&json).map_err(io::Error::other)
+}
+
+/// A day whose billed hours changed, `None` where the day was not billed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub date: String,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+impl Change {
+    /// The hours added, negative when hours were removed.
+    pub fn delta(&self) -> f64 {
+        self.after.unwrap_or(0.0) - self.before.unwrap_or(0.0)
+    }
+}
+
+/// Compares the days of a record to the invoice computed now, returning the days added,
+/// removed, or billed for other hours, by date.
+pub fn compare(record: &Record, invoice: &Invoice) -> Vec<Change> {
+    let mut days: BTreeMap<String, (Option<f64>, Option<f64>)> = BTreeMap::new();
+    for day in &record.days {
+        days.entry(day.date.clone()).or_default().0 = Some(day.hours);
+    }
+    for day in &invoice.days {
+        days.entry(day.date.clone()).or_default().1 = Some(day.hours as f64);
+    }
+    days.into_iter()
+        .map(|(date, (before, after))| Change { date, before, after })
+        .filter(|change| change.before.is_none() || change.after.is_none() || change.delta().abs() > EPSILON)
+        .collect()
+}
+
+fn hours(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |hours| format!("{:.2}", hours))
+}
+
+/// Shows how an invoice computed from the current timesheets differs from when it was
+/// generated.
+pub fn run(sequence: u32, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let Ok(config) = Config::new(config_file.as_deref(), Some(directory)) else {
+        tracing::error!("No configuration found, the invoice cannot be computed");
+        diagnostics::exit(1);
+    };
+    let index_path = index_file_path(Some(&config), directory);
+    let index = Index::open_read_only(&index_path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read index {}: {}", index_path.display(), err);
+        diagnostics::exit(1);
+    });
+    let Some(dates) = index.dates(sequence).filter(|dates| !dates.is_empty()) else {
+        tracing::error!("Sequence {} is not an invoice in {}", sequence, index_path.display());
+        diagnostics::exit(1);
+    };
+    let record = load(directory, sequence).unwrap_or_else(|err| {
+        tracing::error!("No record of invoice {} in {}: {}, generate it again to record it",
+            sequence, record_path(directory, sequence).display(), err);
+        diagnostics::exit(1);
+    });
+
+    let selection = Selection::from_key(dates);
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let mut time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.load_options(Some(&config)) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    time_data.retain_tagged(&selection.tags);
+    let rules = BillingRules::load(Some(&config), directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to load billing rules: {}", err);
+        diagnostics::exit(1);
+    });
+    let day_options = record.generator.as_ref()
+        .map_or_else(DayOptions::default, |generator| DayOptions::from_config(&config, &format!("generator.{}", generator)));
+    let invoice = compute(&config, &time_data, "none", &day_options, rules.as_ref());
+
+    let changes = compare(&record, &invoice);
+    let hours_delta = invoice.total_hours_billed - record.total_hours;
+    let amount_delta = invoice.total_amount - record.total_amount;
+    if quiet::enabled() {
+        quiet::summary("diff", &[
+            ("sequence", sequence.to_string()),
+            ("days", changes.len().to_string()),
+            ("hours", format!("{:+.2}", hours_delta)),
+            ("amount", format!("{:+.2}", amount_delta)),
+        ]);
+        return;
+    }
+    println!("Invoice {} ({}), generated {}", sequence, dates.join(" "), record.generated);
+    if changes.is_empty() && hours_delta.abs() <= EPSILON && amount_delta.abs() <= EPSILON {
+        println!("No changes since it was generated");
+        return;
+    }
+    println!("{:<12} {:>9} {:>9} {:>9}", "Date", "Billed", "Now", "Change");
+    for change in &changes {
+        println!("{:<12} {:>9} {:>9} {:>+9.2}", change.date, hours(change.before), hours(change.after), change.delta());
+    }
+    println!("{:<12} {:>9.2} {:>9.2} {:>+9.2}", "Hours", record.total_hours, invoice.total_hours_billed, hours_delta);
+    println!("{:<12} {:>9.2} {:>9.2} {:>+9.2}", "Amount", record.total_amount, invoice.total_amount, amount_delta);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock;
+    use crate::data::DateSelector;
+
+    fn invoice(text: &str) -> Invoice {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2025-03.cli"), text).unwrap();
+        fs::write(dir.path().join("clinvoice.toml"), "[contract]\nhourly_rate = 10.0\n").unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let config = Config::new(None, Some(directory)).unwrap();
+        let time_data = TimeData::load(directory, &DateSelector::from_dates(&["2025.03".to_string()]).unwrap(), &LoadOptions::default()).unwrap();
+        compute(&config, &time_data, "none", &DayOptions::default(), None)
+    }
+
+    #[test]
+    fn test_compare() {
+        let record = Record::new(3, &["2025.03".to_string()], &invoice("2025.03.03\n    2h = a\n2025.03.04\n    3h = b\n2025.03.05\n    1h = c\n"), clock::now(None));
+        assert_eq!(record.total_amount, 60.0);
+        let now = invoice("2025.03.03\n    2h = a\n2025.03.04\n    4.5h = b\n2025.03.06\n    1h = d\n");
+        let changes = compare(&record, &now);
+        assert_eq!(changes, vec![
+            Change { date: "2025-03-04".to_string(), before: Some(3.0), after: Some(4.5) },
+            Change { date: "2025-03-05".to_string(), before: Some(1.0), after: None },
+            Change { date: "2025-03-06".to_string(), before: None, after: Some(1.0) },
+        ]);
+        assert_eq!(changes[1].delta(), -1.0);
+    }
+
+    #[test]
+    fn test_record_generated_in_timezone() {
+        use chrono::TimeZone;
+        let utc = chrono::Utc.with_ymd_and_hms(2025, 3, 31, 23, 30, 0).unwrap();
+        let tokyo = clock::at(Some(clock::parse_timezone("Asia/Tokyo").unwrap()), utc);
+        let record = Record::new(1, &["2025.03".to_string()], &invoice("2025.03.03\n    2h = a\n"), tokyo);
+        assert_eq!(record.generated, "2025-04-01 08:30");
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let record = Record::new(7, &["2025.03".to_string(), "#acme".to_string()], &invoice("2025.03.03\n    2h = a\n"), clock::now(None));
+        save(directory, &record).unwrap();
+        assert_eq!(load(directory, 7).unwrap(), record);
+        assert!(load(directory, 8).is_err());
+    }
+}
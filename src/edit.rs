@@ -0,0 +1,164 @@
+//! Opening the timesheet holding a date in an editor, at the date's section.
+
+use crate::clock;
+use crate::config::Config;
+use crate::data::{DateSelector, DuplicateDates, LoadOptions, Source, TimeData};
+use crate::diagnostics;
+use crate::import::month_file;
+use crate::parse::{format_directive, parse_date, FORMAT_VERSION};
+use crate::quiet;
+use chrono::NaiveDate;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Returns the editor command, from `$VISUAL` or `$EDITOR`, or `vi`.
+pub fn editor() -> String {
+    std::env::var("VISUAL").ok()
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or("vi".to_string())
+}
+
+/// Returns the program and arguments opening `path` at `line`, with the `+LINE` argument
+/// understood by vi, emacs, nano, and most other editors.
+///
+/// # Errors
+///
+/// Returns a `String` error if the editor command cannot be split into arguments.
+pub fn editor_argv(editor: &str, path: &Path, line: usize) -> Result<Vec<String>, String> {
+    let mut argv = shlex::split(editor).filter(|argv| !argv.is_empty())
+        .ok_or(format!("Invalid editor command: {}", editor))?;
+    argv.push(format!("+{}", line));
+    argv.push(path.display().to_string());
+    Ok(argv)
+}
+
+/// Adds a section for `date` at the end of the timesheet at `path`, creating the file if
+/// needed.
+///
+/// # Returns
+///
+/// The 1-based line number of the date.
+pub fn add_section(path: &Path, date: NaiveDate) -> io::Result<usize> {
+    let mut lines: Vec<String> = if path.exists() {
+        fs::read_to_string(path)?.lines().map(|l| l.to_string()).collect()
+    } else {
+        vec![format_directive(FORMAT_VERSION)]
+    };
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    lines.push(String::new());
+    lines.push(date.format("%Y.%m.%d").to_string());
+    let line = lines.len();
+    lines.push(String::new());
+    fs::write(path, lines.join("\n"))?;
+    Ok(line)
+}
+
+// Finds where `date` is written in the data directory.
+fn find(directory: &str, options: &LoadOptions, date: NaiveDate) -> Option<Source> {
+    let selector = DateSelector::from_dates(&[date.format("%Y.%m.%d").to_string()]).ok()?;
+    let mut time_data = TimeData::load(directory, &selector, options).unwrap_or_else(|err| {
+        tracing::error!("Failed to load data: {}", err);
+        diagnostics::exit(1);
+    });
+    time_data.dates.remove(&date)
+}
+
+/// Opens the timesheet holding `date`, today by default, in the editor at the date's
+/// section.  A date not written yet is added to the timesheet of its month.
+pub fn run(
+    date: Option<String>,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    let date = match date {
+        Some(date) => parse_date(&date).unwrap_or_else(|| {
+            tracing::error!("Invalid date: {}", date);
+            diagnostics::exit(1);
+        }),
+        None => clock::today(config.as_ref()),
+    };
+
+    let options = LoadOptions { duplicate_dates: DuplicateDates::Merge, ..LoadOptions::from_config(config.as_ref()) };
+    let (path, line) = match find(directory, &options, date) {
+        Some(Source { file, line, .. }) => (file, line),
+        None if read_only => {
+            tracing::error!("{} is not in any timesheet, and cannot be added in read-only mode", date.format("%Y.%m.%d"));
+            diagnostics::exit(1);
+        }
+        None => {
+            let path = month_file(Path::new(directory), date, &options).unwrap_or_else(|err| {
+                tracing::error!("Failed to find the timesheet of {}: {}", date.format("%Y.%m.%d"), err);
+                diagnostics::exit(1);
+            });
+            let line = add_section(&path, date).unwrap_or_else(|err| {
+                tracing::error!("Failed to add {} to {}: {}", date.format("%Y.%m.%d"), path.display(), err);
+                diagnostics::exit(1);
+            });
+            tracing::info!("Added {} to {}", date.format("%Y.%m.%d"), path.display());
+            (path, line)
+        }
+    };
+    if path.extension().is_some_and(|ext| ext == "age" || ext == "gpg") {
+        tracing::error!("{} is encrypted, decrypt it to edit it", path.display());
+        diagnostics::exit(1);
+    }
+    if quiet::enabled() {
+        quiet::summary("edit", &[
+            ("file", path.display().to_string()),
+            ("line", line.to_string()),
+        ]);
+    }
+    open(&path, line);
+}
+
+fn open(path: &Path, line: usize) {
+    let argv = editor_argv(&editor(), path, line).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    tracing::info!("Opening {}:{}", path.display(), line);
+    match Command::new(&argv[0]).args(&argv[1..]).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            tracing::error!("{} exited with {}", argv[0], status);
+            diagnostics::exit(1);
+        }
+        Err(err) => {
+            tracing::error!("Failed to run {}: {}", argv[0], err);
+            diagnostics::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_argv() {
+        let argv = editor_argv("code --wait", Path::new("2025-03.cli"), 12).unwrap();
+        assert_eq!(argv, vec!["code", "--wait", "+12", "2025-03.cli"]);
+        assert!(editor_argv("", Path::new("2025-03.cli"), 1).is_err());
+    }
+
+    #[test]
+    fn test_add_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2025-03.cli");
+        let date = |day| NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+        assert_eq!(add_section(&path, date(3)).unwrap(), 3);
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "    2h = review\n\n\n").unwrap();
+        assert_eq!(add_section(&path, date(4)).unwrap(), 6);
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, format!("{}\n\n2025.03.03\n    2h = review\n\n2025.03.04\n", format_directive(FORMAT_VERSION)));
+    }
+}
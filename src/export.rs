@@ -0,0 +1,182 @@
+//! Exporting the entries of the selected dates as CSV or JSON, for spreadsheets and other
+//! tools.
+
+use crate::config::Config;
+use crate::data::{Entry, LoadOptions, Selection, TimeData};
+use crate::diagnostics;
+use crate::quiet;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+
+/// The formats entries are exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// An entry, as exported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row {
+    pub date: String,
+    pub kind: &'static str, // `time`, `nonbillable`, `cost`, `units`, or `note`.
+    pub hours: f32,
+    pub cost: f64,          // Hours at `contract.hourly_rate`, or the fixed cost.
+    pub description: String,
+}
+
+/// Returns the entries by date, in the order they were written, with time priced at
+/// `hourly_rate`.
+pub fn rows(time_data: &TimeData, hourly_rate: f64) -> Vec<Row> {
+    let mut dates: Vec<_> = time_data.entries.keys().collect();
+    dates.sort();
+    let mut rows = Vec::new();
+    for date in dates {
+        for entry in &time_data.entries[date] {
+            let (kind, hours, cost) = match entry {
+                Entry::Time(hours, ..) => ("time", *hours, *hours as f64 * hourly_rate),
+                Entry::FixedCost(cost, _) => ("cost", 0.0, *cost as f64),
+                Entry::Note(_) => ("note", 0.0, 0.0),
+                Entry::NonBillable(hours, _) => ("nonbillable", *hours, 0.0),
+                Entry::Units(..) => ("units", 0.0, entry.cost()),
+            };
+            rows.push(Row {
+                date: date.format("%Y-%m-%d").to_string(),
+                kind,
+                hours,
+                cost: (cost * 100.0).round() / 100.0,
+                description: entry.text().to_string(),
+            });
+        }
+    }
+    rows
+}
+
+/// Writes the rows as CSV, with a header line.
+pub fn write_csv(rows: &[Row], out: impl Write) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the rows as a JSON array.
+pub fn write_json(rows: &[Row], mut out: impl Write) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut out, rows).map_err(io::Error::other)?;
+    writeln!(out)
+}
+
+/// Exports the entries of the selected dates to `output`, or to stdout.
+pub fn run(
+    format: ExportFormat,
+    output: Option<String>,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+    selection: &Selection,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.load_options(config.as_ref()) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    let hourly_rate = config.as_ref().and_then(|c| c.get_f64("contract.hourly_rate")).unwrap_or(0.0);
+    let rows = rows(&time_data, hourly_rate);
+
+    let output = match output {
+        Some(path) if read_only => {
+            tracing::info!("Read-only mode, not writing {}", path);
+            None
+        }
+        output => output,
+    };
+    let out: Box<dyn Write> = match &output {
+        Some(path) => Box::new(File::create(path).unwrap_or_else(|err| {
+            tracing::error!("Failed to create {}: {}", path, err);
+            diagnostics::exit(1);
+        })),
+        None => Box::new(io::stdout()),
+    };
+    let result = match format {
+        ExportFormat::Csv => write_csv(&rows, out).map_err(|err| err.to_string()),
+        ExportFormat::Json => write_json(&rows, out).map_err(|err| err.to_string()),
+    };
+    if let Err(err) = result {
+        tracing::error!("Failed to export: {}", err);
+        diagnostics::exit(1);
+    }
+    // On stdout, the entries are the output, even in quiet mode, and no summary is added.
+    if let Some(path) = &output {
+        tracing::info!("Exported {} entries to {}", rows.len(), path);
+        quiet::summary("exported", &[
+            ("entries", rows.len().to_string()),
+            ("file", path.clone()),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DateSelector;
+
+    fn rows() -> Vec<Row> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "\
+2025.03.04
+    0.1h = review, \"quick\"
+2025.03.03
+    2h = standup #bnl
+    $20 = parking
+    - a note
+").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        super::rows(&time_data, 100.0)
+    }
+
+    #[test]
+    fn test_rows() {
+        let rows = rows();
+        let kinds: Vec<(&str, &str, f32, f64)> = rows.iter().map(|r| (r.date.as_str(), r.kind, r.hours, r.cost)).collect();
+        assert_eq!(kinds, vec![
+            ("2025-03-03", "time", 2.0, 200.0),
+            ("2025-03-03", "cost", 0.0, 20.0),
+            ("2025-03-03", "note", 0.0, 0.0),
+            ("2025-03-04", "time", 0.1, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut out = Vec::new();
+        write_csv(&rows(), &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "date,kind,hours,cost,description");
+        assert_eq!(lines[1], "2025-03-03,time,2.0,200.0,standup #bnl");
+        assert_eq!(lines[4], "2025-03-04,time,0.1,10.0,\"review, \"\"quick\"\"\"");
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut out = Vec::new();
+        write_json(&rows(), &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[1]["kind"], "cost");
+        assert_eq!(json[1]["cost"], 20.0);
+        assert_eq!(json[3]["hours"].as_f64(), Some(0.1));
+        assert_eq!(json[3]["description"], "review, \"quick\"");
+    }
+}
@@ -1,18 +1,34 @@
+use crate::billing::BillingRules;
+use crate::clock;
 use crate::config::Config;
-use crate::data::{DateSelector, TimeData};
+use crate::diagnostics;
+use crate::diff;
+use crate::i18n::Strings;
+use crate::rates::{self, Rates};
+use crate::data::{LoadOptions, Selection, TimeData};
 use crate::latex::latex_escape;
 use crate::markdown::markdown_escape;
 
 use crate::color::*;
-use crate::index::Index;
-use chrono::{Local, NaiveDate};
+use crate::index::{index_file_path, Index, ISSUED, VOIDED};
+use crate::notify;
+use crate::quiet;
+use crate::undo::{self, Mutation};
+use crate::send;
+use crate::upload;
+use crate::validate;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime};
 use colored::Color;
+use regex::Regex;
 use serde::Serialize;
-use std::collections::{HashMap, VecDeque};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::process::{Command, Stdio};
 use std::error::Error;
 use tera::{Context, Tera, to_value, try_get_value, Value};
@@ -70,13 +86,451 @@ impl TeraContextBuilder {
     }
 }
 
-#[derive(Serialize)]
-struct Day {
-    index: usize,
-    date: String,
-    hours: f32,
-    cost: f64,
-    description: String,
+/// A single day of billed work, as made available to templates.
+#[derive(Serialize, Clone, Debug)]
+pub struct Day {
+    pub index: usize,
+    pub date: String,
+    /// The hours billed, after all adjustments.
+    pub hours: f32,
+    pub cost: f64,
+    pub description: String,
+    /// The hours logged.
+    pub raw_hours: f32,
+    /// The hours after `contract.cap_hours_per_day`.
+    pub capped_hours: f32,
+    /// The hours after `contract.round_day_hours`.
+    pub rounded_hours: f32,
+    /// Why the hours billed differ from the hours logged, one reason per adjustment.
+    pub adjustments: Vec<String>,
+    /// The numbers of the footnotes holding the notes of this day.
+    pub footnote_refs: Vec<usize>,
+    /// The time ranges logged, in the order they were written, for timestamped timesheets.
+    pub intervals: Vec<TimeSpan>,
+    /// The `3 x $50` entries, for a quantity and unit price column.
+    pub units: Vec<UnitLine>,
+    /// The notes of the day, escaped, whether or not they are in the description.
+    pub notes: Vec<String>,
+}
+
+/// A quantity at a unit price, like `3 x $50 = licenses`, included in the fixed fees.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct UnitLine {
+    pub quantity: f64,
+    pub unit_price: f64,
+    /// The quantity at the unit price.
+    pub amount: f64,
+    pub description: String,
+}
+
+/// A time range of an entry, like `9:00-12:30`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TimeSpan {
+    /// The start, in `HH:MM` format.
+    pub start: String,
+    /// The end, in `HH:MM` format, `24:00` at midnight.
+    pub end: String,
+    pub hours: f64,
+    /// The description of the entry.
+    pub description: String,
+}
+
+/// A note of a day, listed under the invoice when `footnote_notes` is set.
+#[derive(Serialize, Clone, Debug)]
+pub struct Footnote {
+    pub number: usize,
+    pub date: String,
+    /// The `index` of the day the note belongs to.
+    pub day: usize,
+    pub text: String,
+}
+
+/// The days of one calendar month of an invoice, with their subtotals.
+#[derive(Serialize, Clone, Debug)]
+pub struct Month {
+    /// The month, in `YYYY-MM` format.
+    pub label: String,
+    pub days: Vec<Day>,
+    pub hours: f32,
+    /// The hours at the hourly rate plus the fixed costs of the month, before invoice caps and tax.
+    pub amount: f64,
+}
+
+/// The entries of an invoice carrying one `#tag`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TagTotal {
+    /// The tag, in lowercase and without the `#`.
+    pub tag: String,
+    /// The hours logged, before any adjustments.
+    pub hours: f64,
+    /// The hours at the hourly rate plus the fixed costs.
+    pub amount: f64,
+}
+
+/// An entry of a project, as listed in its section of the invoice.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProjectEntry {
+    /// The date, in `YYYY-MM-DD` format.
+    pub date: String,
+    pub hours: f64,
+    pub amount: f64,
+    pub description: String,
+}
+
+/// The entries of an invoice for one project, given by `@project` or `# clinvoice-project:`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Project {
+    /// The project, empty for the entries without one.
+    pub name: String,
+    /// The hours logged, before any adjustments.
+    pub hours: f64,
+    /// The hours at the hourly rate plus the fixed costs.
+    pub amount: f64,
+    pub entries: Vec<ProjectEntry>,
+}
+
+/// The billing figures computed from a selection of time data.
+#[derive(Serialize, Clone, Debug)]
+pub struct Invoice {
+    pub days: Vec<Day>,
+    pub months: Vec<Month>,
+    pub footnotes: Vec<Footnote>,
+    pub period_start: Option<NaiveDate>,
+    pub period_end: Option<NaiveDate>,
+    pub total_fixed_fees: f64,
+    pub total_discounts: f64,
+    pub total_hours_worked: f64,
+    /// The hours of `~` entries, logged but not billed.
+    pub total_hours_nonbillable: f64,
+    pub total_hours_counted: f64,
+    pub counted_amount: f64,
+    pub overage_hours: f64,
+    pub overage_discount: f64,
+    pub total_hours_billed: f64,
+    pub billed_amount: f64,
+    /// The change to `billed_amount` made by `billed_amount` in the billing script.
+    pub billing_adjustment: f64,
+    pub subtotal_amount: f64,
+    pub tax_amount: f64,
+    pub total_amount: f64,
+    pub total_hours: f32,
+    /// The totals of each `#tag`, by tag.  An entry with several tags counts for each.
+    pub tag_totals: Vec<TagTotal>,
+    /// The entries of each project, by name, those without a project first.
+    pub projects: Vec<Project>,
+}
+
+/// Presentation choices for the `days` of an invoice, set per generator.
+///
+/// These only change which days and descriptions are listed, never the totals.
+#[derive(Debug, Clone)]
+pub struct DayOptions {
+    /// Leave out days without hours or fixed costs, like days with only notes.
+    pub skip_zero_days: bool,
+    /// Include notes in the day descriptions.
+    pub include_notes: bool,
+    /// Leave out the descriptions of entries with zero hours or a zero fixed cost.
+    pub hide_zero_cost_lines: bool,
+    /// List notes as numbered footnotes, instead of in the day descriptions.
+    pub footnote_notes: bool,
+}
+
+impl Default for DayOptions {
+    fn default() -> Self {
+        DayOptions { skip_zero_days: false, include_notes: true, hide_zero_cost_lines: false, footnote_notes: false }
+    }
+}
+
+impl DayOptions {
+    /// Reads the options of a generator, like `generator.pdf.skip_zero_days`.
+    pub fn from_config(config: &Config, generator_prefix: &str) -> Self {
+        let defaults = DayOptions::default();
+        let get = |key: &str, default: bool| config.get_bool(&format!("{}.{}", generator_prefix, key)).unwrap_or(default);
+        DayOptions {
+            skip_zero_days: get("skip_zero_days", defaults.skip_zero_days),
+            include_notes: get("include_notes", defaults.include_notes),
+            hide_zero_cost_lines: get("hide_zero_cost_lines", defaults.hide_zero_cost_lines),
+            footnote_notes: get("footnote_notes", defaults.footnote_notes),
+        }
+    }
+}
+
+/// Computes the per-day lines and totals of an invoice.
+///
+/// This applies the `contract` rate and caps, and the `tax` percentage from the configuration,
+/// and then the billing `rules`, if any.
+/// Day descriptions are escaped according to `escape_mode`, and days are listed as `day_options` say.
+pub fn compute(
+    config: &Config,
+    time_data: &TimeData,
+    escape_mode: &str,
+    day_options: &DayOptions,
+    rules: Option<&BillingRules>,
+) -> Invoice {
+    let mut days = Vec::new();
+    let mut months: Vec<Month> = Vec::new();
+    let mut footnotes: Vec<Footnote> = Vec::new();
+    let mut total_hours_worked = 0.0f64;
+    let mut total_hours_nonbillable = 0.0f64;
+    let mut total_hours_counted = 0.0f64;
+    let mut total_fees = 0.0f64;
+    let mut total_discounts = 0.0f64;
+    let mut tag_totals: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    let mut projects: BTreeMap<String, Project> = BTreeMap::new();
+    let hourly_rate = config.get_f64("contract.hourly_rate").unwrap_or(0.0);
+
+    let mut sorted_dates: Vec<_> = time_data.entries.keys().collect();
+    sorted_dates.sort();
+
+    let cap_hours_per_day = config.get_f64("contract.cap_hours_per_day").unwrap_or(0.0);
+    let round_day_hours = config.get_f64("contract.round_day_hours").unwrap_or(0.0);
+    let cap_hours_per_invoice = config.get_f64("contract.cap_hours_per_invoice").unwrap_or(0.0);
+
+    let escape = |text: &str| if escape_mode == "latex" {
+        latex_escape(text)
+    } else if escape_mode == "markdown" || escape_mode == "md" {
+        markdown_escape(text)
+    } else {
+        text.to_string()
+    };
+
+    for date in sorted_dates.iter() {
+        let entries = &time_data.entries[date];
+        let mut total_hours = 0.0f64;
+        let mut day_cost = 0.0f64;
+        let mut day_fixed_costs = false;
+        let mut day_fixed_amount = 0.0f64;
+        let mut descriptions = Vec::new();
+        let mut notes = Vec::new();
+        let mut day_notes = Vec::new();
+        let mut intervals = Vec::new();
+        let mut units = Vec::new();
+
+        for (i, entry) in entries.iter().enumerate() {
+            let amount = match entry {
+                crate::data::Entry::Time(h, ..) => Some((*h as f64, *h as f64 * hourly_rate)),
+                crate::data::Entry::FixedCost(..) | crate::data::Entry::Units(..) => Some((0.0, entry.cost())),
+                crate::data::Entry::Note(_) | crate::data::Entry::NonBillable(..) => None,
+            };
+            if let Some((hours, amount)) = amount {
+                for tag in entry.tags().into_iter().map(|tag| tag.to_lowercase()).collect::<BTreeSet<_>>() {
+                    let total = tag_totals.entry(tag).or_default();
+                    total.0 += hours;
+                    total.1 += amount;
+                }
+                let name = time_data.sources.get(*date).and_then(|sources| sources.get(i)).and_then(|source| source.project.clone());
+                let project = projects.entry(name.clone().unwrap_or_default())
+                    .or_insert_with(|| Project { name: name.unwrap_or_default(), hours: 0.0, amount: 0.0, entries: Vec::new() });
+                project.hours += hours;
+                project.amount += amount;
+                project.entries.push(ProjectEntry {
+                    date: date.format("%Y-%m-%d").to_string(),
+                    hours,
+                    amount,
+                    description: escape(entry.text()),
+                });
+            }
+            match entry {
+                crate::data::Entry::Time(h, d, spans) => {
+                    total_hours += *h as f64;
+                    for span in spans {
+                        let (end, minutes) = if span.end == NaiveTime::MIN {
+                            ("24:00".to_string(), 24 * 60 - (span.start - NaiveTime::MIN).num_minutes())
+                        } else {
+                            (span.end.format("%H:%M").to_string(), (span.end - span.start).num_minutes())
+                        };
+                        intervals.push(TimeSpan {
+                            start: span.start.format("%H:%M").to_string(),
+                            end,
+                            hours: minutes as f64 / 60.0,
+                            description: escape(d),
+                        });
+                    }
+                    if !(day_options.hide_zero_cost_lines && *h == 0.0) {
+                        descriptions.push(d.clone());
+                    }
+                }
+                crate::data::Entry::FixedCost(_, d) | crate::data::Entry::Units(_, _, d) => {
+                    let entry_cost = entry.cost();
+                    if let crate::data::Entry::Units(quantity, unit_price, _) = entry {
+                        units.push(UnitLine {
+                            quantity: *quantity as f64,
+                            unit_price: *unit_price as f64,
+                            amount: entry_cost,
+                            description: escape(d),
+                        });
+                    }
+                    if !(day_options.hide_zero_cost_lines && entry_cost == 0.0) {
+                        descriptions.push(d.clone());
+                    }
+                    if entry_cost > 0.0 {
+                        total_fees += entry_cost;
+                    } else {
+                        total_discounts += entry_cost;
+                    }
+                    day_fixed_costs |= entry_cost != 0.0;
+                    day_fixed_amount += entry_cost;
+                }
+                crate::data::Entry::NonBillable(h, _) => total_hours_nonbillable += *h as f64,
+                crate::data::Entry::Note(n) => {
+                    day_notes.push(escape(n));
+                    if day_options.footnote_notes {
+                        notes.push(n.clone());
+                    } else if day_options.include_notes {
+                        descriptions.push(n.clone());
+                    }
+                }
+            }
+        }
+
+        let mut desc_text = descriptions.join("; ");
+
+        total_hours_worked += total_hours;
+        let day_hours_worked = total_hours;
+        let mut adjustments = Vec::new();
+
+        if cap_hours_per_day > 0.0 && total_hours > 0.0 && total_hours > cap_hours_per_day {
+            adjustments.push(format!("capped from {} to {} hours a day (contract.cap_hours_per_day)",
+                total_hours as f32, cap_hours_per_day as f32));
+            total_hours = cap_hours_per_day;
+        }
+        let capped_hours = total_hours;
+        if round_day_hours > 0.0 && total_hours > 0.0 {
+            // Allow for floating point error, so that exact multiples are left alone.
+            let rounded = ((total_hours / round_day_hours) - 1e-9).ceil() * round_day_hours;
+            if rounded != total_hours {
+                adjustments.push(format!("rounded up from {} to {} hours, in steps of {} (contract.round_day_hours)",
+                    total_hours as f32, rounded as f32, round_day_hours as f32));
+                total_hours = rounded;
+            }
+        }
+        let rounded_hours = total_hours;
+        if let Some(rules) = rules {
+            total_hours = rules.day_hours(**date, total_hours, day_hours_worked, entries).unwrap_or_else(|err| {
+                tracing::error!("{}", err);
+                diagnostics::exit(1);
+            });
+            if total_hours != rounded_hours {
+                adjustments.push(format!("changed from {} to {} hours by day_hours in the billing script",
+                    rounded_hours as f32, total_hours as f32));
+            }
+        }
+        if total_hours != day_hours_worked {
+            desc_text.push_str(&format!(" ({} worked, {} billed)",
+                day_hours_worked as f32, total_hours as f32));
+        }
+
+        total_hours_counted += total_hours;
+
+        day_cost += total_hours * hourly_rate;
+
+        tracing::trace!("DAY  {} {:3}  {}", date, total_hours, day_cost);
+
+        desc_text = escape(&desc_text);
+
+        if day_options.skip_zero_days && total_hours == 0.0 && !day_fixed_costs {
+            continue;
+        }
+
+        let mut footnote_refs = Vec::new();
+        for note in notes {
+            let number = footnotes.len() + 1;
+            footnotes.push(Footnote {
+                number,
+                date: date.format("%Y-%m-%d").to_string(),
+                day: days.len() + 1,
+                text: escape(&note),
+            });
+            footnote_refs.push(number);
+        }
+
+        let day = Day {
+            index: days.len() + 1,
+            date: date.format("%Y-%m-%d").to_string(),
+            hours: total_hours as f32,
+            cost: day_cost,
+            description: desc_text,
+            raw_hours: day_hours_worked as f32,
+            capped_hours: capped_hours as f32,
+            rounded_hours: rounded_hours as f32,
+            adjustments,
+            footnote_refs,
+            intervals,
+            units,
+            notes: day_notes,
+        };
+
+        let label = date.format("%Y-%m").to_string();
+        if months.last().is_none_or(|month| month.label != label) {
+            months.push(Month { label, days: Vec::new(), hours: 0.0, amount: 0.0 });
+        }
+        let month = months.last_mut().unwrap();
+        month.hours += day.hours;
+        month.amount += day_cost + day_fixed_amount;
+        month.days.push(day.clone());
+
+        days.push(day);
+    }
+
+    let counted_amount = total_hours_counted * hourly_rate;
+
+    let mut overage_hours = 0.0;
+    let mut overage_discount = 0.0;
+    if cap_hours_per_invoice > 0.0 && total_hours_counted > cap_hours_per_invoice  {
+        overage_hours = total_hours_counted - cap_hours_per_invoice;
+        overage_discount = - (overage_hours * hourly_rate);
+    }
+
+    let total_hours_billed = total_hours_counted - overage_hours;
+    let mut billed_amount = total_hours_billed * hourly_rate;
+    let mut billing_adjustment = 0.0;
+    if let Some(rules) = rules {
+        let month_totals: Vec<(String, f64, f64)> = months.iter()
+            .map(|m| (m.label.clone(), m.hours as f64, m.amount))
+            .collect();
+        let period = (sorted_dates.first().map(|d| **d), sorted_dates.last().map(|d| **d));
+        let amount = rules.billed_amount(total_hours_billed, total_hours_worked, hourly_rate, billed_amount, period, &month_totals)
+            .unwrap_or_else(|err| {
+                tracing::error!("{}", err);
+                diagnostics::exit(1);
+            });
+        billing_adjustment = amount - billed_amount;
+        billed_amount = amount;
+    }
+    let subtotal_amount = billed_amount + total_fees + total_discounts;
+
+    let tax_percent = config.get_f64("tax.percent").unwrap_or(0.0);
+    let tax_amount = subtotal_amount * tax_percent / 100.0;
+    let total_amount = subtotal_amount + tax_amount;
+
+    let total_hours: f32 = days.iter().fold(0.0, |acc, d| acc + d.hours);
+    // Summed as f32, durations like `20m` drift from the f64 total by a fraction of a minute.
+    debug_assert!((total_hours as f64 - total_hours_counted).abs() < 0.5 / 60.0);
+
+    Invoice {
+        days,
+        months,
+        footnotes,
+        period_start: sorted_dates.first().map(|d| **d),
+        period_end: sorted_dates.last().map(|d| **d),
+        total_fixed_fees: total_fees,
+        total_discounts,
+        total_hours_worked,
+        total_hours_nonbillable,
+        total_hours_counted,
+        counted_amount,
+        overage_hours,
+        overage_discount,
+        total_hours_billed,
+        billed_amount,
+        billing_adjustment,
+        subtotal_amount,
+        tax_amount,
+        total_amount,
+        total_hours,
+        tag_totals: tag_totals.into_iter().map(|(tag, (hours, amount))| TagTotal { tag, hours, amount }).collect(),
+        projects: projects.into_values().collect(),
+    }
 }
 
 fn date_filter(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
@@ -138,7 +592,17 @@ fn decimal_filter(value: &Value, args: &HashMap<String, Value>) -> tera::Result<
     Ok(to_value(format!("{:.precision$}", num, precision = precision)).unwrap())
 }
 
-fn format_tera_error(e: tera::Error) -> String {
+/// Registers the clinvoice specific filters (`date`, `left`, `right`, `center`, `decimal`).
+pub fn register_filters(tera: &mut Tera) {
+    tera.register_filter("date", date_filter);
+    tera.register_filter("left", left_filter);
+    tera.register_filter("right", right_filter);
+    tera.register_filter("center", center_filter);
+    tera.register_filter("decimal", decimal_filter);
+}
+
+/// Formats a Tera error, including the chain of underlying causes.
+pub fn format_tera_error(e: tera::Error) -> String {
     let mut error_message = String::new();
     error_message.push_str(&format!("{}\n", e));
 
@@ -151,7 +615,95 @@ fn format_tera_error(e: tera::Error) -> String {
     error_message
 }
 
-/// Runs the invoice generation process.
+// Renders a one-off template string, such as the output file name or build command.
+fn render_inline(name: &str, template: &str, context: &Context) -> String {
+    let mut tera = Tera::default();
+    if let Err(e) = tera.add_raw_template(name, template) {
+        tracing::error!("{}", format_tera_error(e));
+        diagnostics::exit(1);
+    }
+    match tera.render(name, context) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("{}", format_tera_error(e));
+            diagnostics::exit(1);
+        }
+    }
+}
+
+// Evaluates the `[context]` table of Tera expressions against the base context.
+//
+// Expressions may refer to each other; they are evaluated repeatedly until all of them render,
+// or no more progress is made, in which case the errors are reported and the run ends.
+fn computed_context(
+    tera: &mut Tera,
+    expressions: &toml::map::Map<String, toml::Value>,
+    base: &Context,
+) -> Vec<(String, String)> {
+    let mut context = base.clone();
+    let mut computed = Vec::new();
+    let mut pending: Vec<(&String, &str)> = Vec::new();
+    for (key, value) in expressions {
+        match value.as_str() {
+            Some(expression) => pending.push((key, expression)),
+            None => tracing::warn!("context.{} is not a string, ignoring it", key),
+        }
+    }
+
+    while !pending.is_empty() {
+        let before = pending.len();
+        let mut errors = Vec::new();
+        let mut remaining = Vec::new();
+        for (key, expression) in pending {
+            match tera.render_str(expression, &context) {
+                Ok(value) => {
+                    tracing::trace!("CTX  {:30}  =>  {}", key, value);
+                    context.insert(key.as_str(), &value);
+                    computed.push((key.clone(), value));
+                }
+                Err(e) => {
+                    errors.push(format!("context.{}: {}", key, format_tera_error(e)));
+                    remaining.push((key, expression));
+                }
+            }
+        }
+        if remaining.len() == before {
+            for error in errors {
+                tracing::error!("{}", error);
+            }
+            diagnostics::exit(1);
+        }
+        pending = remaining;
+    }
+    computed
+}
+
+/// Options for an invoice generation.
+#[derive(Debug, Default, Clone)]
+pub struct GenerateOptions {
+    /// Output file, overriding the generator's `output` setting; `-` is stdout.
+    pub output: Option<String>,
+    /// Generators to use, instead of `generator.default`.
+    pub generators: Vec<String>,
+    /// Sequence number to use, instead of looking it up in the index.
+    pub sequence: Option<u32>,
+    /// Render to stdout only, without writing files, the index, or running builds.
+    pub read_only: bool,
+    /// Number of generators to build at once, or 0 for the number of CPUs.
+    pub jobs: usize,
+    /// Open the finished invoice in a viewer.
+    pub open: bool,
+    /// Refuse to overwrite existing output files.
+    pub no_clobber: bool,
+    /// Only bill entries carrying one of these `#tags`, all entries if empty.
+    pub tags: Vec<String>,
+    /// Write the days of the invoice to a CSV file next to it, as `days_csv` does.
+    pub emit_days: bool,
+    /// Email the finished invoice, as `send` does.
+    pub send: bool,
+}
+
+/// Runs the invoice generation process, with the given `GenerateOptions`.
 ///
 /// This function orchestrates the entire invoice generation, including:
 /// - Loading configuration.
@@ -161,55 +713,252 @@ fn format_tera_error(e: tera::Error) -> String {
 /// - Rendering the invoice template.
 /// - Writing the output file.
 /// - Executing a build command if specified in the configuration.
-pub fn run(
-    output_option: Option<String>,
-    generator_option: &Option<String>,
-    sequence_option: &Option<u32>,
+///
+/// When several generators are given, they share the sequence number and time data.  Their
+/// outputs are rendered and written first, and then the build, upload and notification steps
+/// run concurrently, on up to `jobs` worker threads.
+pub fn generate(
+    options: &GenerateOptions,
     directory_option: &Option<String>,
     config_file: &Option<String>,
-    dates: &[String],
+    selection: &Selection,
 ) {
     let directory = directory_option.as_deref().unwrap_or(".");
     let config = Config::new(config_file.as_deref(), Some(directory))
         .expect("Failed to load config");
-    let use_generator = if let Some(selected) = generator_option {
-        selected.clone()
+    let selection = &Selection {
+        tags: options.tags.iter().map(|tag| tag.trim_start_matches('#').to_string()).collect(),
+        ..selection.clone()
+    };
+    let dates = &selection.key();
+    let read_only = options.read_only || config.get_bool("read_only").unwrap_or(false);
+    let generators = if options.generators.is_empty() {
+        vec![config.get_string("generator.default").expect("generator.default is not defined in config")]
     } else {
-        config.get_string("generator.default").expect("generator.default is not defined in config")
+        options.generators.clone()
     };
+    if generators.len() > 1 && options.output.is_some() {
+        tracing::error!("--output can only be used with a single generator");
+        diagnostics::exit(1);
+    }
 
-    let generator_prefix = format!("generator.{}", use_generator);
-
-    let index_file_name = config.get_string("index.file").unwrap_or(".index".to_string());
-    let index_file_path = Path::new(directory).join(index_file_name);
+    let index_file_path = index_file_path(Some(&config), directory);
     tracing::info!("Index file {}", index_file_path.display());
-    let mut index = Index::new(&index_file_path).expect("Failed to open or lock index file");
+    let mut index = if read_only {
+        Index::open_read_only(&index_file_path).expect("Failed to read index file")
+    } else {
+        Index::create(&index_file_path).expect("Failed to open or lock index file")
+    };
 
-    let sequence:u32 = if let Some(seq) = sequence_option {
-        index.add_sequence(*seq, dates)
+    // The dates and state of the sequence before, if this changes the index.
+    let (sequence, previous) = if let Some(seq) = options.sequence {
+        if index.state(seq) == Some(VOIDED) {
+            tracing::error!("Sequence {} was voided, it cannot be issued again", seq);
+            diagnostics::exit(1);
+        }
+        let previous = (index.dates(seq).map(|d| d.to_vec()), index.state(seq).map(|s| s.to_string()));
+        let changed = previous.0.as_deref() != Some(dates.as_slice()) || previous.1.as_deref() != Some(ISSUED);
+        (index.add_sequence(seq, dates), changed.then_some(previous))
     } else {
-        index.find_sequence(dates)
+        let count = index.sequences().len();
+        let seq = index.find_sequence(dates);
+        (seq, (index.sequences().len() != count).then_some((None, None)))
     };
     tracing::info!("Sequence is {}", sequence);
-    let mut template_path = config
-        .get_string(&format!("{}.template", generator_prefix))
-        .expect("template not specified in config");
-    let path = Path::new(directory).join(template_path.clone());
-    template_path = path.to_str().unwrap().to_string();
-    let template_name = path.file_name().unwrap().to_str().unwrap();
 
-    let selector = DateSelector::from_dates(dates).unwrap_or_else(|err| {
+    let selector = selection.selector().unwrap_or_else(|err| {
         tracing::error!("{}", err);
-        std::process::exit(1);
+        diagnostics::exit(1);
+    });
+
+    let mut time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.checked_load_options(Some(&config)) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    time_data.retain_tagged(&selection.tags);
+    warn_future_dates(&config, &time_data);
+    let anomalies = validate::check_anomalies(&time_data, &validate::Anomalies::from_config(Some(&config)));
+    diagnostics::record(&anomalies);
+    if !anomalies.is_empty() && config.get_bool("validate.strict").unwrap_or(false) {
+        tracing::error!("Not generating, validate.strict is set and {} problems were found", anomalies.len());
+        diagnostics::report();
+        diagnostics::exit(1);
+    }
+
+    let now = clock::now(Some(&config));
+    let jobs: Vec<Job> = generators.iter()
+        .map(|generator| prepare(&config, directory, generator, sequence, &time_data, now, options.output.clone(), options.emit_days))
+        .collect();
+
+    let to_stdout: Vec<&str> = jobs.iter().filter(|job| job.output_path == "-").map(|job| job.generator.as_str()).collect();
+    if !to_stdout.is_empty() && to_stdout.len() < jobs.len() {
+        tracing::error!("The output of {} is -, which only works when every generator writes to stdout", to_stdout.join(", "));
+        diagnostics::exit(1);
+    }
+    if read_only || to_stdout.len() == jobs.len() {
+        for job in &jobs {
+            if job.output_path != "-" {
+                tracing::info!("Read-only mode, not writing {}", job.output_path);
+            }
+            println!("{}", job.rendered);
+        }
+        return;
+    }
+
+    let existing: Vec<&Path> = jobs.iter()
+        .flat_map(|job| std::iter::once(Path::new(&job.output_path)).chain(job.products.iter().map(PathBuf::as_path)))
+        .filter(|path| path.exists())
+        .collect();
+    if options.no_clobber && !existing.is_empty() {
+        for path in existing {
+            tracing::error!("Refusing to overwrite {}", path.display());
+        }
+        diagnostics::exit(1);
+    }
+    for job in &jobs {
+        let output = Path::new(&job.output_path);
+        // The build outputs change along with the rendered output.
+        let changed = !output.exists() || fs::read(output).is_ok_and(|old| old != job.rendered.as_bytes());
+        let products = job.products.iter().filter(|path| changed && path.exists()).map(|path| backup(path));
+        let result = std::iter::once(output).filter(|path| path.exists())
+            .map(|path| backup_output(path, job.rendered.as_bytes()))
+            .chain(products)
+            .collect::<std::io::Result<()>>();
+        if let Err(err) = result {
+            tracing::error!("Failed to back up the outputs of {}: {}", job.generator, err);
+            diagnostics::exit(1);
+        }
+    }
+
+    for job in &jobs {
+        tracing::info!("Generating {}", job.output_path);
+        let mut file = File::create(&job.output_path).expect("Failed to create output file");
+        file.write_all(job.rendered.as_bytes())
+            .expect("Failed to write to output file");
+        if let Some((path, days)) = &job.days_csv {
+            tracing::info!("Writing days to {}", path.display());
+            if let Err(err) = File::create(path).map_err(csv::Error::from).and_then(|file| write_days_csv(days, file)) {
+                tracing::error!("Failed to write {}: {}", path.display(), err);
+                diagnostics::exit(1);
+            }
+        }
+    }
+
+    index.save().expect("Failed to save index file");
+    if let Some(job) = jobs.first() {
+        let files = jobs.iter().map(|job| recorded_path(directory, &job.open_file).display().to_string()).collect();
+        let record = diff::Record { files, generator: Some(job.generator.clone()), ..diff::Record::new(sequence, dates, &job.invoice, now) };
+        if let Err(err) = diff::save(directory, &record) {
+            tracing::warn!("Failed to record invoice {}: {}", sequence, err);
+        }
+    }
+    if let Some((dates, state)) = previous {
+        undo::record(directory, Mutation::Sequence { sequence, dates, state });
+    }
+
+    let workers = match options.jobs {
+        0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    }.min(jobs.len());
+    let prefixed = jobs.len() > 1;
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(job) = jobs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    if let Err(err) = finish(&config, job, sequence, dates, prefixed, options.open) {
+                        tracing::error!("{}: {}", job.generator, err);
+                        failures.lock().unwrap().push(job.generator.clone());
+                    }
+                }
+            });
+        }
     });
 
-    let time_data = TimeData::new(directory, &selector).expect("Failed to load data");
+    let failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        tracing::error!("Failed generators: {}", failures.join(", "));
+        diagnostics::exit(1);
+    }
+    if options.send {
+        if let Err(err) = send::send(&config, directory, sequence, false) {
+            tracing::error!("Failed to send invoice {}: {}", sequence, err);
+            diagnostics::exit(1);
+        }
+    }
+}
+
+// Returns `path` relative to the data directory, so the record holds wherever `send` runs
+// from, or as an absolute path when written outside of it, like with `--output`.
+fn recorded_path(directory: &str, path: &Path) -> PathBuf {
+    match (std::path::absolute(directory), std::path::absolute(path)) {
+        (Ok(directory), Ok(path)) => path.strip_prefix(&directory).map_or(path.clone(), Path::to_path_buf),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Renders the template of `generator` for `time_data`, as generated at `now`, without
+/// writing anything or touching the index.
+pub fn render(config: &Config, directory: &str, generator: &str, sequence: u32, time_data: &TimeData, now: DateTime<FixedOffset>) -> String {
+    prepare(config, directory, generator, sequence, time_data, now, Some("-".to_string()), false).rendered
+}
+
+// A rendered invoice for one generator, with the steps that follow writing it.
+struct Job {
+    generator: String,
+    output_path: String,
+    rendered: String,
+    build: Option<BuildCommand>,
+    build_log: Option<PathBuf>,
+    highlight: BuildHighlight,
+    upload: Option<(String, PathBuf, String)>,
+    open_file: PathBuf,
+    latest: Option<Latest>,
+    products: Vec<PathBuf>,
+    days_csv: Option<(PathBuf, Vec<Day>)>,
+    invoice: Invoice,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+}
+
+/// Finds a template in the data directory, or else next to the configuration, where the
+/// subdirectory of a client finds the templates shared by all clients.
+pub fn template_path(config: &Config, directory: &Path, template: &str) -> PathBuf {
+    let path = directory.join(template);
+    if path.exists() {
+        return path;
+    }
+    config.directory().map(|dir| dir.join(template)).filter(|shared| shared.exists()).unwrap_or(path)
+}
+
+// Renders the invoice for one generator, and works out the build and upload steps.
+#[allow(clippy::too_many_arguments)]
+fn prepare(
+    config: &Config,
+    directory: &str,
+    use_generator: &str,
+    sequence: u32,
+    time_data: &TimeData,
+    now: DateTime<FixedOffset>,
+    output_option: Option<String>,
+    emit_days: bool,
+) -> Job {
+    let generator_prefix = format!("generator.{}", use_generator);
+
+    let template = config
+        .get_string(&format!("{}.template", generator_prefix))
+        .expect("template not specified in config");
+    let path = template_path(config, Path::new(directory), &template);
+    let template_name = path.file_name().unwrap().to_str().unwrap();
 
     let escape_mode = config.get_string(&format!("{}.escape", generator_prefix)).unwrap_or("none".to_string());
     tracing::info!("Escape mode {}", escape_mode);
     let mut context_builder = TeraContextBuilder::new();
 
     context_builder.insert("directory", directory);
+    context_builder.insert("generator", use_generator);
 
     let flat_config_table = config.get_flattened_values("_");
     for (key, value) in flat_config_table.iter() {
@@ -223,22 +972,23 @@ pub fn run(
         tracing::trace!("VAR  {:30}  =>  {}", key, *value);
     }
 
-    let mut days = Vec::new();
-    let mut total_hours_worked = 0.0f64;
-    let mut total_hours_counted = 0.0f64;
-    let mut total_fees = 0.0f64;
-    let mut total_discounts = 0.0f64;
-    let hourly_rate = config.get_f64("contract.hourly_rate").unwrap_or(0.0);
-
-    let mut sorted_dates: Vec<_> = time_data.entries.keys().collect();
-    sorted_dates.sort();
+    let strings = Strings::from_config(Some(config));
+    context_builder.insert("language", &strings.language);
+    for (key, label) in strings.labels() {
+        context_builder.insert(&format!("label_{}", key), label);
+    }
 
-    let now = Local::now();
     let today = now.date_naive();
+    let rules = BillingRules::load(Some(config), directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to load billing rules: {}", err);
+        diagnostics::exit(1);
+    });
+    let day_options = DayOptions::from_config(config, &generator_prefix);
+    let invoice = compute(config, time_data, &escape_mode, &day_options, rules.as_ref());
     let invoice_date = today;
     let due_date = today + chrono::Duration::days(config.get_i64("contract.payment_days").unwrap_or(30));
-    let period_start = sorted_dates.first().copied().unwrap_or(&today);
-    let period_end = sorted_dates.last().copied().unwrap_or(&today);
+    let period_start = invoice.period_start.unwrap_or(today);
+    let period_end = invoice.period_end.unwrap_or(today);
 
     context_builder.insert("now", &now.to_rfc3339());
     context_builder.insert("today", &today.format("%Y-%m-%d").to_string());
@@ -247,117 +997,65 @@ pub fn run(
     context_builder.insert("period_start", &period_start.format("%Y-%m-%d").to_string());
     context_builder.insert("period_end", &period_end.format("%Y-%m-%d").to_string());
 
-    let cap_hours_per_day = config.get_f64("contract.cap_hours_per_day").unwrap_or(0.0);
-    let cap_hours_per_invoice = config.get_f64("contract.cap_hours_per_invoice").unwrap_or(0.0);
-
-    for (index, date) in sorted_dates.iter().enumerate() {
-        let entries = &time_data.entries[date];
-        let mut total_hours = 0.0f64;
-        let mut day_cost = 0.0f64;
-        let mut descriptions = Vec::new();
-
-        for entry in entries {
-            match entry {
-                crate::data::Entry::Time(h, d) => {
-                    total_hours += *h as f64;
-                    descriptions.push(d.clone());
-                }
-                crate::data::Entry::FixedCost(c, d) => {
-                    let entry_cost = *c as f64;
-                    descriptions.push(d.clone());
-                    if entry_cost > 0.0 {
-                        total_fees += entry_cost;
-                    } else {
-                        total_discounts += entry_cost;
-                    }
-                }
-                crate::data::Entry::Note(n) => {
-                    descriptions.push(n.clone());
-                }
+    context_builder.insert("total_fixed_fees", &invoice.total_fixed_fees);
+    context_builder.insert("total_discounts", &invoice.total_discounts);
+    context_builder.insert("total_hours_worked", &invoice.total_hours_worked);
+    context_builder.insert("total_hours_nonbillable", &invoice.total_hours_nonbillable);
+    context_builder.insert("total_hours_counted", &invoice.total_hours_counted);
+    context_builder.insert("counted_amount", &invoice.counted_amount);
+    context_builder.insert("overage_hours", &invoice.overage_hours);
+    context_builder.insert("overage_discount", &invoice.overage_discount);
+    context_builder.insert("total_hours_billed", &invoice.total_hours_billed);
+    context_builder.insert("billed_amount", &invoice.billed_amount);
+    context_builder.insert("billing_adjustment", &invoice.billing_adjustment);
+    context_builder.insert("subtotal_amount", &invoice.subtotal_amount);
+    context_builder.insert("tax_amount", &invoice.tax_amount);
+    context_builder.insert("total_amount", &invoice.total_amount);
+    context_builder.insert("total_hours", &invoice.total_hours);
+
+    // Amounts are in contract.currency; rates cached for the end of the period convert them.
+    let exchange = Rates::cached(directory, Some(period_end)).unwrap_or_else(|err| {
+        tracing::error!("Failed to load exchange rates: {}", err);
+        diagnostics::exit(1);
+    });
+    let currency = rates::currency(config);
+    if let Some(invoice_currency) = config.get_string("contract.invoice_currency").map(|c| c.to_uppercase()) {
+        let Some(currency) = &currency else {
+            tracing::error!("contract.invoice_currency needs contract.currency, the currency of the rate");
+            diagnostics::exit(1);
+        };
+        let exchange_rate = match &exchange {
+            _ if invoice_currency == *currency => 1.0,
+            Some(exchange) => exchange.exchange_rate(currency, &invoice_currency).unwrap_or_else(|err| {
+                tracing::error!("{}", err);
+                diagnostics::exit(1);
+            }),
+            None => {
+                tracing::error!("No exchange rates cached for {} or earlier, run clinvoice rates fetch", period_end);
+                diagnostics::exit(1);
             }
-        }
-
-        let mut desc_text = descriptions.join("; ");
-
-        total_hours_worked += total_hours;
-
-        if cap_hours_per_day > 0.0 && total_hours > 0.0 && total_hours > cap_hours_per_day {
-            desc_text.push_str(&format!(" ({} worked, {} billed)",
-                total_hours, cap_hours_per_day));
-            total_hours = cap_hours_per_day;
-        }
-
-        total_hours_counted += total_hours;
-
-        day_cost += total_hours * hourly_rate;
-
-        tracing::trace!("DAY  {} {:3}  {}", date, total_hours, day_cost);
-
-        if escape_mode == "latex" {
-            desc_text = latex_escape(&desc_text);
-        } else if escape_mode == "markdown" || escape_mode == "md" {
-            desc_text = markdown_escape(&desc_text);
-        }
-
-        days.push(Day {
-            index: index + 1,
-            date: date.format("%Y-%m-%d").to_string(),
-            hours: total_hours as f32,
-            cost: day_cost,
-            description: desc_text,
-        });
-    }
-
-    context_builder.insert("total_fixed_fees", &total_fees);
-    context_builder.insert("total_discounts", &total_discounts);
-
-    context_builder.insert("total_hours_worked", &total_hours_worked);
-    context_builder.insert("total_hours_counted", &total_hours_counted);
-
-    let counted_amount = total_hours_counted * hourly_rate;
-    context_builder.insert("counted_amount", &counted_amount);
-
-    let mut overage_hours = 0.0;
-    let mut overage_discount = 0.0;
-    if cap_hours_per_invoice > 0.0 && total_hours_counted > cap_hours_per_invoice  {
-        overage_hours = total_hours_counted - cap_hours_per_invoice;
-        overage_discount = - (overage_hours * hourly_rate);
+        };
+        context_builder.insert("invoice_currency", &invoice_currency);
+        context_builder.insert("exchange_rate", &exchange_rate);
+        context_builder.insert("exchange_rate_date", &exchange.as_ref().map_or(period_end, |e| e.date).format("%Y-%m-%d").to_string());
+        context_builder.insert("total_amount_converted", &(invoice.total_amount * exchange_rate));
     }
 
-    context_builder.insert("overage_hours", &overage_hours);
-    context_builder.insert("overage_discount", &overage_discount);
-
-    let total_hours_billed = total_hours_counted - overage_hours;
-    context_builder.insert("total_hours_billed", &total_hours_billed);
-
-    let billed_amount = total_hours_billed * hourly_rate;
-    context_builder.insert("billed_amount", &billed_amount);
-
-    let subtotal_amount = billed_amount + total_fees + total_discounts;
-    context_builder.insert("subtotal_amount", &subtotal_amount);
-
-    let tax_percent = config.get_f64("tax.percent").unwrap_or(0.0);
-    let tax_amount = subtotal_amount * tax_percent / 100.0;
-    let total_amount = subtotal_amount + tax_amount;
-
-    context_builder.insert("tax_amount", &tax_amount);
-    context_builder.insert("total_amount", &total_amount);
-
-    let total_hours: f32 = days.iter().map(|d| d.hours).sum();
-    assert!(total_hours as f64 == total_hours_counted);
-    context_builder.insert("total_hours", &total_hours);
-
     let mut tera = Tera::default();
-    tera.register_filter("date", date_filter);
-    tera.register_filter("left", left_filter);
-    tera.register_filter("right", right_filter);
-    tera.register_filter("center", center_filter);
-    tera.register_filter("decimal", decimal_filter);
+    register_filters(&mut tera);
+    strings.register_filter(&mut tera);
+    match &exchange {
+        Some(exchange) => exchange.register_filter(&mut tera, currency.as_deref().unwrap_or(&exchange.base)),
+        None => rates::register_missing_filter(&mut tera),
+    }
 
-    let template_content = fs::read_to_string(&template_path).expect("Unable to read template file");
+    let template_content = fs::read_to_string(&path).unwrap_or_else(|err| {
+        tracing::error!("Unable to read template {}: {}", path.display(), err);
+        diagnostics::exit(1);
+    });
     if let Err(e) = tera.add_raw_template(template_name, &template_content) {
         tracing::error!("{}", format_tera_error(e));
-        std::process::exit(1);
+        diagnostics::exit(1);
     }
 
     let output_path = match output_option {
@@ -367,92 +1065,426 @@ pub fn run(
                 .get_string(&format!("{}.output", generator_prefix))
                 .expect("output not specified in config, use --output option.");
 
-            let mut output_file_tera = Tera::default();
-            if let Err(e) = output_file_tera
-                .add_raw_template("output", &output_file_template_string) {
-                tracing::error!("{}", format_tera_error(e));
-                std::process::exit(1);
-            }
-
             tracing::trace!("output template: {}", output_file_template_string);
 
-            let rendered = match output_file_tera.render("output", &context_builder.build("none")) {
-                Ok(s) => s,
-                Err(e) => {
-                    tracing::error!("{}", format_tera_error(e));
-                    std::process::exit(1);
-                }
-            };
+            let rendered = render_inline("output", &output_file_template_string, &context_builder.build("none"));
 
             tracing::trace!("output filename: {}", rendered);
 
-            let path = Path::new(directory).join(rendered.clone());
-            path.to_str().unwrap().to_string()
+            // `-` writes to stdout, as `--output -` does.
+            if rendered == "-" {
+                rendered
+            } else {
+                let path = Path::new(directory).join(rendered.clone());
+                path.to_str().unwrap().to_string()
+            }
         }
     };
 
     context_builder.insert("output", &output_path);
 
-    let build_command_template_string = config.get_string(&format!("{}.build", generator_prefix));
-    let build_command :Option<String> = match build_command_template_string {
-        None => None,
-        Some(cmd) => {
-            let mut build_cmd_tera = Tera::default();
-            if let Err(e) = build_cmd_tera
-                .add_raw_template("build_command", &cmd) {
-                tracing::error!("{}", format_tera_error(e));
-                std::process::exit(1);
+    let context = context_builder.build("none");
+    let build = BuildCommand::from_config(config, &generator_prefix, &context);
+    let build_log = config.get_string(&format!("{}.build_log", generator_prefix))
+        .map(|log| Path::new(directory).join(render_inline("build_log", &log, &context)));
+
+    let upload_prefix = format!("{}.upload", generator_prefix);
+    let upload = config.get_string(&format!("{}.kind", upload_prefix)).map(|kind| {
+        let local = match config.get_string(&format!("{}.file", upload_prefix)) {
+            Some(file) => Path::new(directory).join(render_inline("upload_file", &file, &context)),
+            None => Path::new(&output_path).to_path_buf(),
+        };
+        let file_name = local.file_name().unwrap().to_str().unwrap().to_string();
+        let remote = match config.get_string(&format!("{}.path_template", upload_prefix)) {
+            Some(path) => render_inline("upload_path", &path, &context),
+            None => file_name,
+        };
+        (kind, local, remote)
+    });
+
+    let latest = config.get_string(&format!("{}.latest", generator_prefix)).map(|link| {
+        let file = match config.get_string(&format!("{}.latest_file", generator_prefix)) {
+            Some(file) => Path::new(directory).join(render_inline("latest_file", &file, &context)),
+            None => Path::new(&output_path).to_path_buf(),
+        };
+        let copy = match config.get_string(&format!("{}.latest_mode", generator_prefix)).as_deref() {
+            Some("copy") => true,
+            Some("symlink") => false,
+            Some(other) => {
+                tracing::warn!("Unknown latest_mode {}, using symlink", other);
+                false
             }
+            None => cfg!(not(unix)),
+        };
+        Latest { file, link: Path::new(directory).join(render_inline("latest", &link, &context)), copy }
+    });
 
-            let rendered = match build_cmd_tera.render("build_command", &context_builder.build("none")) {
-                Ok(s) => s,
-                Err(e) => {
-                    tracing::error!("{}", format_tera_error(e));
-                    std::process::exit(1);
-                }
-            };
+    // The days are computed again without escaping, for spreadsheets.
+    let days_csv = match config.get_string(&format!("{}.days_csv", generator_prefix)) {
+        Some(path) => Some(Path::new(directory).join(render_inline("days_csv", &path, &context))),
+        None if emit_days => Some(Path::new(&output_path).with_extension("days.csv")),
+        None => None,
+    }.map(|path| (path, compute(config, time_data, "none", &day_options, rules.as_ref()).days));
 
-            Some(rendered)
-        },
+    let open_file = match config.get_string(&format!("{}.open", generator_prefix)) {
+        Some(file) => Path::new(directory).join(render_inline("open_file", &file, &context)),
+        None => Path::new(&output_path).to_path_buf(),
     };
 
+    // The files the build command makes, as far as the configuration names them.
+    let mut products: Vec<PathBuf> = Vec::new();
+    if build.is_some() {
+        let named = [Some(&open_file), latest.as_ref().map(|l| &l.file), upload.as_ref().map(|(_, local, _)| local)];
+        for path in named.into_iter().flatten() {
+            if *path != Path::new(&output_path) && !products.contains(path) {
+                products.push(path.clone());
+            }
+        }
+    }
+
     // days are not made available to the output_path Tera context,
     // but must be available for the template processing.
-    context_builder.insert("days", &days);
+    context_builder.insert("days", &invoice.days);
+    context_builder.insert("months", &invoice.months);
+    context_builder.insert("footnotes", &invoice.footnotes);
+    context_builder.insert("tags", &invoice.tag_totals.iter().map(|total| total.tag.clone()).collect::<Vec<_>>());
+    context_builder.insert("tag_totals", &invoice.tag_totals);
+    context_builder.insert("projects", &invoice.projects);
+
+    let mut expressions = config.get_table("context").cloned().unwrap_or_default();
+    expressions.extend(config.get_table(&format!("{}.context", generator_prefix)).cloned().unwrap_or_default());
+    for (key, value) in computed_context(&mut tera, &expressions, &context_builder.build("none")) {
+        context_builder.insert(&key, &value);
+    }
 
     let final_context = context_builder.build(&escape_mode);
     let rendered = match tera.render(template_name, &final_context) {
         Ok(s) => s,
         Err(e) => {
             tracing::error!("{}", format_tera_error(e));
-            std::process::exit(1);
+            diagnostics::exit(1);
         }
     };
 
-    if output_path == "-" {
-        println!("{}", rendered);
-        return;
+    Job {
+        generator: use_generator.to_string(),
+        output_path,
+        rendered,
+        build,
+        build_log,
+        highlight: BuildHighlight::from_config(config, &generator_prefix),
+        upload,
+        open_file,
+        latest,
+        products,
+        days_csv,
+        invoice,
+        period_start,
+        period_end,
+    }
+}
+
+/// Writes the days of an invoice as CSV: date, hours billed, cost, description, and the
+/// adjustments to the hours, separated by `; `.
+pub fn write_days_csv(days: &[Day], out: impl Write) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["date", "hours", "cost", "description", "adjustments"])?;
+    for day in days {
+        writer.write_record([
+            day.date.clone(),
+            day.hours.to_string(),
+            format!("{:.2}", day.cost),
+            day.description.clone(),
+            day.adjustments.join("; "),
+        ])?;
     }
+    writer.flush()?;
+    Ok(())
+}
 
-    tracing::info!("Generating {}", output_path);
-    let mut file = File::create(&output_path).expect("Failed to create output file");
-    file.write_all(rendered.as_bytes())
-        .expect("Failed to write to output file");
+// Warns about entries dated in the future, which are usually a typo in the year.
+fn warn_future_dates(config: &Config, time_data: &TimeData) {
+    let cutoff = validate::future_cutoff(Some(config), clock::today(Some(config)));
+    let mut future: Vec<_> = time_data.entries.keys().filter(|date| **date >= cutoff).collect();
+    future.sort();
+    for date in future {
+        let files: Vec<String> = time_data.sources.get(date).into_iter().flatten()
+            .map(|source| source.file.display().to_string())
+            .collect::<BTreeSet<_>>().into_iter().collect();
+        tracing::warn!("Entries dated {} are in the future ({})",
+            date.format("%Y.%m.%d").to_string().err_colored(theme().warning), files.join(", "));
+    }
+}
 
-    index.save().expect("Failed to save index file");
+// Builds, uploads, and announces a written invoice.
+fn finish(config: &Config, job: &Job, sequence: u32, dates: &[String], prefixed: bool, open: bool) -> Result<(), String> {
+    if let Some(builder) = &job.build {
+        let prefix = if prefixed { Some(job.generator.as_str()) } else { None };
+        process_builder(builder, &job.highlight, job.build_log.as_deref(), prefix)?;
+    }
 
-    if let Some(builder) = build_command {
-        process_builder(builder);
+    let uploaded = match &job.upload {
+        Some((kind, local, remote)) => {
+            let upload_prefix = format!("generator.{}.upload", job.generator);
+            Some(upload::upload(config, &upload_prefix, kind, local, remote)
+                .map_err(|err| format!("Upload failed: {}", err))?)
+        }
+        None => None,
+    };
+
+    if let Some(latest) = &job.latest {
+        latest.update().map_err(|err| format!("Failed to update {}: {}", latest.link.display(), err))?;
+    }
+
+    if open {
+        open_file(config, &job.open_file)?;
+    }
+
+    let invoice = &job.invoice;
+    quiet::summary("generated", &[
+        ("seq", sequence.to_string()),
+        ("hours", invoice.total_hours.to_string()),
+        ("amount", format!("{:.2}", invoice.total_amount)),
+        ("file", uploaded.clone().unwrap_or_else(|| job.output_path.clone())),
+    ]);
+
+    let summary = format!("Generated invoice {} for {} to {}: {} hours, {:.2} total",
+        sequence, job.period_start.format("%Y-%m-%d"), job.period_end.format("%Y-%m-%d"),
+        invoice.total_hours, invoice.total_amount);
+    notify::webhook(config, &json!({
+        "event": "generated",
+        "text": summary,
+        "generator": job.generator,
+        "sequence": sequence,
+        "dates": dates,
+        "period_start": job.period_start.format("%Y-%m-%d").to_string(),
+        "period_end": job.period_end.format("%Y-%m-%d").to_string(),
+        "total_hours": invoice.total_hours,
+        "subtotal_amount": invoice.subtotal_amount,
+        "tax_amount": invoice.tax_amount,
+        "total_amount": invoice.total_amount,
+        "output": job.output_path,
+        "upload": uploaded,
+    }));
+    Ok(())
+}
+
+// Copies an existing output file to a timestamped backup, like `invoice-3.tex.20250701-120000.bak`,
+// unless it already holds the new content.
+fn backup_output(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if fs::read(path)? == content {
+        return Ok(());
+    }
+    backup(path)
+}
+
+// Copies a file to a timestamped backup next to it.
+fn backup(path: &Path) -> std::io::Result<()> {
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let mut saved = path.as_os_str().to_os_string();
+    saved.push(format!(".{}.bak", stamp));
+    tracing::warn!("Overwriting {}, previous version saved to {}", path.display(), Path::new(&saved).display());
+    fs::copy(path, &saved).map(|_| ())
+}
+
+// A stable path, like `invoice-latest.pdf`, kept pointing at the newest invoice.
+struct Latest {
+    file: PathBuf,
+    link: PathBuf,
+    copy: bool,
+}
+
+impl Latest {
+    // Replaces the link with a symlink to, or a copy of, the file.
+    fn update(&self) -> std::io::Result<()> {
+        if self.link.symlink_metadata().is_ok_and(|meta| meta.is_file()) {
+            backup_output(&self.link, &fs::read(&self.file)?)?;
+        }
+        if self.link.symlink_metadata().is_ok() {
+            fs::remove_file(&self.link)?;
+        }
+        tracing::info!("Latest {} is {}", self.link.display(), self.file.display());
+        if self.copy {
+            return fs::copy(&self.file, &self.link).map(|_| ());
+        }
+        #[cfg(unix)]
+        {
+            // link relative to the link's directory when possible, so the directory can be moved
+            let target = match (self.file.parent(), self.link.parent()) {
+                (Some(a), Some(b)) if a == b => Path::new(self.file.file_name().unwrap()),
+                _ => self.file.as_path(),
+            };
+            std::os::unix::fs::symlink(target, &self.link)
+        }
+        #[cfg(not(unix))]
+        fs::copy(&self.file, &self.link).map(|_| ())
+    }
+}
+
+// Opens a file with the `viewer` command from the configuration, or the platform's opener.
+fn open_file(config: &Config, path: &Path) -> Result<(), String> {
+    let mut argv = match config.get_string("viewer") {
+        Some(viewer) => shlex::split(&viewer).ok_or(format!("Invalid viewer command: {}", viewer))?,
+        None if cfg!(windows) => vec!["cmd".to_string(), "/C".to_string(), "start".to_string(), "\"\"".to_string()],
+        None if cfg!(target_os = "macos") => vec!["open".to_string()],
+        None => vec!["xdg-open".to_string()],
+    };
+    if argv.is_empty() {
+        return Err("viewer command is empty".to_string());
+    }
+    argv.push(path.display().to_string());
+
+    tracing::info!("Opening {}", path.display());
+    Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Failed to run {}: {}", argv[0], err))?;
+    Ok(())
+}
+
+/// A build command to run after the output file is written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildCommand {
+    /// A command line run by a shell, like `["sh", "-c"]`.
+    Shell { shell: Vec<String>, script: String },
+    /// A program and its arguments, run without a shell.
+    Argv(Vec<String>),
+}
+
+impl BuildCommand {
+    /// Reads and renders `build` for a generator, which is either a string run by the shell,
+    /// or an array of program and arguments.
+    ///
+    /// The shell comes from `shell` in the generator, or `build.shell`, and defaults to
+    /// `sh -c`, or `cmd /C` on Windows.
+    pub fn from_config(config: &Config, generator_prefix: &str, context: &Context) -> Option<Self> {
+        let key = format!("{}.build", generator_prefix);
+        if let Some(argv) = config.get_string_array(&key) {
+            if argv.is_empty() {
+                return None;
+            }
+            let argv = argv.iter()
+                .map(|arg| render_inline("build_command", arg, context))
+                .collect();
+            return Some(BuildCommand::Argv(argv));
+        }
+        let script = config.get_string(&key)?;
+        let shell = config.get_string_array(&format!("{}.shell", generator_prefix))
+            .or_else(|| config.get_string_array("build.shell"))
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(default_shell);
+        Some(BuildCommand::Shell { shell, script: render_inline("build_command", &script, context) })
+    }
+
+    // Creates the `Command` that runs this build.
+    fn command(&self) -> Command {
+        let (program, args) = match self {
+            BuildCommand::Shell { shell, script } => (&shell[0], [&shell[1..], std::slice::from_ref(script)].concat()),
+            BuildCommand::Argv(argv) => (&argv[0], argv[1..].to_vec()),
+        };
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    }
+}
+
+impl std::fmt::Display for BuildCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildCommand::Shell { script, .. } => write!(f, "{}", script),
+            BuildCommand::Argv(argv) => write!(f, "{}", argv.join(" ")),
+        }
+    }
+}
+
+// The shell used for string build commands, when none is configured.
+fn default_shell() -> Vec<String> {
+    if cfg!(windows) {
+        vec!["cmd".to_string(), "/C".to_string()]
+    } else {
+        vec!["sh".to_string(), "-c".to_string()]
+    }
+}
+
+/// Rules for highlighting build output, configurable in `[build]` or in the generator.
+#[derive(Debug, Clone)]
+pub struct BuildHighlight {
+    /// Words that mark a line as a problem, matched case-insensitively.
+    pub negative_words: Vec<String>,
+    /// Regular expressions that mark a line as a problem.
+    pub negative_patterns: Vec<Regex>,
+    /// Lines shown before the first problem.
+    pub context_lines: usize,
+    /// Lines shown at the end of a successful build.
+    pub tail_lines: usize,
+}
+
+impl Default for BuildHighlight {
+    fn default() -> Self {
+        BuildHighlight {
+            negative_words: ["error", "fail", "fatal", "warn", "undefined", "missing"]
+                .iter().map(|w| w.to_string()).collect(),
+            negative_patterns: Vec::new(),
+            context_lines: 5,
+            tail_lines: 2,
+        }
+    }
+}
+
+impl BuildHighlight {
+    /// Reads the highlighting rules for a generator, falling back to `[build]`, and then
+    /// to the defaults.
+    pub fn from_config(config: &Config, generator_prefix: &str) -> Self {
+        let lookup = |key: &str| {
+            let generator_key = format!("{}.{}", generator_prefix, key);
+            if config.has(&generator_key) { generator_key } else { format!("build.{}", key) }
+        };
+        let mut highlight = BuildHighlight::default();
+        if let Some(words) = config.get_string_array(&lookup("negative_words")) {
+            highlight.negative_words = words.iter().map(|w| w.to_lowercase()).collect();
+        }
+        if let Some(patterns) = config.get_string_array(&lookup("negative_patterns")) {
+            highlight.negative_patterns = patterns.iter().filter_map(|p| Regex::new(p)
+                .map_err(|err| tracing::warn!("Invalid build pattern {}: {}", p, err))
+                .ok()).collect();
+        }
+        if let Some(lines) = config.get_i64(&lookup("context_lines")) {
+            highlight.context_lines = lines.max(0) as usize;
+        }
+        if let Some(lines) = config.get_i64(&lookup("tail_lines")) {
+            highlight.tail_lines = lines.max(0) as usize;
+        }
+        highlight
+    }
+
+    /// Checks if a line of build output looks like a problem.
+    pub fn is_negative(&self, line: &str) -> bool {
+        let lower = line.to_lowercase();
+        self.negative_words.iter().any(|w| lower.contains(w.as_str()))
+            || self.negative_patterns.iter().any(|p| p.is_match(line))
     }
 }
 
 // Executes an external build command and streams its output.
-fn process_builder(builder : String) {
-    tracing::info!("Build with {}", builder.to_string());
+//
+// The full output is also saved to `build_log`, if given.  Lines are prefixed with the
+// generator name when a `prefix` is given, because several builds may run at once.
+fn process_builder(
+    builder: &BuildCommand,
+    highlight: &BuildHighlight,
+    build_log: Option<&Path>,
+    prefix: Option<&str>,
+) -> Result<(), String> {
+    tracing::info!("Build with {}", builder);
+    let show = |line: &str, color: Color| match prefix {
+        Some(prefix) => eprintln!("{} {}", format!("[{}]", prefix).err_colored(color), line.err_colored(color)),
+        None => eprintln!("{}", line.err_colored(color)),
+    };
 
-    let mut cmd = Command::new("sh")
-        .arg("-c")
-        .arg(&builder)
+    let mut cmd = builder.command()
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -483,33 +1515,33 @@ fn process_builder(builder : String) {
     drop(tx);
 
     let mut show_output = false;
-    let success_show_lines = 2;
-    let negative_show_lines = 5;
-    let mut history: VecDeque<String> = VecDeque::with_capacity(negative_show_lines);
+    let mut history: VecDeque<String> = VecDeque::with_capacity(highlight.context_lines);
     let mut full_output = Vec::new();
 
-    let negative_words = ["error", "fail", "fatal", "warn", "undefined", "missing"];
+    let theme = theme();
 
     for line in rx {
         full_output.push(line.clone());
 
-        let line_has_negative = negative_words.iter().any(|w| line.to_lowercase().contains(w));
+        let line_has_negative = highlight.is_negative(&line);
 
-        if show_output {
+        if quiet::enabled() {
+            continue;
+        } else if show_output {
             if line_has_negative {
-                eprintln!("{}", line.colored(Color::BrightRed));
+                show(&line, theme.build_error);
             } else {
-                eprintln!("{}", line.colored(Color::BrightBlack));
+                show(&line, theme.build);
             }
 
         } else if line_has_negative {
             show_output = true;
             for past_line in &history {
-                eprintln!("{}", past_line.colored(Color::BrightBlack));
+                show(past_line, theme.build);
             }
-            eprintln!("{}", line.colored(Color::BrightRed));
-        } else {
-            if history.len() == negative_show_lines {
+            show(&line, theme.build_error);
+        } else if highlight.context_lines > 0 {
+            if history.len() == highlight.context_lines {
                 history.pop_front();
             }
             history.push_back(line);
@@ -521,20 +1553,33 @@ fn process_builder(builder : String) {
 
     let status = cmd.wait().expect("Failed to wait for build command");
 
+    if let Some(log) = build_log {
+        let mut content = full_output.join("\n");
+        content.push('\n');
+        match fs::write(log, content) {
+            Ok(()) => tracing::info!("Build log {}", log.display()),
+            Err(err) => tracing::warn!("Failed to write build log {}: {}", log.display(), err),
+        }
+    }
+
     if !status.success() {
         if !show_output {
             for line in full_output {
-                eprintln!("{}", line.colored(Color::BrightBlack));
+                show(&line, theme.build);
             }
         }
-        tracing::error!("Build command failed with status: {:?}", status);
-        std::process::exit(1);
+        return Err(format!("Build command failed with status: {:?}", status));
     }
 
-    let start_line = full_output.len().saturating_sub(success_show_lines);
+    let start_line = if quiet::enabled() {
+        full_output.len()
+    } else {
+        full_output.len().saturating_sub(highlight.tail_lines)
+    };
     for line in &full_output[start_line..] {
-        eprintln!("{}", line.colored(Color::BrightBlack));
+        show(line, theme.build);
     }
 
     tracing::info!("Build command successful");
+    Ok(())
 }
@@ -1,36 +1,173 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use chrono::{Datelike, NaiveDate, Weekday, Month};
-use crate::data::{DateRange, DateSelector, TimeData, Entry};
+use crate::clock;
+use crate::config::Config;
+use crate::quiet;
+use crate::data::{DateRange, LoadOptions, Selection, TimeData, Entry};
+use crate::diagnostics;
+use crate::color::{color_enabled, theme, DynamicColorize};
+use crate::stats;
+use clap::ValueEnum;
 use num_traits::FromPrimitive;
+use std::env;
+
+/// Selects how heatmap cells are drawn.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Palette {
+    Truecolor, // 24-bit color escapes.
+    Ansi256,   // The 256-color palette.
+    Ascii,     // Character density, without color.
+}
+
+impl Palette {
+    /// Picks a palette for stdout, based on color support, `COLORTERM` and `TERM`.
+    pub fn detect() -> Self {
+        if !color_enabled().stdout {
+            return Palette::Ascii;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        Self::from_env(&colorterm, &term)
+    }
+
+    // Picks a palette from the `COLORTERM` and `TERM` values.
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        if colorterm == "truecolor" || colorterm == "24bit" || term.contains("direct") {
+            Palette::Truecolor
+        } else if term.contains("256color") {
+            Palette::Ansi256
+        } else if term.is_empty() || term == "dumb" {
+            Palette::Ascii
+        } else {
+            Palette::Ansi256
+        }
+    }
+
+    /// Renders a three character wide cell, for a day with the given hours.
+    pub fn cell(&self, hours: Option<f64>, max_hours: f64) -> String {
+        let fraction = match hours {
+            Some(hours) if hours > 0.0 && max_hours > 0.0 => Some((hours / max_hours).min(1.0)),
+            _ => None,
+        };
+        match (self, fraction) {
+            (Palette::Truecolor, Some(fraction)) => {
+                let intensity = (fraction * 230.0) as u8 + 25;
+                format!("\u{1b}[38;2;0;{};0m ◀▶\u{1b}[0m", intensity)
+            }
+            (Palette::Truecolor, None) => "\u{1b}[38;2;20;20;20m ◀▶\u{1b}[0m".to_string(),
+            (Palette::Ansi256, Some(fraction)) => {
+                // green ramp of the 6x6x6 color cube
+                let level = (fraction * 5.0).ceil().max(1.0) as u8;
+                format!("\u{1b}[38;5;{}m ◀▶\u{1b}[0m", 16 + 6 * level)
+            }
+            (Palette::Ansi256, None) => "\u{1b}[38;5;235m ◀▶\u{1b}[0m".to_string(),
+            (Palette::Ascii, Some(fraction)) => {
+                let chars = ASCII_DENSITY.as_bytes();
+                let level = ((fraction * (chars.len() - 1) as f64).ceil() as usize).max(1);
+                let c = chars[level] as char;
+                format!(" {}{}", c, c)
+            }
+            (Palette::Ascii, None) => format!(" {} ", ASCII_DENSITY.chars().next().unwrap()),
+        }
+    }
+
+    /// Renders a cell like `cell`, with a marker in its first column when the day met the
+    /// daily target.
+    pub fn target_cell(&self, hours: Option<f64>, max_hours: f64, target: Option<f64>) -> String {
+        let cell = self.cell(hours, max_hours);
+        match (hours, target) {
+            (Some(hours), Some(target)) if hours > 0.0 && hours >= target => {
+                let marker = if *self == Palette::Ascii { ASCII_TARGET_MARKER } else { TARGET_MARKER };
+                cell.replacen(' ', marker, 1)
+            }
+            _ => cell,
+        }
+    }
+}
+
+// Characters used by the ASCII palette, from no work to the most hours.
+const ASCII_DENSITY: &str = ".:-=+*#";
+// Marks the days that met the daily target.
+const TARGET_MARKER: &str = "▸";
+const ASCII_TARGET_MARKER: &str = ">";
 
 /// Runs the heatmap generation.
-pub fn run(directory: &Option<String>, dates: &[String]) {
-    let date_selector = DateSelector::from_dates(dates).unwrap_or_else(|err| {
+///
+/// The `palette` is taken from the command line, or `heatmap.palette`, or is detected.  Days
+/// that met the `target` hours, or `heatmap.target`, are marked.
+pub fn run(
+    directory: &Option<String>,
+    config_file: &Option<String>,
+    palette: Option<Palette>,
+    target: Option<f64>,
+    tags: &[String],
+    selection: &Selection,
+) {
+    let date_selector = selection.selector().unwrap_or_else(|err| {
         tracing::error!("{}", err);
-        std::process::exit(1);
+        diagnostics::exit(1);
     });
     let dir_path = directory.as_deref().unwrap_or(".");
-    let time_data = TimeData::new(dir_path, &date_selector).unwrap_or_else(|err| {
+    let config = Config::new(config_file.as_deref(), Some(dir_path)).ok();
+    let options = LoadOptions { progress: true, ..selection.checked_load_options(config.as_ref()) };
+    let palette = palette
+        .or_else(|| config.as_ref()
+            .and_then(|c| c.get_string("heatmap.palette"))
+            .and_then(|p| Palette::from_str(&p, true).map_err(|err| tracing::warn!("heatmap.palette: {}", err)).ok()))
+        .unwrap_or_else(Palette::detect);
+    let target = target
+        .or_else(|| config.as_ref().and_then(|c| c.get_f64("heatmap.target")))
+        .filter(|target| *target > 0.0);
+    let mut time_data = TimeData::load(dir_path, &date_selector, &options).unwrap_or_else(|err| {
         tracing::error!("Failed to load data: {}", err);
-        std::process::exit(1);
+        diagnostics::exit(1);
     });
+    time_data.retain_tagged(tags);
     let daily_hours = get_daily_hours(&time_data, &date_selector.ranges);
+    if quiet::enabled() {
+        let total_hours = daily_hours.values().fold(0.0, |acc, h| acc + h);
+        quiet::summary("heatmap", &[
+            ("days", daily_hours.len().to_string()),
+            ("hours", format!("{:.2}", total_hours)),
+            ("max", format!("{:.2}", get_max_hours(&daily_hours))),
+        ]);
+        return;
+    }
     if !daily_hours.is_empty() {
         let (start_date, end_date) = get_date_range(&daily_hours);
         let max_hours = get_max_hours(&daily_hours);
-        draw_heatmap(daily_hours, start_date, end_date, max_hours);
+        let daily: BTreeMap<NaiveDate, f64> = daily_hours.iter().map(|(d, h)| (*d, *h)).collect();
+        draw_heatmap(daily_hours, start_date, end_date, max_hours, palette, target);
+        draw_footer(&daily, clock::today(config.as_ref()), target);
+    }
+}
+
+/// Prints the streaks, the days active, and the total hours under the grid.
+fn draw_footer(daily_hours: &BTreeMap<NaiveDate, f64>, today: NaiveDate, target: Option<f64>) {
+    let stats = stats::compute(daily_hours);
+    let days = |span: Option<stats::Span>| span.map_or(0, |s| s.days());
+    let theme = theme();
+    println!();
+    println!("Current streak {} days   Longest streak {} days   Active days {}   Total hours {}",
+        days(stats::current_streak(daily_hours, today)).to_string().out_colored(theme.count),
+        days(stats.longest_streak).to_string().out_colored(theme.count),
+        stats.working_days.to_string().out_colored(theme.count),
+        format!("{:.2}", stats.total_hours).out_colored(theme.total));
+    if let Some(target) = target {
+        let met = daily_hours.values().filter(|hours| **hours > 0.0 && **hours >= target).count();
+        let summary = format!("{} of {} days", met, stats.working_days);
+        println!("Target {} hours met on {}", target,
+            if met < stats.working_days { summary.out_colored(theme.warning) } else { summary.out_colored(theme.total) });
     }
 }
 
 /// Calculates the total hours worked per day.
-fn get_daily_hours(time_data: &TimeData, date_ranges: &[DateRange]) -> HashMap<NaiveDate, f64> {
+pub fn get_daily_hours(time_data: &TimeData, date_ranges: &[DateRange]) -> HashMap<NaiveDate, f64> {
     let mut daily_hours: HashMap<NaiveDate, f64> = HashMap::new();
     for (date, entries) in &time_data.entries {
         if date_ranges.is_empty() || date_ranges.iter().any(|dr| dr.start <= *date && dr.end >= *date) {
-            for entry in entries {
-                if let Entry::Time(hours, _) = entry {
-                    *daily_hours.entry(*date).or_insert(0.0) += *hours as f64;
-                }
+            for entry in entries.iter().filter(|entry| matches!(entry, Entry::Time(..) | Entry::NonBillable(..))) {
+                *daily_hours.entry(*date).or_insert(0.0) += entry.hours() as f64;
             }
         }
     }
@@ -55,6 +192,8 @@ fn draw_heatmap(
     start_date: NaiveDate,
     end_date: NaiveDate,
     max_hours: f64,
+    palette: Palette,
+    target: Option<f64>,
 ) {
     let mut first_monday = start_date;
     while first_monday.weekday() != Weekday::Mon {
@@ -121,14 +260,7 @@ fn draw_heatmap(
             if current_day < start_date || current_day > end_date {
                 print!("   ");
             } else {
-                let (r, g, b) = match cell {
-                    Some(hours) if hours > 0.0 => {
-                        let intensity = (hours / max_hours * 230.0) as u8 + 25;
-                        (0, intensity, 0)
-                    }
-                    _ => (20, 20, 20),
-                };
-                print!("\u{1b}[38;2;{};{};{}m ◀▶\u{1b}[0m", r, g, b);
+                print!("{}", palette.target_cell(cell, max_hours, target));
             }
         }
         println!();
@@ -147,3 +279,41 @@ fn draw_heatmap(
     }
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_from_env() {
+        assert_eq!(Palette::from_env("truecolor", "xterm-256color"), Palette::Truecolor);
+        assert_eq!(Palette::from_env("", "xterm-256color"), Palette::Ansi256);
+        assert_eq!(Palette::from_env("", "xterm-direct"), Palette::Truecolor);
+        assert_eq!(Palette::from_env("", "dumb"), Palette::Ascii);
+        assert_eq!(Palette::from_env("", ""), Palette::Ascii);
+    }
+
+    #[test]
+    fn test_ascii_cells() {
+        assert_eq!(Palette::Ascii.cell(None, 8.0), " . ");
+        assert_eq!(Palette::Ascii.cell(Some(0.0), 8.0), " . ");
+        assert_eq!(Palette::Ascii.cell(Some(0.1), 8.0), " ::");
+        assert_eq!(Palette::Ascii.cell(Some(8.0), 8.0), " ##");
+    }
+
+    #[test]
+    fn test_target_cells() {
+        assert_eq!(Palette::Ascii.target_cell(Some(8.0), 8.0, Some(6.0)), ">##");
+        assert_eq!(Palette::Ascii.target_cell(Some(4.0), 8.0, Some(6.0)), " ==");
+        assert_eq!(Palette::Ascii.target_cell(None, 8.0, Some(6.0)), " . ");
+        assert_eq!(Palette::Ascii.target_cell(Some(8.0), 8.0, None), " ##");
+        assert_eq!(Palette::Ansi256.target_cell(Some(8.0), 8.0, Some(8.0)), "\u{1b}[38;5;46m▸◀▶\u{1b}[0m");
+    }
+
+    #[test]
+    fn test_ansi256_cells() {
+        assert_eq!(Palette::Ansi256.cell(Some(8.0), 8.0), "\u{1b}[38;5;46m ◀▶\u{1b}[0m");
+        assert_eq!(Palette::Ansi256.cell(Some(0.5), 8.0), "\u{1b}[38;5;22m ◀▶\u{1b}[0m");
+        assert_eq!(Palette::Ansi256.cell(None, 8.0), "\u{1b}[38;5;235m ◀▶\u{1b}[0m");
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::Config;
+use std::collections::HashMap;
+use tera::{to_value, try_get_value, Tera, Value};
+
+const BUILTIN_STRINGS: &str = include_str!("i18n.toml");
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// Translated labels for one invoice language.
+///
+/// Labels come from the built-in string tables, overridden by `[i18n.<language>]` in the
+/// configuration.  Missing labels fall back to English, and then to the key itself.
+#[derive(Debug, Clone)]
+pub struct Strings {
+    pub language: String,
+    labels: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Loads the labels for `invoice.language` from the configuration, English by default.
+    pub fn from_config(config: Option<&Config>) -> Self {
+        let language = config.and_then(|c| c.get_string("invoice.language"))
+            .unwrap_or(DEFAULT_LANGUAGE.to_string());
+        Self::new(&language, config)
+    }
+
+    /// Loads the labels for the given language, with overrides from the configuration.
+    pub fn new(language: &str, config: Option<&Config>) -> Self {
+        let builtin: toml::Value = toml::from_str(BUILTIN_STRINGS).expect("invalid built-in i18n.toml");
+        let mut labels = HashMap::new();
+
+        let mut add_table = |table: Option<&toml::map::Map<String, toml::Value>>| {
+            for (key, value) in table.into_iter().flatten() {
+                if let Some(s) = value.as_str() {
+                    labels.insert(key.clone(), s.to_string());
+                }
+            }
+        };
+        add_table(builtin.get(DEFAULT_LANGUAGE).and_then(|v| v.as_table()));
+        add_table(config.and_then(|c| c.get_table(&format!("i18n.{}", DEFAULT_LANGUAGE))));
+        if language != DEFAULT_LANGUAGE {
+            add_table(builtin.get(language).and_then(|v| v.as_table()));
+            add_table(config.and_then(|c| c.get_table(&format!("i18n.{}", language))));
+            if builtin.get(language).is_none() && config.and_then(|c| c.get_table(&format!("i18n.{}", language))).is_none() {
+                tracing::warn!("No strings for language {}, using {}", language, DEFAULT_LANGUAGE);
+            }
+        }
+
+        Strings { language: language.to_string(), labels }
+    }
+
+    /// Translates a label key, returning the key itself if there is no translation.
+    pub fn get(&self, key: &str) -> String {
+        self.labels.get(key).cloned().unwrap_or(key.to_string())
+    }
+
+    /// Returns all labels, for use as `label_<key>` template variables.
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+
+    /// Registers the `t` filter, which translates a label key, in a Tera instance.
+    pub fn register_filter(&self, tera: &mut Tera) {
+        let strings = self.clone();
+        tera.register_filter("t", move |value: &Value, _: &HashMap<String, Value>| {
+            let key = try_get_value!("t", "value", String, value);
+            Ok(to_value(strings.get(&key)).unwrap())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Context;
+
+    #[test]
+    fn test_builtin_languages() {
+        assert_eq!(Strings::new("en", None).get("invoice"), "Invoice");
+        assert_eq!(Strings::new("fr", None).get("due_date"), "Date d'échéance");
+        assert_eq!(Strings::new("de", None).get("total"), "Gesamt");
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let strings = Strings::new("xx", None);
+        assert_eq!(strings.get("invoice"), "Invoice");
+        assert_eq!(strings.get("no_such_label"), "no_such_label");
+    }
+
+    #[test]
+    fn test_t_filter() {
+        let mut tera = Tera::default();
+        Strings::new("es", None).register_filter(&mut tera);
+        let rendered = tera.render_str("{{ 'invoice' | t }}: {{ 'total' | t }}", &Context::new()).unwrap();
+        assert_eq!(rendered, "Factura: Total");
+    }
+}
@@ -0,0 +1,221 @@
+//! CSV importer for timesheets kept in spreadsheets, with the columns mapped in
+//! `[import.csv]`.
+//!
+//! Each row has a date, and either the hours worked or a start and end time.  The
+//! description and an optional project, which becomes a `#tag`, make up the entry text.
+
+use super::{describe, ImportedEntry};
+use crate::config::Config;
+use crate::parse::parse_date;
+use chrono::{NaiveDate, NaiveTime};
+use std::path::Path;
+
+/// The columns of a CSV file, by header name, and how its values are written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub date: String,
+    pub hours: String,
+    pub start: String,
+    pub end: String,
+    pub description: String,
+    pub project: String,
+    /// A chrono format for dates, like `%d/%m/%Y`, tried after the `.cli` date formats.
+    pub date_format: Option<String>,
+    pub delimiter: u8,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            date: "date".to_string(),
+            hours: "hours".to_string(),
+            start: "start".to_string(),
+            end: "end".to_string(),
+            description: "description".to_string(),
+            project: "project".to_string(),
+            date_format: None,
+            delimiter: b',',
+        }
+    }
+}
+
+impl Mapping {
+    /// Reads the mapping from `[import.csv]`, using the default column names for the
+    /// settings that are missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if `import.csv.delimiter` is not a single character.
+    pub fn from_config(config: Option<&Config>) -> Result<Self, String> {
+        let defaults = Mapping::default();
+        let Some(config) = config else {
+            return Ok(defaults);
+        };
+        let column = |key: &str, default: String| config.get_string(&format!("import.csv.{}", key)).unwrap_or(default);
+        let delimiter = match config.get_string("import.csv.delimiter") {
+            Some(text) if text == "\\t" => b'\t',
+            Some(text) if text.len() == 1 => text.as_bytes()[0],
+            Some(text) => return Err(format!("import.csv.delimiter must be a single character, not '{}'", text)),
+            None => defaults.delimiter,
+        };
+        Ok(Mapping {
+            date: column("date", defaults.date),
+            hours: column("hours", defaults.hours),
+            start: column("start", defaults.start),
+            end: column("end", defaults.end),
+            description: column("description", defaults.description),
+            project: column("project", defaults.project),
+            date_format: config.get_string("import.csv.date_format"),
+            delimiter,
+        })
+    }
+
+    fn parse_date(&self, text: &str) -> Option<NaiveDate> {
+        parse_date(text.trim()).or_else(|| {
+            let format = self.date_format.as_deref()?;
+            NaiveDate::parse_from_str(text.trim(), format).ok()
+        })
+    }
+}
+
+// Parses hours written as a decimal number, like `1.5`, or as `H:MM`.
+fn parse_hours(text: &str) -> Option<f64> {
+    let text = text.trim().trim_end_matches('h');
+    match text.split_once(':') {
+        Some((hours, minutes)) => Some(hours.parse::<u32>().ok()? as f64 + minutes.parse::<u32>().ok()? as f64 / 60.0),
+        None => text.parse().ok(),
+    }
+}
+
+// Parses a time of day, like `09:30` or `09:30:00`.
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text.trim(), "%H:%M:%S"))
+        .ok()
+}
+
+/// Reads entries from a CSV file, with the columns named by `mapping`.
+///
+/// The date and description columns are required, along with either hours or start and end
+/// times; rows with hours use them, and the others the times.  Times ending past midnight,
+/// earlier than they start, are logged as hours, and equal times are skipped.  Rows that
+/// cannot be read are skipped with a warning.
+///
+/// # Errors
+///
+/// Returns a `String` error if the file cannot be read, or lacks a required column.
+pub fn read_csv(path: &Path, mapping: &Mapping) -> Result<Vec<ImportedEntry>, String> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let headers = reader.headers()
+        .map_err(|e| format!("Failed to read header of {}: {}", path.display(), e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let missing = |name: &str| format!("{}: missing '{}' column", path.display(), name);
+    let date_col = column(&mapping.date).ok_or_else(|| missing(&mapping.date))?;
+    let description_col = column(&mapping.description).ok_or_else(|| missing(&mapping.description))?;
+    let hours_col = column(&mapping.hours);
+    let times = column(&mapping.start).zip(column(&mapping.end));
+    if hours_col.is_none() && times.is_none() {
+        return Err(format!("{}: missing '{}' column, or '{}' and '{}' columns",
+            path.display(), mapping.hours, mapping.start, mapping.end));
+    }
+    let project_col = column(&mapping.project);
+
+    let mut entries = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let field = |col: usize| record.get(col).unwrap_or("").trim();
+        let line = row + 2;
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+
+        let Some(date) = mapping.parse_date(field(date_col)) else {
+            tracing::warn!("{}:{}: invalid date '{}'", path.display(), line, field(date_col));
+            continue;
+        };
+        let description = describe(field(description_col), "", project_col.map(field));
+        let hours = hours_col.map(field).filter(|hours| !hours.is_empty());
+        let entry = match (hours, times) {
+            (Some(hours), _) => parse_hours(hours).map(|hours| ImportedEntry::hours(date, hours, &description)),
+            (None, Some((start_col, end_col))) => match (parse_time(field(start_col)), parse_time(field(end_col))) {
+                (Some(start), Some(end)) if end > start => Some(ImportedEntry {
+                    date,
+                    spec: format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")),
+                    description: description.clone(),
+                }),
+                (Some(start), Some(end)) if end < start => {
+                    let minutes = (end - start).num_minutes() + 24 * 60;
+                    Some(ImportedEntry::hours(date, minutes as f64 / 60.0, &description))
+                }
+                _ => None,
+            },
+            (None, None) => None,
+        };
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => tracing::warn!("{}:{}: no valid hours or start and end times", path.display(), line),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(content: &str, mapping: &Mapping) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sheet.csv");
+        std::fs::write(&path, content).unwrap();
+        read_csv(&path, mapping).unwrap().iter()
+            .map(|entry| format!("{} {}", entry.date.format("%Y.%m.%d"), entry.to_line()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_hours() {
+        assert_eq!(parse_hours("1.5"), Some(1.5));
+        assert_eq!(parse_hours("2h"), Some(2.0));
+        assert_eq!(parse_hours("1:45"), Some(1.75));
+        assert_eq!(parse_hours("lots"), None);
+    }
+
+    #[test]
+    fn test_read_csv_hours_and_times() {
+        let entries = read("Date,Hours,Start,End,Description,Project\n\
+            2025-03-03,1:30,,,review,Web Site\n\
+            2025.03.04,,09:00,12:30,workshop,\n\
+            2025.03.05,,22:00,01:00,release,\n\
+            2025.03.05,,10:00,10:00,empty,\n\
+            bogus,2,,,typo,\n\
+            2025.03.06,,,,nothing,\n", &Mapping::default());
+        assert_eq!(entries, vec![
+            "2025.03.03 1.5h = review #web-site",
+            "2025.03.04 09:00-12:30 = workshop",
+            "2025.03.05 3h = release",
+        ]);
+    }
+
+    #[test]
+    fn test_read_csv_mapping() {
+        let mapping = Mapping {
+            date: "Tag".to_string(),
+            hours: "Dauer".to_string(),
+            description: "Tätigkeit".to_string(),
+            date_format: Some("%d.%m.%Y".to_string()),
+            delimiter: b';',
+            ..Mapping::default()
+        };
+        assert_eq!(read("Tag;Dauer;Tätigkeit\n14.03.2025;2;Planung\n", &mapping), vec!["2025.03.14 2h = Planung"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sheet.csv");
+        std::fs::write(&path, "Date,Description\n2025.03.14,x\n").unwrap();
+        assert!(read_csv(&path, &Mapping::default()).unwrap_err().contains("missing 'hours' column"));
+    }
+}
@@ -0,0 +1,174 @@
+//! Draft entries reconstructed from the commit history of a git repository.
+//!
+//! Commits close together are grouped into sessions, each logged as an `HH:MM-HH:MM` entry
+//! starting a little before its first commit.  Alternatively, each commit counts for a fixed
+//! number of hours.  The commit subjects make up the description, tagged with the name of
+//! the repository.
+
+use super::{describe, ImportedEntry};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// The file draft entries are written to, in the data directory, unless another is given.
+pub const REVIEW_FILE: &str = "git-review.txt";
+
+/// How commits are turned into time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Estimate {
+    /// Commits less than `gap` apart are one session, which starts `lead` before its first
+    /// commit.
+    Sessions { gap: Duration, lead: Duration },
+    /// Each commit counts for these hours.
+    PerCommit(f64),
+}
+
+/// A commit, in local time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub time: NaiveDateTime,
+    pub author: String,
+    pub subject: String,
+}
+
+// Fields of `git log`, separated by tabs: the author date, name, email, and the subject.
+const LOG_FORMAT: &str = "--format=%aI%x09%an%x09%ae%x09%s";
+
+// Parses the output of `git log` with `LOG_FORMAT`, converting the times to the time zone `tz`.
+fn parse_log<Tz: TimeZone>(text: &str, tz: &Tz) -> Vec<Commit> {
+    text.lines().filter_map(|line| {
+        let mut fields = line.splitn(4, '\t');
+        let (time, name, email, subject) = (fields.next()?, fields.next()?, fields.next()?, fields.next().unwrap_or(""));
+        let Ok(time) = DateTime::parse_from_rfc3339(time) else {
+            tracing::warn!("Skipping commit with invalid date '{}'", time);
+            return None;
+        };
+        Some(Commit {
+            time: time.with_timezone(tz).naive_local(),
+            author: format!("{} <{}>", name, email),
+            subject: subject.trim().to_string(),
+        })
+    }).collect()
+}
+
+/// Reads the commits of a repository, oldest first, leaving out merges.  With an `author`,
+/// only the commits whose author name or email contains it are kept.
+///
+/// # Errors
+///
+/// Returns a `String` error if git cannot be run, or fails.
+pub fn read_commits(repo: &Path, author: Option<&str>) -> Result<Vec<Commit>, String> {
+    let output = Command::new("git")
+        .arg("-C").arg(repo)
+        .args(["log", "--no-merges", "--reverse", LOG_FORMAT])
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git log failed in {}: {}", repo.display(), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let mut commits = parse_log(&String::from_utf8_lossy(&output.stdout), &Local);
+    if let Some(author) = author {
+        let author = author.to_lowercase();
+        commits.retain(|commit| commit.author.to_lowercase().contains(&author));
+    }
+    commits.sort_by_key(|commit| commit.time);
+    Ok(commits)
+}
+
+// Joins the subjects of commits, without repeating any.
+fn subjects<'a>(commits: impl Iterator<Item = &'a Commit>) -> String {
+    let mut subjects: Vec<&str> = Vec::new();
+    for commit in commits {
+        if !commit.subject.is_empty() && !subjects.contains(&commit.subject.as_str()) {
+            subjects.push(&commit.subject);
+        }
+    }
+    subjects.join("; ")
+}
+
+/// Estimates the time spent on commits, sorted by time, as draft entries tagged with
+/// `project`.
+pub fn entries(commits: &[Commit], estimate: Estimate, project: &str) -> Vec<ImportedEntry> {
+    match estimate {
+        Estimate::PerCommit(hours) => {
+            let mut by_date: BTreeMap<NaiveDate, Vec<&Commit>> = BTreeMap::new();
+            for commit in commits {
+                by_date.entry(commit.time.date()).or_default().push(commit);
+            }
+            by_date.into_iter().map(|(date, commits)| {
+                let description = describe(&subjects(commits.iter().copied()), "", Some(project));
+                ImportedEntry::hours(date, hours * commits.len() as f64, &description)
+            }).collect()
+        }
+        Estimate::Sessions { gap, lead } => {
+            let mut sessions: Vec<&[Commit]> = Vec::new();
+            let mut first = 0;
+            for index in 1..=commits.len() {
+                if index == commits.len() || commits[index].time - commits[index - 1].time > gap {
+                    sessions.push(&commits[first..index]);
+                    first = index;
+                }
+            }
+            sessions.into_iter().filter(|session| !session.is_empty()).flat_map(|session| {
+                let description = describe(&subjects(session.iter()), "", Some(project));
+                let start = session[0].time - lead;
+                ImportedEntry::intervals(start, session[session.len() - 1].time, &description)
+            }).collect()
+        }
+    }
+}
+
+/// Returns the author of new commits in a repository, from its `user.email`.
+pub fn default_author(repo: &Path) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(repo).args(["config", "user.email"]).output().ok()?;
+    let email = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !email.is_empty()).then_some(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn lines(entries: &[ImportedEntry]) -> Vec<String> {
+        entries.iter().map(|entry| format!("{} {}", entry.date.format("%Y.%m.%d"), entry.to_line())).collect()
+    }
+
+    fn commits() -> Vec<Commit> {
+        parse_log("\
+2025-03-04T09:10:00+00:00\tAnn\tann@example.com\tAdd parser
+2025-03-04T10:05:00+00:00\tAnn\tann@example.com\tFix parser
+2025-03-04T10:40:00+00:00\tAnn\tann@example.com\tFix parser
+2025-03-04T16:00:00+01:00\tAnn\tann@example.com\tRelease
+bogus\tAnn\tann@example.com\tBroken
+2025-03-05T08:30:00+00:00\tBob\tbob@example.com\tDocs
+", &Utc)
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let commits = commits();
+        assert_eq!(commits.len(), 5);
+        assert_eq!(commits[3].time, NaiveDate::from_ymd_opt(2025, 3, 4).unwrap().and_hms_opt(15, 0, 0).unwrap());
+        assert_eq!(commits[4].author, "Bob <bob@example.com>");
+    }
+
+    #[test]
+    fn test_entries_sessions() {
+        let estimate = Estimate::Sessions { gap: Duration::hours(2), lead: Duration::minutes(30) };
+        assert_eq!(lines(&entries(&commits(), estimate, "clinvoice")), vec![
+            "2025.03.04 08:40-10:40 = Add parser; Fix parser #clinvoice",
+            "2025.03.04 14:30-15:00 = Release #clinvoice",
+            "2025.03.05 08:00-08:30 = Docs #clinvoice",
+        ]);
+    }
+
+    #[test]
+    fn test_entries_per_commit() {
+        assert_eq!(lines(&entries(&commits(), Estimate::PerCommit(0.5), "clinvoice")), vec![
+            "2025.03.04 2h = Add parser; Fix parser; Release #clinvoice",
+            "2025.03.05 0.5h = Docs #clinvoice",
+        ]);
+    }
+}
@@ -0,0 +1,139 @@
+//! Harvest importer, reading either a CSV export or the v2 REST API.
+//!
+//! Harvest projects are mapped to `#tags` and notes become the entry description.
+//! When an entry has no notes, the task name is used instead.
+
+use super::{describe, ImportedEntry};
+use crate::config::Config;
+use crate::data::DateSelector;
+use crate::parse::parse_date;
+use chrono::NaiveDate;
+use std::env;
+use std::path::Path;
+
+const API_URL: &str = "https://api.harvestapp.com/v2/time_entries";
+
+// Parses the date formats used by Harvest exports.
+fn parse_harvest_date(text: &str) -> Option<NaiveDate> {
+    parse_date(text.trim())
+        .or_else(|| NaiveDate::parse_from_str(text.trim(), "%m/%d/%Y").ok())
+}
+
+/// Reads entries from a Harvest "Detailed time" CSV export.
+///
+/// The `Date`, `Hours` and `Notes` columns are required, `Project` and `Task` are optional.
+pub fn read_csv(path: &Path) -> Result<Vec<ImportedEntry>, String> {
+    let mut reader = csv::Reader::from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let headers = reader.headers()
+        .map_err(|e| format!("Failed to read header of {}: {}", path.display(), e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let date_col = column("Date").ok_or("Missing 'Date' column")?;
+    let hours_col = column("Hours").ok_or("Missing 'Hours' column")?;
+    let notes_col = column("Notes").ok_or("Missing 'Notes' column")?;
+    let project_col = column("Project");
+    let task_col = column("Task");
+
+    let mut entries = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let field = |col: usize| record.get(col).unwrap_or("");
+        let line = row + 2;
+
+        let Some(date) = parse_harvest_date(field(date_col)) else {
+            tracing::warn!("{}:{}: invalid date '{}'", path.display(), line, field(date_col));
+            continue;
+        };
+        let Ok(hours) = field(hours_col).trim().parse::<f64>() else {
+            tracing::warn!("{}:{}: invalid hours '{}'", path.display(), line, field(hours_col));
+            continue;
+        };
+        let project = project_col.map(field);
+        let task = task_col.map(field).unwrap_or("");
+        entries.push(ImportedEntry::hours(date, hours, &describe(field(notes_col), task, project)));
+    }
+    Ok(entries)
+}
+
+/// Fetches time entries from the Harvest API.
+///
+/// Credentials come from `import.harvest.account_id` and `import.harvest.token` in the
+/// configuration, or the `HARVEST_ACCOUNT_ID` and `HARVEST_ACCESS_TOKEN` environment variables.
+/// The request is limited to the span covered by `selector`, if any.
+pub fn fetch_api(config: Option<&Config>, selector: &DateSelector) -> Result<Vec<ImportedEntry>, String> {
+    let setting = |key: &str, var: &str| {
+        config.and_then(|c| c.get_string(&format!("import.harvest.{}", key)))
+            .or_else(|| env::var(var).ok())
+            .ok_or(format!("Harvest {} not set, use import.harvest.{} or {}", key, key, var))
+    };
+    let account_id = setting("account_id", "HARVEST_ACCOUNT_ID")?;
+    let token = setting("token", "HARVEST_ACCESS_TOKEN")?;
+
+    let mut url = API_URL.to_string();
+    if let (Some(from), Some(to)) = (
+        selector.ranges.iter().map(|r| r.start).min(),
+        selector.ranges.iter().map(|r| r.end).max(),
+    ) {
+        url = format!("{}?from={}&to={}", url, from.format("%Y-%m-%d"), to.format("%Y-%m-%d"));
+    }
+
+    let mut entries = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next {
+        tracing::debug!("GET {}", url);
+        let page: serde_json::Value = ureq::get(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("Harvest-Account-Id", &account_id)
+            .set("User-Agent", "clinvoice")
+            .call()
+            .map_err(|e| format!("Harvest API request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Harvest API response: {}", e))?;
+        entries.extend(parse_api_page(&page)?);
+        next = page["links"]["next"].as_str().map(|s| s.to_string());
+    }
+    Ok(entries)
+}
+
+// Converts one page of `time_entries` from the Harvest API.
+fn parse_api_page(page: &serde_json::Value) -> Result<Vec<ImportedEntry>, String> {
+    let items = page["time_entries"].as_array().ok_or("Harvest API response has no time_entries")?;
+    let mut entries = Vec::new();
+    for item in items {
+        let date = item["spent_date"].as_str().and_then(parse_harvest_date);
+        let hours = item["hours"].as_f64();
+        let (Some(date), Some(hours)) = (date, hours) else {
+            tracing::warn!("Skipping malformed Harvest entry {}", item["id"]);
+            continue;
+        };
+        let notes = item["notes"].as_str().unwrap_or("");
+        let task = item["task"]["name"].as_str().unwrap_or("");
+        let project = item["project"]["name"].as_str();
+        entries.push(ImportedEntry::hours(date, hours, &describe(notes, task, project)));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_api_page() {
+        let page = serde_json::json!({
+            "time_entries": [
+                { "id": 1, "spent_date": "2025-03-04", "hours": 1.5, "notes": "review",
+                  "project": { "name": "Web Site" }, "task": { "name": "Design" } },
+                { "id": 2, "spent_date": "2025-03-05", "hours": 2.0, "notes": null,
+                  "project": { "name": "Web Site" }, "task": { "name": "Design" } },
+                { "id": 3, "spent_date": "bogus", "hours": 2.0 }
+            ],
+            "links": { "next": null }
+        });
+        let entries = parse_api_page(&page).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].to_line(), "1.5h = review #web-site");
+        assert_eq!(entries[1].to_line(), "2h = Design #web-site");
+    }
+}
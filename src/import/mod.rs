@@ -0,0 +1,534 @@
+//! Importers that convert time tracked in other tools into `.cli` files.
+
+pub mod csv;
+pub mod git;
+pub mod harvest;
+pub mod timew;
+pub mod toggl;
+
+use crate::backup;
+use crate::clock;
+use crate::config::Config;
+use crate::data::{read_dates, timesheet_files, DateSelector, LoadOptions};
+use crate::parse::{format_directive, parse_date_line, FORMAT_VERSION};
+use crate::quiet;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use clap::Subcommand;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sources that entries can be imported from.
+#[derive(Subcommand, Debug)]
+pub enum ImportSource {
+    /// Import a CSV file, with the columns named in [import.csv]
+    Csv {
+        #[clap(help = "CSV file to read")]
+        file: String,
+        #[clap(long, help = "print the entries instead of writing .cli files")]
+        dry_run: bool,
+        #[clap(value_parser)]
+        dates: Vec<String>,
+    },
+    /// Draft entries from the commits of a git repository, into a file to review
+    Git {
+        #[clap(long, default_value = ".", help = "repository to read the commits of")]
+        repo: String,
+        #[clap(long, help = "only commits whose author name or email contains this [default: git user.email]")]
+        author: Option<String>,
+        #[clap(long, default_value_t = 120, help = "minutes between commits that start a new session")]
+        gap: i64,
+        #[clap(long, default_value_t = 30, help = "minutes of work counted before the first commit of a session")]
+        lead: i64,
+        #[clap(long, help = "count these hours for each commit, instead of sessions")]
+        hours_per_commit: Option<f64>,
+        #[clap(short, long, help = "file to write the draft entries to [default: git-review.txt in the data directory]")]
+        output: Option<String>,
+        #[clap(long, help = "print the entries instead of writing the review file")]
+        dry_run: bool,
+        #[clap(value_parser)]
+        dates: Vec<String>,
+    },
+    /// Import a Harvest CSV export, or fetch time entries from the Harvest API
+    Harvest {
+        #[clap(long, help = "read a Harvest CSV export instead of calling the API")]
+        csv: Option<String>,
+        #[clap(long, help = "print the entries instead of writing .cli files")]
+        dry_run: bool,
+        #[clap(value_parser)]
+        dates: Vec<String>,
+    },
+    /// Import `timew export` JSON or a timeclock file, as HH:MM-HH:MM entries
+    #[clap(alias = "timeclock")]
+    Timew {
+        #[clap(help = "file to read, or - for stdin")]
+        file: String,
+        #[clap(long, help = "print the entries instead of writing .cli files")]
+        dry_run: bool,
+        #[clap(value_parser)]
+        dates: Vec<String>,
+    },
+    /// Import a Toggl Track detailed report CSV, or fetch time entries from the Toggl API
+    Toggl {
+        #[clap(long, help = "read a Toggl detailed report CSV instead of calling the API")]
+        csv: Option<String>,
+        #[clap(long, help = "print the entries instead of writing .cli files")]
+        dry_run: bool,
+        #[clap(value_parser)]
+        dates: Vec<String>,
+    },
+}
+
+/// A single entry converted from an external source, ready to be written to a `.cli` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedEntry {
+    pub date: NaiveDate,
+    pub spec: String,
+    pub description: String,
+}
+
+impl ImportedEntry {
+    /// Creates an entry measured in decimal hours, e.g. `2.5h = description`.
+    pub fn hours(date: NaiveDate, hours: f64, description: &str) -> Self {
+        let hours = (hours * 100.0).round() / 100.0;
+        ImportedEntry {
+            date,
+            spec: format!("{}h", hours),
+            description: description.trim().to_string(),
+        }
+    }
+
+    /// Creates the entries for the time from `start` to `end`, to the minute, written as
+    /// `HH:MM-HH:MM` ranges, one for each day when the time runs past midnight.  There are
+    /// none if less than a minute passes.
+    pub fn intervals(start: NaiveDateTime, end: NaiveDateTime, description: &str) -> Vec<Self> {
+        let to_minute = |time: NaiveDateTime| time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(time);
+        let end = to_minute(end);
+        let mut entries = Vec::new();
+        let mut from = to_minute(start);
+        while from < end {
+            let midnight = from.date().succ_opt().map(|d| d.and_time(NaiveTime::MIN)).unwrap_or(end);
+            let to = end.min(midnight);
+            let until = if to == midnight { "24:00".to_string() } else { to.format("%H:%M").to_string() };
+            entries.push(ImportedEntry {
+                date: from.date(),
+                spec: format!("{}-{}", from.format("%H:%M"), until),
+                description: description.trim().to_string(),
+            });
+            from = to;
+        }
+        entries
+    }
+
+    /// Formats the entry as a `.cli` line, without indentation.
+    pub fn to_line(&self) -> String {
+        format!("{} = {}", self.spec, self.description)
+    }
+}
+
+/// Converts a project or client name into a `#tag` friendly slug.
+pub fn tag_from_name(name: &str) -> String {
+    let mut tag = String::new();
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() {
+            tag.extend(c.to_lowercase());
+        } else if !tag.ends_with('-') {
+            tag.push('-');
+        }
+    }
+    tag.trim_matches('-').to_string()
+}
+
+/// Builds a description from free-form notes and an optional project, which becomes a `#tag`.
+pub fn describe(notes: &str, fallback: &str, project: Option<&str>) -> String {
+    let notes = notes.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut description = if notes.is_empty() { fallback.trim().to_string() } else { notes };
+    if let Some(tag) = project.map(tag_from_name).filter(|t| !t.is_empty()) {
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description.push('#');
+        description.push_str(&tag);
+    }
+    description
+}
+
+/// Runs the selected importer and writes the resulting entries into the data directory.
+pub fn run(
+    source: ImportSource,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+
+    // Draft entries go to a file to review, instead of the timesheets.
+    let mut review: Option<PathBuf> = None;
+    let (entries, dry_run, dates) = match source {
+        ImportSource::Csv { file, dry_run, dates } => {
+            let result = csv::Mapping::from_config(config.as_ref())
+                .and_then(|mapping| csv::read_csv(Path::new(&file), &mapping));
+            (result, dry_run, dates)
+        }
+        ImportSource::Git { repo, author, gap, lead, hours_per_commit, output, dry_run, dates } => {
+            let repo = Path::new(&repo);
+            let author = author.or_else(|| git::default_author(repo));
+            let estimate = match hours_per_commit {
+                Some(hours) => git::Estimate::PerCommit(hours),
+                None => git::Estimate::Sessions { gap: Duration::minutes(gap), lead: Duration::minutes(lead) },
+            };
+            let project = fs::canonicalize(repo).ok()
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            let result = git::read_commits(repo, author.as_deref())
+                .map(|commits| git::entries(&commits, estimate, &project));
+            review = Some(output.map(PathBuf::from).unwrap_or_else(|| Path::new(directory).join(git::REVIEW_FILE)));
+            (result, dry_run, dates)
+        }
+        ImportSource::Harvest { csv, dry_run, dates } => {
+            let selector = selector_or_exit(&dates);
+            let result = match csv {
+                Some(path) => harvest::read_csv(Path::new(&path)),
+                None => harvest::fetch_api(config.as_ref(), &selector),
+            };
+            (result, dry_run, dates)
+        }
+        ImportSource::Timew { file, dry_run, dates } => (timew::read_file(Path::new(&file), clock::timezone(config.as_ref())), dry_run, dates),
+        ImportSource::Toggl { csv, dry_run, dates } => {
+            let selector = selector_or_exit(&dates);
+            let result = match csv {
+                Some(path) => toggl::read_csv(Path::new(&path)),
+                None => toggl::fetch_api(config.as_ref(), &selector),
+            };
+            (result, dry_run, dates)
+        }
+    };
+
+    let entries = entries.unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        std::process::exit(1);
+    });
+
+    let selector = selector_or_exit(&dates);
+    let entries: Vec<ImportedEntry> = entries.into_iter()
+        .filter(|e| selector.selected(&e.date))
+        .collect();
+
+    if dry_run {
+        if quiet::enabled() {
+            quiet::summary("imported", &[
+                ("entries", entries.len().to_string()),
+                ("written", "0".to_string()),
+                ("dry_run", "true".to_string()),
+            ]);
+        } else {
+            print!("{}", format_entries(&entries));
+        }
+        return;
+    }
+
+    if let Some(path) = review {
+        if let Err(err) = fs::write(&path, format_entries(&entries)) {
+            tracing::error!("Failed to write {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        tracing::info!("Wrote {} draft entries to {}, review them before adding them to a timesheet",
+            entries.len(), path.display());
+        quiet::summary("drafted", &[
+            ("entries", entries.len().to_string()),
+            ("file", path.display().to_string()),
+        ]);
+        return;
+    }
+
+    let options = LoadOptions::from_config(config.as_ref());
+    let files: Result<BTreeSet<PathBuf>, _> = entries.iter()
+        .map(|e| month_file(Path::new(directory), e.date, &options))
+        .collect();
+    if let Err(err) = files.and_then(|files| backup::snapshot(directory, &files.into_iter().collect::<Vec<_>>(), "import")) {
+        tracing::error!("Failed to save a snapshot before importing: {}", err);
+        std::process::exit(1);
+    }
+
+    match write_entries(Path::new(directory), &entries, &options) {
+        Ok(count) => {
+            tracing::info!("Imported {} of {} entries", count, entries.len());
+            quiet::summary("imported", &[
+                ("entries", entries.len().to_string()),
+                ("written", count.to_string()),
+            ]);
+        }
+        Err(err) => {
+            tracing::error!("Failed to write entries: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn selector_or_exit(dates: &[String]) -> DateSelector {
+    DateSelector::from_dates(dates).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        std::process::exit(1);
+    })
+}
+
+// Formats entries grouped by date, in the same layout they would be written.
+fn format_entries(entries: &[ImportedEntry]) -> String {
+    let mut by_date: BTreeMap<NaiveDate, Vec<&ImportedEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_date.entry(entry.date).or_default().push(entry);
+    }
+    let mut text = String::new();
+    for (date, entries) in by_date {
+        text.push_str(&format!("{}\n", date.format("%Y.%m.%d")));
+        for entry in entries {
+            text.push_str(&format!("    {}\n", entry.to_line()));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Writes entries into the timesheet of their month inside `directory`, as found by
+/// `month_file`.
+///
+/// New files declare the current format version.
+///
+/// Entries are merged into existing date sections when the file already exists,
+/// and lines that are already present under the same date are skipped, so that
+/// importing the same data twice does not double count anything.
+///
+/// # Returns
+///
+/// The number of entries that were actually added.
+pub fn write_entries(directory: &Path, entries: &[ImportedEntry], options: &LoadOptions) -> Result<usize, std::io::Error> {
+    let timesheets = writable_timesheets(directory, options)?;
+    let mut by_file: BTreeMap<PathBuf, BTreeMap<NaiveDate, Vec<String>>> = BTreeMap::new();
+    for entry in entries {
+        by_file.entry(pick_month_file(directory, &timesheets, entry.date))
+            .or_default()
+            .entry(entry.date)
+            .or_default()
+            .push(entry.to_line());
+    }
+
+    let mut added = 0;
+    for (file_path, days) in by_file {
+        let mut lines: Vec<String> = if file_path.exists() {
+            fs::read_to_string(&file_path)?.lines().map(|l| l.to_string()).collect()
+        } else {
+            vec![format_directive(FORMAT_VERSION)]
+        };
+
+        let before = added;
+        for (date, new_lines) in days {
+            added += merge_day(&mut lines, date, &new_lines);
+        }
+
+        if added != before {
+            tracing::info!("Writing {}", file_path.display());
+            let mut content = lines.join("\n");
+            content.push('\n');
+            fs::write(&file_path, content)?;
+        }
+    }
+    Ok(added)
+}
+
+/// Finds the timesheet that entries of `date` are written to: the `.cli` file in `directory`
+/// or its subdirectories, as far as `options` searches them, that holds `date`, or else
+/// another date of its month, and a new `YYYY-MM.cli` in `directory` otherwise.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the timesheets cannot be listed or read.
+pub fn month_file(directory: &Path, date: NaiveDate, options: &LoadOptions) -> Result<PathBuf, std::io::Error> {
+    Ok(pick_month_file(directory, &writable_timesheets(directory, options)?, date))
+}
+
+// Lists the plain `.cli` timesheets of `directory`, which entries can be written to, with
+// their dates.
+fn writable_timesheets(directory: &Path, options: &LoadOptions) -> Result<Vec<(PathBuf, Vec<NaiveDate>)>, std::io::Error> {
+    let options = LoadOptions { files: Vec::new(), extra_directories: Vec::new(), ..options.clone() };
+    let mut files = timesheet_files(&directory.to_string_lossy(), &options)?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "cli"));
+    files.sort();
+    files.into_iter()
+        .map(|path| {
+            let dates = read_dates(&path, &options)?.into_iter().map(|(_, date)| date).collect();
+            Ok((path, dates))
+        })
+        .collect()
+}
+
+fn pick_month_file(directory: &Path, timesheets: &[(PathBuf, Vec<NaiveDate>)], date: NaiveDate) -> PathBuf {
+    let same_month = |other: &NaiveDate| (other.year(), other.month()) == (date.year(), date.month());
+    timesheets.iter().find(|(_, dates)| dates.contains(&date))
+        .or_else(|| timesheets.iter().find(|(_, dates)| dates.iter().any(same_month)))
+        .map(|(path, _)| path.clone())
+        .unwrap_or_else(|| directory.join(format!("{:04}-{:02}.cli", date.year(), date.month())))
+}
+
+// Merges entry lines into the section for `date`, creating it if needed.
+fn merge_day(lines: &mut Vec<String>, date: NaiveDate, new_lines: &[String]) -> usize {
+    let section = find_section(lines, date);
+    let existing: Vec<String> = match section {
+        Some((start, end)) => lines[start..end].iter().map(|l| l.trim().to_string()).collect(),
+        None => Vec::new(),
+    };
+
+    let mut to_add: Vec<String> = Vec::new();
+    for line in new_lines {
+        if !existing.contains(line) && !to_add.contains(line) {
+            to_add.push(line.clone());
+        }
+    }
+    if to_add.is_empty() {
+        return 0;
+    }
+
+    let count = to_add.len();
+    let indented = to_add.into_iter().map(|l| format!("    {}", l));
+    match section {
+        Some((_, end)) => {
+            lines.splice(end..end, indented);
+        }
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(date.format("%Y.%m.%d").to_string());
+            lines.extend(indented);
+        }
+    }
+    count
+}
+
+/// Removes the last `line` from the section for `date`, and the section itself if it is
+/// left empty.
+///
+/// # Returns
+///
+/// `false` if the line was not found.
+pub fn remove_entry(lines: &mut Vec<String>, date: NaiveDate, line: &str) -> bool {
+    let Some((start, end)) = find_section(lines, date) else {
+        return false;
+    };
+    let Some(found) = (start..end).rev().find(|&i| lines[i].trim() == line) else {
+        return false;
+    };
+    lines.remove(found);
+    if lines[start..end - 1].iter().all(|l| l.trim().is_empty()) {
+        let mut first = start - 1;
+        if first > 0 && lines[first - 1].trim().is_empty() {
+            first -= 1;
+        }
+        lines.drain(first..end - 1);
+    }
+    true
+}
+
+// Finds the lines belonging to the last section for `date`, excluding trailing blank lines.
+fn find_section(lines: &[String], date: NaiveDate) -> Option<(usize, usize)> {
+    let mut section = None;
+    let mut current: Option<usize> = None;
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(Ok(found)) = parse_date_line(line.trim()) {
+            if let Some(start) = current.take() {
+                section = Some((start, index));
+            }
+            if found == date {
+                current = Some(index + 1);
+            }
+        }
+    }
+    if let Some(start) = current {
+        section = Some((start, lines.len()));
+    }
+    section.map(|(start, mut end)| {
+        while end > start && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        (start, end)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_tag_from_name() {
+        assert_eq!(tag_from_name("Website Redesign"), "website-redesign");
+        assert_eq!(tag_from_name("  ACME / Support  "), "acme-support");
+        assert_eq!(tag_from_name(""), "");
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe("fixed  bug\n", "Task", Some("Acme")), "fixed bug #acme");
+        assert_eq!(describe("", "Design", Some("Acme")), "Design #acme");
+        assert_eq!(describe("notes", "", None), "notes");
+    }
+
+    #[test]
+    fn test_merge_day_into_existing_section() {
+        let mut lines: Vec<String> = ["2025.01.01", "    1h = a", "", "2025.01.02", "    2h = b"]
+            .iter().map(|s| s.to_string()).collect();
+        let added = merge_day(&mut lines, date(2025, 1, 1), &["3h = c".to_string(), "1h = a".to_string()]);
+        assert_eq!(added, 1);
+        assert_eq!(lines, vec!["2025.01.01", "    1h = a", "    3h = c", "", "2025.01.02", "    2h = b"]);
+    }
+
+    #[test]
+    fn test_merge_day_after_weekday() {
+        let mut lines: Vec<String> = ["2025.01.01 Wednesday", "    1h = a", "", "2025.01.02 Thu", "    2h = b"]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(merge_day(&mut lines, date(2025, 1, 2), &["3h = c".to_string()]), 1);
+        assert_eq!(lines, vec!["2025.01.01 Wednesday", "    1h = a", "", "2025.01.02 Thu", "    2h = b", "    3h = c"]);
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        let mut lines: Vec<String> = ["2025.01.01", "    1h = a", "", "2025.01.02", "    2h = b", "    3h = c"]
+            .iter().map(|s| s.to_string()).collect();
+        assert!(remove_entry(&mut lines, date(2025, 1, 2), "3h = c"));
+        assert_eq!(lines, vec!["2025.01.01", "    1h = a", "", "2025.01.02", "    2h = b"]);
+        assert!(!remove_entry(&mut lines, date(2025, 1, 1), "3h = c"));
+        assert!(remove_entry(&mut lines, date(2025, 1, 2), "2h = b"));
+        assert_eq!(lines, vec!["2025.01.01", "    1h = a"]);
+    }
+
+    #[test]
+    fn test_merge_day_new_section() {
+        let mut lines: Vec<String> = vec!["2025.01.01".to_string(), "    1h = a".to_string()];
+        let added = merge_day(&mut lines, date(2025, 1, 3), &["4h = d".to_string()]);
+        assert_eq!(added, 1);
+        assert_eq!(lines, vec!["2025.01.01", "    1h = a", "", "2025.01.03", "    4h = d"]);
+    }
+
+    #[test]
+    fn test_write_entries_to_the_file_of_the_month() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("2025")).unwrap();
+        fs::write(dir.path().join("2025/03.cli"), "2025.03.03\n    1h = a\n").unwrap();
+        let options = LoadOptions { depth: usize::MAX, ..LoadOptions::default() };
+        let entries = vec![
+            ImportedEntry::hours(date(2025, 3, 3), 2.0, "b"),
+            ImportedEntry::hours(date(2025, 3, 4), 3.0, "c"),
+            ImportedEntry::hours(date(2025, 4, 1), 4.0, "d"),
+        ];
+        assert_eq!(write_entries(dir.path(), &entries, &options).unwrap(), 3);
+        assert_eq!(fs::read_to_string(dir.path().join("2025/03.cli")).unwrap(),
+            "2025.03.03\n    1h = a\n    2h = b\n\n2025.03.04\n    3h = c\n");
+        assert!(dir.path().join("2025-04.cli").exists());
+        assert!(!dir.path().join("2025-03.cli").exists());
+
+        // Without recursion, the subdirectory is not a timesheet of the month.
+        assert_eq!(month_file(dir.path(), date(2025, 3, 3), &LoadOptions::default()).unwrap(), dir.path().join("2025-03.cli"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Timewarrior and timeclock importer, reading the JSON written by `timew export`, or an
+//! hledger timeclock file.
+//!
+//! Each interval becomes an `HH:MM-HH:MM` entry, split at midnight.  Timewarrior tags and
+//! timeclock accounts become `#tags`, after the annotation or description.
+
+use super::{tag_from_name, ImportedEntry};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use std::io::Read;
+use std::path::Path;
+
+/// Reads entries from `timew export` JSON or a timeclock file, telling them apart by their
+/// content.  A path of `-` reads stdin, so that `timew export | clinvoice import timew -`
+/// works.  Timewarrior intervals are dated in `timezone`, or local time without one.
+///
+/// # Errors
+///
+/// Returns a `String` error if the file cannot be read, or is not valid JSON.
+pub fn read_file(path: &Path, timezone: Option<chrono_tz::Tz>) -> Result<Vec<ImportedEntry>, String> {
+    let mut text = String::new();
+    let result = if path == Path::new("-") {
+        std::io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        std::fs::read_to_string(path).map(|content| text = content)
+    };
+    result.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let entries = if text.trim_start().starts_with('[') {
+        match timezone {
+            Some(timezone) => parse_export(&text, &timezone),
+            None => parse_export(&text, &Local),
+        }.map_err(|e| format!("{}: {}", path.display(), e))?
+    } else {
+        parse_timeclock(&text, &path.display().to_string())
+    };
+    Ok(dedup(entries))
+}
+
+// Builds a description from an annotation and the tags.
+fn describe(text: &str, tags: &[&str]) -> String {
+    let mut words: Vec<String> = text.split_whitespace().map(|w| w.to_string()).collect();
+    words.extend(tags.iter().map(|tag| tag_from_name(tag)).filter(|tag| !tag.is_empty()).map(|tag| format!("#{}", tag)));
+    words.join(" ")
+}
+
+// Parses the intervals of `timew export`, which are in UTC, converting them to the time
+// zone `tz`.  Open intervals, still being tracked, are skipped.
+fn parse_export<Tz: TimeZone>(text: &str, tz: &Tz) -> Result<Vec<ImportedEntry>, String> {
+    let intervals: Vec<serde_json::Value> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let time = |value: &serde_json::Value| -> Option<NaiveDateTime> {
+        let utc = NaiveDateTime::parse_from_str(value.as_str()?, "%Y%m%dT%H%M%SZ").ok()?;
+        Some(Utc.from_utc_datetime(&utc).with_timezone(tz).naive_local())
+    };
+    let mut entries = Vec::new();
+    for interval in &intervals {
+        let Some(start) = time(&interval["start"]) else {
+            tracing::warn!("Skipping malformed Timewarrior interval {}", interval["id"]);
+            continue;
+        };
+        let Some(end) = time(&interval["end"]) else {
+            tracing::info!("Skipping open Timewarrior interval {}", interval["id"]);
+            continue;
+        };
+        let tags: Vec<&str> = interval["tags"].as_array().into_iter().flatten().filter_map(|t| t.as_str()).collect();
+        let annotation = interval["annotation"].as_str().unwrap_or("");
+        entries.extend(ImportedEntry::intervals(start, end, &describe(annotation, &tags)));
+    }
+    Ok(entries)
+}
+
+// Parses a timeclock date and time, like `2025/03/04 09:00:00`.
+fn parse_clock_time(date: &str, time: &str) -> Option<NaiveDateTime> {
+    let date = ["%Y/%m/%d", "%Y-%m-%d", "%Y.%m.%d"].iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())?;
+    let time = NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .ok()?;
+    Some(date.and_time(time))
+}
+
+// Parses a timeclock file, where `i DATE TIME ACCOUNT  DESCRIPTION` clocks in and
+// `o DATE TIME` clocks out.  The account and description are separated by two spaces.
+fn parse_timeclock(text: &str, name: &str) -> Vec<ImportedEntry> {
+    let mut entries = Vec::new();
+    let mut clocked_in: Option<(NaiveDateTime, String)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with([';', '#', '*']) {
+            continue;
+        }
+        let mut fields = line.splitn(4, ' ');
+        let (code, date, time, rest) = (fields.next(), fields.next(), fields.next(), fields.next().unwrap_or(""));
+        let Some(at) = date.zip(time).and_then(|(date, time)| parse_clock_time(date, time)) else {
+            tracing::warn!("{}:{}: invalid timeclock line '{}'", name, index + 1, line);
+            continue;
+        };
+        match code {
+            Some("i" | "I") => {
+                if clocked_in.is_some() {
+                    tracing::warn!("{}:{}: clocked in again without clocking out", name, index + 1);
+                }
+                let (account, description) = rest.trim().split_once("  ").unwrap_or((rest.trim(), ""));
+                clocked_in = Some((at, describe(description, &[account])));
+            }
+            Some("o" | "O") => match clocked_in.take() {
+                Some((start, description)) => entries.extend(ImportedEntry::intervals(start, at, &description)),
+                None => tracing::warn!("{}:{}: clocked out without clocking in", name, index + 1),
+            },
+            _ => tracing::warn!("{}:{}: unknown timeclock line '{}'", name, index + 1, line),
+        }
+    }
+    if clocked_in.is_some() {
+        tracing::info!("{}: skipping the last interval, still clocked in", name);
+    }
+    entries
+}
+
+// Drops entries repeated in the input, like intervals exported twice.  Entries already in
+// the timesheets are skipped when writing.
+fn dedup(entries: Vec<ImportedEntry>) -> Vec<ImportedEntry> {
+    let mut unique: Vec<ImportedEntry> = Vec::new();
+    for entry in entries {
+        if !unique.contains(&entry) {
+            unique.push(entry);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(entries: &[ImportedEntry]) -> Vec<String> {
+        entries.iter().map(|entry| format!("{} {}", entry.date.format("%Y.%m.%d"), entry.to_line())).collect()
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let text = r#"[
+            {"id":3,"start":"20250304T090000Z","end":"20250304T103015Z","tags":["Web Site","review"]},
+            {"id":2,"start":"20250304T223000Z","end":"20250305T010000Z","tags":["release"],"annotation":"ship 1.0"},
+            {"id":1,"start":"20250306T090000Z","tags":["running"]}
+        ]"#;
+        assert_eq!(lines(&parse_export(text, &Utc).unwrap()), vec![
+            "2025.03.04 09:00-10:30 = #web-site #review",
+            "2025.03.04 22:30-24:00 = ship 1.0 #release",
+            "2025.03.05 00:00-01:00 = ship 1.0 #release",
+        ]);
+        assert!(parse_export("[{", &Utc).is_err());
+    }
+
+    #[test]
+    fn test_parse_timeclock() {
+        let text = "; work log\n\
+            i 2025/03/04 09:00:00 acme:web  fixed the build\n\
+            o 2025/03/04 11:45:00\n\
+            o 2025/03/04 12:00:00\n\
+            i 2025-03-05 13:00 acme\n\
+            o 2025-03-05 14:30\n\
+            i 2025/03/06 09:00:00 acme  still going\n";
+        assert_eq!(lines(&parse_timeclock(text, "work.timeclock")), vec![
+            "2025.03.04 09:00-11:45 = fixed the build #acme-web",
+            "2025.03.05 13:00-14:30 = #acme",
+        ]);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let text = "i 2025/03/04 09:00 acme\no 2025/03/04 10:00\ni 2025/03/04 09:00 acme\no 2025/03/04 10:00\n";
+        assert_eq!(dedup(parse_timeclock(text, "twice.timeclock")).len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+//! Toggl Track importer, reading either a detailed report CSV export or the v9 REST API.
+//!
+//! Toggl projects become a prefix of the description, like `Web Site: review`.  Entries
+//! without a description are logged under the project name alone.
+
+use super::ImportedEntry;
+use crate::clock;
+use crate::config::Config;
+use crate::data::DateSelector;
+use crate::parse::parse_date;
+use crate::upload::base64_encode;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+const API_URL: &str = "https://api.track.toggl.com/api/v9/me";
+
+/// Builds a description from a Toggl description and project, which becomes its prefix.
+pub fn describe(description: &str, project: Option<&str>) -> String {
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    match project.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(project) if description.is_empty() => project.to_string(),
+        Some(project) => format!("{}: {}", project, description),
+        None => description,
+    }
+}
+
+// Parses a duration written as `H:MM:SS`, as in Toggl exports, into hours.
+fn parse_duration(text: &str) -> Option<f64> {
+    let parts: Vec<u32> = text.trim().split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match parts[..] {
+        [hours, minutes, seconds] => Some(hours as f64 + minutes as f64 / 60.0 + seconds as f64 / 3600.0),
+        [hours, minutes] => Some(hours as f64 + minutes as f64 / 60.0),
+        _ => None,
+    }
+}
+
+/// Reads entries from a Toggl "Detailed report" CSV export.
+///
+/// The `Start date`, `Duration` and `Description` columns are required, `Project` is optional.
+pub fn read_csv(path: &Path) -> Result<Vec<ImportedEntry>, String> {
+    let mut reader = csv::Reader::from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let headers = reader.headers()
+        .map_err(|e| format!("Failed to read header of {}: {}", path.display(), e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let date_col = column("Start date").ok_or("Missing 'Start date' column")?;
+    let duration_col = column("Duration").ok_or("Missing 'Duration' column")?;
+    let description_col = column("Description").ok_or("Missing 'Description' column")?;
+    let project_col = column("Project");
+
+    let mut entries = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let field = |col: usize| record.get(col).unwrap_or("");
+        let line = row + 2;
+
+        let Some(date) = parse_date(field(date_col).trim()) else {
+            tracing::warn!("{}:{}: invalid date '{}'", path.display(), line, field(date_col));
+            continue;
+        };
+        let Some(hours) = parse_duration(field(duration_col)) else {
+            tracing::warn!("{}:{}: invalid duration '{}'", path.display(), line, field(duration_col));
+            continue;
+        };
+        let project = project_col.map(field);
+        entries.push(ImportedEntry::hours(date, hours, &describe(field(description_col), project)));
+    }
+    Ok(entries)
+}
+
+/// Fetches time entries from the Toggl API.
+///
+/// The API token comes from `import.toggl.token` in the configuration, or the
+/// `TOGGL_API_TOKEN` environment variable.  The request is limited to the span covered by
+/// `selector`, if any, otherwise Toggl returns the entries of the last days.
+pub fn fetch_api(config: Option<&Config>, selector: &DateSelector) -> Result<Vec<ImportedEntry>, String> {
+    let token = config.and_then(|c| c.get_string("import.toggl.token"))
+        .or_else(|| env::var("TOGGL_API_TOKEN").ok())
+        .ok_or("Toggl token not set, use import.toggl.token or TOGGL_API_TOKEN")?;
+
+    // Toggl takes the token as the user name, with the password `api_token`.  It goes into
+    // a header rather than the URL, which ends up in error messages.
+    let authorization = format!("Basic {}", base64_encode(format!("{}:api_token", token).as_bytes()));
+    let get = |path: &str| -> Result<serde_json::Value, String> {
+        tracing::debug!("GET {}{}", API_URL, path);
+        ureq::get(&format!("{}{}", API_URL, path))
+            .set("Authorization", &authorization)
+            .set("User-Agent", "clinvoice")
+            .call()
+            .map_err(|e| format!("Toggl API request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Toggl API response: {}", e))
+    };
+
+    let mut path = "/time_entries".to_string();
+    if let (Some(from), Some(to)) = (
+        selector.ranges.iter().map(|r| r.start).min(),
+        selector.ranges.iter().map(|r| r.end).max(),
+    ) {
+        // The end date is exclusive.
+        let to = to + Duration::days(1);
+        path = format!("{}?start_date={}&end_date={}", path, from.format("%Y-%m-%d"), to.format("%Y-%m-%d"));
+    }
+    let items = get(&path)?;
+    let projects = get("/projects")?;
+    parse_api_entries(&items, &project_names(&projects), clock::timezone(config))
+}
+
+// Maps project ids to names, from the `projects` of the Toggl API.
+fn project_names(projects: &serde_json::Value) -> HashMap<i64, String> {
+    projects.as_array().into_iter().flatten()
+        .filter_map(|p| Some((p["id"].as_i64()?, p["name"].as_str()?.to_string())))
+        .collect()
+}
+
+// Converts the `time_entries` of the Toggl API, dated in `timezone`, or local time without
+// one.  Running entries, which have a negative duration, are skipped.
+fn parse_api_entries(items: &serde_json::Value, projects: &HashMap<i64, String>, timezone: Option<Tz>) -> Result<Vec<ImportedEntry>, String> {
+    let items = items.as_array().ok_or("Toggl API response is not a list of time entries")?;
+    let mut entries = Vec::new();
+    for item in items {
+        let date: Option<NaiveDate> = item["start"].as_str()
+            .and_then(|start| DateTime::parse_from_rfc3339(start).ok())
+            .map(|start| clock::at(timezone, start.with_timezone(&Utc)).date_naive());
+        let seconds = item["duration"].as_i64();
+        let (Some(date), Some(seconds)) = (date, seconds) else {
+            tracing::warn!("Skipping malformed Toggl entry {}", item["id"]);
+            continue;
+        };
+        if seconds < 0 {
+            tracing::info!("Skipping running Toggl entry {}", item["id"]);
+            continue;
+        }
+        let description = item["description"].as_str().unwrap_or("");
+        let project = item["project_id"].as_i64().and_then(|id| projects.get(&id)).map(|p| p.as_str());
+        entries.push(ImportedEntry::hours(date, seconds as f64 / 3600.0, &describe(description, project)));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe("fixed  bug", Some("Web Site")), "Web Site: fixed bug");
+        assert_eq!(describe("", Some("Web Site")), "Web Site");
+        assert_eq!(describe("fixed bug", Some(" ")), "fixed bug");
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("toggl.csv");
+        std::fs::write(&path, "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags\n\
+            Ann,ann@example.com,Acme,Web Site,,review,Yes,2025-03-04,09:00:00,2025-03-04,10:30:00,01:30:00,\n\
+            Ann,ann@example.com,,,,standup,No,2025-03-05,09:00:00,2025-03-05,09:20:00,00:20:00,\n\
+            Ann,ann@example.com,,,,typo,No,someday,09:00:00,2025-03-05,09:20:00,00:20:00,\n").unwrap();
+        let lines: Vec<String> = read_csv(&path).unwrap().iter()
+            .map(|entry| format!("{} {}", entry.date.format("%Y.%m.%d"), entry.to_line()))
+            .collect();
+        assert_eq!(lines, vec!["2025.03.04 1.5h = Web Site: review", "2025.03.05 0.33h = standup"]);
+    }
+
+    #[test]
+    fn test_parse_api_entries() {
+        let projects = project_names(&serde_json::json!([{ "id": 7, "name": "Web Site" }]));
+        let items = serde_json::json!([
+            { "id": 1, "start": "2025-03-04T12:00:00Z", "duration": 5400, "description": "review", "project_id": 7 },
+            { "id": 2, "start": "2025-03-05T12:00:00Z", "duration": 3600, "description": null, "project_id": 7 },
+            { "id": 3, "start": "2025-03-06T12:00:00Z", "duration": -1741262400, "description": "running" },
+            { "id": 4, "start": "bogus", "duration": 60 }
+        ]);
+        let entries = parse_api_entries(&items, &projects, None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].date, NaiveDate::from_ymd_opt(2025, 3, 4).unwrap());
+        assert_eq!(entries[0].to_line(), "1.5h = Web Site: review");
+        assert_eq!(entries[1].to_line(), "1h = Web Site");
+
+        // Dated in the configured timezone, not the machine's.
+        let late = serde_json::json!([{ "id": 5, "start": "2025-03-04T23:30:00Z", "duration": 600, "description": "late" }]);
+        let tokyo = clock::parse_timezone("Asia/Tokyo").ok();
+        assert_eq!(parse_api_entries(&late, &projects, tokyo).unwrap()[0].date, NaiveDate::from_ymd_opt(2025, 3, 5).unwrap());
+    }
+}
@@ -1,11 +1,94 @@
 use std::collections::HashMap;
+use std::env;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 use fs2::FileExt;
 
-use colored::Color;
-use crate::color::DynamicColorize;
+use crate::archive::ARCHIVE_DIR;
+use crate::color::{theme, DynamicColorize};
+use crate::config::Config;
+
+/// Returns the path of the index file for a data directory.
+///
+/// The file name comes from `index.file` in the configuration, defaulting to `.index`.
+/// With `index.location = "xdg"`, the file is kept in `$XDG_DATA_HOME/clinvoice/<project>/`
+/// instead of the data directory, where `<project>` is `index.project`, or the name of the
+/// data directory.  The `archive/` of a data directory is kept in `<project>/archive/`.
+pub fn index_file_path(config: Option<&Config>, directory: &str) -> PathBuf {
+    let index_file_name = config
+        .and_then(|c| c.get_string("index.file"))
+        .unwrap_or(".index".to_string());
+    match config.and_then(|c| c.get_string("index.location")).as_deref() {
+        Some("xdg") => {
+            let project = config.and_then(|c| c.get_string("index.project")).map(PathBuf::from)
+                .or_else(|| project_name(directory))
+                .unwrap_or(PathBuf::from("default"));
+            xdg_data_home().join("clinvoice").join(project).join(index_file_name)
+        }
+        _ => Path::new(directory).join(index_file_name),
+    }
+}
+
+// Names the project of a data directory after it, and the archive of a data directory after
+// both, so that the archives of different projects do not share an index.
+fn project_name(directory: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(directory).ok()?;
+    let name = Path::new(path.file_name()?);
+    match path.parent().and_then(Path::file_name) {
+        Some(parent) if name == Path::new(ARCHIVE_DIR) => Some(Path::new(parent).join(name)),
+        _ => Some(name.to_path_buf()),
+    }
+}
+
+// Returns `$XDG_DATA_HOME`, or `~/.local/share` if it is not set to an absolute path.
+fn xdg_data_home() -> PathBuf {
+    match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(path) if path.is_absolute() => path,
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".local").join("share"),
+    }
+}
+
+/// How long `Index::new` waits for another process to release the index lock.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+const LOCK_RETRY: Duration = Duration::from_millis(100);
+
+// The temporary file `save` writes before renaming it over the index.
+fn temp_file_path(file_path: &Path) -> PathBuf {
+    file_path.with_extension("tmp")
+}
+
+// The file recording the pid of the process holding the index lock.
+fn pid_file_path(file_path: &Path) -> PathBuf {
+    let name = file_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    file_path.with_file_name(format!("{}.lock", name))
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// Checks if a process is running, or returns `None` if that cannot be told on this platform.
+fn process_running(pid: u32) -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+// Prefix of the index line item recording the state of a sequence.
+const STATE_PREFIX: &str = "state=";
+
+/// State of a sequence generated by clinvoice.
+pub const ISSUED: &str = "issued";
+/// State of a sequence set aside with `Index::reserve`.
+pub const RESERVED: &str = "reserved";
+/// State of a sequence cancelled with `Index::void`.
+pub const VOIDED: &str = "voided";
 
 /// Manages invoice sequence numbers and their associated dates.
 ///
@@ -14,13 +97,15 @@ use crate::color::DynamicColorize;
 pub struct Index {
     file_path: PathBuf,
     sequences: HashMap<u32, Vec<String>>,
-    lock_file: File, // Held for exclusive lock
+    states: HashMap<u32, String>, // Sequences not issued normally, like "reserved"
+    lock_file: Option<File>, // Held for exclusive lock, None when read-only
 }
 
 impl Index {
     /// Creates a new `Index` instance, loading existing sequences from the specified file.
     ///
-    /// It acquires an exclusive lock on the index file to prevent concurrent access.
+    /// It acquires an exclusive lock on the index file to prevent concurrent access, waiting
+    /// up to `LOCK_TIMEOUT` for another process to release it.
     ///
     /// # Arguments
     ///
@@ -30,6 +115,33 @@ impl Index {
     ///
     /// Returns an `io::Error` if the file cannot be opened, locked, or read.
     pub fn new(file_path: &Path) -> Result<Self, io::Error> {
+        Self::with_timeout(file_path, LOCK_TIMEOUT)
+    }
+
+    /// Creates a new `Index` like `new`, creating the directory of the file first if needed,
+    /// like the one of `index.location = "xdg"` on a new machine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the directory cannot be created, or as `new` does.
+    pub fn create(file_path: &Path) -> Result<Self, io::Error> {
+        if let Some(parent) = file_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        Self::new(file_path)
+    }
+
+    /// Creates a new `Index` like `new`, waiting up to `timeout` for the lock.
+    ///
+    /// The process holding the lock records its pid in a `.lock` file next to the index.
+    /// A `.lock` file left by a process that is no longer running, and a temporary file
+    /// left by a `save` that did not finish, are removed with a warning.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file cannot be opened or read, or if the lock is still
+    /// held by another process after `timeout`.
+    pub fn with_timeout(file_path: &Path, timeout: Duration) -> Result<Self, io::Error> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -37,34 +149,100 @@ impl Index {
             .truncate(false)
             .open(file_path)?;
 
-        file.lock_exclusive()?;
+        let pid_path = pid_file_path(file_path);
+        let started = Instant::now();
+        let mut warned = false;
+        while let Err(err) = file.try_lock_exclusive() {
+            if err.kind() != fs2::lock_contended_error().kind() {
+                return Err(err);
+            }
+            let holder = read_pid(&pid_path);
+            if !warned {
+                let who = holder.map_or("another process".to_string(), |pid| format!("process {}", pid));
+                tracing::warn!("Index {} is locked by {}, waiting", file_path.display(), who);
+                if holder.and_then(process_running) == Some(false) {
+                    tracing::warn!("{}", "The process holding the lock is no longer running, the lock may be on a network file system"
+                        .err_colored(theme().warning));
+                }
+                warned = true;
+            }
+            if started.elapsed() >= timeout {
+                return Err(io::Error::new(io::ErrorKind::TimedOut,
+                    format!("timed out waiting for the lock on {}", file_path.display())));
+            }
+            thread::sleep(LOCK_RETRY);
+        }
+
+        // We hold the lock, so anything left by an earlier process is stale.
+        if let Some(pid) = read_pid(&pid_path).filter(|pid| *pid != process::id()) {
+            tracing::warn!("Recovering stale lock on {} left by process {}",
+                file_path.display(), pid.to_string().err_colored(theme().warning));
+        }
+        fs::write(&pid_path, format!("{}\n", process::id()))?;
+        let temp_path = temp_file_path(file_path);
+        if temp_path.exists() {
+            tracing::warn!("Removing {} left by an interrupted save",
+                temp_path.display().to_string().err_colored(theme().warning));
+            fs::remove_file(&temp_path)?;
+        }
 
         let mut index = Index {
             file_path: file_path.to_path_buf(),
             sequences: HashMap::new(),
-            lock_file: file,
+            states: HashMap::new(),
+            lock_file: Some(file),
         };
 
         index.load()?;
         Ok(index)
     }
 
+    /// Creates a read-only `Index`, which never creates, locks, or writes the index file.
+    ///
+    /// A missing index file results in an empty index.  Sequences can still be looked up
+    /// and allocated in memory, but `save` will fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file exists but cannot be read.
+    pub fn open_read_only(file_path: &Path) -> Result<Self, io::Error> {
+        let mut index = Index {
+            file_path: file_path.to_path_buf(),
+            sequences: HashMap::new(),
+            states: HashMap::new(),
+            lock_file: None,
+        };
+        if file_path.exists() {
+            index.load()?;
+        }
+        Ok(index)
+    }
+
     // Loads sequence numbers and their associated dates from the index file.
+    //
+    // A `state=...` item on a line records the state of the sequence, instead of a date.
     fn load(&mut self) -> Result<(), io::Error> {
         self.sequences.clear();
+        self.states.clear();
         let file = BufReader::new(File::open(&self.file_path)?);
         for line in file.lines() {
             let line = line?;
             let parts: Vec<&str> = line.splitn(2, ' ').collect();
             if parts.len() == 2 {
                 if let Ok(sequence) = parts[0].parse::<u32>() {
-                    let dates: Vec<String> = parts[1].split_whitespace().map(|s| s.to_string()).collect();
+                    let mut dates = Vec::new();
+                    for item in parts[1].split_whitespace() {
+                        match item.strip_prefix(STATE_PREFIX) {
+                            Some(state) => { self.states.insert(sequence, state.to_string()); }
+                            None => dates.push(item.to_string()),
+                        }
+                    }
                     self.sequences.insert(sequence, dates);
                 } else {
-                    tracing::warn!("Invalid sequence number in index file: {}", line.err_colored(Color::Yellow));
+                    tracing::warn!("Invalid sequence number in index file: {}", line.err_colored(theme().warning));
                 }
             } else {
-                tracing::warn!("Invalid line in index file: {}", line.err_colored(Color::Yellow));
+                tracing::warn!("Invalid line in index file: {}", line.err_colored(theme().warning));
             }
         }
         Ok(())
@@ -78,7 +256,10 @@ impl Index {
     ///
     /// Returns an `io::Error` if the file cannot be written to or renamed.
     pub fn save(&self) -> Result<(), io::Error> {
-        let temp_path = self.file_path.with_extension("tmp");
+        if self.lock_file.is_none() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "index was opened read-only"));
+        }
+        let temp_path = temp_file_path(&self.file_path);
         let mut temp_file = File::create(&temp_path)?;
 
         tracing::debug!("temp index: {}", temp_path.display());
@@ -87,8 +268,11 @@ impl Index {
         sorted_sequences.sort_by_key(|(seq, _)| *seq);
 
         for (sequence, dates) in sorted_sequences {
-            tracing::debug!("INDEX {} {}", sequence, dates.join(" "));
-            writeln!(temp_file, "{} {}", sequence, dates.join(" "))?;
+            let items: Vec<String> = dates.iter().cloned()
+                .chain(self.states.get(sequence).map(|state| format!("{}{}", STATE_PREFIX, state)))
+                .collect();
+            tracing::debug!("INDEX {} {}", sequence, items.join(" "));
+            writeln!(temp_file, "{} {}", sequence, items.join(" "))?;
         }
 
         fs::rename(&temp_path, &self.file_path)?;
@@ -107,9 +291,83 @@ impl Index {
     /// The added sequence number.
     pub fn add_sequence(&mut self, sequence: u32, dates: &[String]) -> u32 {
         self.sequences.insert(sequence, dates.to_vec());
+        self.states.remove(&sequence);
         sequence
     }
 
+    /// Reserves the next `count` sequence numbers, for invoices issued elsewhere.
+    ///
+    /// Reserved sequences have no dates, and are never returned by `find_sequence`, but
+    /// can be used with `add_sequence`.
+    ///
+    /// # Returns
+    ///
+    /// The reserved sequence numbers.
+    pub fn reserve(&mut self, count: u32) -> Vec<u32> {
+        let first = self.sequences.keys().max().map_or(1, |&max_seq| max_seq + 1);
+        let reserved: Vec<u32> = (first..first + count).collect();
+        for seq in &reserved {
+            self.sequences.insert(*seq, Vec::new());
+            self.states.insert(*seq, RESERVED.to_string());
+        }
+        reserved
+    }
+
+    /// Marks a sequence as voided.  It keeps its dates, as a record of the cancelled invoice,
+    /// but `find_sequence` no longer returns it, so the same dates get a new number.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the sequence is not in the index.
+    pub fn void(&mut self, sequence: u32) -> bool {
+        if !self.sequences.contains_key(&sequence) {
+            return false;
+        }
+        self.states.insert(sequence, VOIDED.to_string());
+        true
+    }
+
+    /// Returns the state of a sequence, "issued" unless it was recorded otherwise, or `None`
+    /// if the sequence is not in the index.
+    pub fn state(&self, sequence: u32) -> Option<&str> {
+        if !self.sequences.contains_key(&sequence) {
+            return None;
+        }
+        Some(self.states.get(&sequence).map_or(ISSUED, |state| state.as_str()))
+    }
+
+    /// Returns the dates recorded for a sequence, or `None` if it is not in the index.
+    pub fn dates(&self, sequence: u32) -> Option<&[String]> {
+        self.sequences.get(&sequence).map(|dates| dates.as_slice())
+    }
+
+    /// Puts a sequence back to the dates and state it had before, or removes it from the
+    /// index when `dates` is `None`.
+    pub fn restore(&mut self, sequence: u32, dates: Option<Vec<String>>, state: Option<String>) {
+        match dates {
+            Some(dates) => {
+                self.sequences.insert(sequence, dates);
+                match state.filter(|state| state != ISSUED) {
+                    Some(state) => self.states.insert(sequence, state),
+                    None => self.states.remove(&sequence),
+                };
+            }
+            None => {
+                self.sequences.remove(&sequence);
+                self.states.remove(&sequence);
+            }
+        }
+    }
+
+    /// Returns all sequence numbers and their associated dates, ordered by sequence.
+    pub fn sequences(&self) -> Vec<(u32, Vec<String>)> {
+        let mut sorted: Vec<(u32, Vec<String>)> = self.sequences.iter()
+            .map(|(seq, dates)| (*seq, dates.clone()))
+            .collect();
+        sorted.sort_by_key(|(seq, _)| *seq);
+        sorted
+    }
+
     /// Finds an existing sequence number for a given set of dates, or generates a new one.
     ///
     /// If a matching set of dates is found, its sequence number is returned.
@@ -127,6 +385,9 @@ impl Index {
         sorted_input_dates.sort();
 
         for (seq, stored_dates) in &self.sequences {
+            if self.states.contains_key(seq) {
+                continue;
+            }
             let mut sorted_stored_dates = stored_dates.clone();
             sorted_stored_dates.sort();
             if sorted_stored_dates == sorted_input_dates {
@@ -144,9 +405,12 @@ impl Index {
 impl Drop for Index {
     /// Releases the exclusive lock on the index file when the `Index` instance is dropped.
     fn drop(&mut self) {
-      if let Err(e) = fs2::FileExt::unlock(&self.lock_file) {
-          tracing::error!("Failed to unlock index file: {}",
-              format!("{}", e).err_colored(Color::Red));
+      if let Some(lock_file) = &self.lock_file {
+          let _ = fs::remove_file(pid_file_path(&self.file_path));
+          if let Err(e) = fs2::FileExt::unlock(lock_file) {
+              tracing::error!("Failed to unlock index file: {}",
+                  format!("{}", e).err_colored(theme().error));
+          }
       }
     }
 }
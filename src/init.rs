@@ -0,0 +1,287 @@
+//! Scaffolding a new data directory: the configuration, starter templates, an empty index,
+//! and an example timesheet.
+
+use crate::parse::{format_directive, FORMAT_VERSION};
+use crate::quiet;
+use chrono::{Datelike, Local, NaiveDate};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::fs;
+use std::path::Path;
+
+/// The configuration written by `init`, in the data directory.
+pub const CONFIG_FILE: &str = "clinvoice.toml";
+
+const TEXT_TEMPLATE: &str = r#"INVOICE {{ sequence }}
+
+From:
+    {{ issuer_company }}
+{% if issuer_address %}    {{ issuer_address }}
+{% endif %}
+To:
+{% if client_company %}    {{ client_company }}
+{% endif %}{% if client_address %}    {{ client_address }}
+{% endif %}
+Invoice date:  {{ today | date(format="%B %e, %Y") }}
+Period:        {{ period_start | date(format="%B %e, %Y") }} to {{ period_end | date(format="%B %e, %Y") }}
+Payment due:   {{ due_date | date(format="%B %e, %Y") }}
+
+No. | Date       | Description                                        | Hours | Cost
+--- | ---------- | -------------------------------------------------- | ----- | ----------
+{% for day in days %}{{ day.index | left(width=4) }}| {{ day.date | date(format="%Y-%m-%d") }} | {{ day.description | left(width=50) }} | {{ day.hours | right(width=5) }} | {{ day.cost | decimal(precision=2) | right(width=10) }}
+{% endfor %}
+Subtotal:  {{ subtotal_amount | decimal(precision=2) | right(width=10) }} {{ contract_currency }}
+{% if tax_name %}{{ tax_name }} ({{ tax_percent }}%): {{ tax_amount | decimal(precision=2) | right(width=10) }} {{ contract_currency }}
+{% endif %}Total:     {{ total_amount | decimal(precision=2) | right(width=10) }} {{ contract_currency }}
+"#;
+
+const LATEX_TEMPLATE: &str = r#"\documentclass[a4paper]{article}
+\usepackage[margin=2cm]{geometry}
+
+\begin{document}
+\thispagestyle{empty}
+
+\begin{center}
+\huge{\textbf{INVOICE {{ sequence }}}}
+\end{center}
+
+\subsection*{From}
+{{ issuer_company }}{% if issuer_address %} \\ {{ issuer_address }}{% endif %}
+
+\subsection*{To}
+{% if client_company %}{{ client_company }}{% endif %}{% if client_address %} \\ {{ client_address }}{% endif %}
+
+\subsection*{Details}
+\begin{tabular}{l l}
+Invoice date & {{ today | date(format="%B %e, %Y") }} \\
+Period & {{ period_start | date(format="%B %e, %Y") }} to {{ period_end | date(format="%B %e, %Y") }} \\
+Payment due & {{ due_date | date(format="%B %e, %Y") }} \\
+\end{tabular}
+
+\vspace{0.5cm}
+
+\begin{tabular}{ r l p{0.5\textwidth} r r }
+\hline
+\textbf{No.} & \textbf{Date} & \textbf{Description} & \textbf{Hours} & \textbf{Cost} \\
+\hline
+{% for day in days %}
+{{ day.index }} & {{ day.date | date(format="%Y-%m-%d") }} & {{ day.description }} & {{ day.hours }} & {{ day.cost | decimal(precision=2) }} \\
+{% endfor %}\hline
+\multicolumn{4}{r}{\textbf{Subtotal}} & {{ subtotal_amount | decimal(precision=2) }} \\
+{% if tax_name %}\multicolumn{4}{r}{ {{ tax_name }} ({{ tax_percent }}\%) } & {{ tax_amount | decimal(precision=2) }} \\
+{% endif %}\hline
+\multicolumn{4}{r}{\textbf{Total}} & {{ total_amount | decimal(precision=2) }} {{ contract_currency }} \\
+\end{tabular}
+
+\end{document}
+"#;
+
+/// The answers to the questions asked by `init`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answers {
+    pub company: String,
+    pub hourly_rate: f64,
+    pub currency: String,
+}
+
+impl Default for Answers {
+    fn default() -> Self {
+        Answers { company: "My Company".to_string(), hourly_rate: 100.0, currency: "USD".to_string() }
+    }
+}
+
+// Quotes a string for TOML.
+fn quote(text: &str) -> String {
+    toml::Value::String(text.to_string()).to_string()
+}
+
+/// Returns the configuration written by `init`, with the optional settings commented out.
+pub fn config(answers: &Answers) -> String {
+    format!(r#"# clinvoice configuration, see the README for every setting.
+
+[issuer]
+    company     = {company}
+    # address     = "1 Main Ave | Ottawa, ON, K2C 4E6 | Canada"
+    # phone       = "613-555-0100"
+
+[client]
+    # short_name  = "BNL"
+    # company     = "Buy N Large"
+    # address     = "1 Buy n Large Plaza | Largefield, NJ, 08012 | USA"
+
+[contract]
+    hourly_rate           = {rate:?}
+    currency              = {currency}
+    payment_days          = 30
+    # cap_hours_per_day     = 10
+    # cap_hours_per_invoice = 64
+
+# [tax]
+#     name        = "VAT"
+#     percent     = 10
+
+[index]
+    file        = ".index"
+
+[generator]
+    default     = "txt"
+
+[generator.txt]
+    template    = "template.txt"
+    output      = 'invoice-{{{{ period_end | date(format="%Y%m%d") }}}}-{{{{ sequence }}}}.txt'
+
+[generator.pdf]
+    template    = "template.tex"
+    escape      = "latex"
+    output      = 'invoice-{{{{ period_end | date(format="%Y%m%d") }}}}-{{{{ sequence }}}}.tex'
+    build       = "pdflatex {{{{ output }}}}"
+"#,
+        company = quote(&answers.company),
+        rate = answers.hourly_rate,
+        currency = quote(&answers.currency.to_uppercase()))
+}
+
+/// Returns the example timesheet of the month of `today`, with its entries commented out.
+pub fn timesheet(today: NaiveDate) -> String {
+    format!("{}
+# Each date is followed by its entries, see the README for the format.
+{}
+    # 2h = time entry, with a #tag
+    # 9-12:30 = time range
+    # $50 = fixed cost
+    # - a note
+", format_directive(FORMAT_VERSION), today.format("%Y.%m.%d"))
+}
+
+/// Returns the files written by `init`, by name relative to the data directory, and their
+/// content.
+pub fn files(answers: &Answers, today: NaiveDate) -> Vec<(String, String)> {
+    vec![
+        (CONFIG_FILE.to_string(), config(answers)),
+        ("template.txt".to_string(), TEXT_TEMPLATE.to_string()),
+        ("template.tex".to_string(), LATEX_TEMPLATE.to_string()),
+        (".index".to_string(), String::new()),
+        (format!("{:04}-{:02}.cli", today.year(), today.month()), timesheet(today)),
+    ]
+}
+
+// Asks a question, returning `default` for an empty answer.
+fn ask(editor: &mut Editor<(), DefaultHistory>, question: &str, default: &str) -> String {
+    match editor.readline(&format!("{} [{}]: ", question, default)) {
+        Ok(line) if line.trim().is_empty() => default.to_string(),
+        Ok(line) => line.trim().to_string(),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => std::process::exit(1),
+        Err(err) => {
+            tracing::error!("Failed to read input: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Asks for the answers not given on the command line.
+fn prompt(answers: &mut Answers, company: bool, rate: bool, currency: bool) {
+    let mut editor = Editor::<(), DefaultHistory>::new().unwrap_or_else(|err| {
+        tracing::error!("Failed to start the prompt: {}", err);
+        std::process::exit(1);
+    });
+    if !company {
+        answers.company = ask(&mut editor, "company name", &answers.company);
+    }
+    if !rate {
+        answers.hourly_rate = loop {
+            let answer = ask(&mut editor, "hourly rate", &answers.hourly_rate.to_string());
+            match answer.parse::<f64>() {
+                Ok(rate) if rate >= 0.0 => break rate,
+                _ => eprintln!("Expected a number, like 85 or 92.50"),
+            }
+        };
+    }
+    if !currency {
+        answers.currency = ask(&mut editor, "currency", &answers.currency);
+    }
+}
+
+/// Creates the configuration, templates, index, and an example timesheet in the data
+/// directory, asking for the company name, hourly rate, and currency not given.
+///
+/// Existing files are kept, and nothing is written if the directory already has a
+/// configuration.
+pub fn run(
+    company: Option<String>,
+    hourly_rate: Option<f64>,
+    currency: Option<String>,
+    read_only: bool,
+    directory_option: &Option<String>,
+) {
+    let directory = Path::new(directory_option.as_deref().unwrap_or("."));
+    if read_only {
+        tracing::error!("Cannot initialize a directory in read-only mode");
+        std::process::exit(1);
+    }
+    if directory.join(CONFIG_FILE).exists() {
+        tracing::error!("{} already exists, not overwriting it", directory.join(CONFIG_FILE).display());
+        std::process::exit(1);
+    }
+
+    let mut answers = Answers::default();
+    let given = (company.is_some(), hourly_rate.is_some(), currency.is_some());
+    answers.company = company.unwrap_or(answers.company);
+    answers.hourly_rate = hourly_rate.unwrap_or(answers.hourly_rate);
+    answers.currency = currency.unwrap_or(answers.currency);
+    if atty::is(atty::Stream::Stdin) && !quiet::enabled() {
+        prompt(&mut answers, given.0, given.1, given.2);
+    }
+
+    if let Err(err) = fs::create_dir_all(directory) {
+        tracing::error!("Failed to create {}: {}", directory.display(), err);
+        std::process::exit(1);
+    }
+    let mut written = 0;
+    for (name, content) in files(&answers, Local::now().date_naive()) {
+        let path = directory.join(&name);
+        if path.exists() {
+            tracing::info!("Keeping existing {}", path.display());
+            continue;
+        }
+        if let Err(err) = fs::write(&path, content) {
+            tracing::error!("Failed to write {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        tracing::info!("Created {}", path.display());
+        written += 1;
+    }
+    quiet::summary("initialized", &[
+        ("directory", directory.display().to_string()),
+        ("files", written.to_string()),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_config() {
+        let answers = Answers { company: "Acme \"Rockets\"".to_string(), hourly_rate: 85.0, currency: "eur".to_string() };
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(CONFIG_FILE), config(&answers)).unwrap();
+        let config = Config::new(None, dir.path().to_str()).unwrap();
+        assert_eq!(config.get_string("issuer.company").as_deref(), Some("Acme \"Rockets\""));
+        assert_eq!(config.get_f64("contract.hourly_rate"), Some(85.0));
+        assert_eq!(config.get_string("contract.currency").as_deref(), Some("EUR"));
+        assert_eq!(config.get_string("generator.txt.output").as_deref(),
+            Some(r#"invoice-{{ period_end | date(format="%Y%m%d") }}-{{ sequence }}.txt"#));
+    }
+
+    #[test]
+    fn test_timesheet() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 14).unwrap();
+        let names: Vec<String> = files(&Answers::default(), today).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["clinvoice.toml", "template.txt", "template.tex", ".index", "2025-03.cli"]);
+        let timesheet = timesheet(today);
+        assert!(timesheet.starts_with("# clinvoice-format: "));
+        assert!(timesheet.contains("\n2025.03.14\n"));
+    }
+}
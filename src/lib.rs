@@ -2,9 +2,47 @@ pub mod color;
 pub mod config;
 pub mod data;
 pub mod generate;
+pub mod heatmap;
+pub mod import;
 pub mod latex;
 pub mod log;
 pub mod tracing;
 pub mod parse;
 pub mod index;
 pub mod markdown;
+pub mod notify;
+pub mod serve;
+pub mod upload;
+pub mod publish;
+pub mod quiet;
+pub mod diagnostics;
+pub mod i18n;
+pub mod sequence;
+pub mod project;
+pub mod validate;
+pub mod report;
+pub mod add;
+pub mod undo;
+pub mod backup;
+pub mod migrate;
+pub mod alias;
+pub mod plugin;
+pub mod billing;
+pub mod rates;
+pub mod stats;
+pub mod schema;
+pub mod complete;
+pub mod tsv;
+pub mod timer;
+pub mod init;
+pub mod export;
+pub mod edit;
+pub mod tui;
+pub mod watch;
+pub mod archive;
+pub mod diff;
+pub mod send;
+pub mod template;
+pub mod balance;
+pub mod clock;
+pub mod recurring;
@@ -1,10 +1,14 @@
-use crate::data::{TimeData, DateSelector};
+use crate::config::Config;
+use crate::quiet;
+use crate::data::{Entry, LoadOptions, Selection, TimeData};
+use crate::diagnostics;
 
 use crate::color::*;
+use crate::markdown::markdown_escape;
 use chrono::Datelike;
 use std::collections::HashMap;
-use colored::Color;
 use clap::ValueEnum;
+use serde::Serialize;
 
 /// Defines the available log output formats.
 #[derive(ValueEnum, Clone, Debug)]
@@ -15,48 +19,63 @@ pub enum LogFormat {
     Year,
 }
 
-/// Runs the logging process, displaying time data in various formats.
-///
-/// This function reads time data, filters it by dates, and then formats
-/// and prints it to the console based on the specified `LogFormat`.
-pub fn run(
-    format: LogFormat,
-    directory_option: &Option<String>,
-    dates: &[String],
-) {
-    let directory = directory_option.as_deref().unwrap_or(".");
-
-    let selector = DateSelector::from_dates(dates).unwrap_or_else(|err| {
-        tracing::error!("{}", err);
-        std::process::exit(1);
-    });
+/// Defines how log rows are written.
+#[derive(ValueEnum, Clone, Debug, Default, PartialEq)]
+pub enum LogOutput {
+    #[default]
+    Text,     // Aligned columns, for the terminal.
+    Markdown, // A Markdown table, for status reports and wikis.
+}
 
-    let time_data = TimeData::new(directory, &selector).expect("Failed to load data");
+/// A single line of log output, aggregated according to a `LogFormat`.
+#[derive(Serialize, Clone, Debug)]
+pub struct LogRow {
+    pub period: String,
+    pub hours: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The text of a note, kept out of the description with `LogFormat::Full`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// The files the row was read from, only set when entries come from several directories.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
 
-    let mut grand_total: f32 = 0.0;
-    let grand_total_indent = match format {
+/// Aggregates time data into rows for the given `LogFormat`, in chronological order.
+pub fn aggregate(time_data: &TimeData, format: &LogFormat) -> Vec<LogRow> {
+    let mut rows = Vec::new();
+    let show_sources = time_data.source_directories().len() > 1;
+    let sources = |date, range: std::ops::Range<usize>| -> Option<String> {
+        let sources = time_data.sources.get(date)?.get(range)?;
+        let mut names: Vec<String> = sources.iter().map(|s| s.file.display().to_string()).collect();
+        names.sort();
+        names.dedup();
+        show_sources.then(|| names.join(", "))
+    };
+    match format {
         LogFormat::Full => {
             let mut dates: Vec<_> = time_data.entries.keys().collect();
             dates.sort();
             for date in dates {
-                for entry in &time_data.entries[date] {
-                    let date_str = format!("{:04}.{:02}.{:02}", date.year(), date.month(), date.day());
-                    let (hours, description) = match entry {
-                        crate::data::Entry::Time(h, d) => (*h, d.clone()),
-                        crate::data::Entry::FixedCost(_, d) => (0.0, d.clone()),
-                        crate::data::Entry::Note(n) => (0.0, n.clone()),
+                for (i, entry) in time_data.entries[date].iter().enumerate() {
+                    let (hours, description, note) = match entry {
+                        Entry::Time(h, d, _) | Entry::NonBillable(h, d) => (*h, d.clone(), None),
+                        Entry::FixedCost(_, d) | Entry::Units(_, _, d) => (0.0, d.clone(), None),
+                        Entry::Note(n) => (0.0, String::new(), Some(n.clone())),
                     };
-                    let hours_str = format!("{:8.2}", hours);
-                    println!(
-                        "{}  {}  {}",
-                        date_str.out_colored(Color::Blue),
-                        hours_str.out_colored(Color::Green),
-                        description
-                    );
-                    grand_total += hours;
+                    rows.push(LogRow {
+                        period: format!("{:04}.{:02}.{:02}", date.year(), date.month(), date.day()),
+                        hours,
+                        days: None,
+                        description: Some(description),
+                        note,
+                        source: sources(date, i..i + 1),
+                    });
                 }
             }
-            12 // grand_total_indent
         }
         LogFormat::Day => {
             let mut dates: Vec<_> = time_data.entries.keys().collect();
@@ -67,30 +86,27 @@ pub fn run(
                 let mut descriptions = Vec::new();
                 for entry in entries {
                     match entry {
-                        crate::data::Entry::Time(h, d) => {
+                        Entry::Time(h, d, _) | Entry::NonBillable(h, d) => {
                             total_hours += h;
                             descriptions.push(d.clone());
                         }
-                        crate::data::Entry::FixedCost(_, d) => {
+                        Entry::FixedCost(_, d) | Entry::Units(_, _, d) => {
                             descriptions.push(d.clone());
                         }
-                        crate::data::Entry::Note(n) => {
+                        Entry::Note(n) => {
                             descriptions.push(n.clone());
                         }
                     }
                 }
-                let desc_str = descriptions.join("; ");
-                let date_str = format!("{:04}.{:02}.{:02}", date.year(), date.month(), date.day());
-                let hours_str = format!("{:8.2}", total_hours);
-                println!(
-                    "{}  {}  {}",
-                    date_str.out_colored(Color::Blue),
-                    hours_str.out_colored(Color::Green),
-                    desc_str
-                );
-                grand_total += total_hours;
+                rows.push(LogRow {
+                    period: format!("{:04}.{:02}.{:02}", date.year(), date.month(), date.day()),
+                    hours: total_hours,
+                    days: None,
+                    description: Some(descriptions.join("; ")),
+                    note: None,
+                    source: sources(date, 0..entries.len()),
+                });
             }
-            12 // grand_total_indent
         }
         LogFormat::Month => {
             let mut monthly_totals: HashMap<(i32, u32), f32> = HashMap::new();
@@ -99,34 +115,23 @@ pub fn run(
                 let year = date.year();
                 let month = date.month();
                 let day = date.day();
-                let total: f32 = entries.iter().map(|e| {
-                    match e {
-                        crate::data::Entry::Time(h, _) => *h,
-                        _ => 0.0,
-                    }
-                }).sum();
-                *monthly_totals.entry((year, month)).or_insert(0.0) += total;
+                *monthly_totals.entry((year, month)).or_insert(0.0) += time_hours(entries);
                 *monthly_counts.entry((year, month)).or_insert(0) |= 1u64 << day;
-                grand_total += total;
             }
 
             let mut months: Vec<_> = monthly_totals.keys().collect();
             months.sort();
             for (year, month) in months {
-                let total_hours = monthly_totals[&(*year, *month)];
                 let day_mask = monthly_counts[&(*year, *month)];
-                let day_count = day_mask.count_ones();
-                let date_str = format!("{:04}.{:02}", year, month);
-                let hours_str = format!("{:8.2}", total_hours);
-                let count_str = format!("{} day{}", day_count, match day_count { 1 => "", _ => "s" });
-                println!(
-                    "{}  {}  ({})",
-                    date_str.out_colored(Color::Blue),
-                    hours_str.out_colored(Color::Green),
-                    count_str.out_colored(Color::Yellow),
-                );
+                rows.push(LogRow {
+                    period: format!("{:04}.{:02}", year, month),
+                    hours: monthly_totals[&(*year, *month)],
+                    days: Some(day_mask.count_ones()),
+                    description: None,
+                    note: None,
+                    source: None,
+                });
             }
-            9 // grand_total_indent
         }
         LogFormat::Year => {
             let mut yearly_totals: HashMap<i32, f32> = HashMap::new();
@@ -135,46 +140,170 @@ pub fn run(
                 let year = date.year();
                 let month = date.month();
                 let day = date.day();
-                let total: f32 = entries.iter().map(|e| {
-                    match e {
-                        crate::data::Entry::Time(h, _) => *h,
-                        _ => 0.0,
-                    }
-                }).sum();
-                *yearly_totals.entry(year).or_insert(0.0) += total;
+                *yearly_totals.entry(year).or_insert(0.0) += time_hours(entries);
                 *monthly_counts.entry((year, month)).or_insert(0) |= 1u64 << day;
-                grand_total += total;
             }
 
             let mut yearly_counts: HashMap<i32, u32> = HashMap::new();
-            let months: Vec<_> = monthly_counts.keys().collect();
-            for (year, month) in months {
-                let day_mask = monthly_counts[&(*year, *month)];
-                let day_count = day_mask.count_ones();
-                *yearly_counts.entry(*year).or_insert(0) += day_count;
+            for ((year, _), day_mask) in &monthly_counts {
+                *yearly_counts.entry(*year).or_insert(0) += day_mask.count_ones();
             }
 
             let mut years: Vec<_> = yearly_totals.keys().collect();
             years.sort();
             for year in years {
-                let total_hours = yearly_totals[year];
-                let day_count = yearly_counts[year];
-                let year_str = format!("{:04}", year);
-                let hours_str = format!("{:8.2}", total_hours);
-                let count_str = format!("{} day{}", day_count, match day_count { 1 => "", _ => "s" });
-                println!(
-                    "{}  {}  ({})",
-                    year_str.out_colored(Color::Blue),
-                    hours_str.out_colored(Color::Green),
-                    count_str.out_colored(Color::Yellow),
-                );
+                rows.push(LogRow {
+                    period: format!("{:04}", year),
+                    hours: yearly_totals[year],
+                    days: Some(yearly_counts[year]),
+                    description: None,
+                    note: None,
+                    source: None,
+                });
             }
-            6 // grand_total_indent
         }
+    }
+    rows
+}
+
+// Sums the hours of all time entries in a day.
+fn time_hours(entries: &[Entry]) -> f32 {
+    entries.iter().map(Entry::hours).sum()
+}
+
+// Escapes text for a Markdown table cell, where a `|` would end the cell.
+fn markdown_cell(text: &str) -> String {
+    markdown_escape(text).replace('|', "\\|")
+}
+
+/// Renders log rows as a Markdown table, with a total row.
+pub fn markdown_table(rows: &[LogRow], format: &LogFormat, grand_total: f32) -> String {
+    let with_source = rows.iter().any(|row| row.source.is_some());
+    let detailed = matches!(format, LogFormat::Full | LogFormat::Day);
+    let mut header = match format {
+        LogFormat::Full | LogFormat::Day => vec![("Date", ":---"), ("Hours", "---:"), ("Description", ":---")],
+        LogFormat::Month => vec![("Month", ":---"), ("Hours", "---:"), ("Days", "---:")],
+        LogFormat::Year => vec![("Year", ":---"), ("Hours", "---:"), ("Days", "---:")],
+    };
+    if matches!(format, LogFormat::Full) {
+        header.push(("Note", ":---"));
+    }
+    if with_source {
+        header.push(("Source", ":---"));
+    }
+
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let mut table = line(header.iter().map(|(name, _)| name.to_string()).collect());
+    table.push_str(&line(header.iter().map(|(_, align)| align.to_string()).collect()));
+    for row in rows {
+        let mut cells = vec![row.period.clone(), format!("{:.2}", row.hours)];
+        cells.push(if detailed {
+            markdown_cell(row.description.as_deref().unwrap_or(""))
+        } else {
+            row.days.unwrap_or(0).to_string()
+        });
+        if matches!(format, LogFormat::Full) {
+            cells.push(markdown_cell(row.note.as_deref().unwrap_or("")));
+        }
+        if with_source {
+            cells.push(markdown_cell(row.source.as_deref().unwrap_or("")));
+        }
+        table.push_str(&line(cells));
+    }
+    let mut total = vec!["**Total**".to_string(), format!("**{:.2}**", grand_total), String::new()];
+    if matches!(format, LogFormat::Full) {
+        total.push(String::new());
+    }
+    if with_source {
+        total.push(String::new());
+    }
+    table.push_str(&line(total));
+    table
+}
+
+/// Runs the logging process, displaying time data in various formats.
+///
+/// This function reads time data, filters it by dates, and then formats
+/// and prints it to the console based on the specified `LogFormat`.
+pub fn run(
+    format: LogFormat,
+    output: LogOutput,
+    tags: &[String],
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+    selection: &Selection,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+
+    let mut time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.checked_load_options(config.as_ref()) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    time_data.retain_tagged(tags);
+
+    let rows = aggregate(&time_data, &format);
+    let grand_total = rows.iter().fold(0.0, |acc, r| acc + r.hours);
+
+    if quiet::enabled() {
+        quiet::summary("log", &[
+            ("rows", rows.len().to_string()),
+            ("hours", format!("{:.2}", grand_total)),
+        ]);
+        return;
+    }
+
+    if output == LogOutput::Markdown {
+        print!("{}", markdown_table(&rows, &format, grand_total));
+        return;
+    }
+
+    let theme = theme();
+    for row in &rows {
+        let hours_str = format!("{:8.2}", row.hours);
+        if let Some(description) = &row.description {
+            let note = match (&row.note, description.is_empty()) {
+                (Some(n), true) => n.out_colored(theme.count).to_string(),
+                (Some(n), false) => format!("  {}", n).out_colored(theme.count).to_string(),
+                (None, _) => String::new(),
+            };
+            let source = row.source.as_ref()
+                .map(|s| format!("  [{}]", s).out_colored(theme.count))
+                .unwrap_or_default();
+            println!(
+                "{}  {}  {}{}{}",
+                row.period.out_colored(theme.period),
+                hours_str.out_colored(theme.hours),
+                description,
+                note,
+                source
+            );
+        } else {
+            let day_count = row.days.unwrap_or(0);
+            let count_str = format!("{} day{}", day_count, match day_count { 1 => "", _ => "s" });
+            println!(
+                "{}  {}  ({})",
+                row.period.out_colored(theme.period),
+                hours_str.out_colored(theme.hours),
+                count_str.out_colored(theme.count),
+            );
+        }
+    }
+
+    let grand_total_indent = match format {
+        LogFormat::Full | LogFormat::Day => 12,
+        LogFormat::Month => 9,
+        LogFormat::Year => 6,
     };
     let grand_total_str = format!("{:8.2}", grand_total);
     println!("{:<width$}{}",
-        "Total:".out_colored(Color::Red),
-        grand_total_str.out_colored(Color::Green),
+        "Total:".out_colored(theme.total),
+        grand_total_str.out_colored(theme.hours),
         width = grand_total_indent);
 }
@@ -2,19 +2,65 @@ use clap::{Parser, Subcommand};
 use clap::CommandFactory;
 use crate::tracing::TraceLevel;
 use crate::color::*;
-use crate::log::LogFormat;
+use crate::log::{LogFormat, LogOutput};
+use crate::heatmap::Palette;
+use crate::import::ImportSource;
+use crate::sequence::SequenceAction;
+use crate::rates::RatesAction;
+use crate::config::ConfigAction;
+use crate::diagnostics::DiagnosticsFormat;
+use crate::data::Selection;
+use crate::export::ExportFormat;
+use crate::template::TemplateAction;
 
+mod add;
+mod alias;
+mod archive;
+mod backup;
+mod balance;
+mod billing;
+mod clock;
 mod color;
+mod complete;
 mod config;
 mod data;
+mod diagnostics;
+mod diff;
+mod edit;
+mod export;
 mod generate;
 mod heatmap;
+mod i18n;
+mod import;
 mod index;
+mod init;
 mod latex;
 mod log;
 mod markdown;
+mod migrate;
+mod notify;
 mod parse;
+mod plugin;
+mod project;
+mod publish;
+mod quiet;
+mod rates;
+mod recurring;
+mod report;
+mod schema;
+mod send;
+mod sequence;
+mod serve;
+mod stats;
+mod template;
+mod timer;
 mod tracing;
+mod tsv;
+mod tui;
+mod undo;
+mod upload;
+mod validate;
+mod watch;
 
 /// Command-line interface arguments for the clinvoice application.
 #[derive(Parser)]
@@ -23,12 +69,22 @@ struct Cli {
     log_level: TraceLevel,
     #[clap(short = 'L', long, help = "select log destination file (- is stderr", default_value = "-")]
     log_file: String,
-    #[clap(short, long, help = "select directory with .cli files")]
-    directory: Option<String>,
+    #[clap(short, long, help = "select directory with .cli files, can be repeated to merge several")]
+    directory: Vec<String>,
+    #[clap(short, long, conflicts_with = "directory", help = "select a project from ~/.config/clinvoice/projects.toml")]
+    project: Option<String>,
+    #[clap(long, help = "select a client from the [clients.<name>] sections of the configuration")]
+    client: Option<String>,
     #[clap(short, long, help = "select configuration file, use .clinvoice otherwise")]
     config: Option<String>,
     #[clap(short = 'C', long, default_value = "auto")]
     color: ColorOption,
+    #[clap(long, help = "never write files or run build commands, render to stdout instead")]
+    read_only: bool,
+    #[clap(short, long, help = "only log errors, and print a single summary line per command")]
+    quiet: bool,
+    #[clap(long, help = "select how timesheet problems are reported at the end", default_value = "text")]
+    diagnostics: DiagnosticsFormat,
     #[clap(subcommand)]
     command: Option<Command>,
 }
@@ -37,13 +93,72 @@ struct Cli {
 #[derive(Subcommand)]
 enum Command {
 
+    /// Add an entry, or prompt for one
+    #[clap(about = "Add an entry, or prompt for one")]
+    Add {
+        #[clap(long, help = "date of the entry, today otherwise")]
+        date: Option<String>,
+        #[clap(allow_hyphen_values = true, help = "time and description, like: 2h fixed the build #acme")]
+        entry: Vec<String>,
+    },
+
+    /// Open the timesheet holding a date in $EDITOR
+    #[clap(about = "Open the timesheet holding a date in $EDITOR, at the date")]
+    Edit {
+        #[clap(help = "date to edit, today otherwise")]
+        date: Option<String>,
+    },
+
     /// Display existing entries
     #[clap(about = "Display existing entries")]
     Log {
         #[clap(short, long, default_value = "day")]
         format: LogFormat,
-        #[clap(value_parser)]
-        dates: Vec<String>,
+        #[clap(short, long, value_enum, default_value_t, help = "write rows as aligned text, or a Markdown table")]
+        output: LogOutput,
+        #[clap(short, long, help = "only show entries with this #tag, can be repeated")]
+        tag: Vec<String>,
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Print a shell completion script
+    #[clap(about = "Print a shell completion script")]
+    Completions {
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Suggest dates and generators for the completion scripts
+    #[clap(name = "__complete", hide = true)]
+    Complete {
+        #[clap(allow_hyphen_values = true, trailing_var_arg = true)]
+        words: Vec<String>,
+    },
+
+    /// Inspect the configuration format
+    #[clap(about = "Inspect the configuration format")]
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Show how an invoice differs from the timesheets now
+    #[clap(about = "Show how an invoice differs from the timesheets now")]
+    Diff {
+        #[clap(help = "sequence number of the invoice")]
+        sequence: u32,
+    },
+
+    /// Export entries as CSV or JSON
+    #[clap(about = "Export entries as CSV or JSON")]
+    Export {
+        #[clap(short, long, value_enum, default_value_t)]
+        format: ExportFormat,
+        #[clap(short, long, help = "file to write, stdout otherwise")]
+        output: Option<String>,
+        #[clap(flatten)]
+        selection: Selection,
     },
 
     /// Generate an invoice
@@ -51,39 +166,412 @@ enum Command {
     Generate {
         #[clap(short, long)]
         output: Option<String>,
-        #[clap(short, long)]
-        generator: Option<String>,
+        #[clap(short, long, help = "select generator, can be repeated to run several")]
+        generator: Vec<String>,
+        #[clap(short, long, default_value = "0", help = "number of builds to run at once, 0 is the number of CPUs")]
+        jobs: usize,
+        #[clap(long, help = "open the finished invoice in a viewer")]
+        open: bool,
+        #[clap(long, help = "refuse to overwrite existing output files")]
+        no_clobber: bool,
+        #[clap(short, long, help = "only bill entries with this #tag, can be repeated")]
+        tag: Vec<String>,
+        #[clap(long, help = "also write the days to a CSV file, days_csv or <output>.days.csv")]
+        emit_days: bool,
+        #[clap(long, help = "generate again whenever a timesheet, template, or the config changes")]
+        watch: bool,
+        #[clap(long, help = "email the finished invoice, as send does")]
+        send: bool,
         #[clap(short, long)]
         sequence: Option<u32>,
-        #[clap(value_parser)]
-        dates: Vec<String>,
+        #[clap(flatten)]
+        selection: Selection,
     },
 
     /// Display a heatmap of entries
     #[clap(about = "Display a heatmap of entries")]
     Heatmap {
-        #[clap(value_parser)]
-        dates: Vec<String>,
+        #[clap(short, long, help = "select how cells are drawn, detected from the terminal by default")]
+        palette: Option<Palette>,
+        #[clap(long, help = "mark the days with at least this many hours, heatmap.target by default")]
+        target: Option<f64>,
+        #[clap(short, long, help = "only count entries with this #tag, can be repeated")]
+        tag: Vec<String>,
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Import entries from other time tracking tools
+    #[clap(about = "Import entries from other time tracking tools")]
+    Import {
+        #[clap(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Create a configuration, templates, and a timesheet in a new data directory
+    #[clap(about = "Create a configuration, templates, and a timesheet in a new data directory")]
+    Init {
+        #[clap(long, help = "your company name, asked for otherwise")]
+        company: Option<String>,
+        #[clap(long, help = "hourly rate, asked for otherwise")]
+        rate: Option<f64>,
+        #[clap(long, help = "currency of the rate, like USD, asked for otherwise")]
+        currency: Option<String>,
+    },
+
+    /// Publish a static HTML dashboard
+    #[clap(about = "Publish a static HTML dashboard")]
+    Publish {
+        #[clap(help = "directory to write the dashboard to")]
+        output_dir: String,
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Move entries before a date out of the data directory, into archive/
+    #[clap(about = "Move entries before a date out of the data directory, into archive/")]
+    Archive {
+        #[clap(long, help = "archive the dates before this one")]
+        before: String,
+        #[clap(long, help = "list the files that would change, without writing them")]
+        dry_run: bool,
+    },
+
+    /// Show the amounts billed, paid, and still owed for each invoice
+    #[clap(about = "Show the amounts billed, paid, and still owed for each invoice")]
+    Balance {
+        #[clap(long, help = "count overdue days up to this date, today by default")]
+        as_of: Option<String>,
+    },
+
+    /// Record a payment received for an invoice
+    #[clap(about = "Record a payment received for an invoice")]
+    Pay {
+        #[clap(help = "sequence number of the invoice")]
+        sequence: u32,
+        #[clap(help = "amount received")]
+        amount: f64,
+        #[clap(short, long, help = "date of the payment, today by default")]
+        date: Option<String>,
+        #[clap(short, long, help = "note kept with the payment, like a reference")]
+        note: Option<String>,
+    },
+
+    /// List the working days with nothing logged
+    #[clap(about = "List the working days with nothing logged")]
+    Missing {
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Upgrade timesheets written for an older format version
+    #[clap(about = "Upgrade timesheets written for an older format version")]
+    Migrate {
+        #[clap(long, help = "list the files that would change, without writing them")]
+        dry_run: bool,
+    },
+
+    /// Restore files saved before an operation that rewrote them
+    #[clap(about = "Restore files saved before an operation that rewrote them")]
+    Restore {
+        #[clap(long, help = "list the snapshots instead")]
+        list: bool,
+        #[clap(help = "snapshot to restore, the latest otherwise")]
+        snapshot: Option<String>,
+    },
+
+    /// Compare logged time against expectations, or explain the hours billed
+    #[clap(about = "Compare logged time against expectations, or explain the hours billed")]
+    Report {
+        #[clap(long, help = "list missing, short, and over days per week, against [schedule]")]
+        compliance: bool,
+        #[clap(long, help = "list the days whose billed hours differ from the hours logged, and why")]
+        audit: bool,
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Fetch and list cached exchange rates
+    #[clap(about = "Fetch and list cached exchange rates")]
+    Rates {
+        #[clap(subcommand)]
+        action: RatesAction,
+    },
+
+    /// Show statistics on the time logged
+    #[clap(about = "Show statistics on the time logged")]
+    Stats {
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Show the hours, earnings, and working days of each month
+    #[clap(about = "Show the hours, earnings, and working days of each month")]
+    Summary {
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Email a generated invoice to the client
+    #[clap(about = "Email a generated invoice to the client")]
+    Send {
+        #[clap(help = "sequence number of the invoice")]
+        sequence: u32,
+        #[clap(long, help = "show the email without sending it")]
+        dry_run: bool,
+    },
+
+    /// Manage invoice sequence numbers
+    #[clap(about = "Manage invoice sequence numbers")]
+    Sequence {
+        #[clap(subcommand)]
+        action: SequenceAction,
+    },
+
+    /// Serve a web dashboard and JSON API of the timesheets, generating invoices on request
+    #[clap(about = "Serve a web dashboard and JSON API of the timesheets, generating invoices on request")]
+    Serve {
+        #[clap(short, long, default_value = "8123")]
+        port: u16,
+        #[clap(short, long, default_value = "127.0.0.1", help = "address to listen on")]
+        bind: String,
+    },
+
+    /// Work on the templates of generators
+    #[clap(about = "Work on the templates of generators")]
+    Template {
+        #[clap(subcommand)]
+        action: TemplateAction,
     },
+
+    /// Void an invoice, keeping its sequence number as cancelled
+    #[clap(about = "Void an invoice, keeping its sequence number as cancelled")]
+    Void {
+        #[clap(help = "sequence number of the invoice")]
+        sequence: u32,
+    },
+
+    /// Browse the timesheets in an interactive calendar
+    #[clap(about = "Browse the timesheets in an interactive calendar, and add entries")]
+    Tui,
+
+    /// Start a timer, logged as an entry by stop
+    #[clap(about = "Start a timer, logged as an entry by stop")]
+    Start {
+        #[clap(required = true, allow_hyphen_values = true, help = "description of the work, like: fixed the build #acme")]
+        description: Vec<String>,
+    },
+
+    /// Show the hours logged today, this week, and this month, and the running timer
+    #[clap(about = "Show the hours logged today, this week, and this month, and the running timer")]
+    Status {
+        #[clap(short, long, help = "print one line, for status bars")]
+        short: bool,
+    },
+
+    /// Stop the running timer, and log the time as an entry
+    #[clap(about = "Stop the running timer, and log the time as an entry")]
+    Stop {
+        #[clap(allow_hyphen_values = true, help = "description replacing the one given to start")]
+        description: Vec<String>,
+    },
+
+    /// Check timesheets for likely mistakes
+    #[clap(about = "Check timesheets for likely mistakes")]
+    Validate {
+        #[clap(flatten)]
+        selection: Selection,
+    },
+
+    /// Revert the last entry added, or sequence added by generate
+    #[clap(about = "Revert the last entry added, or sequence added by generate")]
+    Undo,
+
+    /// Run a clinvoice-<name> plugin found on PATH
+    #[clap(external_subcommand)]
+    External(Vec<String>),
 }
 
 /// Main entry point of the clinvoice application.
 fn main() {
-    let cli = Cli::parse();
+    let args = alias::expand(std::env::args().collect(), &Cli::command()).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(2);
+    });
+    let mut cli = Cli::parse_from(args);
     color::init(&cli.color);
-    tracing::init(&cli.log_level, &cli.log_file);
+    quiet::init(cli.quiet);
+    diagnostics::init(cli.diagnostics);
+    let log_level = if cli.quiet { TraceLevel::Error } else { cli.log_level.clone() };
+    tracing::init(&log_level, &cli.log_file);
+    if let Some(name) = &cli.project {
+        let project = project::registry_path()
+            .ok_or_else(|| "HOME is not set, cannot find the project registry".to_string())
+            .and_then(|registry| project::lookup(&registry, name))
+            .unwrap_or_else(|err| {
+                ::tracing::error!("{}", err);
+                std::process::exit(1);
+            });
+        cli.directory = vec![project.directory];
+        cli.config = cli.config.or(project.config);
+    }
+    if let Some(name) = &cli.client {
+        let directory = cli.directory.first().map_or(".", |d| d.as_str());
+        let (client_directory, config_file) = config::select_client(name, directory, cli.config.as_deref())
+            .unwrap_or_else(|err| {
+                ::tracing::error!("{}", err);
+                std::process::exit(1);
+            });
+        match cli.directory.first_mut() {
+            Some(first) => *first = client_directory,
+            None => cli.directory.push(client_directory),
+        }
+        cli.config = Some(config_file);
+    }
+    // The first directory holds the configuration, index, and outputs; the others only add entries.
+    let directory = cli.directory.first().cloned();
+    let extra_directories: Vec<String> = cli.directory.iter().skip(1).cloned().collect();
+    let selected = |selection: Selection| Selection { directories: extra_directories.clone(), ..selection };
+    let config = config::Config::new(cli.config.as_deref(), Some(directory.as_deref().unwrap_or("."))).ok();
+    color::init_theme(config.as_ref());
     match cli.command {
         None => {
             Cli::command().print_long_help().unwrap();
         }
-        Some(Command::Log { format, dates }) => {
-            log::run(format, &cli.directory, &dates)
+        Some(Command::Add { date, entry }) => {
+            add::run(date, entry, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Edit { date }) => {
+            edit::run(date, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Log { format, output, tag, selection }) => {
+            log::run(format, output, &tag, &directory, &cli.config, &selected(selection))
         },
-        Some(Command::Generate { output, generator, sequence, dates }) => {
-            generate::run(output, &generator, &sequence, &cli.directory, &cli.config, &dates)
+        Some(Command::Completions { shell }) => {
+            if let Err(err) = complete::generate(shell, &mut Cli::command(), &mut std::io::stdout()) {
+                ::tracing::error!("Failed to write the completion script: {}", err);
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Complete { words }) => {
+            complete::run(&words, &Cli::command())
+        }
+        Some(Command::Config { action }) => {
+            config::run(action, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Diff { sequence }) => {
+            diff::run(sequence, &directory, &cli.config)
+        }
+        Some(Command::Export { format, output, selection }) => {
+            export::run(format, output, cli.read_only, &directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Generate { watch: true, .. }) => {
+            let args: Vec<String> = std::env::args().skip(1).filter(|arg| arg != "--watch").collect();
+            watch::run(&args, &directory, &cli.config)
+        }
+        Some(Command::Generate { output, generator, jobs, open, no_clobber, tag, emit_days, watch: _, send, sequence, selection }) => {
+            let options = generate::GenerateOptions {
+                output,
+                generators: generator,
+                sequence,
+                read_only: cli.read_only,
+                jobs,
+                open,
+                no_clobber,
+                tags: tag,
+                emit_days,
+                send,
+            };
+            generate::generate(&options, &directory, &cli.config, &selected(selection))
         },
-        Some(Command::Heatmap { dates }) => {
-            heatmap::run(&cli.directory, &dates)
+        Some(Command::Heatmap { palette, target, tag, selection }) => {
+            heatmap::run(&directory, &cli.config, palette, target, &tag, &selected(selection))
+        }
+        Some(Command::Import { source }) => {
+            import::run(source, &directory, &cli.config)
+        }
+        Some(Command::Init { company, rate, currency }) => {
+            init::run(company, rate, currency, cli.read_only, &directory)
+        }
+        Some(Command::Publish { output_dir, selection }) => {
+            publish::run(&output_dir, &directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Archive { before, dry_run }) => {
+            archive::run(before, dry_run, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Missing { selection }) => {
+            report::run_missing(&directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Balance { as_of }) => {
+            balance::run(as_of, &directory, &cli.config)
+        }
+        Some(Command::Pay { sequence, amount, date, note }) => {
+            balance::pay(sequence, amount, date, note, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Migrate { dry_run }) => {
+            migrate::run(dry_run, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Restore { list, snapshot }) => {
+            backup::run(snapshot, list, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Report { compliance, audit, selection }) => {
+            report::run(compliance, audit, &directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Rates { action }) => {
+            rates::run(action, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Stats { selection }) => {
+            stats::run(&directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Summary { selection }) => {
+            report::run_summary(&directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Send { sequence, dry_run }) => {
+            send::run(sequence, dry_run, &directory, &cli.config)
+        }
+        Some(Command::Sequence { action }) => {
+            sequence::run(action, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Serve { port, bind }) => {
+            serve::run(&bind, port, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Template { action }) => {
+            template::run(action, &directory, &cli.config)
+        }
+        Some(Command::Void { sequence }) => {
+            sequence::void(sequence, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Tui) => {
+            tui::run(cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Start { description }) => {
+            timer::start(description, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Status { short }) => {
+            timer::status(short, &directory, &cli.config)
+        }
+        Some(Command::Stop { description }) => {
+            timer::stop(description, cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::Validate { selection }) => {
+            validate::run(&directory, &cli.config, &selected(selection))
+        }
+        Some(Command::Undo) => {
+            undo::run(cli.read_only, &directory, &cli.config)
+        }
+        Some(Command::External(args)) => {
+            let flag = |enabled: bool| if enabled { "1" } else { "0" }.to_string();
+            let env = [
+                ("CLINVOICE_DIRECTORY", directory.unwrap_or(".".to_string())),
+                ("CLINVOICE_EXTRA_DIRECTORIES", cli.directory.iter().skip(1).cloned().collect::<Vec<_>>().join("\n")),
+                ("CLINVOICE_CONFIG", cli.config.unwrap_or_default()),
+                ("CLINVOICE_COLOR", if color_enabled().stdout { "always" } else { "never" }.to_string()),
+                ("CLINVOICE_QUIET", flag(cli.quiet)),
+                ("CLINVOICE_READ_ONLY", flag(cli.read_only)),
+                ("CLINVOICE_LOG_LEVEL", log_level.as_str().to_string()),
+            ];
+            plugin::run(args, &env)
         }
     }
+    diagnostics::report();
 }
@@ -0,0 +1,197 @@
+//! Rewriting timesheets written for an older `.cli` format version.
+
+use crate::backup;
+use crate::config::Config;
+use crate::data::{timesheet_files, LoadOptions};
+use crate::parse::{format_directive, parse_date, parse_date_line, parse_format_directive, FORMAT_VERSION};
+use crate::quiet;
+use crate::tsv;
+use std::fs;
+use std::path::PathBuf;
+
+// A change from one format version to the next.
+struct Migration {
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut Vec<String>),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { from: 1, description: "write dates as YYYY.MM.DD", apply: canonical_dates },
+];
+
+// Rewrites `YYYYMMDD` and `YYYY-MM-DD` date lines as `YYYY.MM.DD`, keeping their weekday.
+#[allow(clippy::ptr_arg)] // migrations share a signature, and others may add lines
+fn canonical_dates(lines: &mut Vec<String>) {
+    for line in lines.iter_mut() {
+        let trimmed = line.trim();
+        if parse_date_line(trimmed).is_none() {
+            continue;
+        }
+        let written = trimmed.split(char::is_whitespace).next().unwrap_or(trimmed);
+        if let Some(date) = parse_date(written) {
+            let canonical = date.format("%Y.%m.%d").to_string();
+            if written != canonical {
+                *line = line.replacen(written, &canonical, 1);
+            }
+        }
+    }
+}
+
+/// Returns the format version a timesheet declares, 1 if it does not.
+pub fn format_version(lines: &[String]) -> u32 {
+    lines.iter().find_map(|line| parse_format_directive(line)).unwrap_or(1)
+}
+
+/// Upgrades the lines of a timesheet to `FORMAT_VERSION`, and declares the version.
+///
+/// # Returns
+///
+/// The descriptions of the changes applied, empty if the file is already current.
+///
+/// # Errors
+///
+/// Returns a `String` error if the file declares a version newer than `FORMAT_VERSION`.
+pub fn migrate(lines: &mut Vec<String>) -> Result<Vec<&'static str>, String> {
+    let version = format_version(lines);
+    if version > FORMAT_VERSION {
+        return Err(format!("Written for format version {}, newer than {}", version, FORMAT_VERSION));
+    }
+    if version == FORMAT_VERSION {
+        return Ok(Vec::new());
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        (migration.apply)(lines);
+        applied.push(migration.description);
+    }
+    match lines.iter().position(|line| parse_format_directive(line).is_some()) {
+        Some(index) => lines[index] = format_directive(FORMAT_VERSION),
+        None => lines.insert(0, format_directive(FORMAT_VERSION)),
+    }
+    Ok(applied)
+}
+
+/// Upgrades the timesheets of the data directory to the current format version.
+///
+/// The files are saved in a snapshot before they are rewritten.  Encrypted timesheets are
+/// left alone.
+pub fn run(
+    dry_run: bool,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    if read_only && !dry_run {
+        tracing::error!("Cannot migrate in read-only mode, use --dry-run to see what would change");
+        std::process::exit(1);
+    }
+    let files = timesheet_files(directory, &LoadOptions::from_config(config.as_ref())).unwrap_or_else(|err| {
+        tracing::error!("Failed to list timesheets: {}", err);
+        std::process::exit(1);
+    });
+
+    let mut changed: Vec<(PathBuf, Vec<String>)> = Vec::new();
+    let mut failed = 0;
+    for path in files.into_iter().filter(|path| !tsv::is_tsv(path)) {
+        if path.extension().is_none_or(|ext| ext != "cli") {
+            tracing::warn!("Not migrating encrypted {}", path.display());
+            continue;
+        }
+        let mut lines: Vec<String> = match fs::read_to_string(&path) {
+            Ok(content) => content.lines().map(|l| l.to_string()).collect(),
+            Err(err) => {
+                tracing::error!("Failed to read {}: {}", path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        match migrate(&mut lines) {
+            Ok(applied) if applied.is_empty() => {}
+            Ok(applied) => {
+                if !quiet::enabled() {
+                    println!("{}: {}", path.display(), applied.join(", "));
+                }
+                changed.push((path, lines));
+            }
+            Err(err) => {
+                tracing::error!("{}: {}", path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    if !dry_run && !changed.is_empty() {
+        let paths: Vec<PathBuf> = changed.iter().map(|(path, _)| path.clone()).collect();
+        if let Err(err) = backup::snapshot(directory, &paths, &format!("migrate to format {}", FORMAT_VERSION)) {
+            tracing::error!("Failed to save a snapshot before migrating: {}", err);
+            std::process::exit(1);
+        }
+        for (path, lines) in &changed {
+            let mut content = lines.join("\n");
+            content.push('\n');
+            if let Err(err) = fs::write(path, content) {
+                tracing::error!("Failed to write {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+        tracing::info!("Migrated {} files to format version {}", changed.len(), FORMAT_VERSION);
+    }
+    quiet::summary("migrated", &[
+        ("files", changed.len().to_string()),
+        ("failed", failed.to_string()),
+        ("dry_run", dry_run.to_string()),
+    ]);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_migrate_from_version_1() {
+        let mut file = lines("# March\n20250303\n    2h = a\n2025-03-04\n    1h = b\n2025.03.05\n    1h = c");
+        assert_eq!(migrate(&mut file).unwrap(), vec!["write dates as YYYY.MM.DD"]);
+        assert_eq!(file, lines("# clinvoice-format: 2\n# March\n2025.03.03\n    2h = a\n2025.03.04\n    1h = b\n2025.03.05\n    1h = c"));
+        assert_eq!(format_version(&file), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_dates_with_weekdays() {
+        let mut file = lines("2024-05-03 Friday
+    2h = a
+20240506 mon
+    1h = b
+2024-05-08 Tuesday
+  2024-05-09 = c");
+        migrate(&mut file).unwrap();
+        assert_eq!(file, lines("# clinvoice-format: 2
+2024.05.03 Friday
+    2h = a
+2024.05.06 mon
+    1h = b
+2024.05.08 Tuesday
+  2024-05-09 = c"));
+    }
+
+    #[test]
+    fn test_migrate_current_and_newer() {
+        let mut file = lines("# clinvoice-format: 2\n20250303\n    2h = a");
+        assert!(migrate(&mut file).unwrap().is_empty());
+        assert_eq!(file[1], "20250303");
+
+        let mut file = lines("# clinvoice-format: 99\n2025.03.03");
+        assert!(migrate(&mut file).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+//! Notifications sent after an invoice has been generated.
+
+use crate::config::Config;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Posts `payload` as JSON to `notify.webhook.url`, if configured.
+///
+/// Extra request headers can be given in the `notify.webhook.headers` table.
+/// The invoice has already been produced when this is called, so failures
+/// are reported as warnings rather than aborting.
+pub fn webhook(config: &Config, payload: &Value) {
+    let Some(url) = config.get_string("notify.webhook.url") else {
+        return;
+    };
+    let timeout = config.get_i64("notify.webhook.timeout").unwrap_or(10).max(1) as u64;
+
+    tracing::info!("Notifying {}", url);
+    let mut request = ureq::post(&url).timeout(Duration::from_secs(timeout));
+    if let Some(headers) = config.get_table("notify.webhook.headers") {
+        for (name, value) in headers {
+            if let Some(value) = value.as_str() {
+                request = request.set(name, value);
+            }
+        }
+    }
+
+    match request.send_json(payload) {
+        Ok(response) => tracing::debug!("webhook responded with {}", response.status()),
+        Err(err) => tracing::warn!("Webhook notification to {} failed: {}", url, err),
+    }
+}
@@ -1,5 +1,5 @@
-use crate::data::{DateRange, Entry};
-use chrono::{NaiveDate, NaiveTime};
+use crate::data::{DateRange, Entry, Interval};
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
 
 /// Parses a date string from a line using various formats.
 ///
@@ -11,6 +11,71 @@ pub fn parse_date(line: &str) -> Option<NaiveDate> {
         .ok()
 }
 
+/// Parses a date line of a timesheet, a date optionally followed by its weekday, like
+/// "2024.05.03 Friday" or "2024.05.03 fri".
+///
+/// Returns `None` for lines that are not dates, and an error when the weekday is not the one
+/// of the date, which is usually a date copied from the week before.
+pub fn parse_date_line(line: &str) -> Option<Result<NaiveDate, String>> {
+    let (date, weekday) = match line.split_once(char::is_whitespace) {
+        Some((date, weekday)) => (date, Some(weekday.trim())),
+        None => (line, None),
+    };
+    let date = parse_date(date)?;
+    match weekday.map(|name| (name, name.parse::<Weekday>())) {
+        None => Some(Ok(date)),
+        Some((_, Ok(weekday))) if weekday == date.weekday() => Some(Ok(date)),
+        Some((name, Ok(_))) => Some(Err(format!("{} is a {}, not {}", date.format("%Y.%m.%d"), date.format("%A"), name))),
+        Some((_, Err(_))) => None,
+    }
+}
+
+/// Checks if a line is shaped like a date, valid or not, like `2025.02.30`.
+pub fn looks_like_date(line: &str) -> bool {
+    let parts: Vec<&str> = line.split(['.', '-']).collect();
+    let digits = |part: &str, len: std::ops::RangeInclusive<usize>| len.contains(&part.len()) && part.chars().all(|c| c.is_ascii_digit());
+    match parts.as_slice() {
+        [date] => digits(date, 8..=8),
+        [year, month, day] => digits(year, 4..=4) && digits(month, 1..=2) && digits(day, 1..=2),
+        _ => false,
+    }
+}
+
+/// The newest `.cli` format version, declared in a file with a `# clinvoice-format: N` comment.
+///
+/// Files without the comment are version 1.  `clinvoice migrate` rewrites older files.
+pub const FORMAT_VERSION: u32 = 2;
+const FORMAT_DIRECTIVE: &str = "clinvoice-format:";
+
+/// Parses a `# clinvoice-format: N` comment, returning `None` for any other line.
+pub fn parse_format_directive(line: &str) -> Option<u32> {
+    line.trim()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix(FORMAT_DIRECTIVE)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+const PROJECT_DIRECTIVE: &str = "clinvoice-project:";
+
+/// Parses a `# clinvoice-project: NAME` comment, assigning the entries below it to a project,
+/// returning `None` for any other line.  An empty name ends the assignment.
+pub fn parse_project_directive(line: &str) -> Option<String> {
+    Some(line.trim()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix(PROJECT_DIRECTIVE)?
+        .trim()
+        .to_string())
+}
+
+/// Returns the comment declaring a format version.
+pub fn format_directive(version: u32) -> String {
+    format!("# {} {}", FORMAT_DIRECTIVE, version)
+}
+
 /// Calculates the last day of a given month and year.
 pub fn last_day_of_month(year: i32, month: u32) -> NaiveDate {
     if month == 12 {
@@ -74,18 +139,56 @@ pub fn parse_date_arg(arg: &str) -> Result<DateRange, String> {
     }
 }
 
-/// Parses a time specification string into a floating-point number of hours.
-///
-/// Supported formats:
-/// - "Xh": X hours (e.g., "8h", "0.5h").
-/// - "HH:MM-HH:MM": A time range (e.g., "09:00-17:00", "9-17").
-pub fn parse_time_spec(time_spec: &str) -> Result<f32, String> {
+/// Parses a weekday argument, a day name like "mon" or "Monday", or a range of days like "mon-wed".
+pub fn parse_weekdays(arg: &str) -> Result<Vec<Weekday>, String> {
+    let day = |name: &str| name.trim().parse::<Weekday>()
+        .map_err(|_| format!("Invalid weekday: {}", name));
+    match arg.split_once('-') {
+        Some((first, last)) => {
+            let (mut day, last) = (day(first)?, day(last)?);
+            let mut days = vec![day];
+            while day != last {
+                day = day.succ();
+                days.push(day);
+            }
+            Ok(days)
+        }
+        None => Ok(vec![day(arg)?]),
+    }
+}
+
+// Parses a time specification into hours, and the interval of a time range, with `now` as
+// the end of open-ended time ranges like "9:00-", which are an error without it.
+//
+// Supported formats:
+// - "Xh": X hours (e.g., "8h", "0.5h").
+// - "Xm" or "XhYm": minutes, alone or after hours (e.g., "90m", "1h30m").
+// - "H:MM": hours and minutes (e.g., "1:30").
+// - "HH:MM-HH:MM": A time range (e.g., "09:00-17:00", "9-17").
+fn parse_span(time_spec: &str, now: Option<NaiveTime>) -> Result<(f32, Option<Interval>), String> {
     let time_spec = time_spec.trim();
     if time_spec.ends_with('h') {
         let hours_str = time_spec.trim_end_matches('h');
         hours_str
             .parse::<f32>()
+            .map(|hours| (hours, None))
             .map_err(|_| "Invalid hour format".to_string())
+    } else if let Some(minutes_str) = time_spec.strip_suffix('m') {
+        let (sign, minutes_str) = match minutes_str.strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (1.0, minutes_str),
+        };
+        let (hours_str, minutes_str) = minutes_str.split_once('h').unwrap_or(("0", minutes_str));
+        let hours = hours_str.parse::<u32>().map_err(|_| "Invalid minute format".to_string())?;
+        let minutes = minutes_str.parse::<f32>().map_err(|_| "Invalid minute format".to_string())?;
+        Ok((sign * (hours as f32 + minutes / 60.0), None))
+    } else if time_spec.contains(':') && !time_spec.contains('-') {
+        let (hours_str, minutes_str) = time_spec.split_once(':').unwrap();
+        let hours = hours_str.parse::<u32>().map_err(|_| "Invalid duration format".to_string())?;
+        let minutes = minutes_str.parse::<u32>().ok()
+            .filter(|minutes| *minutes < 60 && minutes_str.len() == 2)
+            .ok_or("Invalid duration format".to_string())?;
+        Ok((hours as f32 + minutes as f32 / 60.0, None))
     } else if time_spec.contains('-') {
         let parts: Vec<&str> = time_spec.split('-').map(|s| s.trim()).collect();
         if parts.len() != 2 {
@@ -94,6 +197,19 @@ pub fn parse_time_spec(time_spec: &str) -> Result<f32, String> {
         let start_str = parts[0];
         let end_str_raw = parts[1];
 
+        if end_str_raw.is_empty() && !start_str.is_empty() {
+            let now = now.ok_or("Open-ended time range, only allowed on today's date".to_string())?;
+            let start_str = if start_str.contains(':') { start_str.to_string() } else { format!("{}:00", start_str) };
+            let start = NaiveTime::parse_from_str(&start_str, "%H:%M")
+                .map_err(|_| "Invalid start time".to_string())?;
+            let minutes = now.signed_duration_since(start).num_minutes();
+            if minutes < 0 {
+                return Err("Start time after the current time".to_string());
+            }
+            let end = start + chrono::Duration::minutes(minutes);
+            return Ok((minutes as f32 / 60.0, Some(Interval { start, end })));
+        }
+
         let start_str_formatted = if start_str.contains(':') {
             start_str.to_string()
         } else {
@@ -118,16 +234,16 @@ pub fn parse_time_spec(time_spec: &str) -> Result<f32, String> {
 
         let duration = end.signed_duration_since(start);
 
-        if duration.num_minutes() < 0 {
+        if duration.num_minutes() < 0 || (is_midnight && duration.num_minutes() == 0) {
             if is_midnight {
                 let hours = (chrono::Duration::hours(24) + duration).num_minutes() as f32 / 60.0;
-                return Ok(hours);
+                return Ok((hours, Some(Interval { start, end })));
             }
             return Err("End time before start time".to_string());
         }
 
         let hours = duration.num_minutes() as f32 / 60.0;
-        Ok(hours)
+        Ok((hours, Some(Interval { start, end })))
     } else {
         Err("Invalid time specification format".to_string())
     }
@@ -137,7 +253,18 @@ pub fn parse_time_spec(time_spec: &str) -> Result<f32, String> {
 ///
 /// Lines can represent time entries, fixed costs, or notes.
 pub fn parse_line(line: &str) -> Result<Entry, String> {
+    parse_line_at(line, None)
+}
+
+/// Parses a line like `parse_line`, with `now` as the end of open-ended time ranges.
+pub fn parse_line_at(line: &str, now: Option<NaiveTime>) -> Result<Entry, String> {
     let line = line.trim();
+    if let Some(rest) = line.strip_prefix('~') {
+        return match parse_line_at(rest, now)? {
+            Entry::Time(hours, description, _) => Ok(Entry::NonBillable(hours, description)),
+            _ => Err("Only time entries can be non-billable".to_string()),
+        };
+    }
     if line.starts_with('-') || line.starts_with('*') {
         let (first, rest) = line.split_at(1);
         if first == "-" && rest.contains('=') {
@@ -163,6 +290,15 @@ pub fn parse_line(line: &str) -> Result<Entry, String> {
         let cost_str = value_part.trim_start_matches("-$");
         let cost = cost_str.parse::<f32>().map_err(|_| "Invalid cost format".to_string())?;
         Ok(Entry::FixedCost(-cost, description))
+    } else if let Some((quantity, price)) = value_part.split_once(['x', '×'])
+        .filter(|(_, price)| price.trim().trim_start_matches('-').starts_with('$')) {
+        let quantity = quantity.trim().parse::<f32>().map_err(|_| "Invalid quantity format".to_string())?;
+        let (sign, price) = match price.trim().strip_prefix('-') {
+            Some(price) => (-1.0, price),
+            None => (1.0, price.trim()),
+        };
+        let price = price.trim_start_matches('$').parse::<f32>().map_err(|_| "Invalid cost format".to_string())?;
+        Ok(Entry::Units(quantity, sign * price, description))
     }
     else {
         let time_specs: Vec<&str> = value_part.split(',').map(|s| s.trim()).collect();
@@ -170,10 +306,13 @@ pub fn parse_line(line: &str) -> Result<Entry, String> {
             return Err("No time specifications provided".to_string());
         }
         let mut total_hours = 0.0;
+        let mut intervals = Vec::new();
         for time_spec in time_specs {
-            total_hours += parse_time_spec(time_spec)?;
+            let (hours, interval) = parse_span(time_spec, now)?;
+            total_hours += hours;
+            intervals.extend(interval);
         }
-        Ok(Entry::Time(total_hours, description))
+        Ok(Entry::Time(total_hours, description, intervals))
     }
 }
 
@@ -183,6 +322,14 @@ mod tests {
     use crate::data::Entry;
     use chrono::NaiveDate;
 
+    fn parse_time_spec(time_spec: &str) -> Result<f32, String> {
+        parse_time_spec_at(time_spec, None)
+    }
+
+    fn parse_time_spec_at(time_spec: &str, now: Option<NaiveTime>) -> Result<f32, String> {
+        parse_span(time_spec, now).map(|(hours, _)| hours)
+    }
+
     #[test]
     fn test_parse_date_valid_formats() {
         assert_eq!(parse_date("2023.01.15"), Some(NaiveDate::from_ymd_opt(2023, 1, 15).unwrap()));
@@ -190,6 +337,17 @@ mod tests {
         assert_eq!(parse_date("2023-01-15"), Some(NaiveDate::from_ymd_opt(2023, 1, 15).unwrap()));
     }
 
+    #[test]
+    fn test_parse_date_line() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 3).unwrap();
+        assert_eq!(parse_date_line("2024.05.03"), Some(Ok(date)));
+        assert_eq!(parse_date_line("2024.05.03 Friday"), Some(Ok(date)));
+        assert_eq!(parse_date_line("2024-05-03  fri"), Some(Ok(date)));
+        assert_eq!(parse_date_line("2024.05.03 Thursday"), Some(Err("2024.05.03 is a Friday, not Thursday".to_string())));
+        assert_eq!(parse_date_line("2024.05.03 standup"), None);
+        assert_eq!(parse_date_line("8h = work"), None);
+    }
+
     #[test]
     fn test_parse_date_invalid_formats() {
         assert_eq!(parse_date("2023/01/15"), None);
@@ -203,6 +361,25 @@ mod tests {
         assert_eq!(parse_date("2023.02.29"), None); // Non-leap year
     }
 
+    #[test]
+    fn test_looks_like_date() {
+        assert!(looks_like_date("2025.02.30"));
+        assert!(looks_like_date("20251340"));
+        assert!(looks_like_date("2025-2-3"));
+        assert!(!looks_like_date("9-12 = meeting"));
+        assert!(!looks_like_date("2025.02"));
+        assert!(!looks_like_date("8h"));
+    }
+
+    #[test]
+    fn test_parse_format_directive() {
+        assert_eq!(parse_format_directive("# clinvoice-format: 2"), Some(2));
+        assert_eq!(parse_format_directive("  #clinvoice-format:3  "), Some(3));
+        assert_eq!(parse_format_directive(&format_directive(FORMAT_VERSION)), Some(FORMAT_VERSION));
+        assert_eq!(parse_format_directive("# clinvoice-format: two"), None);
+        assert_eq!(parse_format_directive("# a comment"), None);
+    }
+
     #[test]
     fn test_last_day_of_month_valid_months() {
         assert_eq!(last_day_of_month(2023, 1), NaiveDate::from_ymd_opt(2023, 1, 31).unwrap());
@@ -290,39 +467,99 @@ mod tests {
         assert_eq!(parse_time_spec("-5h").unwrap(), -5.0); // Negative hours
     }
 
+    #[test]
+    fn test_parse_time_spec_valid_minutes() {
+        assert_eq!(parse_time_spec("90m").unwrap(), 1.5);
+        assert_eq!(parse_time_spec("1h30m").unwrap(), 1.5);
+        assert_eq!(parse_time_spec("2h0m").unwrap(), 2.0);
+        assert_eq!(parse_time_spec("-15m").unwrap(), -0.25);
+        assert_eq!(parse_time_spec("1:30").unwrap(), 1.5);
+        assert_eq!(parse_time_spec("0:45").unwrap(), 0.75);
+        assert_eq!(parse_time_spec("9:00").unwrap(), 9.0);
+    }
+
     #[test]
     fn test_parse_time_spec_valid_range() {
         assert_eq!(parse_time_spec("9:00-17:00").unwrap(), 8.0);
         assert_eq!(parse_time_spec("9-17").unwrap(), 8.0);
         assert_eq!(parse_time_spec("22-24").unwrap(), 2.0);
+        assert_eq!(parse_time_spec("00:00-24:00").unwrap(), 24.0);
         assert_eq!(parse_time_spec("9:30-10:00").unwrap(), 0.5);
         assert_eq!(parse_time_spec("17:00-9:00").unwrap_err(), "End time before start time".to_string());
     }
 
+    #[test]
+    fn test_parse_time_spec_open_ended() {
+        let now = NaiveTime::from_hms_opt(11, 45, 30);
+        assert_eq!(parse_time_spec_at("9:00-", now).unwrap(), 2.75);
+        assert_eq!(parse_time_spec_at("11-", now).unwrap(), 0.75);
+        assert_eq!(parse_time_spec_at("12:00-", now).unwrap_err(), "Start time after the current time");
+        assert!(parse_time_spec_at("9:00-", None).unwrap_err().contains("today"));
+        assert!(parse_time_spec_at("-", now).is_err());
+    }
+
     #[test]
     fn test_parse_time_spec_invalid() {
         assert!(parse_time_spec("invalid").is_err());
-        assert!(parse_time_spec("9:00").is_err()); // Not a range or hours
+        assert!(parse_time_spec("9:5").is_err()); // Minutes need two digits
+        assert!(parse_time_spec("1:75").is_err());
+        assert!(parse_time_spec("1.5h30m").is_err());
+        assert!(parse_time_spec("m").is_err());
         assert!(parse_time_spec("9:00-").is_err()); // Incomplete range
         assert!(parse_time_spec("-17:00").is_err()); // Incomplete range
     }
 
+    #[test]
+    fn test_parse_project_directive() {
+        assert_eq!(parse_project_directive("# clinvoice-project: acme"), Some("acme".to_string()));
+        assert_eq!(parse_project_directive("#clinvoice-project:"), Some(String::new()));
+        assert_eq!(parse_project_directive("# project: acme"), None);
+        assert_eq!(parse_project_directive("3h = clinvoice-project: acme"), None);
+    }
+
     #[test]
     fn test_parse_line_time_entry() {
         let entry = parse_line("8h = Development").unwrap();
-        assert!(matches!(entry, Entry::Time(h, d) if h == 8.0 && d == "Development"));
+        assert!(matches!(entry, Entry::Time(h, d, _) if h == 8.0 && d == "Development"));
+    }
+
+    #[test]
+    fn test_parse_line_non_billable() {
+        assert_eq!(parse_line("~2h = internal meeting"), Ok(Entry::NonBillable(2.0, "internal meeting".to_string())));
+        assert_eq!(parse_line("~ 9-10:30 = training"), Ok(Entry::NonBillable(1.5, "training".to_string())));
+        assert_eq!(parse_line("~$50 = lunch").unwrap_err(), "Only time entries can be non-billable");
+    }
+
+    #[test]
+    fn test_parse_line_intervals() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert_eq!(parse_line("9-12:30, 1h, 22-24 = Development"), Ok(Entry::Time(6.5, "Development".to_string(), vec![
+            Interval { start: time(9, 0), end: time(12, 30) },
+            Interval { start: time(22, 0), end: time(0, 0) },
+        ])));
+        let entry = parse_line_at("9:00- = Support", Some(time(10, 15))).unwrap();
+        assert_eq!(entry, Entry::Time(1.25, "Support".to_string(), vec![Interval { start: time(9, 0), end: time(10, 15) }]));
+    }
+
+    #[test]
+    fn test_parse_line_units() {
+        assert_eq!(parse_line("3 x $50 = licenses"), Ok(Entry::Units(3.0, 50.0, "licenses".to_string())));
+        assert_eq!(parse_line("2.5x$8.40 = parking"), Ok(Entry::Units(2.5, 8.4, "parking".to_string())));
+        assert_eq!(parse_line("2 × -$10 = refunds"), Ok(Entry::Units(2.0, -10.0, "refunds".to_string())));
+        assert_eq!(parse_line("a x $50 = licenses").unwrap_err(), "Invalid quantity format");
+        assert_eq!(parse_line("3 x $5o = licenses").unwrap_err(), "Invalid cost format");
     }
 
     #[test]
     fn test_parse_line_time_entry_negative() {
         let entry = parse_line("-2h = Correction").unwrap();
-        assert!(matches!(entry, Entry::Time(h, d) if h == -2.0 && d == "Correction"));
+        assert!(matches!(entry, Entry::Time(h, d, _) if h == -2.0 && d == "Correction"));
     }
 
     #[test]
     fn test_parse_line_multiple_time_specs() {
         let entry = parse_line("1h, 2h, 3h = Multiple Tasks").unwrap();
-        assert!(matches!(entry, Entry::Time(h, d) if h == 6.0 && d == "Multiple Tasks"));
+        assert!(matches!(entry, Entry::Time(h, d, _) if h == 6.0 && d == "Multiple Tasks"));
     }
 
     #[test]
@@ -352,4 +589,13 @@ mod tests {
         assert!(parse_line("invalid = Description").is_err()); // Invalid time spec
         assert!(parse_line("").is_err()); // Empty string
     }
+
+    #[test]
+    fn test_parse_weekdays() {
+        assert_eq!(parse_weekdays("mon"), Ok(vec![Weekday::Mon]));
+        assert_eq!(parse_weekdays("Tuesday"), Ok(vec![Weekday::Tue]));
+        assert_eq!(parse_weekdays("mon-wed"), Ok(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed]));
+        assert_eq!(parse_weekdays("sat-mon"), Ok(vec![Weekday::Sat, Weekday::Sun, Weekday::Mon]));
+        assert!(parse_weekdays("someday").is_err());
+    }
 }
@@ -0,0 +1,87 @@
+//! External subcommands: `clinvoice foo` runs a `clinvoice-foo` executable found on `PATH`.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The prefix of plugin executables.
+pub const PREFIX: &str = "clinvoice-";
+
+/// Finds the `clinvoice-<name>` executable in the directories of a `PATH` value.
+pub fn find(name: &str, path: &OsStr) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return None;
+    }
+    env::split_paths(path)
+        .map(|dir| dir.join(format!("{}{}", PREFIX, name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs the plugin named by the first of `args`, with the rest as its arguments, and exits
+/// with its status.
+///
+/// The global options are passed in `env`, as `CLINVOICE_*` environment variables.
+pub fn run(args: Vec<String>, env: &[(&str, String)]) -> ! {
+    let Some((name, rest)) = args.split_first() else {
+        tracing::error!("No command given");
+        std::process::exit(1);
+    };
+    let Some(program) = find(name, &env::var_os("PATH").unwrap_or_default()) else {
+        tracing::error!("Unknown command {}, and no {}{} was found on PATH", name, PREFIX, name);
+        std::process::exit(1);
+    };
+    tracing::debug!("running plugin {}", program.display());
+
+    let mut command = Command::new(&program);
+    command.args(rest).envs(env.iter().map(|(key, value)| (key, value)));
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let err = command.exec();
+        tracing::error!("Failed to run {}: {}", program.display(), err);
+        std::process::exit(1);
+    }
+    #[cfg(not(unix))]
+    match command.status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            tracing::error!("Failed to run {}: {}", program.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find() {
+        use std::os::unix::fs::PermissionsExt;
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let plain = first.path().join("clinvoice-hello");
+        std::fs::write(&plain, "not executable").unwrap();
+        let plugin = second.path().join("clinvoice-hello");
+        std::fs::write(&plugin, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = env::join_paths([first.path(), second.path()]).unwrap();
+        assert_eq!(find("hello", &path), Some(plugin));
+        assert_eq!(find("missing", &path), None);
+        assert_eq!(find("../clinvoice-hello", &path), None);
+    }
+}
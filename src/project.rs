@@ -0,0 +1,90 @@
+//! Named projects, registered in `~/.config/clinvoice/projects.toml`.
+//!
+//! Each project maps a name to a data directory, and optionally a configuration file:
+//!
+//! ```toml
+//! [projects]
+//! acme = "~/work/acme/timesheets"
+//!
+//! [projects.globex]
+//! directory = "~/work/globex"
+//! config = "~/work/globex/invoicing.toml"
+//! ```
+
+use crate::data::expand_home;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// The data directory and configuration file of a registered project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub directory: String,
+    pub config: Option<String>,
+}
+
+/// Returns the path of the project registry, `~/.config/clinvoice/projects.toml`.
+pub fn registry_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(Path::new(&home).join(".config").join("clinvoice").join("projects.toml"))
+}
+
+/// Looks up a project by name in the registry file.
+///
+/// # Errors
+///
+/// Returns a `String` error if the registry cannot be read or parsed, or has no such project.
+pub fn lookup(registry: &Path, name: &str) -> Result<Project, String> {
+    let content = fs::read_to_string(registry)
+        .map_err(|e| format!("Failed to read project registry {}: {}", registry.display(), e))?;
+    parse(&content, name).map_err(|e| format!("{}: {}", registry.display(), e))
+}
+
+/// Finds a project by name in the content of a registry file.
+///
+/// # Errors
+///
+/// Returns a `String` error if the content is invalid, or has no such project.
+pub fn parse(content: &str, name: &str) -> Result<Project, String> {
+    let value: Value = toml::from_str(content).map_err(|e| e.to_string())?;
+    let entry = value.get("projects").and_then(|p| p.get(name))
+        .ok_or_else(|| format!("no project named {}", name))?;
+    let path = |s: &str| expand_home(s).to_string_lossy().to_string();
+    match entry {
+        Value::String(directory) => Ok(Project { directory: path(directory), config: None }),
+        Value::Table(table) => {
+            let directory = table.get("directory").and_then(|d| d.as_str())
+                .ok_or_else(|| format!("project {} has no directory", name))?;
+            Ok(Project {
+                directory: path(directory),
+                config: table.get("config").and_then(|c| c.as_str()).map(path),
+            })
+        }
+        _ => Err(format!("project {} must be a directory or a table", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+[projects]
+acme = "/work/acme"
+
+[projects.globex]
+directory = "/work/globex"
+config = "/work/globex/invoicing.toml"
+"#;
+        assert_eq!(parse(content, "acme"), Ok(Project { directory: "/work/acme".to_string(), config: None }));
+        assert_eq!(parse(content, "globex"), Ok(Project {
+            directory: "/work/globex".to_string(),
+            config: Some("/work/globex/invoicing.toml".to_string()),
+        }));
+        assert!(parse(content, "initech").unwrap_err().contains("no project named initech"));
+        assert!(parse("[projects]\nbad = 3\n", "bad").is_err());
+    }
+}
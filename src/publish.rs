@@ -0,0 +1,218 @@
+//! Publishing of a static HTML dashboard built from the data directory.
+
+use crate::billing::BillingRules;
+use crate::clock;
+use crate::config::Config;
+use crate::data::{DateSelector, Selection, TimeData};
+use crate::diagnostics;
+use crate::generate::{compute, format_tera_error, register_filters, DayOptions};
+use crate::heatmap::get_daily_hours;
+use crate::index::{index_file_path, Index, ISSUED};
+use crate::log::{aggregate, LogFormat};
+use crate::quiet;
+use crate::rates::{self, Rates};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use tera::{Context, Tera};
+
+const DEFAULT_TEMPLATE: &str = include_str!("publish.html");
+
+#[derive(Serialize)]
+struct MonthBar {
+    label: String,
+    hours: f32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct HeatmapCell {
+    date: Option<String>,
+    hours: f64,
+    class: &'static str,
+}
+
+#[derive(Serialize)]
+struct HeatmapRow {
+    label: &'static str,
+    cells: Vec<HeatmapCell>,
+}
+
+/// Renders `index.html` and `data.json` into `output_dir`.
+///
+/// The page shows monthly hours, a heatmap of daily hours, and the invoices recorded
+/// in the index with their computed amounts.  The built-in page can be replaced with
+/// a Tera template named by `publish.template`.
+pub fn run(
+    output_dir: &str,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+    selection: &Selection,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let time_data = TimeData::load(directory, &selector, &selection.load_options(config.as_ref())).unwrap_or_else(|err| {
+        tracing::error!("Failed to load data: {}", err);
+        diagnostics::exit(1);
+    });
+
+    let (rendered, data) = render(directory, config.as_ref(), &selector, &time_data, &Context::new()).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let invoices = data["invoices"].as_array().map_or(0, |invoices| invoices.len());
+
+    let output = Path::new(output_dir);
+    let result = fs::create_dir_all(output)
+        .and_then(|_| fs::write(output.join("index.html"), rendered))
+        .and_then(|_| fs::write(output.join("data.json"), serde_json::to_string_pretty(&data).unwrap()));
+    if let Err(err) = result {
+        tracing::error!("Failed to write dashboard to {}: {}", output.display(), err);
+        diagnostics::exit(1);
+    }
+    tracing::info!("Published dashboard to {}", output.join("index.html").display());
+    quiet::summary("published", &[
+        ("invoices", invoices.to_string()),
+        ("file", output.join("index.html").display().to_string()),
+    ]);
+}
+
+/// Renders the dashboard page, and the data it shows as JSON.
+///
+/// `extra` holds variables added to the template context, like `served` for the pages
+/// of `serve`.
+///
+/// # Errors
+///
+/// Returns a `String` error if the template cannot be read or rendered.
+pub fn render(
+    directory: &str,
+    config: Option<&Config>,
+    selector: &DateSelector,
+    time_data: &TimeData,
+    extra: &Context,
+) -> Result<(String, Value), String> {
+    let month_rows = aggregate(time_data, &LogFormat::Month);
+    let max_month = month_rows.iter().map(|r| r.hours).fold(0.0, f32::max);
+    let months: Vec<MonthBar> = month_rows.iter().map(|r| MonthBar {
+        label: r.period.clone(),
+        hours: r.hours,
+        height: if max_month > 0.0 { (r.hours.max(0.0) / max_month * 120.0) as u32 } else { 0 },
+    }).collect();
+
+    let mut dates_worked: Vec<&NaiveDate> = time_data.entries.keys().collect();
+    dates_worked.sort();
+
+    let invoices = invoices(config, directory);
+
+    let title = config
+        .and_then(|c| c.get_string("publish.title").or_else(|| c.get_string("client.company")))
+        .unwrap_or("clinvoice".to_string());
+
+    let mut context = extra.clone();
+    context.insert("title", &title);
+    context.insert("generated", &clock::now(config).format("%Y-%m-%d %H:%M").to_string());
+    context.insert("first_date", &dates_worked.first().map(|d| d.format("%Y-%m-%d").to_string()));
+    context.insert("last_date", &dates_worked.last().map(|d| d.format("%Y-%m-%d").to_string()));
+    context.insert("days_worked", &dates_worked.len());
+    context.insert("total_hours", &month_rows.iter().fold(0.0, |acc, r| acc + r.hours));
+    context.insert("months", &months);
+    context.insert("heatmap", &heatmap_rows(time_data, selector));
+    context.insert("invoices", &invoices);
+
+    let template = match config.and_then(|c| c.get_string("publish.template")) {
+        Some(path) => fs::read_to_string(Path::new(directory).join(&path))
+            .map_err(|err| format!("Unable to read template file {}: {}", path, err))?,
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+
+    let mut tera = Tera::default();
+    register_filters(&mut tera);
+    match Rates::cached(directory, None) {
+        Ok(Some(exchange)) => {
+            let currency = config.and_then(rates::currency).unwrap_or(exchange.base.clone());
+            exchange.register_filter(&mut tera, &currency);
+        }
+        Ok(None) => rates::register_missing_filter(&mut tera),
+        Err(err) => return Err(format!("Failed to load exchange rates: {}", err)),
+    }
+    let rendered = tera.add_raw_template("dashboard", &template)
+        .and_then(|_| tera.render("dashboard", &context))
+        .map_err(format_tera_error)?;
+
+    let data = json!({ "months": months, "invoices": invoices });
+    Ok((rendered, data))
+}
+
+// Lays out daily hours as seven weekday rows, one column per week.
+fn heatmap_rows(time_data: &TimeData, selector: &DateSelector) -> Vec<HeatmapRow> {
+    let daily_hours = get_daily_hours(time_data, &selector.ranges);
+    let (Some(start), Some(end)) = (daily_hours.keys().min().copied(), daily_hours.keys().max().copied()) else {
+        return Vec::new();
+    };
+    let max_hours = daily_hours.values().cloned().fold(0.0, f64::max);
+
+    let mut first_monday = start;
+    while first_monday.weekday() != Weekday::Mon {
+        first_monday = first_monday.pred_opt().unwrap();
+    }
+
+    let labels = ["Mon", "", "Wed", "", "Fri", "", "Sun"];
+    let mut rows: Vec<HeatmapRow> = labels.iter().map(|label| HeatmapRow { label, cells: Vec::new() }).collect();
+    let mut date = first_monday;
+    while date <= end || date.weekday() != Weekday::Mon {
+        let row = &mut rows[date.weekday().num_days_from_monday() as usize];
+        if date < start || date > end {
+            row.cells.push(HeatmapCell { date: None, hours: 0.0, class: "none" });
+        } else {
+            let hours = daily_hours.get(&date).cloned().unwrap_or(0.0);
+            let class = match hours / max_hours {
+                h if h <= 0.0 || h.is_nan() => "l0",
+                h if h <= 0.25 => "l1",
+                h if h <= 0.5 => "l2",
+                h if h <= 0.75 => "l3",
+                _ => "l4",
+            };
+            row.cells.push(HeatmapCell { date: Some(date.format("%Y-%m-%d").to_string()), hours, class });
+        }
+        date = date.succ_opt().unwrap();
+    }
+    rows
+}
+
+// Lists the invoices in the index, recomputing their hours and amounts from the current data.
+fn invoices(config: Option<&Config>, directory: &str) -> Vec<Value> {
+    let path = index_file_path(config, directory);
+    let index = match Index::open_read_only(&path) {
+        Ok(index) => index,
+        Err(err) => {
+            tracing::warn!("Failed to open index {}: {}", path.display(), err);
+            return Vec::new();
+        }
+    };
+
+    index.sequences().into_iter().map(|(sequence, dates)| {
+        let state = index.state(sequence).unwrap_or(ISSUED);
+        let totals = config.filter(|_| state == ISSUED).and_then(|config| {
+            let selection = Selection::from_key(&dates);
+            let mut time_data = TimeData::load(directory, &selection.selector().ok()?, &selection.load_options(Some(config))).ok()?;
+            time_data.retain_tagged(&selection.tags);
+            let rules = BillingRules::load(Some(config), directory).ok()?;
+            Some(compute(config, &time_data, "none", &DayOptions::default(), rules.as_ref()))
+        });
+        json!({
+            "sequence": sequence,
+            "dates": dates,
+            "state": state,
+            "hours": totals.as_ref().map(|t| t.total_hours),
+            "amount": totals.as_ref().map(|t| t.total_amount),
+        })
+    }).collect()
+}
@@ -0,0 +1,62 @@
+use std::sync::OnceLock;
+
+static G_QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Initializes the global quiet state.
+///
+/// This function should be called once at the application startup.
+pub fn init(quiet: bool) {
+    G_QUIET.set(quiet).expect("init called multiple times");
+}
+
+/// Returns true if quiet mode was requested.
+///
+/// Defaults to false if `init` has not been called.
+pub fn enabled() -> bool {
+    G_QUIET.get().copied().unwrap_or(false)
+}
+
+/// Formats a single machine-friendly summary line, like `generated seq=14 hours=86.5`.
+pub fn format_summary(event: &str, fields: &[(&str, String)]) -> String {
+    let mut line = event.to_string();
+    for (key, value) in fields {
+        line.push(' ');
+        line.push_str(key);
+        line.push('=');
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            line.push_str(&format!("{:?}", value));
+        } else {
+            line.push_str(value);
+        }
+    }
+    line
+}
+
+/// Prints the summary line for a command, only when quiet mode is enabled.
+pub fn summary(event: &str, fields: &[(&str, String)]) {
+    if enabled() {
+        println!("{}", format_summary(event, fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_summary() {
+        let line = format_summary("generated", &[
+            ("seq", "14".to_string()),
+            ("hours", format!("{}", 86.5)),
+            ("amount", format!("{:.2}", 12975.0)),
+            ("file", "invoice-14.pdf".to_string()),
+        ]);
+        assert_eq!(line, "generated seq=14 hours=86.5 amount=12975.00 file=invoice-14.pdf");
+    }
+
+    #[test]
+    fn test_format_summary_quotes_spaces() {
+        let line = format_summary("published", &[("dir", "my site".to_string()), ("title", String::new())]);
+        assert_eq!(line, "published dir=\"my site\" title=\"\"");
+    }
+}
@@ -0,0 +1,312 @@
+//! Exchange rates, fetched from a provider and cached in the data directory.
+
+use crate::config::Config;
+use crate::quiet;
+use chrono::NaiveDate;
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::{to_value, try_get_value, Tera, Value};
+
+/// Where fetched rates are cached, inside the data directory.
+pub const RATES_DIR: &str = ".clinvoice/rates";
+
+/// The provider used unless `rates.provider` names another, in the format of the ECB data
+/// published by frankfurter.app.  `{base}` and `{date}` are replaced.
+pub const DEFAULT_PROVIDER: &str = "https://api.frankfurter.app/{date}?from={base}";
+
+/// Actions on the cached exchange rates.
+#[derive(Subcommand, Debug)]
+pub enum RatesAction {
+    /// Fetch the daily exchange rates and cache them in the data directory
+    Fetch {
+        #[clap(short, long, help = "currency the rates are quoted against, rates.base or EUR by default")]
+        base: Option<String>,
+        #[clap(long, help = "day to fetch, YYYY-MM-DD, the latest by default")]
+        date: Option<String>,
+    },
+    /// List the cached exchange rates
+    List,
+}
+
+/// The rates of one day, as units of each currency per unit of `base`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rates {
+    pub base: String,
+    pub date: NaiveDate,
+    pub rates: BTreeMap<String, f64>,
+}
+
+impl Rates {
+    /// Parses a provider response, like `{"base": "EUR", "date": "2025-03-04", "rates": {"USD": 1.08}}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the response is not in this format.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let mut rates: Rates = serde_json::from_str(json).map_err(|e| format!("Invalid rates: {}", e))?;
+        rates.base = rates.base.to_uppercase();
+        rates.rates = rates.rates.into_iter().map(|(currency, rate)| (currency.to_uppercase(), rate)).collect();
+        Ok(rates)
+    }
+
+    // Units of `currency` per unit of the base currency.
+    fn rate(&self, currency: &str) -> Option<f64> {
+        let currency = currency.to_uppercase();
+        if currency == self.base {
+            Some(1.0)
+        } else {
+            self.rates.get(&currency).copied().filter(|rate| *rate > 0.0)
+        }
+    }
+
+    /// Returns how many units of `to` one unit of `from` is worth, through the base currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if either currency has no rate.
+    pub fn exchange_rate(&self, from: &str, to: &str) -> Result<f64, String> {
+        let missing = |currency: &str| format!("No {} rate for {} on {}", self.base, currency.to_uppercase(), self.date);
+        let from_rate = self.rate(from).ok_or_else(|| missing(from))?;
+        let to_rate = self.rate(to).ok_or_else(|| missing(to))?;
+        Ok(to_rate / from_rate)
+    }
+
+    /// Converts an amount of `from` into `to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if either currency has no rate.
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Result<f64, String> {
+        Ok(amount * self.exchange_rate(from, to)?)
+    }
+
+    // The cache file holding these rates.
+    fn cache_path(&self, directory: &str) -> PathBuf {
+        rates_dir(directory).join(format!("{}-{}.json", self.base, self.date.format("%Y-%m-%d")))
+    }
+
+    /// Loads the latest cached rates on or before `date`, or the latest of all without one.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no rates were cached for that day or earlier.
+    pub fn cached(directory: &str, date: Option<NaiveDate>) -> Result<Option<Self>, String> {
+        let latest = cached_files(directory)?.into_iter()
+            .filter(|(day, _)| date.is_none_or(|date| *day <= date))
+            .max_by_key(|(day, _)| *day);
+        let Some((_, path)) = latest else {
+            return Ok(None);
+        };
+        let json = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Rates::parse(&json).map(Some).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Registers the `convert` filter, which converts an amount between currencies, in a
+    /// Tera instance, like `{{ total_amount | convert(to="USD") }}`.
+    ///
+    /// `from` defaults to `default_currency`, the currency of the amounts.
+    pub fn register_filter(&self, tera: &mut Tera, default_currency: &str) {
+        let rates = self.clone();
+        let default_currency = default_currency.to_string();
+        tera.register_filter("convert", move |value: &Value, args: &HashMap<String, Value>| {
+            let amount = try_get_value!("convert", "value", f64, value);
+            let from = match args.get("from") {
+                Some(from) => try_get_value!("convert", "from", String, from),
+                None => default_currency.clone(),
+            };
+            let to = match args.get("to") {
+                Some(to) => try_get_value!("convert", "to", String, to),
+                None => return Err("Filter `convert` expected an arg called `to`".into()),
+            };
+            let converted = rates.convert(amount, &from, &to).map_err(tera::Error::msg)?;
+            Ok(to_value(converted).unwrap())
+        });
+    }
+}
+
+/// Registers a `convert` filter that fails with a hint to fetch rates, for when none are cached.
+pub fn register_missing_filter(tera: &mut Tera) {
+    tera.register_filter("convert", |_: &Value, _: &HashMap<String, Value>| {
+        Err(tera::Error::msg("No exchange rates cached, run clinvoice rates fetch"))
+    });
+}
+
+/// Returns the directory holding the cached rates of a data directory.
+pub fn rates_dir(directory: &str) -> PathBuf {
+    Path::new(directory).join(RATES_DIR)
+}
+
+// Lists the cached rate files with their dates, from their `<BASE>-<YYYY-MM-DD>.json` names.
+fn cached_files(directory: &str) -> Result<Vec<(NaiveDate, PathBuf)>, String> {
+    let dir = rates_dir(directory);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut found = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let date = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split_once('-'))
+            .and_then(|(_, date)| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        if let (Some(date), true) = (date, path.extension().is_some_and(|ext| ext == "json")) {
+            found.push((date, path));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Returns the currency of amounts, from `contract.currency`.
+pub fn currency(config: &Config) -> Option<String> {
+    config.get_string("contract.currency").map(|c| c.to_uppercase())
+}
+
+// Builds the provider URL for a base currency and day.
+fn provider_url(provider: &str, base: &str, date: Option<NaiveDate>) -> String {
+    let date = date.map_or("latest".to_string(), |d| d.format("%Y-%m-%d").to_string());
+    provider.replace("{base}", base).replace("{date}", &date)
+}
+
+/// Fetches the daily rates for a base currency from the provider.
+///
+/// # Errors
+///
+/// Returns a `String` error if the request fails, or the response is not in the provider format.
+pub fn fetch(provider: &str, base: &str, date: Option<NaiveDate>) -> Result<Rates, String> {
+    let url = provider_url(provider, base, date);
+    tracing::debug!("GET {}", url);
+    let body = ureq::get(&url)
+        .set("User-Agent", "clinvoice")
+        .call()
+        .map_err(|e| format!("Exchange rate request failed: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Invalid exchange rate response: {}", e))?;
+    let rates = Rates::parse(&body)?;
+    if rates.base != base {
+        return Err(format!("Asked for {} rates, got {}", base, rates.base));
+    }
+    Ok(rates)
+}
+
+/// Fetches rates into the cache, or lists the cached ones.
+pub fn run(
+    action: RatesAction,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+
+    match action {
+        RatesAction::Fetch { base, date } => {
+            if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+                tracing::error!("Cannot fetch rates in read-only mode");
+                std::process::exit(1);
+            }
+            let base = base.or_else(|| config.as_ref().and_then(|c| c.get_string("rates.base")))
+                .unwrap_or("EUR".to_string())
+                .to_uppercase();
+            let date = date.map(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap_or_else(|err| {
+                tracing::error!("Invalid date {}: {}", date, err);
+                std::process::exit(1);
+            }));
+            let provider = config.as_ref().and_then(|c| c.get_string("rates.provider"))
+                .unwrap_or(DEFAULT_PROVIDER.to_string());
+
+            let rates = fetch(&provider, &base, date).unwrap_or_else(|err| {
+                tracing::error!("{}", err);
+                std::process::exit(1);
+            });
+            let path = rates.cache_path(directory);
+            let result = fs::create_dir_all(rates_dir(directory))
+                .and_then(|_| fs::write(&path, serde_json::to_string_pretty(&rates).unwrap()));
+            if let Err(err) = result {
+                tracing::error!("Failed to write {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+            tracing::info!("Cached {} {} rates for {} in {}", rates.rates.len(), rates.base, rates.date, path.display());
+            quiet::summary("rates", &[
+                ("base", rates.base.clone()),
+                ("date", rates.date.to_string()),
+                ("count", rates.rates.len().to_string()),
+            ]);
+        }
+        RatesAction::List => {
+            let files = cached_files(directory).unwrap_or_else(|err| {
+                tracing::error!("Failed to read cached rates: {}", err);
+                std::process::exit(1);
+            });
+            if quiet::enabled() {
+                quiet::summary("rates", &[("count", files.len().to_string())]);
+                return;
+            }
+            for (_, path) in files {
+                match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|json| Rates::parse(&json)) {
+                    Ok(rates) => println!("{}  {}  {} currencies", rates.date, rates.base, rates.rates.len()),
+                    Err(err) => tracing::warn!("{}: {}", path.display(), err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Context;
+
+    fn rates() -> Rates {
+        Rates::parse(r#"{"amount": 1.0, "base": "EUR", "date": "2025-03-04", "rates": {"USD": 1.25, "GBP": 0.8}}"#).unwrap()
+    }
+
+    #[test]
+    fn test_convert() {
+        let rates = rates();
+        assert_eq!(rates.convert(100.0, "EUR", "USD").unwrap(), 125.0);
+        assert_eq!(rates.convert(125.0, "usd", "EUR").unwrap(), 100.0);
+        assert_eq!(rates.convert(80.0, "GBP", "USD").unwrap(), 125.0);
+        assert!(rates.convert(1.0, "EUR", "JPY").is_err());
+    }
+
+    #[test]
+    fn test_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        assert_eq!(Rates::cached(directory, None).unwrap(), None);
+
+        let first = rates();
+        let mut second = rates();
+        second.date = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        fs::create_dir_all(rates_dir(directory)).unwrap();
+        for rates in [&first, &second] {
+            fs::write(rates.cache_path(directory), serde_json::to_string(rates).unwrap()).unwrap();
+        }
+
+        assert_eq!(Rates::cached(directory, None).unwrap(), Some(second));
+        assert_eq!(Rates::cached(directory, NaiveDate::from_ymd_opt(2025, 3, 9)).unwrap(), Some(first));
+        assert_eq!(Rates::cached(directory, NaiveDate::from_ymd_opt(2025, 3, 1)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_provider_url() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 4);
+        assert_eq!(provider_url(DEFAULT_PROVIDER, "EUR", date), "https://api.frankfurter.app/2025-03-04?from=EUR");
+        assert_eq!(provider_url(DEFAULT_PROVIDER, "USD", None), "https://api.frankfurter.app/latest?from=USD");
+    }
+
+    #[test]
+    fn test_convert_filter() {
+        let mut tera = Tera::default();
+        rates().register_filter(&mut tera, "EUR");
+        let mut context = Context::new();
+        context.insert("total", &200.0);
+        let rendered = tera.render_str("{{ total | convert(to='USD') }} {{ 100 | convert(from='GBP', to='EUR') }}", &context).unwrap();
+        assert_eq!(rendered, "250 125");
+    }
+}
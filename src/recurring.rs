@@ -0,0 +1,143 @@
+//! Entries that recur, from the `[recurring]` tables of the configuration.
+//!
+//! Each table names an entry, written as in a timesheet, and when it happens: on a `day` of
+//! every month, or on some `weekday`s.  `start` and `end` limit it to a period:
+//!
+//! ```toml
+//! [recurring.retainer]
+//!     entry = "$2000 = retainer"
+//!     day   = 1
+//!
+//! [recurring.maintenance]
+//!     entry   = "2h = maintenance"
+//!     weekday = "mon"
+//!     start   = "2025.01.01"
+//! ```
+//!
+//! `TimeData` adds them to the selected dates when loading, as if written in a timesheet.
+
+use crate::config::Config;
+use crate::data::Entry;
+use crate::parse::{last_day_of_month, parse_date, parse_line, parse_weekdays};
+use chrono::{Datelike, NaiveDate, Weekday};
+use toml::Value;
+
+/// When a recurring entry happens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum When {
+    /// On this day of every month, or its last day in shorter months.
+    MonthDay(u32),
+    /// On these days of every week.
+    Weekdays(Vec<Weekday>),
+}
+
+/// An entry added on a schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurring {
+    /// The name of its table, like `retainer` for `[recurring.retainer]`.
+    pub name: String,
+    /// The entry, as written in a timesheet.
+    pub line: String,
+    pub when: When,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl Recurring {
+    /// Checks if the entry happens on `date`.
+    pub fn occurs(&self, date: NaiveDate) -> bool {
+        if self.start.is_some_and(|start| date < start) || self.end.is_some_and(|end| date > end) {
+            return false;
+        }
+        match &self.when {
+            When::MonthDay(day) => date.day() == (*day).min(last_day_of_month(date.year(), date.month()).day()),
+            When::Weekdays(days) => days.contains(&date.weekday()),
+        }
+    }
+
+    /// Returns the entry, parsed.
+    pub fn entry(&self) -> Entry {
+        parse_line(&self.line).expect("recurring entries are checked when read")
+    }
+}
+
+// Reads one `[recurring.<name>]` table.
+fn read(name: &str, value: &Value) -> Result<Recurring, String> {
+    let field = |key: &str| value.get(key);
+    let date = |key: &str| match field(key) {
+        Some(value) => value.as_str().and_then(parse_date).map(Some)
+            .ok_or(format!("recurring.{}.{} is not a date like 2025.01.31", name, key)),
+        None => Ok(None),
+    };
+    let line = field("entry").and_then(Value::as_str)
+        .ok_or(format!("recurring.{}.entry is missing, like \"2h = maintenance\"", name))?;
+    parse_line(line).map_err(|err| format!("recurring.{}.entry: {}", name, err))?;
+    let when = match (field("day"), field("weekday")) {
+        (Some(day), None) => match day.as_integer().filter(|day| (1..=31).contains(day)) {
+            Some(day) => When::MonthDay(day as u32),
+            None => return Err(format!("recurring.{}.day is not a day of the month, from 1 to 31", name)),
+        },
+        (None, Some(weekday)) => {
+            let arg = weekday.as_str().ok_or(format!("recurring.{}.weekday is not a string, like \"mon\"", name))?;
+            let days = arg.split(',').map(parse_weekdays).collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("recurring.{}.weekday: {}", name, err))?;
+            When::Weekdays(days.concat())
+        }
+        _ => return Err(format!("recurring.{} needs either a day of the month or a weekday", name)),
+    };
+    Ok(Recurring { name: name.to_string(), line: line.to_string(), when, start: date("start")?, end: date("end")? })
+}
+
+/// Reads the `[recurring]` tables of the configuration.
+///
+/// # Errors
+///
+/// Returns a `String` error for tables without a valid entry and schedule.
+pub fn from_config(config: &Config) -> Result<Vec<Recurring>, String> {
+    config.get_table("recurring").into_iter().flatten()
+        .map(|(name, value)| read(name, value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn recurring(text: &str) -> Result<Recurring, String> {
+        let value: Value = toml::from_str(text).unwrap();
+        read("test", &value)
+    }
+
+    #[test]
+    fn test_month_day() {
+        let retainer = recurring("entry = \"$2000 = retainer\"\nday = 31\nend = \"2025.03.31\"").unwrap();
+        assert_eq!(retainer.entry(), Entry::FixedCost(2000.0, "retainer".to_string()));
+        assert!(retainer.occurs(date(1, 31)));
+        assert!(retainer.occurs(date(2, 28)));
+        assert!(!retainer.occurs(date(3, 30)));
+        assert!(!retainer.occurs(date(4, 30)));
+    }
+
+    #[test]
+    fn test_weekdays() {
+        let maintenance = recurring("entry = \"2h = maintenance\"\nweekday = \"mon,thu-fri\"\nstart = \"2025.03.04\"").unwrap();
+        assert_eq!(maintenance.when, When::Weekdays(vec![Weekday::Mon, Weekday::Thu, Weekday::Fri]));
+        assert!(!maintenance.occurs(date(3, 3)));
+        assert!(maintenance.occurs(date(3, 6)));
+        assert!(maintenance.occurs(date(3, 10)));
+        assert!(!maintenance.occurs(date(3, 11)));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(recurring("entry = \"2h = a\"").unwrap_err().contains("either a day"));
+        assert!(recurring("entry = \"2h = a\"\nday = 1\nweekday = \"mon\"").unwrap_err().contains("either a day"));
+        assert!(recurring("entry = \"2x = a\"\nday = 1").unwrap_err().contains("recurring.test.entry"));
+        assert!(recurring("day = 32\nentry = \"2h = a\"").unwrap_err().contains("from 1 to 31"));
+        assert!(recurring("entry = \"2h = a\"\nweekday = \"someday\"").unwrap_err().contains("Invalid weekday"));
+    }
+}
@@ -0,0 +1,510 @@
+//! Reports that compare the logged time against expectations, or explain the hours billed.
+
+use crate::color::*;
+use crate::billing::BillingRules;
+use crate::clock;
+use crate::config::Config;
+use crate::generate::{compute, DayOptions};
+use crate::data::{DateRange, DateSelector, LoadOptions, Selection, TimeData};
+use crate::diagnostics;
+use crate::parse::{parse_date_arg, parse_weekdays};
+use crate::quiet;
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::BTreeSet;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// The hours expected on each day of the week, from the `[schedule]` configuration.
+///
+/// `schedule.hours` are expected on each of `schedule.days`, and a day can be given its own
+/// hours, like `schedule.fri = 4`.  Nothing is expected on the `schedule.holidays`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    hours: [f64; 7],
+    holidays: Vec<DateRange>,
+}
+
+impl Default for Schedule {
+    /// Eight hours from Monday to Friday.
+    fn default() -> Self {
+        Schedule { hours: [8.0, 8.0, 8.0, 8.0, 8.0, 0.0, 0.0], holidays: Vec::new() }
+    }
+}
+
+impl Schedule {
+    /// Reads the schedule from the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if there is no `[schedule]`, or it names invalid weekdays or
+    /// holidays.
+    pub fn from_config(config: Option<&Config>) -> Result<Self, String> {
+        let config = config.filter(|c| c.has("schedule"))
+            .ok_or("No [schedule] is configured, like days = \"mon-fri\" and hours = 8")?;
+        let default_hours = config.get_f64("schedule.hours").unwrap_or(0.0);
+        let days = config.get_string("schedule.days").unwrap_or("mon-fri".to_string());
+        let mut hours = [0.0; 7];
+        for days in days.split(',') {
+            for day in parse_weekdays(days)? {
+                hours[day.num_days_from_monday() as usize] = default_hours;
+            }
+        }
+        for (i, name) in WEEKDAYS.iter().enumerate() {
+            if let Some(day_hours) = config.get_f64(&format!("schedule.{}", name)) {
+                hours[i] = day_hours;
+            }
+        }
+        let holidays = config.get_string_array("schedule.holidays").unwrap_or_default().iter()
+            .map(|holiday| parse_date_arg(holiday).map_err(|e| format!("Invalid schedule.holidays date {}: {}", holiday, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Schedule { hours, holidays })
+    }
+
+    /// Returns the hours expected on a day of the week.
+    pub fn expected(&self, weekday: Weekday) -> f64 {
+        self.hours[weekday.num_days_from_monday() as usize]
+    }
+
+    /// Returns the hours expected on a date, none on holidays.
+    pub fn expected_on(&self, date: NaiveDate) -> f64 {
+        if self.holidays.iter().any(|r| r.start <= date && date <= r.end) {
+            return 0.0;
+        }
+        self.expected(date.weekday())
+    }
+}
+
+/// How a day compares with the schedule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Missing, // Nothing logged on a scheduled day.
+    Short,   // Fewer hours than scheduled.
+    Over,    // More hours than scheduled.
+}
+
+/// A day that does not match the schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayStatus {
+    pub date: NaiveDate,
+    pub expected: f64,
+    pub logged: f64,
+    pub status: Status,
+}
+
+/// The expected and logged hours of an ISO week, with the days that do not match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Week {
+    pub year: i32,
+    pub week: u32,
+    pub expected: f64,
+    pub logged: f64,
+    pub days: Vec<DayStatus>,
+}
+
+/// Compares the hours logged on each of `days` with the schedule, grouped by ISO week.
+pub fn compliance(time_data: &TimeData, schedule: &Schedule, days: &BTreeSet<NaiveDate>) -> Vec<Week> {
+    let mut weeks: Vec<Week> = Vec::new();
+    for date in days {
+        let iso = date.iso_week();
+        if weeks.last().is_none_or(|w| (w.year, w.week) != (iso.year(), iso.week())) {
+            weeks.push(Week { year: iso.year(), week: iso.week(), expected: 0.0, logged: 0.0, days: Vec::new() });
+        }
+        let week = weeks.last_mut().unwrap();
+
+        let entries = time_data.entries.get(date);
+        let logged = entries.into_iter().flatten().fold(0.0, |acc, entry| acc + entry.hours() as f64);
+        let expected = schedule.expected_on(*date);
+        week.expected += expected;
+        week.logged += logged;
+
+        let status = if expected > 0.0 && entries.is_none() {
+            Some(Status::Missing)
+        } else if logged < expected {
+            Some(Status::Short)
+        } else if logged > expected {
+            Some(Status::Over)
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            week.days.push(DayStatus { date: *date, expected, logged, status });
+        }
+    }
+    weeks
+}
+
+/// Returns the working days of the schedule among `days` with nothing logged.
+pub fn missing(time_data: &TimeData, schedule: &Schedule, days: &BTreeSet<NaiveDate>) -> Vec<NaiveDate> {
+    days.iter()
+        .filter(|date| schedule.expected_on(**date) > 0.0 && !time_data.entries.contains_key(date))
+        .copied()
+        .collect()
+}
+
+// The days to report on: the selected days up to today, or the days from the first entry.
+fn report_days(time_data: &TimeData, selector: &DateSelector, today: NaiveDate) -> BTreeSet<NaiveDate> {
+    let mut days = BTreeSet::new();
+    let ranges: Vec<(NaiveDate, NaiveDate)> = if selector.ranges.is_empty() {
+        time_data.entries.keys().min().map(|first| (*first, today)).into_iter().collect()
+    } else {
+        selector.ranges.iter().map(|r| (r.start, r.end.min(today))).collect()
+    };
+    for (start, end) in ranges {
+        days.extend(start.iter_days().take_while(|d| *d <= end).filter(|d| selector.selected(d)));
+    }
+    days
+}
+
+/// Runs the selected report.
+pub fn run(
+    compliance_report: bool,
+    audit_report: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+    selection: &Selection,
+) {
+    if compliance_report == audit_report {
+        tracing::error!("Select one report, --compliance or --audit");
+        diagnostics::exit(1);
+    }
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    // Recurring entries are billed, but are not work logged against the schedule.
+    let mut options = LoadOptions { progress: true, ..selection.load_options(config.as_ref()) };
+    if compliance_report {
+        options.recurring.clear();
+    }
+    let time_data = TimeData::load(directory, &selector, &options)
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+
+    if audit_report {
+        let Some(config) = config else {
+            tracing::error!("No configuration found, the audit needs the [contract]");
+            diagnostics::exit(1);
+        };
+        audit(&config, directory, &time_data);
+        return;
+    }
+
+    let schedule = Schedule::from_config(config.as_ref()).unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let days = report_days(&time_data, &selector, clock::today(config.as_ref()));
+    let weeks = compliance(&time_data, &schedule, &days);
+    if quiet::enabled() {
+        let count = |status| weeks.iter().flat_map(|w| &w.days).filter(|d| d.status == status).count().to_string();
+        quiet::summary("compliance", &[
+            ("weeks", weeks.len().to_string()),
+            ("missing", count(Status::Missing)),
+            ("short", count(Status::Short)),
+            ("over", count(Status::Over)),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    for week in &weeks {
+        let balance = format!("{:+8.2}", week.logged - week.expected);
+        println!("{}-W{:02}  expected {:8.2}  logged {}  {}",
+            week.year, week.week, week.expected,
+            format!("{:8.2}", week.logged).out_colored(theme.hours),
+            if week.logged < week.expected { balance.out_colored(theme.warning) } else { balance.out_colored(theme.total) });
+        for day in &week.days {
+            let (label, color) = match day.status {
+                Status::Missing => ("missing", theme.error),
+                Status::Short => ("short", theme.warning),
+                Status::Over => ("over", theme.count),
+            };
+            println!("  {} {}  {:7}  {:6.2} / {:.2}",
+                day.date.format("%Y.%m.%d").to_string().out_colored(theme.period),
+                day.date.format("%a"),
+                label.out_colored(color),
+                day.logged, day.expected);
+        }
+    }
+}
+
+/// Lists the working days with nothing logged, in the selection up to today, or from the
+/// first entry.  The working days come from the `[schedule]`, Monday to Friday without one.
+pub fn run_missing(directory_option: &Option<String>, config_file: &Option<String>, selection: &Selection) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    // A day with only recurring entries has nothing logged.
+    let options = LoadOptions { progress: true, recurring: Vec::new(), ..selection.load_options(config.as_ref()) };
+    let time_data = TimeData::load(directory, &selector, &options)
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    let schedule = match config.as_ref().filter(|c| c.has("schedule")) {
+        Some(config) => Schedule::from_config(Some(config)).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            diagnostics::exit(1);
+        }),
+        None => Schedule::default(),
+    };
+    let days = report_days(&time_data, &selector, clock::today(config.as_ref()));
+    let missing = missing(&time_data, &schedule, &days);
+    if quiet::enabled() {
+        quiet::summary("missing", &[
+            ("days", missing.len().to_string()),
+            ("first", missing.first().map(|d| d.format("%Y.%m.%d").to_string()).unwrap_or_default()),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    for date in &missing {
+        println!("{} {}", date.format("%Y.%m.%d").to_string().out_colored(theme.period), date.format("%a"));
+    }
+    let count = format!("{} working days missing", missing.len());
+    if missing.is_empty() {
+        println!("{}", "No working days missing".out_colored(theme.total));
+    } else {
+        println!("{}", count.out_colored(theme.warning));
+    }
+}
+
+/// The hours worked and earned in a month, against `contract.target_hours_per_month`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonthSummary {
+    /// The month, in `YYYY-MM` format.
+    pub label: String,
+    pub hours: f64,
+    pub target: Option<f64>,
+    /// The hours billed at the hourly rate plus the fixed costs, before invoice caps and tax.
+    pub earned: f64,
+    /// The days with time logged.
+    pub working_days: usize,
+}
+
+impl MonthSummary {
+    /// Returns the hours worked as a percentage of the target.
+    pub fn utilization(&self) -> Option<f64> {
+        self.target.filter(|t| *t > 0.0).map(|target| self.hours / target * 100.0)
+    }
+
+    /// Returns the average hours per working day.
+    pub fn average(&self) -> f64 {
+        if self.working_days == 0 { 0.0 } else { self.hours / self.working_days as f64 }
+    }
+}
+
+/// Sums the hours worked and earned in each month of the time data.
+pub fn summary(config: &Config, time_data: &TimeData, rules: Option<&BillingRules>) -> Vec<MonthSummary> {
+    let target = config.get_f64("contract.target_hours_per_month");
+    let invoice = compute(config, time_data, "none", &DayOptions::default(), rules);
+    invoice.months.iter().map(|month| MonthSummary {
+        label: month.label.clone(),
+        hours: month.days.iter().map(|day| day.raw_hours as f64).sum(),
+        target,
+        earned: month.amount,
+        working_days: month.days.iter().filter(|day| day.raw_hours > 0.0).count(),
+    }).collect()
+}
+
+/// Prints the hours worked against the target, the amount earned, and the working days of
+/// each selected month, as a table.
+pub fn run_summary(directory_option: &Option<String>, config_file: &Option<String>, selection: &Selection) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let Ok(config) = Config::new(config_file.as_deref(), Some(directory)) else {
+        tracing::error!("No configuration found, the summary needs the [contract]");
+        diagnostics::exit(1);
+    };
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.load_options(Some(&config)) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    let rules = BillingRules::load(Some(&config), directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to load billing rules: {}", err);
+        diagnostics::exit(1);
+    });
+    let months = summary(&config, &time_data, rules.as_ref());
+    let total = MonthSummary {
+        label: "Total".to_string(),
+        hours: months.iter().map(|m| m.hours).sum(),
+        target: months.iter().map(|m| m.target).sum(),
+        earned: months.iter().map(|m| m.earned).sum(),
+        working_days: months.iter().map(|m| m.working_days).sum(),
+    };
+    if quiet::enabled() {
+        quiet::summary("summary", &[
+            ("months", months.len().to_string()),
+            ("hours", format!("{:.2}", total.hours)),
+            ("earned", format!("{:.2}", total.earned)),
+            ("days", total.working_days.to_string()),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    let percent = |m: &MonthSummary| m.utilization().map_or("-".to_string(), |u| format!("{:.0}%", u));
+    let target = |m: &MonthSummary| m.target.map_or("-".to_string(), |t| format!("{:.2}", t));
+    println!("{:<8} {:>8} {:>8} {:>6} {:>10} {:>5} {:>8}", "Month", "Hours", "Target", "Util", "Earned", "Days", "Avg/day");
+    for month in &months {
+        println!("{} {} {:>8} {:>6} {:>10.2} {:>5} {:>8.2}",
+            format!("{:<8}", month.label).out_colored(theme.period),
+            format!("{:>8.2}", month.hours).out_colored(theme.hours),
+            target(month), percent(month), month.earned, month.working_days, month.average());
+    }
+    println!("{} {:>8.2} {:>8} {:>6} {:>10.2} {:>5} {:>8.2}",
+        format!("{:<8}", total.label).out_colored(theme.total),
+        total.hours, target(&total), percent(&total), total.earned, total.working_days, total.average());
+}
+
+// Lists the days whose billed hours differ from the hours logged, with the reasons, and the
+// adjustments made to the whole invoice.
+fn audit(config: &Config, directory: &str, time_data: &TimeData) {
+    let rules = BillingRules::load(Some(config), directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to load billing rules: {}", err);
+        diagnostics::exit(1);
+    });
+    let invoice = compute(config, time_data, "none", &DayOptions::default(), rules.as_ref());
+    let adjusted: Vec<_> = invoice.days.iter().filter(|day| !day.adjustments.is_empty()).collect();
+    if quiet::enabled() {
+        quiet::summary("audit", &[
+            ("days", invoice.days.len().to_string()),
+            ("adjusted", adjusted.len().to_string()),
+            ("worked", format!("{:.2}", invoice.total_hours_worked)),
+            ("billed", format!("{:.2}", invoice.total_hours_billed)),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    for day in &adjusted {
+        println!("{}  raw {:6.2}  capped {:6.2}  rounded {:6.2}  billed {}",
+            day.date.out_colored(theme.period),
+            day.raw_hours, day.capped_hours, day.rounded_hours,
+            format!("{:6.2}", day.hours).out_colored(theme.hours));
+        for reason in &day.adjustments {
+            println!("    {}", reason);
+        }
+    }
+    if invoice.overage_hours > 0.0 {
+        println!("{}: {:.2} hours over contract.cap_hours_per_invoice are not billed",
+            "Invoice".out_colored(theme.period), invoice.overage_hours);
+    }
+    if invoice.billing_adjustment != 0.0 {
+        println!("{}: billed_amount in the billing script changed the amount by {:+.2}",
+            "Invoice".out_colored(theme.period), invoice.billing_adjustment);
+    }
+    println!("{} {:.2} hours worked, {:.2} billed, {} of {} days adjusted",
+        "Total:".out_colored(theme.total),
+        invoice.total_hours_worked, invoice.total_hours_billed, adjusted.len(), invoice.days.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_compliance() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "\
+2025.03.03
+8h = Work
+2025.03.04
+4h = Work
+2025.03.06
+10h = Work
+2025.03.07
+4h = Work
+2025.03.08
+2h = Weekend work
+").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        let schedule = Schedule { hours: [8.0, 8.0, 8.0, 8.0, 4.0, 0.0, 0.0], holidays: Vec::new() };
+        let days: BTreeSet<NaiveDate> = (3..=10).map(date).collect();
+
+        let weeks = compliance(&time_data, &schedule, &days);
+        assert_eq!(weeks.len(), 2);
+        assert_eq!((weeks[0].year, weeks[0].week), (2025, 10));
+        assert_eq!((weeks[0].expected, weeks[0].logged), (36.0, 28.0));
+        let found: Vec<_> = weeks[0].days.iter().map(|d| (d.date, d.status)).collect();
+        assert_eq!(found, vec![
+            (date(4), Status::Short),
+            (date(5), Status::Missing),
+            (date(6), Status::Over),
+            (date(8), Status::Over),
+        ]);
+        assert_eq!(weeks[1].days, vec![DayStatus { date: date(10), expected: 8.0, logged: 0.0, status: Status::Missing }]);
+    }
+
+    #[test]
+    fn test_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n8h = Work\n2025.03.05\n- a note\n2025.03.08\n2h = Weekend work\n").unwrap();
+        std::fs::write(dir.path().join("clinvoice.toml"), "[schedule]\nhours = 8\nholidays = [\"2025.03.06\", \"2025.03.11-2025.03.12\"]\n").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        let days: BTreeSet<NaiveDate> = (3..=14).map(date).collect();
+
+        assert_eq!(missing(&time_data, &Schedule::default(), &days), vec![date(4), date(6), date(7), date(10), date(11), date(12), date(13), date(14)]);
+        let config = Config::new(None, dir.path().to_str()).unwrap();
+        let schedule = Schedule::from_config(Some(&config)).unwrap();
+        assert_eq!(schedule.expected_on(date(6)), 0.0);
+        assert_eq!(schedule.expected_on(date(7)), 8.0);
+        assert_eq!(missing(&time_data, &schedule, &days), vec![date(4), date(7), date(10), date(13), date(14)]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("clinvoice.toml"), "[contract]\nhourly_rate = 100\ntarget_hours_per_month = 20\n").unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "\
+2025.03.03
+8h = Work
+$50 = Parking
+2025.03.04
+4h = Work
+2025.03.05
+- a note
+2025.04.01
+6h = Work
+").unwrap();
+        let config = Config::new(None, dir.path().to_str()).unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+
+        let months = summary(&config, &time_data, None);
+        assert_eq!(months.len(), 2);
+        assert_eq!(months[0], MonthSummary {
+            label: "2025-03".to_string(), hours: 12.0, target: Some(20.0), earned: 1250.0, working_days: 2,
+        });
+        assert_eq!(months[0].utilization(), Some(60.0));
+        assert_eq!(months[0].average(), 6.0);
+        assert_eq!((months[1].hours, months[1].working_days), (6.0, 1));
+    }
+
+    #[test]
+    fn test_report_days() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n8h = Work\n").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+
+        let days = report_days(&time_data, &DateSelector::new(), date(5));
+        assert_eq!(days, (3..=5).map(date).collect());
+        let selector = DateSelector::from_dates(&["2025.03".to_string()]).unwrap();
+        assert_eq!(report_days(&time_data, &selector, date(10)).len(), 10);
+    }
+}
@@ -0,0 +1,302 @@
+//! The settings clinvoice reads from `clinvoice.toml`, and a JSON Schema describing them.
+//!
+//! Other keys are allowed everywhere, since every setting is also a template variable.
+
+use serde_json::{json, Value};
+
+/// The type of a setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    StringArray,
+    StringOrArray, // A string or an array of them, like a command or a program and its arguments.
+    Table,         // Keys chosen by the user.
+}
+
+impl Kind {
+    /// Describes the values of the kind, for error messages.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Kind::String => "a string",
+            Kind::Number => "a number",
+            Kind::Integer => "an integer",
+            Kind::Boolean => "true or false",
+            Kind::StringArray => "an array of strings",
+            Kind::StringOrArray => "a string, or an array of strings",
+            Kind::Table => "a table",
+        }
+    }
+}
+
+/// A setting, by its dotted path.
+#[derive(Debug, Clone, Copy)]
+pub struct Key {
+    pub path: &'static str,
+    pub kind: Kind,
+    pub values: &'static [&'static str], // The accepted values, any if empty.
+    pub description: &'static str,
+}
+
+const fn key(path: &'static str, kind: Kind, description: &'static str) -> Key {
+    Key { path, kind, values: &[], description }
+}
+
+const fn choice(path: &'static str, values: &'static [&'static str], description: &'static str) -> Key {
+    Key { path, kind: Kind::String, values, description }
+}
+
+/// The settings outside of `[generator.<name>]`.
+pub const KEYS: &[Key] = &[
+    key("read_only", Kind::Boolean, "Never write files, as with --read-only"),
+    key("viewer", Kind::String, "Program opening generated files, with generate --open"),
+    key("timezone", Kind::String, "IANA timezone of today and now, like America/Toronto, local time by default"),
+    key("contract.hourly_rate", Kind::Number, "Rate billed per hour"),
+    key("contract.payment_days", Kind::Integer, "Days from the invoice date to the due date"),
+    key("contract.cap_hours_per_day", Kind::Number, "Most hours billed for one day, 0 for no cap"),
+    key("contract.cap_hours_per_invoice", Kind::Number, "Most hours billed on one invoice, 0 for no cap"),
+    key("contract.round_day_hours", Kind::Number, "Round the hours of each day up to a multiple of this"),
+    key("contract.target_hours_per_month", Kind::Number, "Hours aimed for each month, compared in summary"),
+    key("contract.currency", Kind::String, "Currency of the rate and amounts"),
+    key("contract.invoice_currency", Kind::String, "Currency the client pays in, converted with the cached rates"),
+    key("tax.percent", Kind::Number, "Tax added to the subtotal, in percent"),
+    key("generator.default", Kind::String, "Generator used when generate has no --generator"),
+    key("build.shell", Kind::StringArray, "Shell running string build commands"),
+    key("build.negative_words", Kind::StringArray, "Words marking build output lines as problems"),
+    key("build.negative_patterns", Kind::StringArray, "Regular expressions marking build output lines as problems"),
+    key("build.context_lines", Kind::Integer, "Build output lines shown before the first problem"),
+    key("build.tail_lines", Kind::Integer, "Build output lines shown after a successful build"),
+    key("context", Kind::Table, "Template variables computed from Tera expressions"),
+    key("clients", Kind::Table, "Settings of each client, laid over the others with --client"),
+    key("alias", Kind::Table, "Command aliases, like month = \"log -f month\""),
+    key("snippets", Kind::Table, "Entries written as @name lines in timesheets"),
+    key("recurring", Kind::Table, "Entries added on a day of each month or on weekdays, by name"),
+    key("i18n", Kind::Table, "Label translations, by language"),
+    key("invoice.language", Kind::String, "Language of the labels in invoices"),
+    key("index.file", Kind::String, "Name of the index file"),
+    choice("index.location", &["data", "xdg"], "Keep the index in the data directory, or under $XDG_DATA_HOME"),
+    key("index.project", Kind::String, "Directory of the index under $XDG_DATA_HOME"),
+    key("encryption.age_identity", Kind::String, "Identity file decrypting .cli.age timesheets"),
+    key("encryption.age_command", Kind::String, "Program decrypting .cli.age timesheets"),
+    key("encryption.gpg_command", Kind::String, "Program decrypting .cli.gpg timesheets"),
+    key("tsv.columns", Kind::StringArray, "Columns of .tsv timesheets without a header row"),
+    key("data.recursive", Kind::Boolean, "Read the timesheets of subdirectories too"),
+    key("data.max_depth", Kind::Integer, "How many levels of subdirectories are read, with data.recursive"),
+    key("data.filename_pattern", Kind::String, "Pattern timesheet names must match, checked by validate"),
+    choice("parse.duplicate_dates", &["merge", "warn", "error"], "What to do with a date written in several timesheet files"),
+    key("parse.strict", Kind::Boolean, "Stop log, generate and heatmap on any timesheet problem"),
+    key("validate.strict", Kind::Boolean, "Refuse to generate invoices with anomalies"),
+    key("validate.max_hours_per_day", Kind::Number, "Hours in a day reported as an anomaly"),
+    key("validate.zero_hour_entries", Kind::Boolean, "Report entries of zero hours"),
+    key("validate.duplicate_entries", Kind::Boolean, "Report entries repeated on the same day"),
+    key("validate.future_tolerance_days", Kind::Integer, "Days in the future entries may be dated"),
+    key("colors.period", Kind::String, "Color of periods, a name or #rrggbb"),
+    key("colors.hours", Kind::String, "Color of hours"),
+    key("colors.count", Kind::String, "Color of counts"),
+    key("colors.total", Kind::String, "Color of totals"),
+    key("colors.warning", Kind::String, "Color of warnings"),
+    key("colors.error", Kind::String, "Color of errors"),
+    key("colors.build", Kind::String, "Color of build output"),
+    key("colors.build_error", Kind::String, "Color of build problems"),
+    choice("heatmap.palette", &["truecolor", "ansi256", "ascii"], "How heatmap cells are drawn"),
+    key("heatmap.target", Kind::Number, "Daily hours marked on the heatmap"),
+    key("schedule.days", Kind::String, "Working days, like mon-fri, for report --compliance and missing"),
+    key("schedule.hours", Kind::Number, "Hours expected on each working day, 8 by default"),
+    key("schedule.holidays", Kind::StringArray, "Dates or date ranges when no work is expected"),
+    key("schedule.mon", Kind::Number, "Hours expected on Mondays"),
+    key("schedule.tue", Kind::Number, "Hours expected on Tuesdays"),
+    key("schedule.wed", Kind::Number, "Hours expected on Wednesdays"),
+    key("schedule.thu", Kind::Number, "Hours expected on Thursdays"),
+    key("schedule.fri", Kind::Number, "Hours expected on Fridays"),
+    key("schedule.sat", Kind::Number, "Hours expected on Saturdays"),
+    key("schedule.sun", Kind::Number, "Hours expected on Sundays"),
+    key("billing.script", Kind::String, "Rhai script with billing rules, billing.rhai by default"),
+    key("rates.base", Kind::String, "Base currency of rates fetch"),
+    key("rates.provider", Kind::String, "Exchange rate URL, with {base} and {date}"),
+    key("publish.title", Kind::String, "Title of the published dashboard"),
+    key("publish.template", Kind::String, "Tera template of the published dashboard"),
+    key("notify.webhook.url", Kind::String, "URL posted to after an invoice is generated"),
+    key("notify.webhook.timeout", Kind::Integer, "Seconds to wait for the webhook"),
+    key("notify.webhook.headers", Kind::Table, "Headers sent to the webhook"),
+    key("email.host", Kind::String, "SMTP server invoices are sent through"),
+    key("email.port", Kind::Integer, "SMTP port, the default of the security"),
+    choice("email.security", &["starttls", "tls", "none"], "How the SMTP connection is secured, starttls by default"),
+    key("email.username", Kind::String, "SMTP user"),
+    key("email.password", Kind::String, "SMTP password"),
+    key("email.from", Kind::String, "Sender of invoices"),
+    key("email.to", Kind::StringOrArray, "Recipients of invoices"),
+    key("email.cc", Kind::StringOrArray, "Copied recipients of invoices"),
+    key("email.subject", Kind::String, "Subject, a Tera template"),
+    key("email.body", Kind::String, "Body, a Tera template"),
+    key("email.attachments", Kind::StringArray, "Files attached, Tera templates, the finished invoice by default"),
+    key("import.csv.date", Kind::String, "Column of the date, for import csv"),
+    key("import.csv.hours", Kind::String, "Column of the hours, decimal or H:MM"),
+    key("import.csv.start", Kind::String, "Column of the start time, used when there are no hours"),
+    key("import.csv.end", Kind::String, "Column of the end time"),
+    key("import.csv.description", Kind::String, "Column of the description"),
+    key("import.csv.project", Kind::String, "Column of the project, added as a #tag"),
+    key("import.csv.date_format", Kind::String, "Format of dates not written like in timesheets, like %d/%m/%Y"),
+    key("import.csv.delimiter", Kind::String, "Character separating the columns, , by default"),
+    key("import.harvest.account_id", Kind::String, "Harvest account, for import harvest --api"),
+    key("import.harvest.token", Kind::String, "Harvest personal access token"),
+    key("import.toggl.token", Kind::String, "Toggl Track API token, for import toggl"),
+];
+
+/// The settings of each `[generator.<name>]`.
+pub const GENERATOR_KEYS: &[Key] = &[
+    key("template", Kind::String, "Tera template rendered into the invoice"),
+    choice("escape", &["none", "latex", "tex", "markdown", "md"], "Escaping applied to values"),
+    key("output", Kind::String, "File written, a Tera template"),
+    key("build", Kind::StringOrArray, "Command run after the output is written"),
+    key("shell", Kind::StringArray, "Shell running a string build command"),
+    key("build_log", Kind::String, "File the build output is saved to"),
+    key("open", Kind::String, "File opened by generate --open, the output by default"),
+    key("latest", Kind::String, "Link updated to the latest invoice"),
+    key("latest_file", Kind::String, "File the latest link points to, the output by default"),
+    choice("latest_mode", &["symlink", "copy"], "How the latest link is made"),
+    key("days_csv", Kind::String, "CSV file of the days written with the invoice, a Tera template"),
+    key("skip_zero_days", Kind::Boolean, "Leave out days without hours"),
+    key("include_notes", Kind::Boolean, "Include notes in day descriptions"),
+    key("hide_zero_cost_lines", Kind::Boolean, "Leave out lines that cost nothing"),
+    key("footnote_notes", Kind::Boolean, "List notes as numbered footnotes instead of in descriptions"),
+    key("negative_words", Kind::StringArray, "Words marking build output lines as problems"),
+    key("negative_patterns", Kind::StringArray, "Regular expressions marking build output lines as problems"),
+    key("context_lines", Kind::Integer, "Build output lines shown before the first problem"),
+    key("tail_lines", Kind::Integer, "Build output lines shown after a successful build"),
+    key("context", Kind::Table, "Template variables computed for this generator"),
+    choice("upload.kind", &["webdav", "s3", "sftp"], "Where the invoice is uploaded"),
+    key("upload.url", Kind::String, "Upload location"),
+    key("upload.file", Kind::String, "File uploaded, the output by default"),
+    key("upload.path_template", Kind::String, "Remote path, a Tera template"),
+    key("upload.username", Kind::String, "WebDAV user"),
+    key("upload.password", Kind::String, "WebDAV password"),
+    key("upload.profile", Kind::String, "AWS profile for s3"),
+    key("upload.endpoint_url", Kind::String, "S3 endpoint for other providers"),
+    key("upload.port", Kind::Integer, "SSH port for sftp"),
+];
+
+/// Finds the setting for a dotted path, like `contract.hourly_rate` or `generator.pdf.build`.
+pub fn find(path: &str) -> Option<&'static Key> {
+    if let Some(found) = KEYS.iter().find(|k| k.path == path) {
+        return Some(found);
+    }
+    let (_, key) = path.strip_prefix("generator.")?.split_once('.')?;
+    GENERATOR_KEYS.iter().find(|k| k.path == key)
+}
+
+fn kind_schema(key: &Key) -> Value {
+    let mut schema = match key.kind {
+        Kind::String => json!({ "type": "string" }),
+        Kind::Number => json!({ "type": "number" }),
+        Kind::Integer => json!({ "type": "integer" }),
+        Kind::Boolean => json!({ "type": "boolean" }),
+        Kind::StringArray => json!({ "type": "array", "items": { "type": "string" } }),
+        Kind::StringOrArray => json!({ "anyOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }] }),
+        Kind::Table => json!({ "type": "object" }),
+    };
+    if !key.values.is_empty() {
+        schema["enum"] = json!(key.values);
+    }
+    schema["description"] = json!(key.description);
+    schema
+}
+
+// Builds an object schema from settings, nesting their dotted paths.
+fn object_schema(keys: &[Key]) -> Value {
+    let mut root = json!({ "type": "object", "properties": {} });
+    for key in keys {
+        let mut node = &mut root;
+        let mut parts = key.path.split('.').peekable();
+        while let Some(part) = parts.next() {
+            let properties = node["properties"].as_object_mut().unwrap();
+            if parts.peek().is_none() {
+                properties.insert(part.to_string(), kind_schema(key));
+                break;
+            }
+            node = properties.entry(part).or_insert_with(|| json!({ "type": "object", "properties": {} }));
+        }
+    }
+    root
+}
+
+/// Returns a JSON Schema of `clinvoice.toml`, for editors to validate and complete it.
+pub fn schema() -> Value {
+    let mut root = object_schema(KEYS);
+    root["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+    root["title"] = json!("clinvoice configuration");
+    root["definitions"] = json!({ "generator": object_schema(GENERATOR_KEYS) });
+    let generator = root["properties"]["generator"].as_object_mut().unwrap();
+    generator.insert("additionalProperties".to_string(), json!({ "$ref": "#/definitions/generator" }));
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use serde_json::Map;
+    use std::fs;
+    use std::path::Path;
+
+    fn properties(schema: &Value) -> &Map<String, Value> {
+        schema["properties"].as_object().unwrap()
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = schema();
+        let contract = &properties(&schema)["contract"];
+        assert_eq!(properties(contract)["hourly_rate"]["type"], "number");
+        assert_eq!(properties(&schema)["generator"]["additionalProperties"]["$ref"], "#/definitions/generator");
+        let generator = &schema["definitions"]["generator"];
+        assert_eq!(properties(&properties(generator)["upload"])["kind"]["enum"], json!(["webdav", "s3", "sftp"]));
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("tax.percent").unwrap().kind, Kind::Number);
+        assert_eq!(find("generator.pdf.build").unwrap().kind, Kind::StringOrArray);
+        assert!(find("generator.pdf.nothing").is_none());
+        assert!(find("client.company").is_none());
+    }
+
+    // Every setting read by the code, with a literal key, must be in the schema.
+    #[test]
+    fn test_keys_in_sync_with_code() {
+        let read = Regex::new(r#"\.(?:get_string|get_f64|get_bool|get_i64|get_string_array|get_table|has)\("([a-z_.]+)"\)"#).unwrap();
+        let generator = Regex::new(r#"format!\("\{\}\.([a-z_]+)", generator_prefix\)"#).unwrap();
+        // Read as a template variable, or with a fallback, rather than as a setting.
+        let ignored = ["client.company"];
+
+        let mut sources = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("src")];
+        let mut missing = Vec::new();
+        while let Some(path) = sources.pop() {
+            if path.is_dir() {
+                sources.extend(fs::read_dir(&path).unwrap().map(|entry| entry.unwrap().path()));
+                continue;
+            }
+            if path.extension().is_none_or(|ext| ext != "rs") || path.ends_with("config.rs") {
+                continue;
+            }
+            let code = fs::read_to_string(&path).unwrap();
+            let code = code.split("#[cfg(test)]").next().unwrap();
+            for captures in read.captures_iter(code) {
+                let key = &captures[1];
+                let section = format!("{}.", key);
+                let known = find(key).is_some() || KEYS.iter().any(|k| k.path.starts_with(&section));
+                if !known && !ignored.contains(&key) {
+                    missing.push(format!("{}: {}", path.display(), key));
+                }
+            }
+            for captures in generator.captures_iter(code) {
+                let section = format!("{}.", &captures[1]);
+                if !GENERATOR_KEYS.iter().any(|k| k.path == &captures[1] || k.path.starts_with(&section)) {
+                    missing.push(format!("{}: generator.<name>.{}", path.display(), &captures[1]));
+                }
+            }
+        }
+        assert!(missing.is_empty(), "settings missing from the schema: {:?}", missing);
+    }
+}
@@ -0,0 +1,270 @@
+//! Emailing generated invoices to the client, over SMTP.
+//!
+//! The settings live in the `email` table.  `subject` and `body` are Tera templates, given
+//! the configuration variables, the `sequence`, `dates`, `total_hours` and `total_amount`
+//! of the invoice:
+//!
+//! ```toml
+//! [email]
+//!     host    = "smtp.example.com"
+//!     from    = "Me <me@example.com>"
+//!     to      = ["Client <billing@client.example.com>"]
+//!     subject = "Invoice {{ sequence }}"
+//! ```
+//!
+//! The files attached are those `generate` recorded for the invoice, or the `attachments`
+//! templates.
+
+use crate::config::Config;
+use crate::diff::{self, Record};
+use crate::generate::{format_tera_error, register_filters};
+use crate::quiet;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+
+const SUBJECT: &str = "Invoice {{ sequence }}";
+const BODY: &str = "Hello,\n\nPlease find invoice {{ sequence }} attached, for {{ total_amount | decimal(precision=2) }}.\n\nThank you\n";
+
+/// An email ready to be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub from: String,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<PathBuf>,
+}
+
+// Reads a setting holding one address or an array of them.
+fn addresses(config: &Config, key: &str) -> Vec<String> {
+    config.get_string_array(key)
+        .or_else(|| config.get_string(key).map(|address| vec![address]))
+        .unwrap_or_default()
+}
+
+fn render(name: &str, template: &str, context: &Context) -> Result<String, String> {
+    let mut tera = Tera::default();
+    register_filters(&mut tera);
+    tera.add_raw_template(name, template)
+        .and_then(|_| tera.render(name, context))
+        .map_err(|err| format!("email.{}: {}", name, format_tera_error(err).trim_end()))
+}
+
+/// Composes the email of a recorded invoice, from the `email` settings.
+///
+/// # Errors
+///
+/// Returns a `String` error if `email.from` or `email.to` are missing, a template does not
+/// render, or there is nothing to attach.
+pub fn compose(config: &Config, directory: &str, record: &Record) -> Result<Email, String> {
+    let from = config.get_string("email.from").ok_or("email.from is not defined in config")?;
+    let to = addresses(config, "email.to");
+    if to.is_empty() {
+        return Err("email.to is not defined in config".to_string());
+    }
+
+    let mut context = Context::new();
+    for (key, value) in config.get_flattened_values("_") {
+        context.insert(key, &value);
+    }
+    context.insert("sequence", &record.sequence);
+    context.insert("dates", &record.dates.join(" "));
+    context.insert("generated", &record.generated);
+    context.insert("total_hours", &record.total_hours);
+    context.insert("total_amount", &record.total_amount);
+
+    let subject = render("subject", &config.get_string("email.subject").unwrap_or(SUBJECT.to_string()), &context)?;
+    let body = render("body", &config.get_string("email.body").unwrap_or(BODY.to_string()), &context)?;
+    let attachments: Vec<PathBuf> = match config.get_string_array("email.attachments") {
+        Some(templates) => templates.iter()
+            .map(|template| render("attachments", template, &context).map(|file| Path::new(directory).join(file)))
+            .collect::<Result<_, _>>()?,
+        None => record.files.iter().map(|file| Path::new(directory).join(file)).collect(),
+    };
+    if attachments.is_empty() {
+        return Err(format!("Nothing to attach to invoice {}, set email.attachments or generate it again", record.sequence));
+    }
+    Ok(Email { from, to, cc: addresses(config, "email.cc"), subject: subject.trim().to_string(), body, attachments })
+}
+
+// Guesses the content type of an attachment from its extension.
+fn content_type(path: &Path) -> ContentType {
+    let mime = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pdf") => "application/pdf",
+        Some("html") => "text/html",
+        Some("txt") | Some("md") | Some("tex") => "text/plain",
+        Some("csv") => "text/csv",
+        _ => "application/octet-stream",
+    };
+    ContentType::parse(mime).unwrap()
+}
+
+/// Builds the message of an email, reading its attachments.
+///
+/// # Errors
+///
+/// Returns a `String` error for invalid addresses, or attachments that cannot be read.
+pub fn message(email: &Email) -> Result<Message, String> {
+    let mailbox = |address: &str| address.parse::<Mailbox>().map_err(|err| format!("Invalid address {}: {}", address, err));
+    let mut builder = Message::builder().from(mailbox(&email.from)?).subject(&email.subject);
+    for address in &email.to {
+        builder = builder.to(mailbox(address)?);
+    }
+    for address in &email.cc {
+        builder = builder.cc(mailbox(address)?);
+    }
+    let mut parts = MultiPart::mixed().singlepart(SinglePart::plain(email.body.clone()));
+    for path in &email.attachments {
+        let content = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        parts = parts.singlepart(Attachment::new(name).body(content, content_type(path)));
+    }
+    builder.multipart(parts).map_err(|err| err.to_string())
+}
+
+// Sends a message through the SMTP server of the `email` settings.
+fn deliver(config: &Config, message: &Message) -> Result<(), String> {
+    let host = config.get_string("email.host").ok_or("email.host is not defined in config")?;
+    let security = config.get_string("email.security").unwrap_or("starttls".to_string());
+    let mut transport = match security.as_str() {
+        "starttls" => SmtpTransport::starttls_relay(&host).map_err(|err| err.to_string())?,
+        "tls" => SmtpTransport::relay(&host).map_err(|err| err.to_string())?,
+        "none" => SmtpTransport::builder_dangerous(&host),
+        other => return Err(format!("unknown email.security '{}', expected starttls, tls or none", other)),
+    };
+    if let Some(port) = config.get_i64("email.port") {
+        let port = u16::try_from(port).ok().filter(|port| *port > 0).ok_or(format!("Invalid email.port {}", port))?;
+        transport = transport.port(port);
+    }
+    let username = config.get_string("email.username").or_else(|| env::var("CLINVOICE_EMAIL_USERNAME").ok());
+    let password = config.get_string("email.password").or_else(|| env::var("CLINVOICE_EMAIL_PASSWORD").ok());
+    if let Some(username) = username {
+        transport = transport.credentials(Credentials::new(username, password.unwrap_or_default()));
+    }
+    tracing::info!("Sending through {}", host);
+    transport.build().send(message).map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// Shows an email instead of sending it.
+fn preview(email: &Email) {
+    println!("From: {}", email.from);
+    println!("To: {}", email.to.join(", "));
+    if !email.cc.is_empty() {
+        println!("Cc: {}", email.cc.join(", "));
+    }
+    println!("Subject: {}", email.subject);
+    for path in &email.attachments {
+        match fs::metadata(path) {
+            Ok(metadata) => println!("Attachment: {} ({} bytes)", path.display(), metadata.len()),
+            Err(_) => println!("Attachment: {} (missing)", path.display()),
+        }
+    }
+    println!();
+    print!("{}", email.body);
+}
+
+/// Emails invoice `sequence`, or only shows the email with `dry_run`.
+///
+/// # Errors
+///
+/// Returns a `String` describing the failure.
+pub fn send(config: &Config, directory: &str, sequence: u32, dry_run: bool) -> Result<(), String> {
+    let record = diff::load(directory, sequence).map_err(|err| format!("No record of invoice {} in {}: {}, generate it again to record it",
+        sequence, diff::record_path(directory, sequence).display(), err))?;
+    let email = compose(config, directory, &record)?;
+    if dry_run {
+        preview(&email);
+        return Ok(());
+    }
+    let message = message(&email)?;
+    deliver(config, &message)?;
+    tracing::info!("Sent invoice {} to {}", sequence, email.to.join(", "));
+    quiet::summary("sent", &[
+        ("seq", sequence.to_string()),
+        ("to", email.to.join(",")),
+        ("attachments", email.attachments.len().to_string()),
+    ]);
+    Ok(())
+}
+
+/// Emails a generated invoice to the client.
+pub fn run(sequence: u32, dry_run: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let Ok(config) = Config::new(config_file.as_deref(), Some(directory)) else {
+        tracing::error!("No configuration found, email settings are needed to send");
+        std::process::exit(1);
+    };
+    if let Err(err) = send(&config, directory, sequence, dry_run) {
+        tracing::error!("Failed to send invoice {}: {}", sequence, err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(files: &[&str]) -> Record {
+        Record {
+            sequence: 4,
+            dates: vec!["2025.03".to_string()],
+            generated: "2025-04-01 09:00".to_string(),
+            days: Vec::new(),
+            total_hours: 12.0,
+            total_amount: 1200.0,
+            files: files.iter().map(|file| file.to_string()).collect(),
+            generator: None,
+        }
+    }
+
+    fn config(dir: &Path, text: &str) -> Config {
+        fs::write(dir.join("clinvoice.toml"), text).unwrap();
+        Config::new(None, Some(dir.to_str().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_compose() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let config = config(dir.path(), concat!(
+            "[contract]\ncompany = \"Acme\"\n",
+            "[email]\nfrom = \"me@example.com\"\nto = \"billing@acme.example.com\"\n",
+            "subject = \"Invoice {{ sequence }} for {{ contract_company }}\"\n",
+        ));
+        let email = compose(&config, directory, &record(&["invoice-4.pdf"])).unwrap();
+        assert_eq!(email.to, vec!["billing@acme.example.com"]);
+        assert_eq!(email.subject, "Invoice 4 for Acme");
+        assert!(email.body.contains("invoice 4 attached, for 1200.00"));
+        assert_eq!(email.attachments, vec![dir.path().join("invoice-4.pdf")]);
+        assert!(compose(&config, directory, &record(&[])).unwrap_err().contains("Nothing to attach"));
+    }
+
+    #[test]
+    fn test_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("invoice-4.pdf"), "%PDF-1.4").unwrap();
+        let config = config(dir.path(), concat!(
+            "[email]\nfrom = \"Me <me@example.com>\"\nto = [\"a@example.com\", \"b@example.com\"]\n",
+            "attachments = [\"invoice-{{ sequence }}.pdf\"]\n",
+        ));
+        let email = compose(&config, directory, &record(&[])).unwrap();
+        let formatted = String::from_utf8(message(&email).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("To: a@example.com, b@example.com"));
+        assert!(formatted.contains("filename=\"invoice-4.pdf\""));
+        assert!(formatted.contains("application/pdf"));
+
+        let missing = Email { attachments: vec![dir.path().join("invoice-5.pdf")], ..email.clone() };
+        assert!(message(&missing).unwrap_err().contains("invoice-5.pdf"));
+        let invalid = Email { to: vec!["not an address".to_string()], ..email };
+        assert!(message(&invalid).unwrap_err().contains("Invalid address"));
+    }
+}
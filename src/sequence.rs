@@ -0,0 +1,105 @@
+//! Managing invoice sequence numbers in the index, outside of `generate`.
+
+use crate::config::Config;
+use crate::index::{index_file_path, Index, VOIDED};
+use crate::quiet;
+use crate::undo::{self, Mutation};
+use clap::Subcommand;
+use std::fs;
+
+/// Actions on the sequence numbers recorded in the index.
+#[derive(Subcommand, Debug)]
+pub enum SequenceAction {
+    /// Reserve the next sequence numbers for invoices issued from another system
+    Reserve {
+        #[clap(default_value = "1", help = "number of sequence numbers to reserve")]
+        count: u32,
+    },
+}
+
+/// Runs a sequence action against the index of the data directory.
+pub fn run(
+    action: SequenceAction,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot change the index in read-only mode");
+        std::process::exit(1);
+    }
+
+    let path = index_file_path(config.as_ref(), directory);
+    if let Some(index_dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(index_dir) {
+            tracing::error!("Failed to create {}: {}", index_dir.display(), err);
+            std::process::exit(1);
+        }
+    }
+    let mut index = Index::create(&path).unwrap_or_else(|err| {
+        tracing::error!("Failed to open index {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+
+    match action {
+        SequenceAction::Reserve { count } => {
+            let reserved = index.reserve(count);
+            if let Err(err) = index.save() {
+                tracing::error!("Failed to save index {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+            let list: Vec<String> = reserved.iter().map(|seq| seq.to_string()).collect();
+            if quiet::enabled() {
+                quiet::summary("reserved", &[("sequences", list.join(","))]);
+            } else {
+                for seq in list {
+                    println!("{}", seq);
+                }
+            }
+        }
+    }
+}
+
+/// Voids an invoice: its sequence is kept in the index as `voided`, and generating the same
+/// dates again gives them a new number.
+pub fn void(
+    sequence: u32,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot change the index in read-only mode");
+        std::process::exit(1);
+    }
+
+    let path = index_file_path(config.as_ref(), directory);
+    let mut index = Index::create(&path).unwrap_or_else(|err| {
+        tracing::error!("Failed to open index {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    let previous = (index.dates(sequence).map(|dates| dates.to_vec()), index.state(sequence).map(|state| state.to_string()));
+    match previous.1.as_deref() {
+        None => {
+            tracing::error!("Sequence {} is not in {}", sequence, path.display());
+            std::process::exit(1);
+        }
+        Some(VOIDED) => {
+            tracing::warn!("Sequence {} is already voided", sequence);
+            return;
+        }
+        Some(_) => {}
+    }
+    index.void(sequence);
+    if let Err(err) = index.save() {
+        tracing::error!("Failed to save index {}: {}", path.display(), err);
+        std::process::exit(1);
+    }
+    undo::record(directory, Mutation::Sequence { sequence, dates: previous.0, state: previous.1 });
+    tracing::info!("Voided sequence {}", sequence);
+    quiet::summary("voided", &[("seq", sequence.to_string())]);
+}
@@ -0,0 +1,411 @@
+//! A small HTTP server exposing timesheet data as JSON, and as HTML pages: the dashboard of
+//! `publish`, and the log.  Invoices can be generated from the dashboard.
+//!
+//! Data is reloaded from the data directory on every request, so edits to
+//! `.cli` files are visible without restarting the server.
+
+use crate::clock;
+use crate::config::Config;
+use crate::generate::{format_tera_error, register_filters};
+use crate::publish;
+use crate::quiet;
+use crate::data::{DateSelector, LoadOptions, Selection, TimeData};
+use crate::diagnostics;
+use crate::heatmap::get_daily_hours;
+use crate::index::{index_file_path, Index};
+use crate::log::{aggregate, LogFormat};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Command;
+use tera::{Context, Tera};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const PAGE_TEMPLATE: &str = include_str!("serve.html");
+
+// A response, as JSON or as an HTML page.
+enum Reply {
+    Json(u16, Value),
+    Html(u16, String),
+}
+
+/// Runs the HTTP server until the process is interrupted.
+///
+/// Generating invoices is refused in read-only mode.
+pub fn run(
+    bind: &str,
+    port: u16,
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let read_only = read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false);
+    let address = format!("{}:{}", bind, port);
+    let server = Server::http(&address).unwrap_or_else(|err| {
+        tracing::error!("Failed to listen on {}: {}", address, err);
+        std::process::exit(1);
+    });
+    tracing::info!("Serving {} on http://{}/", directory, address);
+    quiet::summary("serving", &[("url", format!("http://{}/", address))]);
+
+    let hosts = hosts(bind, port);
+
+    for mut request in server.incoming_requests() {
+        let (path, mut query) = split_url(request.url());
+        tracing::debug!("{} {} {:?}", request.method(), path, query);
+        let header = |name: &'static str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.to_string());
+        let same_origin = same_origin(header("Host").as_deref(), header("Origin").as_deref(), &hosts);
+        let reply = match (request.method(), path.as_str()) {
+            (Method::Post, _) if !same_origin => {
+                tracing::warn!("Refusing a POST to {} from another site", path);
+                Reply::Json(403, json!({ "error": "requests must come from the pages of this server" }))
+            }
+            (Method::Post, "/api/generate" | "/generate") => {
+                // Forms send their fields in the body.
+                let mut body = String::new();
+                if request.as_reader().read_to_string(&mut body).is_ok() {
+                    query.extend(split_url(&format!("?{}", body)).1);
+                }
+                let result = generate(directory, config_file, read_only, &query);
+                if path == "/generate" {
+                    let (status, success, output) = match result {
+                        Ok(output) => (200, true, output),
+                        Err(output) => (500, false, output),
+                    };
+                    let mut context = Context::new();
+                    context.insert("success", &success);
+                    context.insert("output", &output);
+                    page(status, "generate", context)
+                } else {
+                    match result {
+                        Ok(output) => Reply::Json(200, json!({ "output": output })),
+                        Err(err) => Reply::Json(500, json!({ "error": err })),
+                    }
+                }
+            }
+            (Method::Get, path) if path.starts_with("/api/") => match handle(path, &query, directory, config_file) {
+                Ok(Some(body)) => Reply::Json(200, body),
+                Ok(None) => Reply::Json(404, json!({ "error": format!("no such endpoint: {}", path) })),
+                Err(err) => Reply::Json(400, json!({ "error": err })),
+            },
+            (Method::Get, "") => dashboard(directory, config.as_ref(), read_only, &query),
+            (Method::Get, "/log") => log_page(directory, config_file, &query),
+            (Method::Get, path) => Reply::Json(404, json!({ "error": format!("no such page: {}", path) })),
+            _ => Reply::Json(405, json!({ "error": "method not allowed" })),
+        };
+        respond(request, reply);
+    }
+}
+
+// Returns the `Host` headers of requests to the server: the address it listens on, and the
+// names of the local host when it listens on the loopback or on every address.
+fn hosts(bind: &str, port: u16) -> Vec<String> {
+    let mut names = vec![bind];
+    if matches!(bind, "127.0.0.1" | "localhost" | "0.0.0.0") {
+        names.extend(["127.0.0.1", "localhost"]);
+    }
+    names.iter().map(|name| format!("{}:{}", name, port)).collect()
+}
+
+// Checks that a request comes from the pages of this server, not from a form of another site
+// open in the browser: its `Host`, and its `Origin` when sent, must be one of the `hosts`.
+fn same_origin(host: Option<&str>, origin: Option<&str>, hosts: &[String]) -> bool {
+    let served = |host: &str| hosts.iter().any(|h| h.eq_ignore_ascii_case(host));
+    host.is_some_and(served) && origin.is_none_or(|origin| origin.strip_prefix("http://").is_some_and(served))
+}
+
+// Renders the dashboard of `publish`, with links to the other pages and a form generating
+// invoices.
+fn dashboard(directory: &str, config: Option<&Config>, read_only: bool, query: &HashMap<String, String>) -> Reply {
+    let result = selector(query).and_then(|selector| {
+        let time_data = TimeData::load(directory, &selector, &LoadOptions::from_config(config))
+            .map_err(|e| format!("Failed to load data: {}", e))?;
+        diagnostics::report();
+        let mut extra = Context::new();
+        extra.insert("served", &true);
+        extra.insert("read_only", &read_only);
+        publish::render(directory, config, &selector, &time_data, &extra)
+    });
+    match result {
+        Ok((html, _)) => Reply::Html(200, html),
+        Err(err) => Reply::Json(400, json!({ "error": err })),
+    }
+}
+
+// Renders the log as a table, in the `format` and for the `dates` of the query.
+fn log_page(directory: &str, config_file: &Option<String>, query: &HashMap<String, String>) -> Reply {
+    let format = match query.get("format").map(|f| LogFormat::from_str(f, true)) {
+        Some(Ok(format)) => format,
+        Some(Err(err)) => return Reply::Json(400, json!({ "error": err })),
+        None => LogFormat::Day,
+    };
+    let time_data = match load(directory, config_file, query) {
+        Ok(time_data) => time_data,
+        Err(err) => return Reply::Json(400, json!({ "error": err })),
+    };
+    let rows = aggregate(&time_data, &format);
+    let formats: Vec<String> = LogFormat::value_variants().iter()
+        .filter_map(|f| f.to_possible_value().map(|v| v.get_name().to_string()))
+        .collect();
+    let mut context = Context::new();
+    context.insert("format", &format.to_possible_value().map(|v| v.get_name().to_string()));
+    context.insert("formats", &formats);
+    context.insert("dates", query.get("dates").map_or("", |d| d.as_str()));
+    context.insert("total", &rows.iter().fold(0.0, |acc, r| acc + r.hours));
+    context.insert("rows", &rows);
+    page(200, "log", context)
+}
+
+// Renders one of the pages of `PAGE_TEMPLATE`.
+fn page(status: u16, name: &str, mut context: Context) -> Reply {
+    context.insert("page", name);
+    context.insert("title", &format!("clinvoice {}", name));
+    let mut tera = Tera::default();
+    register_filters(&mut tera);
+    match tera.add_raw_template("serve.html", PAGE_TEMPLATE).and_then(|_| tera.render("serve.html", &context)) {
+        Ok(html) => Reply::Html(status, html),
+        Err(err) => Reply::Json(500, json!({ "error": format_tera_error(err) })),
+    }
+}
+
+// Runs `generate` for the `dates` and `generator` of the query in a child process, so that
+// its failures do not stop the server.  Returns its output.
+fn generate(
+    directory: &str,
+    config_file: &Option<String>,
+    read_only: bool,
+    query: &HashMap<String, String>,
+) -> Result<String, String> {
+    if read_only {
+        return Err("Cannot generate invoices in read-only mode".to_string());
+    }
+    let exe = std::env::current_exe().map_err(|e| format!("Failed to find clinvoice: {}", e))?;
+    let mut command = Command::new(exe);
+    command.args(["--color", "never", "--directory", directory]);
+    if let Some(config) = config_file {
+        command.args(["--config", config]);
+    }
+    command.arg("generate");
+    for generator in list(query, "generator") {
+        if generator.starts_with('-') || !generator.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("Invalid generator name: {}", generator));
+        }
+        command.arg(format!("--generator={}", generator));
+    }
+    // Only date arguments are passed on, after `--`, so that they cannot be read as options.
+    let dates = list(query, "dates");
+    DateSelector::from_dates(&dates)?;
+    command.arg("--").args(&dates);
+    tracing::info!("Generating for {:?}", dates);
+    let output = command.output().map_err(|e| format!("Failed to run generate: {}", e))?;
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    if output.status.success() { Ok(text) } else { Err(text) }
+}
+
+// Dispatches a request path to the matching endpoint.
+fn handle(
+    path: &str,
+    query: &HashMap<String, String>,
+    directory: &str,
+    config_file: &Option<String>,
+) -> Result<Option<Value>, String> {
+    match path {
+        "/api/log" => {
+            let format = match query.get("format") {
+                Some(f) => LogFormat::from_str(f, true)?,
+                None => LogFormat::Day,
+            };
+            let time_data = load(directory, config_file, query)?;
+            let rows = aggregate(&time_data, &format);
+            let total = rows.iter().fold(0.0, |acc, r| acc + r.hours);
+            Ok(Some(json!({ "rows": rows, "total": total })))
+        }
+        "/api/heatmap" => {
+            let selector = selector(query)?;
+            let time_data = load(directory, config_file, query)?;
+            let mut days: Vec<_> = get_daily_hours(&time_data, &selector.ranges).into_iter().collect();
+            days.sort_by_key(|(date, _)| *date);
+            let max = days.iter().map(|(_, h)| *h).fold(0.0, f64::max);
+            let days: Vec<Value> = days.iter()
+                .map(|(date, hours)| json!({ "date": date.format("%Y-%m-%d").to_string(), "hours": hours }))
+                .collect();
+            Ok(Some(json!({ "days": days, "max_hours": max })))
+        }
+        "/api/invoices" => Ok(Some(json!({ "invoices": invoices(directory, config_file)? }))),
+        "/api/status" => {
+            let time_data = load(directory, config_file, query)?;
+            let mut dates: Vec<_> = time_data.entries.keys().collect();
+            dates.sort();
+            let rows = aggregate(&time_data, &LogFormat::Day);
+            let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+            let today = clock::today(config.as_ref()).format("%Y.%m.%d").to_string();
+            let today_hours = rows.iter().filter(|r| r.period == today).fold(0.0, |acc, r| acc + r.hours);
+            Ok(Some(json!({
+                "directory": directory,
+                "first_date": dates.first().map(|d| d.format("%Y-%m-%d").to_string()),
+                "last_date": dates.last().map(|d| d.format("%Y-%m-%d").to_string()),
+                "days": dates.len(),
+                "total_hours": rows.iter().fold(0.0, |acc, r| acc + r.hours),
+                "today_hours": today_hours,
+                "invoices": invoices(directory, config_file)?.len(),
+                "diagnostics": diagnostics::summarize(&time_data.diagnostics),
+            })))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Builds a `DateSelector` from the comma separated `dates`, `exclude` and `weekday` query
+// parameters, and the `weekdays_only` flag.
+fn selector(query: &HashMap<String, String>) -> Result<DateSelector, String> {
+    let weekdays_only = query.get("weekdays_only").is_some_and(|v| v == "true" || v == "1");
+    Selection {
+        dates: list(query, "dates"),
+        exclude: list(query, "exclude"),
+        weekdays_only,
+        weekday: list(query, "weekday"),
+        ..Selection::default()
+    }.selector()
+}
+
+// Splits a comma or space separated query parameter.
+fn list(query: &HashMap<String, String>, key: &str) -> Vec<String> {
+    query.get(key)
+        .map(|d| d.split([',', ' ']).filter(|s| !s.is_empty()).map(|s| s.to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn load(directory: &str, config_file: &Option<String>, query: &HashMap<String, String>) -> Result<TimeData, String> {
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let time_data = TimeData::load(directory, &selector(query)?, &LoadOptions::from_config(config.as_ref()))
+        .map_err(|e| format!("Failed to load data: {}", e))?;
+    // The server runs until killed, report the new problems of each load.
+    diagnostics::report();
+    Ok(time_data)
+}
+
+// Lists the invoices recorded in the index, without creating an index if there is none.
+fn invoices(directory: &str, config_file: &Option<String>) -> Result<Vec<Value>, String> {
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let path = index_file_path(config.as_ref(), directory);
+    let index = Index::open_read_only(&path).map_err(|e| format!("Failed to open index: {}", e))?;
+    Ok(index.sequences().into_iter()
+        .map(|(sequence, dates)| json!({ "sequence": sequence, "dates": dates, "state": index.state(sequence) }))
+        .collect())
+}
+
+// Splits a request URL into its path and decoded query parameters.
+fn split_url(url: &str) -> (String, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = query.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            (url_decode(k), url_decode(v))
+        })
+        .collect();
+    (path.trim_end_matches('/').to_string(), params)
+}
+
+// Decodes `+` and `%XX` escapes in a query string component.
+fn url_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn respond(request: Request, reply: Reply) {
+    let (status, content_type, body) = match reply {
+        Reply::Json(status, body) => (status, "application/json", body.to_string()),
+        Reply::Html(status, body) => (status, "text/html; charset=utf-8", body),
+    };
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(err) = request.respond(response) {
+        tracing::warn!("Failed to send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_url() {
+        let (path, query) = split_url("/api/log/?format=month&dates=2025.01%2C2025.02");
+        assert_eq!(path, "/api/log");
+        assert_eq!(query.get("format").unwrap(), "month");
+        assert_eq!(query.get("dates").unwrap(), "2025.01,2025.02");
+    }
+
+    #[test]
+    fn test_log_page() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n    2h = review <b>\n").unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let query = HashMap::from([("format".to_string(), "full".to_string())]);
+        let Reply::Html(200, html) = log_page(directory, &None, &query) else {
+            panic!("expected a page");
+        };
+        assert!(html.contains("<option selected>full</option>"));
+        assert!(html.contains("review &lt;b&gt;"));
+        assert!(matches!(log_page(directory, &None, &HashMap::from([("format".to_string(), "weekly".to_string())])), Reply::Json(400, _)));
+    }
+
+    #[test]
+    fn test_generate_read_only() {
+        assert!(generate(".", &None, true, &HashMap::new()).unwrap_err().contains("read-only"));
+    }
+
+    #[test]
+    fn test_generate_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let query = |key: &str, value: &str| HashMap::from([(key.to_string(), value.to_string())]);
+        assert!(generate(directory, &None, false, &query("dates", "--output /tmp/x")).is_err());
+        assert!(generate(directory, &None, false, &query("dates", "2025.01 --send")).is_err());
+        assert!(generate(directory, &None, false, &query("generator", "--send")).unwrap_err().contains("Invalid generator"));
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let hosts = hosts("127.0.0.1", 8123);
+        assert!(same_origin(Some("127.0.0.1:8123"), None, &hosts));
+        assert!(same_origin(Some("localhost:8123"), Some("http://localhost:8123"), &hosts));
+        assert!(!same_origin(Some("127.0.0.1:8123"), Some("https://evil.example.com"), &hosts));
+        assert!(!same_origin(Some("127.0.0.1:8123"), Some("null"), &hosts));
+        assert!(!same_origin(Some("evil.example.com"), None, &hosts));
+        assert!(!same_origin(None, None, &hosts));
+        assert_eq!(super::hosts("192.168.1.2", 80), vec!["192.168.1.2:80"]);
+    }
+
+    #[test]
+    fn test_url_decode() {
+        assert_eq!(url_decode("a+b%20c"), "a b c");
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("%zz"), "%zz");
+    }
+}
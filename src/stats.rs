@@ -0,0 +1,316 @@
+//! Statistics on the time logged: averages, weekdays, streaks, the busiest day and week, the
+//! spread of daily hours, and the trend over the months.
+
+use crate::color::*;
+use crate::config::Config;
+use crate::data::{LoadOptions, Selection, TimeData};
+use crate::diagnostics;
+use crate::heatmap::get_daily_hours;
+use crate::quiet;
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::BTreeMap;
+
+// Days with this many hours or more share the last histogram bucket.
+const HISTOGRAM_MAX: usize = 12;
+const BAR_WIDTH: usize = 40;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A run of consecutive days, worked or not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Span {
+    /// Returns the number of days in the span, counting both ends.
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+}
+
+/// Statistics on the days with time logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub working_days: usize,
+    pub total_hours: f64,
+    pub weekdays: Vec<(Weekday, usize, f64)>, // Days worked and hours, for each weekday worked.
+    pub longest_streak: Option<Span>,
+    pub longest_break: Option<Span>,
+    pub histogram: Vec<usize>,                // Days with [n, n + 1) hours, the last one open-ended.
+    pub busiest_date: Option<(NaiveDate, f64)>,
+    pub busiest_week: Option<(i32, u32, f64)>, // ISO year, week, and hours.
+    pub months: Vec<(String, f64)>,           // Hours of each month worked, as `YYYY-MM`.
+}
+
+impl Stats {
+    /// Returns the average hours per working day.
+    pub fn average(&self) -> f64 {
+        if self.working_days == 0 { 0.0 } else { self.total_hours / self.working_days as f64 }
+    }
+
+    // The weekday with the highest, or lowest, average hours.
+    fn weekday_by(&self, pick_max: bool) -> Option<(Weekday, f64)> {
+        let averages = self.weekdays.iter().map(|(day, days, hours)| (*day, hours / *days as f64));
+        if pick_max {
+            averages.fold(None, |best, (day, avg)| match best {
+                Some((_, b)) if b >= avg => best,
+                _ => Some((day, avg)),
+            })
+        } else {
+            averages.fold(None, |best, (day, avg)| match best {
+                Some((_, b)) if b <= avg => best,
+                _ => Some((day, avg)),
+            })
+        }
+    }
+
+    /// Returns the weekday with the highest average hours per working day.
+    pub fn busiest(&self) -> Option<(Weekday, f64)> {
+        self.weekday_by(true)
+    }
+
+    /// Returns the weekday with the lowest average hours per working day.
+    pub fn lightest(&self) -> Option<(Weekday, f64)> {
+        self.weekday_by(false)
+    }
+}
+
+/// Computes the statistics of the hours logged each day.  Days without hours are not worked.
+pub fn compute(daily_hours: &BTreeMap<NaiveDate, f64>) -> Stats {
+    let worked: Vec<(NaiveDate, f64)> = daily_hours.iter()
+        .filter(|(_, hours)| **hours > 0.0)
+        .map(|(date, hours)| (*date, *hours))
+        .collect();
+
+    let mut weekdays: Vec<(Weekday, usize, f64)> = Vec::new();
+    let mut histogram = vec![0; HISTOGRAM_MAX + 1];
+    let mut weeks: BTreeMap<(i32, u32), f64> = BTreeMap::new();
+    let mut months: BTreeMap<String, f64> = BTreeMap::new();
+    let mut busiest_date: Option<(NaiveDate, f64)> = None;
+    for (date, hours) in &worked {
+        let week = date.iso_week();
+        *weeks.entry((week.year(), week.week())).or_default() += hours;
+        *months.entry(date.format("%Y-%m").to_string()).or_default() += hours;
+        if busiest_date.is_none_or(|(_, most)| *hours > most) {
+            busiest_date = Some((*date, *hours));
+        }
+        match weekdays.iter_mut().find(|(day, _, _)| *day == date.weekday()) {
+            Some((_, days, total)) => {
+                *days += 1;
+                *total += hours;
+            }
+            None => weekdays.push((date.weekday(), 1, *hours)),
+        }
+        histogram[(*hours as usize).min(HISTOGRAM_MAX)] += 1;
+    }
+    weekdays.sort_by_key(|(day, _, _)| day.num_days_from_monday());
+
+    let mut longest_streak: Option<Span> = None;
+    let mut longest_break: Option<Span> = None;
+    let mut streak: Option<Span> = None;
+    for (date, _) in &worked {
+        streak = match streak {
+            Some(span) if span.end.succ_opt() == Some(*date) => Some(Span { start: span.start, end: *date }),
+            Some(span) => {
+                let gap = Span { start: span.end.succ_opt().unwrap(), end: date.pred_opt().unwrap() };
+                if longest_break.is_none_or(|b| gap.days() > b.days()) {
+                    longest_break = Some(gap);
+                }
+                Some(Span { start: *date, end: *date })
+            }
+            None => Some(Span { start: *date, end: *date }),
+        };
+        if let Some(span) = streak {
+            if longest_streak.is_none_or(|s| span.days() > s.days()) {
+                longest_streak = Some(span);
+            }
+        }
+    }
+
+    // Drop the empty buckets past the longest day.
+    while histogram.len() > 1 && histogram.last() == Some(&0) {
+        histogram.pop();
+    }
+
+    Stats {
+        working_days: worked.len(),
+        total_hours: worked.iter().fold(0.0, |acc, (_, hours)| acc + hours),
+        weekdays,
+        longest_streak,
+        longest_break,
+        histogram,
+        busiest_date,
+        busiest_week: weeks.into_iter()
+            .fold(None, |best: Option<(i32, u32, f64)>, ((year, week), hours)| match best {
+                Some((_, _, most)) if most >= hours => best,
+                _ => Some((year, week, hours)),
+            }),
+        months: months.into_iter().collect(),
+    }
+}
+
+/// Draws values as a line of block characters, the highest as a full block.
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().copied().fold(0.0, f64::max);
+    values.iter().map(|value| {
+        if max <= 0.0 {
+            return SPARKS[0];
+        }
+        SPARKS[((value / max) * (SPARKS.len() - 1) as f64).round() as usize]
+    }).collect()
+}
+
+/// Returns the run of days worked up to `today`, or up to yesterday while today has no hours
+/// yet, like contribution graphs show.
+pub fn current_streak(daily_hours: &BTreeMap<NaiveDate, f64>, today: NaiveDate) -> Option<Span> {
+    let worked = |date: &NaiveDate| daily_hours.get(date).is_some_and(|hours| *hours > 0.0);
+    let end = [Some(today), today.pred_opt()].into_iter().flatten().find(worked)?;
+    let mut start = end;
+    while let Some(previous) = start.pred_opt().filter(worked) {
+        start = previous;
+    }
+    Some(Span { start, end })
+}
+
+fn describe_span(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{:>8} days, {} to {}", span.days(), span.start.format("%Y.%m.%d"), span.end.format("%Y.%m.%d")),
+        None => format!("{:>8}", "-"),
+    }
+}
+
+/// Prints the statistics of the selected days.
+pub fn run(directory_option: &Option<String>, config_file: &Option<String>, selection: &Selection) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+    let time_data = TimeData::load(directory, &selector, &LoadOptions { progress: true, ..selection.load_options(config.as_ref()) })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to load data: {}", err);
+            diagnostics::exit(1);
+        });
+    let daily_hours: BTreeMap<NaiveDate, f64> = get_daily_hours(&time_data, &selector.ranges).into_iter().collect();
+    let stats = compute(&daily_hours);
+
+    if quiet::enabled() {
+        quiet::summary("stats", &[
+            ("days", stats.working_days.to_string()),
+            ("hours", stats.total_hours.to_string()),
+            ("average", format!("{:.2}", stats.average())),
+            ("streak", stats.longest_streak.map_or(0, |s| s.days()).to_string()),
+            ("break", stats.longest_break.map_or(0, |s| s.days()).to_string()),
+        ]);
+        return;
+    }
+
+    let theme = theme();
+    let weekday = |day: Option<(Weekday, f64)>| match day {
+        Some((day, avg)) => format!("{:>8} {} average", day.to_string(), format!("{:6.2}", avg).out_colored(theme.hours)),
+        None => format!("{:>8}", "-"),
+    };
+    println!("Working days    {}", format!("{:8}", stats.working_days).out_colored(theme.count));
+    println!("Total hours     {}", format!("{:8.2}", stats.total_hours).out_colored(theme.total));
+    println!("Average         {} per working day", format!("{:8.2}", stats.average()).out_colored(theme.hours));
+    println!("Busiest day     {}", weekday(stats.busiest()));
+    println!("Lightest day    {}", weekday(stats.lightest()));
+    println!("Longest streak  {}", describe_span(stats.longest_streak));
+    println!("Longest break   {}", describe_span(stats.longest_break));
+    if let Some((date, hours)) = stats.busiest_date {
+        println!("Busiest date    {} {} hours", format!("{:>10}", date.format("%Y.%m.%d")).out_colored(theme.period),
+            format!("{:6.2}", hours).out_colored(theme.hours));
+    }
+    if let Some((year, week, hours)) = stats.busiest_week {
+        println!("Busiest week    {} {} hours", format!("{:>10}", format!("{}-W{:02}", year, week)).out_colored(theme.period),
+            format!("{:6.2}", hours).out_colored(theme.hours));
+    }
+
+    if stats.working_days == 0 {
+        return;
+    }
+    println!();
+    println!("Hours per weekday");
+    let most = stats.weekdays.iter().map(|(_, days, hours)| hours / *days as f64).fold(0.0, f64::max);
+    for (day, days, hours) in &stats.weekdays {
+        let average = hours / *days as f64;
+        let bar = "#".repeat(((average * BAR_WIDTH as f64) / most).ceil() as usize);
+        println!("  {:>5}  {:3} days {} {}", day.to_string(), days,
+            format!("{:6.2}", average).out_colored(theme.hours), bar.out_colored(theme.period));
+    }
+    println!();
+    println!("Hours per month");
+    for (month, hours) in &stats.months {
+        println!("  {}  {}", month.out_colored(theme.period), format!("{:8.2}", hours).out_colored(theme.hours));
+    }
+    let hours: Vec<f64> = stats.months.iter().map(|(_, hours)| *hours).collect();
+    println!("  {:<7}  {}", "Trend", sparkline(&hours).out_colored(theme.total));
+    println!();
+    println!("Hours per day");
+    let most = stats.histogram.iter().copied().max().unwrap_or(0).max(1);
+    for (hours, days) in stats.histogram.iter().enumerate() {
+        let label = if hours == HISTOGRAM_MAX { format!("{}+", hours) } else { format!("{}-{}", hours, hours + 1) };
+        let bar = "#".repeat((days * BAR_WIDTH).div_ceil(most));
+        println!("  {:>5}  {} {}", label, bar.out_colored(theme.period), days);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_compute() {
+        // Mon 3 to Wed 5, then Mon 10 and Tue 11, with a zero-hour Sat 8.
+        let daily: BTreeMap<NaiveDate, f64> = [
+            (date(3), 8.0), (date(4), 6.5), (date(5), 9.0), (date(8), 0.0), (date(10), 4.0), (date(11), 13.0),
+        ].into();
+        let stats = compute(&daily);
+        assert_eq!(stats.working_days, 5);
+        assert_eq!(stats.total_hours, 40.5);
+        assert_eq!(stats.average(), 8.1);
+        assert_eq!(stats.busiest(), Some((Weekday::Tue, 9.75)));
+        assert_eq!(stats.lightest(), Some((Weekday::Mon, 6.0)));
+        assert_eq!(stats.longest_streak, Some(Span { start: date(3), end: date(5) }));
+        assert_eq!(stats.longest_break, Some(Span { start: date(6), end: date(9) }));
+        assert_eq!(stats.longest_break.unwrap().days(), 4);
+        assert_eq!(stats.histogram, vec![0, 0, 0, 0, 1, 0, 1, 0, 1, 1, 0, 0, 1]);
+        assert_eq!(stats.busiest_date, Some((date(11), 13.0)));
+        assert_eq!(stats.busiest_week, Some((2025, 10, 23.5)));
+        assert_eq!(stats.months, vec![("2025-03".to_string(), 40.5)]);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[25.1, 153.0, 9.0, 80.0]), "▂█▁▅");
+        assert_eq!(sparkline(&[0.0, 0.0]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_current_streak() {
+        let daily: BTreeMap<NaiveDate, f64> = [(date(3), 8.0), (date(4), 6.5), (date(5), 0.0), (date(6), 2.0)].into();
+        assert_eq!(current_streak(&daily, date(4)), Some(Span { start: date(3), end: date(4) }));
+        assert_eq!(current_streak(&daily, date(5)), Some(Span { start: date(3), end: date(4) }));
+        assert_eq!(current_streak(&daily, date(7)), Some(Span { start: date(6), end: date(6) }));
+        assert_eq!(current_streak(&daily, date(9)), None);
+    }
+
+    #[test]
+    fn test_compute_empty() {
+        let stats = compute(&BTreeMap::new());
+        assert_eq!(stats.working_days, 0);
+        assert_eq!(stats.average(), 0.0);
+        assert_eq!(stats.busiest(), None);
+        assert_eq!(stats.longest_streak, None);
+        assert_eq!(stats.histogram, vec![0]);
+        assert_eq!((stats.busiest_date, stats.busiest_week), (None, None));
+        assert!(stats.months.is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Rendering templates with sample data, for template authors.
+//!
+//! `template preview` renders a generator's template for two weeks of made-up entries in
+//! March 2025, as if generated on April 1st, so the output only changes with the template
+//! and the configuration.  Sample issuer, client, and contract settings fill in for those
+//! the configuration leaves out.  Nothing is written, and no sequence number is used up.
+
+use crate::config::Config;
+use crate::data::{Entry, Source, TimeData};
+use crate::generate;
+use crate::parse::parse_line;
+use chrono::{Local, NaiveDate, TimeZone};
+use clap::Subcommand;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Actions on the templates of generators.
+#[derive(Subcommand, Debug)]
+pub enum TemplateAction {
+    /// Render a generator's template with sample data
+    Preview {
+        #[clap(help = "generator to render, generator.default by default")]
+        generator: Option<String>,
+        #[clap(short, long, default_value = "1", help = "sequence number shown in the invoice")]
+        sequence: u32,
+    },
+}
+
+// The settings used where the configuration has none.
+const SAMPLE_CONFIG: &str = r#"
+[issuer]
+    company = "Sample Consulting"
+    address = "1 Sample Street | Ottawa, ON | Canada"
+    email   = "billing@sample.example.com"
+
+[client]
+    company = "Example Corp"
+    care_of = "Jane Doe"
+    address = "2 Example Avenue | Springfield | USA"
+
+[contract]
+    hourly_rate  = 100.0
+    currency     = "USD"
+    payment_days = 30
+"#;
+
+// The days of March 2025 in the sample, with their entries written as in a timesheet.
+const SAMPLE_DAYS: &[(u32, &[&str])] = &[
+    (3, &["4h = Requirements review", "3.5h = Project setup"]),
+    (4, &["8h = Design of the data model"]),
+    (5, &["6h = Importer", "- waiting for sample files"]),
+    (6, &["7.5h = Importer"]),
+    (7, &["4h = Code review", "$120 = Hosting"]),
+    (10, &["8h = Reports"]),
+    (11, &["5.25h = Reports", "2h = Meeting with the client"]),
+    (12, &["8h = Testing"]),
+    (13, &["6.5h = Bug fixes"]),
+    (14, &["3h = Documentation", "1h = Deployment"]),
+];
+
+/// Returns the sample time data, read from an imaginary `sample.cli`.
+pub fn sample() -> TimeData {
+    let file = PathBuf::from("sample.cli");
+    let mut entries: HashMap<NaiveDate, Vec<Entry>> = HashMap::new();
+    let mut sources: HashMap<NaiveDate, Vec<Source>> = HashMap::new();
+    let mut dates = HashMap::new();
+    let mut line = 1;
+    for (day, lines) in SAMPLE_DAYS {
+        let date = NaiveDate::from_ymd_opt(2025, 3, *day).unwrap();
+        dates.insert(date, Source { file: file.clone(), line, project: None });
+        for text in *lines {
+            line += 1;
+            entries.entry(date).or_default().push(parse_line(text).expect("valid sample entry"));
+            sources.entry(date).or_default().push(Source { file: file.clone(), line, project: None });
+        }
+        line += 2;
+    }
+    TimeData { entries, sources, dates, diagnostics: Vec::new() }
+}
+
+/// Runs a template action.
+pub fn run(action: TemplateAction, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let Ok(config) = Config::new(config_file.as_deref(), Some(directory)) else {
+        tracing::error!("No configuration found, the generators and their templates are set there");
+        std::process::exit(1);
+    };
+    let config = config.with_defaults(SAMPLE_CONFIG).expect("valid sample config");
+
+    match action {
+        TemplateAction::Preview { generator, sequence } => {
+            let Some(generator) = generator.or_else(|| config.get_string("generator.default")) else {
+                tracing::error!("No generator given, and generator.default is not defined in config");
+                std::process::exit(1);
+            };
+            if !config.has(&format!("generator.{}.template", generator)) {
+                tracing::error!("generator.{}.template is not defined in config", generator);
+                std::process::exit(1);
+            }
+            let now = Local.with_ymd_and_hms(2025, 4, 1, 9, 0, 0).earliest().unwrap().fixed_offset();
+            println!("{}", generate::render(&config, directory, &generator, sequence, &sample(), now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let time_data = sample();
+        assert_eq!(time_data.dates.len(), 10);
+        let hours: f32 = time_data.entries.values().flatten()
+            .map(|entry| match entry {
+                Entry::Time(hours, ..) => *hours,
+                _ => 0.0,
+            })
+            .sum();
+        assert_eq!(hours, 66.75);
+        let date = NaiveDate::from_ymd_opt(2025, 3, 7).unwrap();
+        assert_eq!(time_data.entries[&date][1], Entry::FixedCost(120.0, "Hosting".to_string()));
+        assert_eq!(time_data.sources[&date][1].line, 17);
+    }
+}
@@ -0,0 +1,315 @@
+//! Live time tracking: `start` records a running timer in the data directory, and `stop`
+//! logs the time since as `HH:MM-HH:MM` entries.  `status` shows the timer, with the hours
+//! logged today, this week, and this month.
+
+use crate::add::write_entry;
+use crate::clock;
+use crate::color::*;
+use crate::config::Config;
+use crate::data::{DateRange, DateSelector, DuplicateDates, LoadOptions, TimeData};
+use crate::diagnostics;
+use crate::import::ImportedEntry;
+use crate::quiet;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The running timer, in the data directory.
+pub const TIMER_FILE: &str = ".clinvoice/timer.json";
+
+/// A running timer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timer {
+    pub start: NaiveDateTime,
+    pub description: String,
+}
+
+impl Timer {
+    /// Starts a timer at `now`, to the minute.
+    pub fn new(now: NaiveDateTime, description: &str) -> Self {
+        Timer { start: to_minute(now), description: description.trim().to_string() }
+    }
+
+    /// Returns the time elapsed since the start, to the minute.
+    pub fn elapsed(&self, now: NaiveDateTime) -> Duration {
+        to_minute(now) - self.start
+    }
+
+    /// Returns the entries for the time from the start to `now`, one for each day when the
+    /// timer ran past midnight.  There are none if less than a minute has passed.
+    pub fn entries(&self, now: NaiveDateTime) -> Vec<ImportedEntry> {
+        ImportedEntry::intervals(self.start, now, &self.description)
+    }
+}
+
+fn to_minute(time: NaiveDateTime) -> NaiveDateTime {
+    time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(time)
+}
+
+/// Formats a duration as `H:MM`.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    format!("{}:{:02}", elapsed.num_hours(), elapsed.num_minutes() % 60)
+}
+
+/// Returns the timer file of a data directory.
+pub fn timer_path(directory: &str) -> PathBuf {
+    Path::new(directory).join(TIMER_FILE)
+}
+
+/// Reads the running timer of a data directory, if any.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the timer file cannot be read or parsed.
+pub fn load(directory: &str) -> io::Result<Option<Timer>> {
+    let path = timer_path(directory);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+fn save(directory: &str, timer: &Timer) -> io::Result<()> {
+    let path = timer_path(directory);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(timer).map_err(io::Error::other)?)
+}
+
+// Reads the running timer, or exits if the timer file is broken.
+fn running(directory: &str) -> Option<Timer> {
+    load(directory).unwrap_or_else(|err| {
+        tracing::error!("Failed to read the timer: {}", err);
+        diagnostics::exit(1);
+    })
+}
+
+fn check_writable(read_only: bool, config: Option<&Config>, action: &str) {
+    if read_only || config.and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot {} the timer in read-only mode", action);
+        diagnostics::exit(1);
+    }
+}
+
+/// Starts a timer for the description, unless one is already running.
+pub fn start(description: Vec<String>, read_only: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    check_writable(read_only, config.as_ref(), "start");
+    let description = description.join(" ");
+    if description.trim().is_empty() {
+        tracing::error!("Missing a description, like: start fixed the build #acme");
+        diagnostics::exit(1);
+    }
+    if let Some(timer) = running(directory) {
+        tracing::error!("A timer is already running since {}: {}, stop it first",
+            timer.start.format("%Y.%m.%d %H:%M"), timer.description);
+        diagnostics::exit(1);
+    }
+
+    let timer = Timer::new(clock::now(config.as_ref()).naive_local(), &description);
+    if let Err(err) = save(directory, &timer) {
+        tracing::error!("Failed to save the timer: {}", err);
+        diagnostics::exit(1);
+    }
+    tracing::info!("Started at {}: {}", timer.start.format("%H:%M"), timer.description);
+    quiet::summary("started", &[
+        ("start", timer.start.format("%Y.%m.%d %H:%M").to_string()),
+        ("description", timer.description.clone()),
+    ]);
+}
+
+/// Stops the running timer, and logs the time since it started into the timesheet of its
+/// month.  A `description` replaces the one given to `start`.
+pub fn stop(description: Vec<String>, read_only: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    check_writable(read_only, config.as_ref(), "stop");
+    let Some(mut timer) = running(directory) else {
+        tracing::error!("No timer is running, start one with: start <description>");
+        diagnostics::exit(1);
+    };
+    if !description.is_empty() {
+        timer.description = description.join(" ").trim().to_string();
+    }
+
+    let now = clock::now(config.as_ref()).naive_local();
+    let entries = timer.entries(now);
+    if entries.is_empty() {
+        tracing::warn!("Stopped after less than a minute, nothing logged");
+    }
+    let options = LoadOptions { duplicate_dates: DuplicateDates::Merge, ..LoadOptions::from_config(config.as_ref()) };
+    // Only the entries written are recorded for `undo`, not those already logged.
+    for entry in &entries {
+        match write_entry(directory, entry, &options) {
+            Ok(false) => tracing::warn!("Already logged on {}: {}", entry.date.format("%Y.%m.%d"), entry.to_line()),
+            Ok(true) => tracing::info!("Added to {}: {}", entry.date.format("%Y.%m.%d"), entry.to_line()),
+            Err(err) => {
+                tracing::error!("Failed to write entry: {}", err);
+                diagnostics::exit(1);
+            }
+        }
+    }
+    if let Err(err) = fs::remove_file(timer_path(directory)) {
+        tracing::error!("Failed to clear the timer: {}", err);
+        diagnostics::exit(1);
+    }
+    quiet::summary("stopped", &[
+        ("elapsed", format_elapsed(timer.elapsed(now))),
+        ("entries", entries.len().to_string()),
+    ]);
+}
+
+/// The hours logged today, in the week since Monday, and in the month.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Totals {
+    pub today: f64,
+    pub week: f64,
+    pub month: f64,
+}
+
+// Returns the first day of the week of `today`, a Monday, or of the month if it is later.
+fn first_day(today: NaiveDate) -> NaiveDate {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    monday.min(today.with_day(1).unwrap_or(today))
+}
+
+/// Sums the hours logged up to `today`.
+pub fn totals(time_data: &TimeData, today: NaiveDate) -> Totals {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let mut totals = Totals::default();
+    for (date, entries) in &time_data.entries {
+        if *date > today {
+            continue;
+        }
+        let hours = entries.iter().fold(0.0, |acc, entry| acc + entry.hours() as f64);
+        if *date == today {
+            totals.today += hours;
+        }
+        if *date >= monday {
+            totals.week += hours;
+        }
+        if (date.year(), date.month()) == (today.year(), today.month()) {
+            totals.month += hours;
+        }
+    }
+    totals
+}
+
+/// Shows the hours logged today, this week, and this month, and the running timer, if any.
+/// With `short`, all on one line, for status bars.
+pub fn status(short: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let timer = running(directory);
+    let now = clock::now(config.as_ref()).naive_local();
+    let today = now.date();
+    let mut selector = DateSelector::new();
+    selector.add_range(DateRange { start: first_day(today), end: today });
+    // Only the hours logged count, not the recurring entries.
+    let options = LoadOptions { recurring: Vec::new(), duplicate_dates: DuplicateDates::Merge, ..LoadOptions::from_config(config.as_ref()) };
+    let time_data = TimeData::load(directory, &selector, &options).unwrap_or_else(|err| {
+        tracing::error!("Failed to load data: {}", err);
+        diagnostics::exit(1);
+    });
+    let totals = totals(&time_data, today);
+
+    if quiet::enabled() {
+        let mut fields = vec![
+            ("today", format!("{:.2}", totals.today)),
+            ("week", format!("{:.2}", totals.week)),
+            ("month", format!("{:.2}", totals.month)),
+            ("running", timer.is_some().to_string()),
+        ];
+        if let Some(timer) = &timer {
+            fields.push(("start", timer.start.format("%Y.%m.%d %H:%M").to_string()));
+            fields.push(("elapsed", format_elapsed(timer.elapsed(now))));
+        }
+        quiet::summary("status", &fields);
+        return;
+    }
+    let theme = theme();
+    if short {
+        let mut line = format!("{:.2}h today, {:.2}h week, {:.2}h month", totals.today, totals.week, totals.month);
+        if let Some(timer) = &timer {
+            line.push_str(&format!(", timer {}", format_elapsed(timer.elapsed(now))));
+        }
+        println!("{}", line);
+        return;
+    }
+    for (label, hours) in [("Today", totals.today), ("Week", totals.week), ("Month", totals.month)] {
+        println!("{:<8}{}", label, format!("{:>8.2}", hours).out_colored(theme.hours));
+    }
+    match timer {
+        Some(timer) => println!("Timer running since {} ({}): {}",
+            timer.start.format("%Y.%m.%d %H:%M").to_string().out_colored(theme.period),
+            format_elapsed(timer.elapsed(now)).out_colored(theme.hours),
+            timer.description),
+        None => println!("No timer running"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32, second: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap().and_hms_opt(hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn test_entries() {
+        let timer = Timer::new(at(3, 9, 15, 42), "fixed the build #acme");
+        assert_eq!(timer.start, at(3, 9, 15, 0));
+        let entries = timer.entries(at(3, 11, 40, 5));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].date, NaiveDate::from_ymd_opt(2025, 3, 3).unwrap());
+        assert_eq!(entries[0].to_line(), "09:15-11:40 = fixed the build #acme");
+        assert_eq!(format_elapsed(timer.elapsed(at(3, 11, 40, 5))), "2:25");
+        assert!(timer.entries(at(3, 9, 15, 59)).is_empty());
+    }
+
+    #[test]
+    fn test_entries_past_midnight() {
+        let timer = Timer::new(at(3, 22, 30, 0), "release");
+        let lines: Vec<(u32, String)> = timer.entries(at(5, 1, 5, 0)).iter()
+            .map(|entry| (entry.date.day(), entry.to_line()))
+            .collect();
+        assert_eq!(lines, vec![
+            (3, "22:30-24:00 = release".to_string()),
+            (4, "00:00-24:00 = release".to_string()),
+            (5, "00:00-01:05 = release".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2025-02.cli"), "2025.02.28\n    3h = a\n").unwrap();
+        fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n    2h = b\n    1h = review\n    $50 = fee\n2025.03.04\n    1.5h = c\n2025.03.05\n    4h = d\n").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        let today = NaiveDate::from_ymd_opt(2025, 3, 4).unwrap();
+        assert_eq!(first_day(today), NaiveDate::from_ymd_opt(2025, 3, 1).unwrap());
+        assert_eq!(first_day(NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()), NaiveDate::from_ymd_opt(2025, 2, 24).unwrap());
+        assert_eq!(totals(&time_data, today), Totals { today: 1.5, week: 4.5, month: 4.5 });
+        let saturday = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        assert_eq!(totals(&time_data, saturday), Totals { today: 0.0, week: 3.0, month: 0.0 });
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        assert_eq!(load(directory).unwrap(), None);
+        let timer = Timer::new(at(3, 9, 0, 0), "review");
+        save(directory, &timer).unwrap();
+        assert_eq!(load(directory).unwrap(), Some(timer));
+    }
+}
@@ -1,8 +1,12 @@
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use clap::{ValueEnum};
 use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::color;
 
+static G_MUTED: AtomicBool = AtomicBool::new(false);
+
 /// Defines the available tracing levels for logging.
 #[derive(ValueEnum, Clone, Debug)]
 pub enum TraceLevel {
@@ -26,6 +30,20 @@ impl TraceLevel {
     }
 }
 
+/// Stops or resumes logging to stderr, while a full screen interface owns the terminal.
+pub fn mute_stderr(muted: bool) {
+    G_MUTED.store(muted, Ordering::Relaxed);
+}
+
+// Returns stderr, or a sink while muted.
+fn stderr() -> Box<dyn Write> {
+    if G_MUTED.load(Ordering::Relaxed) {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stderr())
+    }
+}
+
 /// Initializes the tracing subscriber for logging.
 ///
 /// Configures the logging level and output destination (stderr or a file).
@@ -42,7 +60,7 @@ pub fn init(trace_level : &TraceLevel, trace_output : &String) {
 
     if trace_output == "-" {
         tracing_subscriber::registry()
-            .with(fmt_layer.with_writer(std::io::stderr))
+            .with(fmt_layer.with_writer(stderr))
             .with(filter)
             .init();
     } else {
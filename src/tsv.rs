@@ -0,0 +1,196 @@
+//! Timesheets written as tab-separated values, like spreadsheets export them.
+//!
+//! Each row is an entry with a date, a duration, a description, and tags.  A first row naming
+//! the columns sets their order, otherwise `tsv.columns` does.
+
+use crate::config::Config;
+use crate::data::Entry;
+use crate::parse::{parse_date, parse_line};
+use chrono::NaiveDate;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// The columns of a TSV timesheet, in their default order.
+pub const COLUMNS: &[&str] = &["date", "duration", "description", "tags"];
+
+/// Checks if a path names a TSV timesheet.
+pub fn is_tsv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "tsv")
+}
+
+/// Where each field is found in a row.  Columns with other names are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    pub date: usize,
+    pub duration: Option<usize>,
+    pub description: Option<usize>,
+    pub tags: Option<usize>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns { date: 0, duration: Some(1), description: Some(2), tags: Some(3) }
+    }
+}
+
+impl Columns {
+    /// Creates the columns from their names, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if there is no `date` column.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let find = |name: &str| names.iter().position(|n| n.as_ref().trim().eq_ignore_ascii_case(name));
+        Ok(Columns {
+            date: find("date").ok_or("TSV columns have no date column")?,
+            duration: find("duration"),
+            description: find("description"),
+            tags: find("tags"),
+        })
+    }
+
+    /// Reads the columns from `tsv.columns`, or returns the default ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if `tsv.columns` has no `date` column.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        match config.get_string_array("tsv.columns") {
+            Some(names) => Self::from_names(&names),
+            None => Ok(Self::default()),
+        }
+    }
+
+    // Reads a header row, one where every cell is a column name.
+    fn from_header(cells: &[String]) -> Option<Self> {
+        let known = cells.iter().all(|cell| cell.is_empty() || COLUMNS.iter().any(|c| cell.eq_ignore_ascii_case(c)));
+        if !known {
+            return None;
+        }
+        Self::from_names(cells).ok()
+    }
+
+    /// Parses the entry of a row.  A row without a duration is a note, and the tags are
+    /// added to the description as `#tag` words.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error if the duration or cost is invalid.
+    pub fn entry(&self, cells: &[String]) -> Result<Entry, String> {
+        let cell = |index: Option<usize>| index.and_then(|i| cells.get(i)).map_or("", |c| c.as_str());
+        let mut description = cell(self.description).to_string();
+        let tags = cell(self.tags).split([',', ' ']).map(|t| t.trim().trim_start_matches('#')).filter(|t| !t.is_empty());
+        for tag in tags {
+            if !description.is_empty() {
+                description.push(' ');
+            }
+            description.push('#');
+            description.push_str(tag);
+        }
+
+        let duration = cell(self.duration);
+        if duration.is_empty() {
+            return Ok(Entry::Note(description));
+        }
+        // Spreadsheets write hours as plain numbers.
+        if let Ok(hours) = duration.parse::<f32>() {
+            return Ok(Entry::Time(hours, description, Vec::new()));
+        }
+        parse_line(&format!("{} = {}", duration, description))
+    }
+}
+
+/// A row of a TSV timesheet.
+#[derive(Debug)]
+pub struct Row {
+    /// The 1-based line number.
+    pub line: usize,
+    /// The text of the line, for diagnostics.
+    pub text: String,
+    pub date: Result<NaiveDate, String>,
+    pub entry: Result<Entry, String>,
+}
+
+// Splits a line into its cells, trimmed and without the quotes spreadsheets put around
+// cells with quotes or tabs.
+fn cells(line: &str) -> Vec<String> {
+    line.split('\t').map(|cell| {
+        let cell = cell.trim();
+        match cell.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\"\"", "\""),
+            None => cell.to_string(),
+        }
+    }).collect()
+}
+
+/// Reads the rows of a TSV timesheet, skipping blank rows, `#` comments, and a header row.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read.
+pub fn read_rows(reader: impl BufRead, columns: &Columns) -> Result<Vec<Row>, io::Error> {
+    let mut columns = columns.clone();
+    let mut rows = Vec::new();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let cells = cells(&line);
+        if line.trim_start().starts_with('#') || cells.iter().all(|cell| cell.is_empty()) {
+            continue;
+        }
+        if rows.is_empty() {
+            if let Some(header) = Columns::from_header(&cells) {
+                columns = header;
+                continue;
+            }
+        }
+        let date = cells.get(columns.date).map_or("", |c| c.as_str());
+        rows.push(Row {
+            line: line_number + 1,
+            text: line.trim().to_string(),
+            date: parse_date(date).ok_or(format!("Invalid date: {}", date)),
+            entry: columns.entry(&cells),
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Interval;
+    use chrono::NaiveTime;
+
+    fn rows(content: &str) -> Vec<Row> {
+        read_rows(content.as_bytes(), &Columns::default()).unwrap()
+    }
+
+    #[test]
+    fn test_from_names() {
+        let columns = Columns::from_names(&["Tags", "Date", "Notes", "Description"]).unwrap();
+        assert_eq!(columns, Columns { date: 1, duration: None, description: Some(3), tags: Some(0) });
+        assert!(Columns::from_names(&["duration", "description"]).is_err());
+    }
+
+    #[test]
+    fn test_read_rows() {
+        let rows = rows("2025.03.03\t2\tmeeting\tclient, call\n\n# comment\n2025-03-04\t9-12\tcode\n2025.03.04\t$50\thosting\n2025.03.05\t\tout sick\n");
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].date, Ok(NaiveDate::from_ymd_opt(2025, 3, 3).unwrap()));
+        assert_eq!(rows[0].entry, Ok(Entry::Time(2.0, "meeting #client #call".to_string(), Vec::new())));
+        assert_eq!(rows[1].line, 4);
+        let interval = Interval { start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(), end: NaiveTime::from_hms_opt(12, 0, 0).unwrap() };
+        assert_eq!(rows[1].entry, Ok(Entry::Time(3.0, "code".to_string(), vec![interval])));
+        assert_eq!(rows[2].entry, Ok(Entry::FixedCost(50.0, "hosting".to_string())));
+        assert_eq!(rows[3].entry, Ok(Entry::Note("out sick".to_string())));
+    }
+
+    #[test]
+    fn test_read_rows_header() {
+        let rows = rows("Description\tDate\tDuration\nreview\t2025.03.03\t1.5h\nreview\tMonday\tlots\n");
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].entry, Ok(Entry::Time(1.5, "review".to_string(), Vec::new())));
+        assert_eq!(rows[1].date, Err("Invalid date: Monday".to_string()));
+        assert!(rows[1].entry.is_err());
+    }
+}
@@ -0,0 +1,412 @@
+//! An interactive browser of the timesheets: a calendar of the month, the entries of the
+//! selected day, the totals of the month, and a form adding entries like `add` does.
+
+use crate::add::{check_spec, expand, write_entry};
+use crate::clock;
+use crate::config::Config;
+use crate::data::{DateSelector, DuplicateDates, Entry, LoadOptions, TimeData};
+use crate::diagnostics;
+use crate::import::ImportedEntry;
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Flex, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, Paragraph};
+use ratatui::Frame;
+
+// The field being typed in the add form.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Time,
+    Description,
+}
+
+// The add form, for an entry on the selected day.
+#[derive(Debug, Clone)]
+struct Form {
+    time: String,
+    description: String,
+    field: Field,
+    error: Option<String>,
+}
+
+/// The state of the browser.
+pub struct App {
+    directory: String,
+    options: LoadOptions,
+    hourly_rate: f64,
+    read_only: bool,
+    time_data: TimeData,
+    /// The selected day.
+    pub selected: NaiveDate,
+    form: Option<Form>,
+    message: Option<String>,
+    /// Set when the browser should close.
+    pub quit: bool,
+}
+
+impl App {
+    /// Loads the timesheets of the data directory, selecting `today`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the timesheets cannot be read.
+    pub fn new(directory: &str, config: Option<&Config>, read_only: bool, today: NaiveDate) -> std::io::Result<Self> {
+        let options = LoadOptions { duplicate_dates: DuplicateDates::Merge, ..LoadOptions::from_config(config) };
+        let time_data = TimeData::load(directory, &DateSelector::new(), &options)?;
+        Ok(App {
+            directory: directory.to_string(),
+            options,
+            hourly_rate: config.and_then(|c| c.get_f64("contract.hourly_rate")).unwrap_or(0.0),
+            read_only: read_only || config.and_then(|c| c.get_bool("read_only")).unwrap_or(false),
+            time_data,
+            selected: today,
+            form: None,
+            message: None,
+            quit: false,
+        })
+    }
+
+    fn reload(&mut self) {
+        match TimeData::load(&self.directory, &DateSelector::new(), &self.options) {
+            Ok(time_data) => self.time_data = time_data,
+            Err(err) => self.message = Some(format!("Failed to reload: {}", err)),
+        }
+    }
+
+    // Returns the hours logged on a day.
+    fn hours(&self, date: NaiveDate) -> f64 {
+        self.time_data.entries.get(&date).into_iter().flatten().fold(0.0, |acc, entry| acc + entry.hours() as f64)
+    }
+
+    /// Returns the hours logged, the fixed costs, and the days worked in the month of the
+    /// selected day.
+    pub fn month_totals(&self) -> (f64, f64, usize) {
+        let month = (self.selected.year(), self.selected.month());
+        let mut totals = (0.0, 0.0, 0);
+        for (date, entries) in &self.time_data.entries {
+            if (date.year(), date.month()) != month {
+                continue;
+            }
+            let hours = self.hours(*date);
+            totals.0 += hours;
+            totals.1 += entries.iter().map(Entry::cost).sum::<f64>();
+            if hours > 0.0 {
+                totals.2 += 1;
+            }
+        }
+        totals
+    }
+
+    fn shift_months(&mut self, months: i32) {
+        let shifted = if months < 0 {
+            self.selected.checked_sub_months(Months::new(months.unsigned_abs()))
+        } else {
+            self.selected.checked_add_months(Months::new(months as u32))
+        };
+        self.selected = shifted.unwrap_or(self.selected);
+    }
+
+    /// Handles a key press, browsing the calendar or typing in the add form.
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if self.form.is_some() {
+            self.handle_form_key(key);
+            return;
+        }
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Left | KeyCode::Char('h') => self.selected -= Duration::days(1),
+            KeyCode::Right | KeyCode::Char('l') => self.selected += Duration::days(1),
+            KeyCode::Up | KeyCode::Char('k') => self.selected -= Duration::days(7),
+            KeyCode::Down | KeyCode::Char('j') => self.selected += Duration::days(7),
+            KeyCode::PageUp | KeyCode::Char('[') => self.shift_months(-1),
+            KeyCode::PageDown | KeyCode::Char(']') => self.shift_months(1),
+            KeyCode::Char('t') => self.selected = clock::at(self.options.timezone, Utc::now()).date_naive(),
+            KeyCode::Char('r') => self.reload(),
+            KeyCode::Char('a') if self.read_only => self.message = Some("Read-only mode, cannot add entries".to_string()),
+            KeyCode::Char('a') => {
+                self.form = Some(Form { time: String::new(), description: String::new(), field: Field::Time, error: None });
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_form_key(&mut self, key: KeyEvent) {
+        let Some(form) = self.form.as_mut() else {
+            return;
+        };
+        let text = match form.field {
+            Field::Time => &mut form.time,
+            Field::Description => &mut form.description,
+        };
+        match key.code {
+            KeyCode::Esc => self.form = None,
+            KeyCode::Tab | KeyCode::BackTab => {
+                form.field = if form.field == Field::Time { Field::Description } else { Field::Time };
+            }
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Enter => self.submit(),
+            _ => {}
+        }
+    }
+
+    // Writes the entry of the add form, through the same checks as `add`.
+    fn submit(&mut self) {
+        let Some(form) = self.form.as_mut() else {
+            return;
+        };
+        let words: Vec<String> = std::iter::once(form.time.trim().to_string())
+            .chain(form.description.split_whitespace().map(|w| w.to_string()))
+            .collect();
+        let (spec, description) = expand(&words, &self.options.snippets);
+        if let Err(err) = check_spec(&spec) {
+            form.error = Some(err);
+            form.field = Field::Time;
+            return;
+        }
+        if description.is_empty() {
+            form.error = Some("Missing a description".to_string());
+            form.field = Field::Description;
+            return;
+        }
+        let entry = ImportedEntry { date: self.selected, spec, description };
+        self.message = Some(match write_entry(&self.directory, &entry, &self.options) {
+            Ok(true) => format!("Added to {}: {}", entry.date.format("%Y.%m.%d"), entry.to_line()),
+            Ok(false) => format!("Already logged: {}", entry.to_line()),
+            Err(err) => format!("Failed to write entry: {}", err),
+        });
+        self.form = None;
+        self.reload();
+    }
+
+    /// Draws the calendar, the entries of the selected day, the totals of the month, and the
+    /// add form when open.
+    pub fn draw(&self, frame: &mut Frame) {
+        let [title, body, totals, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(10),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [calendar, entries] = Layout::horizontal([Constraint::Length(31), Constraint::Min(20)]).areas(body);
+
+        frame.render_widget(Line::from(format!(" clinvoice  {}", self.directory)).bold(), title);
+        frame.render_widget(self.calendar(), calendar);
+        frame.render_widget(self.entries(), entries);
+        frame.render_widget(self.totals(), totals);
+        let help_line = match &self.message {
+            Some(message) => Line::from(format!(" {}", message)).fg(Color::Yellow),
+            None => Line::from(" ←→↑↓ day  [ ] month  t today  a add  r reload  q quit").dim(),
+        };
+        frame.render_widget(help_line, help);
+
+        if let Some(form) = &self.form {
+            self.draw_form(frame, form);
+        }
+    }
+
+    fn calendar(&self) -> Paragraph<'_> {
+        let first = self.selected.with_day(1).unwrap_or(self.selected);
+        let mut lines = vec![Line::from(" Mo  Tu  We  Th  Fr  Sa  Su").dim()];
+        let mut cells: Vec<Span> = vec![Span::raw("    "); first.weekday().num_days_from_monday() as usize];
+        let mut day = first;
+        while day.month() == first.month() {
+            let mut style = Style::new();
+            if self.hours(day) > 0.0 {
+                style = style.fg(Color::Green).add_modifier(Modifier::BOLD);
+            } else if self.time_data.entries.contains_key(&day) {
+                style = style.fg(Color::Cyan);
+            }
+            if day == self.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            cells.push(Span::styled(format!("{:>3}", day.day()), style));
+            cells.push(Span::raw(" "));
+            if cells.len() == 14 {
+                lines.push(Line::from(std::mem::take(&mut cells)));
+            }
+            day += Duration::days(1);
+        }
+        if !cells.is_empty() {
+            lines.push(Line::from(cells));
+        }
+        Paragraph::new(lines).block(Block::bordered().title(format!(" {} ", self.selected.format("%B %Y"))))
+    }
+
+    fn entries(&self) -> Paragraph<'_> {
+        let entries = self.time_data.entries.get(&self.selected);
+        let lines: Vec<Line> = match entries {
+            None => vec![Line::from("No entries, press a to add one").dim()],
+            Some(entries) => entries.iter().map(|entry| match entry {
+                Entry::Time(hours, text, _) => Line::from(vec![
+                    Span::styled(format!("{:>8.2}h ", hours), Style::new().fg(Color::Green)),
+                    Span::raw(text.clone()),
+                ]),
+                Entry::FixedCost(cost, text) => Line::from(vec![
+                    Span::styled(format!("{:>8.2}$ ", cost), Style::new().fg(Color::Magenta)),
+                    Span::raw(text.clone()),
+                ]),
+                Entry::Note(text) => Line::from(vec![Span::raw("        - "), Span::raw(text.clone()).italic()]),
+                Entry::Units(quantity, price, text) => Line::from(vec![
+                    Span::styled(format!("{:>8.2}$ ", *quantity as f64 * *price as f64), Style::new().fg(Color::Magenta)),
+                    Span::raw(format!("{} x {:.2} ", quantity, price)).dim(),
+                    Span::raw(text.clone()),
+                ]),
+                Entry::NonBillable(hours, text) => Line::from(vec![
+                    Span::styled(format!("{:>8.2}h ", hours), Style::new().fg(Color::Green).dim()),
+                    Span::raw(text.clone()).dim(),
+                ]),
+            }).collect(),
+        };
+        let title = format!(" {}  {:.2}h ", self.selected.format("%Y.%m.%d %a"), self.hours(self.selected));
+        Paragraph::new(lines).block(Block::bordered().title(title))
+    }
+
+    fn totals(&self) -> Paragraph<'_> {
+        let (hours, costs, days) = self.month_totals();
+        let average = if days == 0 { 0.0 } else { hours / days as f64 };
+        let line = Line::from(vec![
+            Span::styled(format!("{:.2}", hours), Style::new().fg(Color::Green).bold()),
+            Span::raw(" hours   "),
+            Span::styled(format!("{:.2}", hours * self.hourly_rate + costs), Style::new().bold()),
+            Span::raw(" earned   "),
+            Span::raw(format!("{} days worked   {:.2} hours per day", days, average)),
+        ]);
+        Paragraph::new(line).block(Block::bordered().title(format!(" {} ", self.selected.format("%B total"))))
+    }
+
+    fn draw_form(&self, frame: &mut Frame, form: &Form) {
+        let [area] = Layout::vertical([Constraint::Length(6)]).flex(Flex::Center).areas(frame.area());
+        let [area] = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+        let field = |label: &str, value: &str, active: bool| {
+            let cursor = if active { "█" } else { "" };
+            let style = if active { Style::new().bold() } else { Style::new() };
+            Line::from(vec![Span::styled(format!("{:>12}: ", label), style), Span::raw(format!("{}{}", value, cursor))])
+        };
+        let mut lines = vec![
+            field("Time", &form.time, form.field == Field::Time),
+            field("Description", &form.description, form.field == Field::Description),
+        ];
+        lines.push(match &form.error {
+            Some(error) => Line::from(format!("  {}", error)).fg(Color::Red),
+            None => Line::from("  Tab next field  Enter add  Esc cancel").dim(),
+        });
+        let title = format!(" Add to {} ", self.selected.format("%Y.%m.%d"));
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), area);
+    }
+}
+
+/// Runs the browser until it is closed.
+pub fn run(read_only: bool, directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    if !atty::is(atty::Stream::Stdout) || !atty::is(atty::Stream::Stdin) {
+        tracing::error!("The browser needs a terminal");
+        diagnostics::exit(1);
+    }
+    let mut app = App::new(directory, config.as_ref(), read_only, clock::today(config.as_ref())).unwrap_or_else(|err| {
+        tracing::error!("Failed to load data: {}", err);
+        diagnostics::exit(1);
+    });
+
+    // Messages are shown in the help line instead.
+    crate::tracing::mute_stderr(true);
+    let mut terminal = ratatui::init();
+    let result = (|| -> std::io::Result<()> {
+        while !app.quit {
+            terminal.draw(|frame| app.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    crate::tracing::mute_stderr(false);
+    if let Err(err) = result {
+        tracing::error!("Terminal error: {}", err);
+        diagnostics::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn app(dir: &tempfile::TempDir) -> App {
+        std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n    2h = review #acme\n    $20 = parking\n2025.03.04\n    - a note\n").unwrap();
+        App::new(dir.path().to_str().unwrap(), None, false, date(3, 3)).unwrap()
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        text.chars().for_each(|c| press(app, KeyCode::Char(c)));
+    }
+
+    #[test]
+    fn test_navigation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected, date(3, 11));
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(app.selected, date(4, 11));
+        press(&mut app, KeyCode::PageUp);
+        press(&mut app, KeyCode::Up);
+        assert_eq!(app.selected, date(3, 4));
+        assert_eq!(app.month_totals(), (2.0, 20.0, 1));
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_add_form() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "1x");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.form.as_ref().unwrap().error.is_some());
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "h");
+        press(&mut app, KeyCode::Tab);
+        type_text(&mut app, "standup");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.form.is_none());
+        assert_eq!(app.month_totals(), (3.0, 20.0, 1));
+        let content = std::fs::read_to_string(dir.path().join("2025-03.cli")).unwrap();
+        assert!(content.contains("    $20 = parking\n    1h = standup\n"));
+    }
+
+    #[test]
+    fn test_draw() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = app(&dir);
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let text: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(text.contains("March 2025"));
+        assert!(text.contains("2025.03.03 Mon  2.00h"));
+        assert!(text.contains("review #acme"));
+        assert!(text.contains("20.00 earned"));
+    }
+}
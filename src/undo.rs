@@ -0,0 +1,190 @@
+//! A journal of the changes made by `add` and `generate`, so that `undo` can revert them.
+
+use crate::config::Config;
+use crate::import::remove_entry;
+use crate::index::{index_file_path, Index};
+use crate::parse::parse_date;
+use crate::quiet;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The journal file, in the data directory.
+pub const JOURNAL_FILE: &str = ".undo";
+const JOURNAL_LENGTH: usize = 20;
+
+/// A change that can be reverted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Mutation {
+    /// An entry line added under a date, in a file of the data directory.
+    Entry { file: String, date: String, line: String },
+    /// A sequence added or changed in the index, with the dates and state it had before.
+    Sequence { sequence: u32, dates: Option<Vec<String>>, state: Option<String> },
+}
+
+impl Mutation {
+    /// Describes the change, for messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Mutation::Entry { file, date, line } => format!("entry {} on {} in {}", line, date, file),
+            Mutation::Sequence { sequence, dates: None, .. } => format!("sequence {}", sequence),
+            Mutation::Sequence { sequence, .. } => format!("changes to sequence {}", sequence),
+        }
+    }
+}
+
+/// Returns the journal file of a data directory.
+pub fn journal_path(directory: &str) -> PathBuf {
+    Path::new(directory).join(JOURNAL_FILE)
+}
+
+fn read_journal(path: &Path) -> io::Result<Vec<Mutation>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::other))
+        .collect()
+}
+
+fn write_journal(path: &Path, mutations: &[Mutation]) -> io::Result<()> {
+    let mut content = String::new();
+    for mutation in mutations {
+        content.push_str(&serde_json::to_string(mutation).map_err(io::Error::other)?);
+        content.push('\n');
+    }
+    fs::write(path, content)
+}
+
+/// Records a change in the journal, which keeps the last few changes.
+///
+/// Failing to record is only a warning, since the change itself was already made.
+pub fn record(directory: &str, mutation: Mutation) {
+    let path = journal_path(directory);
+    let result = read_journal(&path).and_then(|mut mutations| {
+        mutations.push(mutation);
+        let skip = mutations.len().saturating_sub(JOURNAL_LENGTH);
+        write_journal(&path, &mutations[skip..])
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to record the change in {}: {}", path.display(), err);
+    }
+}
+
+/// Reverts a change.
+///
+/// # Errors
+///
+/// Returns a `String` error if the change can no longer be found, like an entry that was
+/// edited since.
+pub fn revert(mutation: &Mutation, directory: &str, config: Option<&Config>) -> Result<(), String> {
+    match mutation {
+        Mutation::Entry { file, date, line } => {
+            let path = Path::new(directory).join(file);
+            let date = parse_date(date).ok_or(format!("Invalid date: {}", date))?;
+            let content = fs::read_to_string(&path)
+                .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+            let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+            if !remove_entry(&mut lines, date, line) {
+                return Err(format!("{} is no longer in {}", line, path.display()));
+            }
+            let mut content = lines.join("\n");
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            fs::write(&path, content).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+        }
+        Mutation::Sequence { sequence, dates, state } => {
+            let path = index_file_path(config, directory);
+            let mut index = Index::create(&path)
+                .map_err(|err| format!("Failed to open index {}: {}", path.display(), err))?;
+            if index.state(*sequence).is_none() {
+                return Err(format!("Sequence {} is no longer in {}", sequence, path.display()));
+            }
+            index.restore(*sequence, dates.clone(), state.clone());
+            index.save().map_err(|err| format!("Failed to save index {}: {}", path.display(), err))
+        }
+    }
+}
+
+/// Reverts the last change recorded in the journal.
+pub fn run(
+    read_only: bool,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    if read_only || config.as_ref().and_then(|c| c.get_bool("read_only")).unwrap_or(false) {
+        tracing::error!("Cannot undo in read-only mode");
+        std::process::exit(1);
+    }
+
+    let path = journal_path(directory);
+    let mut mutations = read_journal(&path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    let Some(mutation) = mutations.pop() else {
+        tracing::error!("Nothing to undo");
+        std::process::exit(1);
+    };
+
+    if let Err(err) = revert(&mutation, directory, config.as_ref()) {
+        tracing::error!("Cannot undo the {}: {}", mutation.describe(), err);
+        std::process::exit(1);
+    }
+    if let Err(err) = write_journal(&path, &mutations) {
+        tracing::warn!("Failed to update {}: {}", path.display(), err);
+    }
+    tracing::info!("Undid the {}", mutation.describe());
+    quiet::summary("undone", &[("change", mutation.describe())]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_revert_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        let file = dir.path().join("2025-03.cli");
+        fs::write(&file, "2025.03.03\n    1h = a\n    2h = b\n").unwrap();
+
+        let mutation = Mutation::Entry { file: "2025-03.cli".to_string(), date: "2025.03.03".to_string(), line: "2h = b".to_string() };
+        record(directory, mutation.clone());
+        assert_eq!(read_journal(&journal_path(directory)).unwrap(), vec![mutation.clone()]);
+
+        revert(&mutation, directory, None).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "2025.03.03\n    1h = a\n");
+        assert!(revert(&mutation, directory, None).is_err());
+    }
+
+    #[test]
+    fn test_revert_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        fs::write(dir.path().join(".index"), "1 2025.01\n2 2025.02\n3 state=reserved\n").unwrap();
+
+        revert(&Mutation::Sequence { sequence: 2, dates: None, state: None }, directory, None).unwrap();
+        revert(&Mutation::Sequence { sequence: 3, dates: Some(vec![]), state: Some("reserved".to_string()) }, directory, None).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join(".index")).unwrap(), "1 2025.01\n3 state=reserved\n");
+    }
+
+    #[test]
+    fn test_journal_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_str().unwrap();
+        for sequence in 0..JOURNAL_LENGTH as u32 + 5 {
+            record(directory, Mutation::Sequence { sequence, dates: None, state: None });
+        }
+        let mutations = read_journal(&journal_path(directory)).unwrap();
+        assert_eq!(mutations.len(), JOURNAL_LENGTH);
+        assert_eq!(mutations[0], Mutation::Sequence { sequence: 5, dates: None, state: None });
+    }
+}
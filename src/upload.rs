@@ -0,0 +1,119 @@
+//! Uploading of generated invoices to shared storage.
+//!
+//! Supported kinds are `webdav` (an HTTP `PUT`), `s3` (through the `aws` command line tool),
+//! and `sftp` (through `scp`).  The settings live in the `upload` table of a generator:
+//!
+//! ```toml
+//! [generator.pdf]
+//!     upload = { kind = "webdav", url = "https://dav.example.com/invoices", path_template = "{{ sequence }}.pdf" }
+//! ```
+
+use crate::config::Config;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Uploads `local` to `remote`, relative to the `url` configured under `prefix`.
+///
+/// # Returns
+///
+/// The full remote location of the uploaded file.
+///
+/// # Errors
+///
+/// Returns a `String` describing the failure.
+pub fn upload(config: &Config, prefix: &str, kind: &str, local: &Path, remote: &str) -> Result<String, String> {
+    let setting = |key: &str| config.get_string(&format!("{}.{}", prefix, key));
+    let url = setting("url").ok_or(format!("{}.url is not defined in config", prefix))?;
+    let target = format!("{}/{}", url.trim_end_matches('/'), remote.trim_start_matches('/'));
+
+    if !local.exists() {
+        return Err(format!("{} does not exist", local.display()));
+    }
+    tracing::info!("Uploading {} to {}", local.display(), target);
+
+    match kind {
+        "webdav" => {
+            let body = fs::read(local).map_err(|e| format!("{}: {}", local.display(), e))?;
+            let mut request = ureq::put(&target);
+            let username = setting("username").or_else(|| env::var("CLINVOICE_UPLOAD_USERNAME").ok());
+            let password = setting("password").or_else(|| env::var("CLINVOICE_UPLOAD_PASSWORD").ok());
+            if let Some(username) = username {
+                let credentials = format!("{}:{}", username, password.unwrap_or_default());
+                request = request.set("Authorization", &format!("Basic {}", base64_encode(credentials.as_bytes())));
+            }
+            request.send_bytes(&body).map_err(|e| e.to_string())?;
+        }
+        "s3" => {
+            let mut command = Command::new("aws");
+            command.arg("s3").arg("cp").arg(local).arg(&target);
+            if let Some(profile) = setting("profile") {
+                command.arg("--profile").arg(profile);
+            }
+            if let Some(endpoint) = setting("endpoint_url") {
+                command.arg("--endpoint-url").arg(endpoint);
+            }
+            run_command(command)?;
+        }
+        "sftp" => {
+            let destination = target.strip_prefix("sftp://").unwrap_or(&target);
+            let (host, path) = destination.split_once('/')
+                .ok_or(format!("{} must look like sftp://user@host/path", url))?;
+            let mut command = Command::new("scp");
+            command.arg("-q").arg("-o").arg("BatchMode=yes");
+            if let Some(port) = config.get_i64(&format!("{}.port", prefix)) {
+                command.arg("-P").arg(port.to_string());
+            }
+            command.arg(local).arg(format!("{}:{}", host, path));
+            run_command(command)?;
+        }
+        _ => return Err(format!("unknown upload kind '{}', expected webdav, s3 or sftp", kind)),
+    }
+
+    tracing::info!("Upload successful");
+    Ok(target)
+}
+
+// Runs an external upload tool, turning a non-zero exit into an error.
+fn run_command(mut command: Command) -> Result<(), String> {
+    tracing::debug!("running {:?}", command);
+    let output = command.output().map_err(|e| format!("failed to run {:?}: {}", command.get_program(), e))?;
+    if !output.status.success() {
+        return Err(format!("{:?} failed with {}: {}", command.get_program(), output.status,
+            String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Encodes bytes using the standard base64 alphabet, for HTTP basic authentication.
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+    }
+}
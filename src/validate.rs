@@ -0,0 +1,360 @@
+//! Checks timesheets for mistakes that parse fine but are likely wrong.
+
+use crate::clock;
+use crate::config::Config;
+use crate::data::{read_dates, timesheet_files, DateRange, DateSelector, Entry, LoadOptions, Selection, TimeData};
+use crate::diagnostics::{self, Diagnostic};
+use crate::parse::parse_specifier_to_range;
+use crate::quiet;
+use chrono::{Duration, NaiveDate};
+use regex::Regex;
+use std::path::Path;
+
+/// A file naming rule, like `{year}-{month}.cli`, and the period each name stands for.
+#[derive(Debug)]
+pub struct FilenamePattern {
+    regex: Regex,
+}
+
+impl FilenamePattern {
+    /// Creates a pattern from `data.filename_pattern`.
+    ///
+    /// The `{year}`, `{month}`, and `{day}` placeholders match 4, 2, and 2 digits; the rest
+    /// of the pattern is matched literally.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `String` error for an unknown placeholder.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut regex = String::from("^");
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            regex.push_str(&regex::escape(&rest[..start]));
+            let end = rest[start..].find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in filename pattern: {}", pattern))? + start;
+            regex.push_str(match &rest[start + 1..end] {
+                "year" => r"(?P<year>\d{4})",
+                "month" => r"(?P<month>\d{2})",
+                "day" => r"(?P<day>\d{2})",
+                other => return Err(format!("Unknown placeholder {{{}}} in filename pattern: {}", other, pattern)),
+            });
+            rest = &rest[end + 1..];
+        }
+        regex.push_str(&regex::escape(rest));
+        regex.push('$');
+        Ok(FilenamePattern { regex: Regex::new(&regex).map_err(|e| e.to_string())? })
+    }
+
+    /// Returns the period encoded in a file name, or `None` if the name does not match.
+    ///
+    /// An `.age` or `.gpg` suffix is ignored.  A name without a `{year}` matches any period.
+    pub fn period(&self, name: &str) -> Option<Option<DateRange>> {
+        let name = name.strip_suffix(".age").or_else(|| name.strip_suffix(".gpg")).unwrap_or(name);
+        let captures = self.regex.captures(name)?;
+        let spec: Vec<&str> = ["year", "month", "day"].iter()
+            .map_while(|part| captures.name(part).map(|m| m.as_str()))
+            .collect();
+        if spec.is_empty() {
+            return Some(None);
+        }
+        Some(parse_specifier_to_range(&spec.join(".")).ok())
+    }
+}
+
+/// Checks file names against `data.filename_pattern`, and the dates in each file against
+/// the period in its name.
+///
+/// Only the selected dates are checked, and files with dates but none selected are skipped.
+pub fn check_filenames(
+    files: &[impl AsRef<Path>],
+    pattern: &FilenamePattern,
+    selector: &DateSelector,
+    options: &LoadOptions,
+) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    for file in files {
+        let file = file.as_ref();
+        let dates = match read_dates(file, options) {
+            Ok(dates) => dates,
+            Err(err) => {
+                problems.push(Diagnostic::warning(file.to_path_buf(), 0, "Failed to read file", &err.to_string()));
+                continue;
+            }
+        };
+        if !dates.is_empty() && !dates.iter().any(|(_, date)| selector.selected(date)) {
+            continue;
+        }
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let Some(period) = pattern.period(&name) else {
+            problems.push(Diagnostic::warning(file.to_path_buf(), 0, "File name does not match data.filename_pattern", &name));
+            continue;
+        };
+        let Some(period) = period else {
+            continue;
+        };
+        for (line, date) in dates.into_iter().filter(|(_, date)| selector.selected(date)) {
+            if date < period.start || date > period.end {
+                let message = format!("Date is outside the period of the file name, {} to {}", period.start, period.end);
+                problems.push(Diagnostic::warning(file.to_path_buf(), line, &message, &date.format("%Y.%m.%d").to_string()));
+            }
+        }
+    }
+    problems
+}
+
+/// Returns the first date considered to be in the future, `validate.future_tolerance_days`
+/// days after `today`.
+pub fn future_cutoff(config: Option<&Config>, today: NaiveDate) -> NaiveDate {
+    let tolerance = config.and_then(|c| c.get_i64("validate.future_tolerance_days")).unwrap_or(0);
+    today + Duration::days(tolerance.max(0) + 1)
+}
+
+/// Checks the selected dates for dates on or after `cutoff`, usually a typo in the year.
+pub fn check_future(files: &[impl AsRef<Path>], cutoff: NaiveDate, selector: &DateSelector, options: &LoadOptions) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    for file in files {
+        let file = file.as_ref();
+        let dates = match read_dates(file, options) {
+            Ok(dates) => dates,
+            Err(err) => {
+                problems.push(Diagnostic::warning(file.to_path_buf(), 0, "Failed to read file", &err.to_string()));
+                continue;
+            }
+        };
+        for (line, date) in dates.into_iter().filter(|(_, date)| *date >= cutoff && selector.selected(date)) {
+            problems.push(Diagnostic::warning(file.to_path_buf(), line, "Date is in the future", &date.format("%Y.%m.%d").to_string()));
+        }
+    }
+    problems
+}
+
+/// Thresholds for `check_anomalies`, from the `[validate]` configuration.
+#[derive(Debug, Clone)]
+pub struct Anomalies {
+    /// Days with more hours than this are reported, 0 to disable.
+    pub max_hours_per_day: f64,
+    /// Report time entries with zero hours but a description.
+    pub zero_hour_entries: bool,
+    /// Report identical entries repeated on the same day, in the same file or another one.
+    pub duplicate_entries: bool,
+}
+
+impl Default for Anomalies {
+    fn default() -> Self {
+        Anomalies { max_hours_per_day: 14.0, zero_hour_entries: true, duplicate_entries: true }
+    }
+}
+
+impl Anomalies {
+    /// Reads the thresholds, like `validate.max_hours_per_day`.
+    pub fn from_config(config: Option<&Config>) -> Self {
+        let defaults = Anomalies::default();
+        let Some(config) = config else {
+            return defaults;
+        };
+        Anomalies {
+            max_hours_per_day: config.get_f64("validate.max_hours_per_day").unwrap_or(defaults.max_hours_per_day),
+            zero_hour_entries: config.get_bool("validate.zero_hour_entries").unwrap_or(defaults.zero_hour_entries),
+            duplicate_entries: config.get_bool("validate.duplicate_entries").unwrap_or(defaults.duplicate_entries),
+        }
+    }
+}
+
+/// Checks for implausible days and entries, as selected by `anomalies`.
+pub fn check_anomalies(time_data: &TimeData, anomalies: &Anomalies) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    let mut dates: Vec<_> = time_data.entries.keys().collect();
+    dates.sort();
+    for date in dates {
+        let entries = &time_data.entries[date];
+        let Some(sources) = time_data.sources.get(date) else {
+            continue;
+        };
+        let line = |i: usize| describe(&entries[i]);
+
+        let hours: f64 = entries.iter().fold(0.0, |acc, entry| acc + entry.hours() as f64);
+        if anomalies.max_hours_per_day > 0.0 && hours > anomalies.max_hours_per_day {
+            let message = format!("Day has {} hours, more than validate.max_hours_per_day ({})", hours, anomalies.max_hours_per_day);
+            problems.push(Diagnostic::warning(sources[0].file.clone(), sources[0].line, &message, &date.format("%Y.%m.%d").to_string()));
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            if anomalies.zero_hour_entries && matches!(entry, Entry::Time(h, d, _) if *h == 0.0 && !d.trim().is_empty()) {
+                problems.push(Diagnostic::warning(sources[i].file.clone(), sources[i].line, "Entry has zero hours", &line(i)));
+            }
+            if anomalies.duplicate_entries && !matches!(entry, Entry::Note(_)) {
+                if let Some(j) = (0..i).find(|&j| entries[j] == *entry) {
+                    let message = if sources[j].file == sources[i].file {
+                        format!("Duplicate of the entry on line {}", sources[j].line)
+                    } else {
+                        format!("Duplicate of the entry at {}:{}", sources[j].file.display(), sources[j].line)
+                    };
+                    problems.push(Diagnostic::warning(sources[i].file.clone(), sources[i].line, &message, &line(i)));
+                }
+            }
+        }
+    }
+    problems
+}
+
+// Formats an entry like the line it was read from.
+fn describe(entry: &Entry) -> String {
+    match entry {
+        Entry::Time(h, d, _) => format!("{}h = {}", h, d),
+        Entry::FixedCost(c, d) => format!("${} = {}", c, d),
+        Entry::Note(n) => format!("* {}", n),
+        Entry::NonBillable(h, d) => format!("~{}h = {}", h, d),
+        Entry::Units(q, p, d) => format!("{} x ${} = {}", q, p, d),
+    }
+}
+
+/// Runs all checks on the selected dates of the data directory, and reports the problems
+/// found.
+///
+/// Exits with a failure status if there are any problems.
+pub fn run(directory_option: &Option<String>, config_file: &Option<String>, selection: &Selection) {
+    let directory = directory_option.as_deref().unwrap_or(".");
+    let config = Config::new(config_file.as_deref(), Some(directory)).ok();
+    let options = selection.load_options(config.as_ref());
+    let selector = selection.selector().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        diagnostics::exit(1);
+    });
+
+    let time_data = TimeData::load(directory, &selector, &options).unwrap_or_else(|err| {
+        tracing::error!("Failed to load data: {}", err);
+        diagnostics::exit(1);
+    });
+    let files = timesheet_files(directory, &options).unwrap_or_else(|err| {
+        tracing::error!("Failed to list files: {}", err);
+        diagnostics::exit(1);
+    });
+
+    let mut found = Vec::new();
+    if let Some(pattern) = config.as_ref().and_then(|c| c.get_string("data.filename_pattern")) {
+        let pattern = FilenamePattern::new(&pattern).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            diagnostics::exit(1);
+        });
+        found.extend(check_filenames(&files, &pattern, &selector, &options));
+    }
+    let cutoff = future_cutoff(config.as_ref(), clock::today(config.as_ref()));
+    found.extend(check_future(&files, cutoff, &selector, &options));
+    found.extend(check_anomalies(&time_data, &Anomalies::from_config(config.as_ref())));
+
+    diagnostics::record(&found);
+    let mut problems = time_data.diagnostics.clone();
+    problems.extend(found);
+
+    quiet::summary("validated", &[("problems", problems.len().to_string())]);
+    if problems.is_empty() {
+        if !quiet::enabled() {
+            println!("No problems found");
+        }
+        return;
+    }
+    diagnostics::report();
+    diagnostics::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filename_pattern() {
+        let pattern = FilenamePattern::new("{year}-{month}.cli").unwrap();
+        let period = pattern.period("2025-02.cli").unwrap().unwrap();
+        assert_eq!(period.start, NaiveDate::from_ymd_opt(2025, 2, 1).unwrap());
+        assert_eq!(period.end, NaiveDate::from_ymd_opt(2025, 2, 28).unwrap());
+        assert!(pattern.period("2025-02.cli.age").unwrap().is_some());
+        assert!(pattern.period("2025-2.cli").is_none());
+        assert!(pattern.period("notes.cli").is_none());
+
+        let pattern = FilenamePattern::new("acme-{year}.cli").unwrap();
+        assert_eq!(pattern.period("acme-2024.cli").unwrap().unwrap().end, NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+
+        assert!(FilenamePattern::new("{client}.cli").is_err());
+        assert!(FilenamePattern::new("{year.cli").is_err());
+    }
+
+    #[test]
+    fn test_check_filenames() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("2025-02.cli");
+        let copied = dir.path().join("2025-03.cli");
+        let misnamed = dir.path().join("march.cli");
+        std::fs::write(&good, "2025.02.03\n8h = Work\n").unwrap();
+        std::fs::write(&copied, "2025.03.03\n8h = Work\n\n2025.02.04\n8h = Work\n").unwrap();
+        std::fs::write(&misnamed, "2025.03.05\n8h = Work\n").unwrap();
+
+        let pattern = FilenamePattern::new("{year}-{month}.cli").unwrap();
+        let files = [good, copied.clone(), misnamed.clone()];
+        let problems = check_filenames(&files, &pattern, &DateSelector::new(), &LoadOptions::default());
+        assert_eq!(problems.len(), 2);
+        assert_eq!((problems[0].file.clone(), problems[0].line), (copied, 4));
+        assert_eq!((problems[1].file.clone(), problems[1].line), (misnamed.clone(), 0));
+
+        let march = DateSelector::from_dates(&["2025.03".to_string()]).unwrap();
+        let problems = check_filenames(&files, &pattern, &march, &LoadOptions::default());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].file, misnamed);
+    }
+
+    #[test]
+    fn test_check_anomalies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-03.cli"), "\
+2025.03.03
+10h = Release
+6h = Release follow-up
+2025.03.04
+0h = Development
+2h = Review
+2h = Review
+").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+
+        let problems = check_anomalies(&time_data, &Anomalies::default());
+        let found: Vec<_> = problems.iter().map(|p| (p.line, p.message.as_str())).collect();
+        assert_eq!(found, vec![
+            (2, "Day has 16 hours, more than validate.max_hours_per_day (14)"),
+            (5, "Entry has zero hours"),
+            (7, "Duplicate of the entry on line 6"),
+        ]);
+
+        // The same entry in another file is most likely counted twice.
+        std::fs::write(dir.path().join("merged.cli"), "2025.03.04\n2h = Review\n1h = Planning\n").unwrap();
+        let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default()).unwrap();
+        let problems = check_anomalies(&time_data, &Anomalies::default());
+        let copy = dir.path().join("merged.cli");
+        let duplicate = problems.iter().find(|p| p.file == copy).unwrap();
+        assert_eq!(duplicate.line, 2);
+        assert!(duplicate.message.contains("2025-03.cli:"));
+        assert_eq!(problems.len(), 4);
+
+        let relaxed = Anomalies { max_hours_per_day: 0.0, zero_hour_entries: false, duplicate_entries: false };
+        assert!(check_anomalies(&time_data, &relaxed).is_empty());
+    }
+
+    #[test]
+    fn test_check_future() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("2025-03.cli");
+        std::fs::write(&file, "2025.03.14\n8h = Work\n\n2025.03.15\n8h = Work\n\n2052.03.16\n8h = Typo\n").unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2025, 3, 14).unwrap();
+        let cutoff = future_cutoff(None, today);
+        assert_eq!(cutoff, NaiveDate::from_ymd_opt(2025, 3, 15).unwrap());
+        let all = DateSelector::new();
+        let problems = check_future(&[&file], cutoff, &all, &LoadOptions::default());
+        assert_eq!(problems.iter().map(|p| p.line).collect::<Vec<_>>(), vec![4, 7]);
+
+        let problems = check_future(&[&file], cutoff + Duration::days(1), &all, &LoadOptions::default());
+        assert_eq!(problems.iter().map(|p| p.line).collect::<Vec<_>>(), vec![7]);
+
+        let selector = DateSelector::from_dates(&["2025".to_string()]).unwrap();
+        let problems = check_future(&[&file], cutoff, &selector, &LoadOptions::default());
+        assert_eq!(problems.iter().map(|p| p.line).collect::<Vec<_>>(), vec![4]);
+    }
+}
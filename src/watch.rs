@@ -0,0 +1,127 @@
+//! Watching the data directory, and generating the invoice again when a timesheet, a
+//! template, or the configuration changes.
+//!
+//! Each generation runs in a child process, so that a failure, like a broken template, is
+//! reported without ending the watch.
+
+use crate::config::Config;
+use crate::data::{is_timesheet, IGNORE_FILE};
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::Duration;
+
+// How long to wait for more changes before generating, since editors often write a file in
+// several steps.
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Returns the templates of all the generators in the configuration, as `generate` finds them.
+pub fn templates(config: &Config, directory: &Path) -> Vec<PathBuf> {
+    config.get_table("generator").into_iter().flatten()
+        .filter_map(|(_, generator)| generator.get("template")?.as_str())
+        .map(|template| crate::generate::template_path(config, directory, template))
+        .collect()
+}
+
+/// Checks if a change to `path` calls for generating again: it is a timesheet, the ignore
+/// file, the configuration, or one of the `templates`.  Outputs written by the generation
+/// itself are not watched, so that it does not trigger itself.
+pub fn is_watched(path: &Path, config_path: Option<&Path>, templates: &[PathBuf]) -> bool {
+    let same = |other: &Path| other == path || other.canonicalize().is_ok_and(|other| path.canonicalize().is_ok_and(|path| other == path));
+    is_timesheet(path)
+        || path.file_name().is_some_and(|name| name == IGNORE_FILE)
+        || config_path.is_some_and(same)
+        || templates.iter().any(|template| same(template))
+}
+
+// Runs clinvoice with `args`, reporting a failure.
+fn generate(args: &[String]) {
+    let exe = std::env::current_exe().unwrap_or_else(|err| {
+        tracing::error!("Failed to find clinvoice: {}", err);
+        std::process::exit(1);
+    });
+    match Command::new(exe).args(args).status() {
+        Ok(status) if status.success() => tracing::info!("Generated, watching for changes"),
+        Ok(status) => tracing::error!("Generation failed with {}, watching for changes", status),
+        Err(err) => tracing::error!("Failed to run generate: {}", err),
+    }
+}
+
+/// Generates with the command line arguments `args`, and again whenever a watched file
+/// changes, until the process is interrupted.
+pub fn run(args: &[String], directory_option: &Option<String>, config_file: &Option<String>) {
+    let directory = Path::new(directory_option.as_deref().unwrap_or("."));
+    let config_path = Config::find_config_path(config_file.as_deref(), directory.to_str()).ok();
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).unwrap_or_else(|err| {
+        tracing::error!("Failed to watch {}: {}", directory.display(), err);
+        std::process::exit(1);
+    });
+    if let Err(err) = watcher.watch(directory, RecursiveMode::Recursive) {
+        tracing::error!("Failed to watch {}: {}", directory.display(), err);
+        std::process::exit(1);
+    }
+    // The configuration may live outside of the data directory.
+    if let Some(path) = &config_path {
+        if let Err(err) = watcher.watch(path, RecursiveMode::NonRecursive) {
+            tracing::warn!("Failed to watch {}: {}", path.display(), err);
+        }
+    }
+
+    generate(args);
+    loop {
+        // The templates are looked up again each time, as the configuration may have changed.
+        let templates = Config::new(config_file.as_deref(), directory.to_str())
+            .map(|config| templates(&config, directory))
+            .unwrap_or_default();
+        let mut changed: Vec<PathBuf> = Vec::new();
+        let mut next = receiver.recv().ok();
+        while let Some(result) = next {
+            match result {
+                Ok(event) if !event.kind.is_access() => {
+                    changed.extend(event.paths.into_iter().filter(|path| is_watched(path, config_path.as_deref(), &templates)));
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Watch error: {}", err),
+            }
+            next = if changed.is_empty() { receiver.recv().ok() } else { receiver.recv_timeout(SETTLE).ok() };
+        }
+        if changed.is_empty() {
+            // The watcher is gone.
+            break;
+        }
+        changed.sort();
+        changed.dedup();
+        for path in &changed {
+            tracing::info!("Changed: {}", path.display());
+        }
+        generate(args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("clinvoice.toml");
+        std::fs::write(&config_path, "[generator.latex]\ntemplate = \"invoice.tex\"\noutput = \"out/invoice.tex\"\n").unwrap();
+        let config = Config::new(config_path.to_str(), None).unwrap();
+        let templates = templates(&config, dir.path());
+        assert_eq!(templates, vec![dir.path().join("invoice.tex")]);
+
+        let watched = |name: &str| is_watched(&dir.path().join(name), Some(&config_path), &templates);
+        assert!(watched("2025-03.cli"));
+        assert!(watched("2025/2025-03.cli.age"));
+        assert!(watched(".clinvoiceignore"));
+        assert!(watched("clinvoice.toml"));
+        assert!(watched("invoice.tex"));
+        assert!(!watched("out/invoice.tex"));
+        assert!(!watched("invoice.pdf"));
+        assert!(!watched(".index"));
+    }
+}
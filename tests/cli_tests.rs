@@ -1,3 +1,5 @@
+#![allow(clippy::expect_fun_call, clippy::needless_borrow, clippy::needless_borrows_for_generic_args)]
+
 use std::env;
 use std::fs;
 use std::path::Path;
@@ -84,6 +86,29 @@ fn extract_generated_filename(stderr: &str) -> Option<String> {
 #[case("18_generate_txt_index_seq_3_diff_dates")]
 #[case("19_generate_markdown_single_file")]
 #[case("20_generate_with_build")]
+#[case("21_import_harvest_csv_dry_run")]
+#[case("22_log_quiet")]
+#[case("23_log_exclude")]
+#[case("24_log_multiple_directories")]
+#[case("25_validate")]
+#[case("26_report_compliance")]
+#[case("27_alias")]
+#[case("28_report_audit")]
+#[case("29_stats")]
+#[case("30_log_markdown")]
+#[case("31_export_csv")]
+#[case("32_import_csv_dry_run")]
+#[case("33_summary")]
+#[case("34_config_list")]
+#[case("35_client_summary")]
+#[case("36_diff")]
+#[case("37_missing")]
+#[case("38_send")]
+#[case("39_template_preview")]
+#[case("40_balance")]
+#[case("41_log_full_markdown")]
+#[case("42_export_quiet")]
+#[case("43_client_generate")]
 fn cli_test_case(#[case] test_name: &str) {
     let test_dir_base = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("cli");
     let original_test_case_dir = test_dir_base.join(test_name);
@@ -1,8 +1,18 @@
-use clinvoice::data::{DateSelector, Entry, TimeData};
+use clinvoice::config::Config;
+use clinvoice::data::{timesheet_files, DateSelector, DuplicateDates, Entry, LoadOptions, Selection, Source, TimeData};
 use clinvoice::parse::parse_date_arg;
 use chrono::NaiveDate;
 use tempfile::tempdir;
 
+#[test]
+fn test_entry_tags() {
+    let entry = Entry::Time(3.0, "code review #backend, with #Ops. #".to_string(), Vec::new());
+    assert_eq!(entry.tags(), vec!["backend", "Ops"]);
+    assert!(entry.has_tag("#ops"));
+    assert!(!entry.has_tag("review"));
+    assert!(Entry::Note("no tags".to_string()).tags().is_empty());
+}
+
 fn create_test_cli_files(dir: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
     let file1_content = r#"
 2025.01.01
@@ -46,15 +56,15 @@ fn test_time_data_new_with_comments() -> Result<(), Box<dyn std::error::Error>>
     std::fs::write(dir.path().join("test.cli"), file_content)?;
 
     let selector = DateSelector::new();
-    let time_data = TimeData::new(dir.path().to_str().unwrap(), &selector)?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
 
     assert_eq!(time_data.entries.len(), 1);
 
     let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
     let entries = time_data.entries.get(&date).unwrap();
     assert_eq!(entries.len(), 2);
-    assert!(matches!(entries[0], Entry::Time(h, _) if h == 8.0));
-    assert!(matches!(entries[1], Entry::Time(h, _) if h == -2.0));
+    assert!(matches!(entries[0], Entry::Time(h, ..) if h == 8.0));
+    assert!(matches!(entries[1], Entry::Time(h, ..) if h == -2.0));
 
     Ok(())
 }
@@ -66,7 +76,7 @@ fn test_time_data_new_all_files() -> Result<(), Box<dyn std::error::Error>> {
     create_test_cli_files(dir.path())?;
 
     let selector = DateSelector::new(); // Selects all dates by default
-    let time_data = TimeData::new(dir.path().to_str().unwrap(), &selector)?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
 
     assert_eq!(time_data.entries.len(), 3);
 
@@ -74,8 +84,8 @@ fn test_time_data_new_all_files() -> Result<(), Box<dyn std::error::Error>> {
     let date1 = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
     let entries1 = time_data.entries.get(&date1).unwrap();
     assert_eq!(entries1.len(), 6);
-    assert!(matches!(entries1[0], Entry::Time(h, _) if h == 8.0));
-    assert!(matches!(entries1[1], Entry::Time(h, _) if h == -2.0));
+    assert!(matches!(entries1[0], Entry::Time(h, ..) if h == 8.0));
+    assert!(matches!(entries1[1], Entry::Time(h, ..) if h == -2.0));
     assert!(matches!(entries1[2], Entry::FixedCost(c, _) if c == 50.0));
     assert!(matches!(entries1[3], Entry::FixedCost(c, _) if c == -10.0));
     assert!(matches!(entries1[4], Entry::Note(_)));
@@ -85,14 +95,115 @@ fn test_time_data_new_all_files() -> Result<(), Box<dyn std::error::Error>> {
     let date2 = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
     let entries2 = time_data.entries.get(&date2).unwrap();
     assert_eq!(entries2.len(), 1);
-    assert!(matches!(entries2[0], Entry::Time(h, _) if h == 4.0));
+    assert!(matches!(entries2[0], Entry::Time(h, ..) if h == 4.0));
 
     // Test 2025.02.01 entries
     let date3 = NaiveDate::from_ymd_opt(2025, 2, 1).unwrap();
     let entries3 = time_data.entries.get(&date3).unwrap();
     assert_eq!(entries3.len(), 1);
-    assert!(matches!(entries3[0], Entry::Time(h, _) if h == 6.0));
+    assert!(matches!(entries3[0], Entry::Time(h, ..) if h == 6.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_selected_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    create_test_cli_files(dir.path())?;
+    let path = dir.path().to_str().unwrap();
+    let selector = DateSelector::new();
+
+    let options = LoadOptions { files: vec!["test3.cli".to_string()], ..LoadOptions::default() };
+    let time_data = TimeData::load(path, &selector, &options)?;
+    assert_eq!(time_data.entries.len(), 1);
+    assert!(time_data.entries.contains_key(&NaiveDate::from_ymd_opt(2025, 2, 1).unwrap()));
+
+    let options = LoadOptions { files: vec!["test[12].cli".to_string()], ..LoadOptions::default() };
+    let time_data = TimeData::load(path, &selector, &options)?;
+    assert_eq!(time_data.entries.len(), 2);
+
+    let options = LoadOptions { files: vec!["missing.cli".to_string()], ..LoadOptions::default() };
+    assert!(TimeData::load(path, &selector, &options).is_err());
+    let options = LoadOptions { files: vec!["acme-*.cli".to_string()], ..LoadOptions::default() };
+    assert!(TimeData::load(path, &selector, &options).is_err());
+    Ok(())
+}
 
+#[test]
+fn test_time_data_load_extra_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let other = tempdir()?;
+    create_test_cli_files(dir.path())?;
+    std::fs::write(other.path().join("oncall.cli"), "2025.01.02\n1h = Paged\n")?;
+
+    // The directories are part of the key, so that an invoice is computed again from all of them.
+    let selection = Selection { directories: vec![other.path().to_str().unwrap().to_string()], ..Selection::default() };
+    let selection = Selection::from_key(&selection.key());
+    let options = selection.load_options(None);
+    assert_eq!(options.extra_directories, selection.directories);
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options)?;
+    let date = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+    assert_eq!(time_data.entries[&date].len(), 2);
+    assert_eq!(time_data.sources[&date].len(), 2);
+    assert!(time_data.sources[&date].iter().any(|s| s.file == other.path().join("oncall.cli") && s.line == 2));
+    assert_eq!(time_data.source_directories().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_snippets() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("2025-01.cli"), "2025.01.02\n@standup\n1h = Review\n@lunch\n")?;
+
+    let options = LoadOptions {
+        snippets: [("standup".to_string(), "0.25h = daily standup #meetings".to_string())].into(),
+        ..LoadOptions::default()
+    };
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options)?;
+    let date = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+    assert_eq!(time_data.entries[&date], vec![
+        Entry::Time(0.25, "daily standup #meetings".to_string(), Vec::new()),
+        Entry::Time(1.0, "Review".to_string(), Vec::new()),
+    ]);
+    assert_eq!(time_data.sources[&date][0].line, 2);
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 4);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_projects() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("2025-01.cli"), concat!(
+        "2025.01.02\n1h = Planning\n",
+        "# clinvoice-project: acme\n2h = Review\n3h = Support @globex.\n",
+        "# clinvoice-project:\n4h = Admin\n",
+    ))?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    let date = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+    let projects: Vec<Option<String>> = time_data.sources[&date].iter().map(|source| source.project.clone()).collect();
+    assert_eq!(projects, vec![None, Some("acme".to_string()), Some("globex".to_string()), None]);
+    assert_eq!(Entry::Time(1.0, "mail to me@example.com".to_string(), Vec::new()).project(), None);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_honors_ignore_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    create_test_cli_files(dir.path())?;
+    std::fs::write(dir.path().join("scratch.cli"), "2025.03.01\n9h = Not real\n")?;
+    std::fs::write(dir.path().join(".clinvoiceignore"), "# scratch files\nscratch*.cli\ntest3.cli\n")?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    assert_eq!(time_data.entries.len(), 2);
+    assert!(!time_data.entries.contains_key(&NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()));
+    assert!(!time_data.entries.contains_key(&NaiveDate::from_ymd_opt(2025, 2, 1).unwrap()));
+
+    // Naming an ignored file explicitly still reads it.
+    let options = LoadOptions { files: vec!["test3.cli".to_string()], ..LoadOptions::default() };
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options)?;
+    assert_eq!(time_data.entries.len(), 1);
     Ok(())
 }
 
@@ -104,7 +215,7 @@ fn test_time_data_new_with_date_selector() -> Result<(), Box<dyn std::error::Err
 
     let mut selector = DateSelector::new();
     selector.add_range(parse_date_arg("2025.01").unwrap()); // Select only January 2025
-    let time_data = TimeData::new(dir.path().to_str().unwrap(), &selector)?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
 
     assert_eq!(time_data.entries.len(), 2);
     assert!(time_data.entries.contains_key(&NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()));
@@ -119,7 +230,7 @@ fn test_time_data_new_empty_directory() -> Result<(), Box<dyn std::error::Error>
     let dir = tempdir()?;
     tracing::trace!("tmpdir: {}", dir.path().display());
     let selector = DateSelector::new();
-    let time_data = TimeData::new(dir.path().to_str().unwrap(), &selector)?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
     assert!(time_data.entries.is_empty());
     Ok(())
 }
@@ -130,11 +241,94 @@ fn test_time_data_new_non_existent_directory() {
     tracing::trace!("tmpdir: {}", dir.path().display());
     let non_existent_path = dir.path().join("non_existent_dir");
     let selector = DateSelector::new();
-    let result = TimeData::new(non_existent_path.to_str().unwrap(), &selector);
+    let result = TimeData::load(non_existent_path.to_str().unwrap(), &selector, &LoadOptions::default());
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
 }
 
+#[test]
+fn test_time_data_load_recurring() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n2h = maintenance\n2025.03.05\n4h = code\n")?;
+    std::fs::write(dir.path().join("clinvoice.toml"), concat!(
+        "[recurring.retainer]\nentry = \"$2000 = retainer\"\nday = 1\n",
+        "[recurring.maintenance]\nentry = \"2h = maintenance\"\nweekday = \"mon\"\n",
+    ))?;
+    let directory = dir.path().to_str().unwrap();
+    let config = Config::new(None, Some(directory))?;
+    let options = LoadOptions::from_config(Some(&config));
+
+    let time_data = TimeData::load(directory, &DateSelector::from_dates(&["2025.03".to_string()])?, &options)?;
+    let date = |day| NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+    assert_eq!(time_data.entries[&date(1)], vec![Entry::FixedCost(2000.0, "retainer".to_string())]);
+    assert_eq!(time_data.entries[&date(3)], vec![Entry::Time(2.0, "maintenance".to_string(), Vec::new())]);
+    assert_eq!(time_data.entries[&date(10)], vec![Entry::Time(2.0, "maintenance".to_string(), Vec::new())]);
+    assert_eq!(time_data.entries.len(), 7);
+    assert_eq!(time_data.sources[&date(10)][0].file.display().to_string(), "[recurring.maintenance]");
+    assert!(time_data.sources[&date(10)][0].is_recurring());
+    assert_eq!(time_data.source_directories(), std::collections::BTreeSet::from([dir.path().to_path_buf()]));
+
+    // Without dates, only from the first to the last date written.
+    let time_data = TimeData::load(directory, &DateSelector::new(), &options)?;
+    assert_eq!(time_data.entries.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_checks_weekdays() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("2024-05.cli"), "2024.05.03 Friday\n1h = a\n2024.05.06 Friday\n2h = b\n2024.05.07 tue\n3h = c\n")?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    let mut dates: Vec<_> = time_data.entries.keys().map(|date| date.format("%m.%d").to_string()).collect();
+    dates.sort();
+    assert_eq!(dates, vec!["05.03", "05.07"]);
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 3);
+    assert!(time_data.diagnostics[0].message.contains("2024.05.06 is a Monday, not Friday"));
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_open_ended_range() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let today = chrono::Local::now().date_naive();
+    std::fs::write(dir.path().join("open.cli"), format!(
+        "2025.01.02\n9:00- = forgot to stop\n{}\n0:00- = working on X\n", today.format("%Y.%m.%d")))?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    assert!(matches!(time_data.entries[&today][..], [Entry::Time(hours, ..)] if hours >= 0.0));
+    assert!(!time_data.entries.contains_key(&NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()));
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 2);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_new_reports_unreadable_line() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("binary.cli"), b"2025.01.01\n1h = ok\n2h = \xff\xfe\n")?;
+    let selector = DateSelector::new();
+    let err = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("binary.cli:3"), "unexpected error: {}", err);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_new_collects_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("bad.cli"), "1h = before date\n2025.01.01\n1h = ok\nxh = bad\n")?;
+    let selector = DateSelector::new();
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
+    assert_eq!(time_data.entries.len(), 1);
+    let lines: Vec<usize> = time_data.diagnostics.iter().map(|d| d.line).collect();
+    assert_eq!(lines, vec![1, 4]);
+    assert_eq!(time_data.diagnostics[0].message, "Expected date, found:");
+    assert_eq!(time_data.diagnostics[1].text, "xh = bad");
+    Ok(())
+}
+
 #[test]
 fn test_date_selector_from_dates() {
     // Test with valid date arguments
@@ -156,3 +350,349 @@ fn test_date_selector_from_dates() {
     let selector = DateSelector::from_dates(&[]).unwrap();
     assert_eq!(selector.ranges.len(), 0);
 }
+
+#[test]
+fn test_selection_exclude() {
+    let selection = Selection {
+        dates: vec!["2025.02".to_string()],
+        exclude: vec!["2025.02.10-2025.02.14".to_string()],
+        ..Selection::default()
+    };
+    let selector = selection.selector().unwrap();
+    assert!(selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 7).unwrap()));
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 10).unwrap()));
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 14).unwrap()));
+    assert!(selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 17).unwrap()));
+
+    // The index key keeps the excludes, so the same selection maps to the same invoice.
+    let key = selection.key();
+    assert_eq!(key, vec!["2025.02".to_string(), "!2025.02.10-2025.02.14".to_string()]);
+    assert_eq!(Selection::from_key(&key).exclude, selection.exclude);
+
+    let result = Selection { exclude: vec!["nope".to_string()], ..Selection::default() }.selector();
+    assert!(result.unwrap_err().contains("Invalid exclude argument"));
+}
+
+#[test]
+fn test_selection_weekdays_only() {
+    let selection = Selection { dates: vec!["2025.02".to_string()], weekdays_only: true, ..Selection::default() };
+    let selector = selection.selector().unwrap();
+    assert!(selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 7).unwrap())); // Friday
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 8).unwrap())); // Saturday
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 9).unwrap())); // Sunday
+
+    let key = selection.key();
+    assert_eq!(key, vec!["2025.02".to_string(), "@weekdays".to_string()]);
+    assert!(Selection::from_key(&key).weekdays_only);
+}
+
+#[test]
+fn test_selection_weekday() {
+    let selection = Selection {
+        dates: vec!["2025.02".to_string()],
+        weekday: vec!["mon".to_string(), "wed-thu".to_string()],
+        ..Selection::default()
+    };
+    let selector = selection.selector().unwrap();
+    assert!(selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 3).unwrap())); // Monday
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 4).unwrap())); // Tuesday
+    assert!(selector.selected(&NaiveDate::from_ymd_opt(2025, 2, 6).unwrap())); // Thursday
+    assert!(!selector.selected(&NaiveDate::from_ymd_opt(2025, 3, 3).unwrap())); // Monday, outside the dates
+
+    let key = selection.key();
+    assert_eq!(key.last().unwrap(), "@weekday=mon,wed-thu");
+    assert_eq!(Selection::from_key(&key).weekday, selection.weekday);
+
+    // Tagged invoices keep their tags after the rest of the key.
+    let tagged = Selection { tags: vec!["acme".to_string()], ..selection.clone() };
+    let key = tagged.key();
+    assert_eq!(key.last().unwrap(), "#acme");
+    let parsed = Selection::from_key(&key);
+    assert_eq!((parsed.dates, parsed.tags), (selection.dates.clone(), vec!["acme".to_string()]));
+    assert!(Selection::from_key(&key).selector().is_ok());
+
+    let result = Selection { weekday: vec!["someday".to_string()], ..Selection::default() }.selector();
+    assert!(result.unwrap_err().contains("Invalid weekday"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_time_data_load_encrypted_files() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    // stand-in for gpg/age, which "decrypts" by printing the last argument (the file)
+    let decrypt = dir.path().join("decrypt.sh");
+    std::fs::write(&decrypt, "#!/bin/sh\nfor f in \"$@\"; do :; done\ncat \"$f\"\n")?;
+    std::fs::set_permissions(&decrypt, std::fs::Permissions::from_mode(0o755))?;
+
+    std::fs::write(dir.path().join("plain.cli"), "2025.01.01\n1h = plain\n")?;
+    std::fs::write(dir.path().join("secret.cli.gpg"), "2025.01.02\n2h = gpg\n")?;
+    std::fs::write(dir.path().join("secret.cli.age"), "2025.01.03\n3h = age\n")?;
+
+    let options = LoadOptions {
+        age_identity: Some(dir.path().join("key.txt")),
+        age_command: Some(decrypt.to_str().unwrap().to_string()),
+        gpg_command: Some(decrypt.to_str().unwrap().to_string()),
+        ..LoadOptions::default()
+    };
+    let selector = DateSelector::new();
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &options)?;
+    assert_eq!(time_data.entries.len(), 3);
+    let day = &time_data.entries[&NaiveDate::from_ymd_opt(2025, 1, 3).unwrap()];
+    assert!(matches!(&day[0], Entry::Time(h, d, _) if *h == 3.0 && d == "age"));
+
+    // .age files cannot be read without an identity
+    let result = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default());
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_time_data_load_encrypted_files_with_chatty_tool() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    // writes more to stderr than a pipe holds before printing the file, then fails for "bad"
+    let decrypt = dir.path().join("decrypt.sh");
+    std::fs::write(&decrypt, "#!/bin/sh\nfor f in \"$@\"; do :; done\nhead -c 200000 /dev/zero | tr '\\0' x >&2\n\
+        case \"$f\" in *bad*) echo 'no secret key' >&2; exit 2;; esac\ncat \"$f\"\n")?;
+    std::fs::set_permissions(&decrypt, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(dir.path().join("secret.cli.gpg"), "2025.01.02\n2h = gpg\n")?;
+
+    let options = LoadOptions { gpg_command: Some(decrypt.to_str().unwrap().to_string()), ..LoadOptions::default() };
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options)?;
+    assert_eq!(time_data.entries.len(), 1);
+
+    std::fs::write(dir.path().join("bad.cli.gpg"), "2025.01.03\n3h = gpg\n")?;
+    let err = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options).unwrap_err();
+    assert!(err.to_string().ends_with("no secret key"));
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_continuation_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let file_content = r#"
+2025.01.01
+    8h = Project Alpha: reworked the parser
+        and the loader,
+        # a comment in between
+        then the tests
+    $50 = Hosting \
+for January
+    * note \
+      over two lines
+    1h = Project Beta
+    ~1h = internal \
+tooling
+    2 x $10 = licenses
+        for the new hires
+
+2025.01.02
+    2h = Standup
+"#;
+    std::fs::write(dir.path().join("test.cli"), file_content)?;
+
+    let selector = DateSelector::new();
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
+    assert!(time_data.diagnostics.is_empty());
+
+    let day = &time_data.entries[&NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()];
+    assert_eq!(day.len(), 6);
+    assert_eq!(day[0], Entry::Time(8.0, "Project Alpha: reworked the parser and the loader, then the tests".to_string(), Vec::new()));
+    assert_eq!(day[1], Entry::FixedCost(50.0, "Hosting for January".to_string()));
+    assert_eq!(day[2], Entry::Note("note over two lines".to_string()));
+    assert_eq!(day[3], Entry::Time(1.0, "Project Beta".to_string(), Vec::new()));
+    assert_eq!(day[4], Entry::NonBillable(1.0, "internal tooling".to_string()));
+    assert_eq!(day[5], Entry::Units(2.0, 10.0, "licenses for the new hires".to_string()));
+    assert_eq!(time_data.sources[&NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()][1].line, 7);
+
+    let day = &time_data.entries[&NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()];
+    assert_eq!(day, &vec![Entry::Time(2.0, "Standup".to_string(), Vec::new())]);
+
+    // Continuations of days that are not selected are skipped quietly.
+    let selector = DateSelector::from_dates(&["2025.01.02".to_string()])?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
+    assert!(time_data.diagnostics.is_empty());
+    assert_eq!(time_data.entries.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_indented_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("test.cli"), "2025.01.01\n  8h = a\n    2h = b\n      #client\n")?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    let day = &time_data.entries[&NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()];
+    assert_eq!(day, &vec![
+        Entry::Time(8.0, "a".to_string(), Vec::new()),
+        Entry::Time(2.0, "b #client".to_string(), Vec::new()),
+    ]);
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 3);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_tsv_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("a.cli"), "2025.01.01\n1h = plain\n")?;
+    std::fs::write(dir.path().join("b.tsv"), "date\tduration\tdescription\ttags\n\
+        2025.01.01\t2.5\tspreadsheet\tclient\n\
+        2025.01.02\t$30\tparking\t\n\
+        2025.01.02\tlong\tbroken\t\n")?;
+    std::fs::write(dir.path().join("c.tsv"), "2025.01.03\treview\t1h\n")?;
+
+    let options = LoadOptions {
+        tsv_columns: clinvoice::tsv::Columns::from_names(&["date", "description", "duration"])?,
+        ..LoadOptions::default()
+    };
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &options)?;
+    let date = |day| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+    assert_eq!(time_data.entries[&date(1)], vec![
+        Entry::Time(1.0, "plain".to_string(), Vec::new()),
+        Entry::Time(2.5, "spreadsheet #client".to_string(), Vec::new()),
+    ]);
+    assert_eq!(time_data.sources[&date(1)][1].line, 2);
+    assert_eq!(time_data.entries[&date(2)], vec![Entry::FixedCost(30.0, "parking".to_string())]);
+    assert_eq!(time_data.entries[&date(3)], vec![Entry::Time(1.0, "review".to_string(), Vec::new())]);
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 4);
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_invalid_date() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("test.cli"), "2025.02.27\n8h = Work\n\n2025.02.30\n8h = Misdated\n- note\n\n2025.03.03\n2h = Review\n")?;
+
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    let date = |month, day| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+    assert_eq!(time_data.entries[&date(2, 27)], vec![Entry::Time(8.0, "Work".to_string(), Vec::new())]);
+    assert_eq!(time_data.entries[&date(3, 3)], vec![Entry::Time(2.0, "Review".to_string(), Vec::new())]);
+    assert_eq!(time_data.diagnostics.len(), 1);
+    assert_eq!(time_data.diagnostics[0].line, 4);
+    assert_eq!(time_data.diagnostics[0].message, "Invalid date, skipping its entries:");
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_date_sources() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("2025-02.cli"), "2025.02.27\n8h = Work\n\n2025.02.28\n# nothing yet\n")?;
+    std::fs::write(dir.path().join("2025-03.cli"), "2025.03.03\n2h = Review\n\n2025.02.27\n1h = Late entry\n")?;
+
+    let selector = DateSelector::from_dates(&["2025.02".to_string()])?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &selector, &LoadOptions::default())?;
+    let date = |month, day| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+    assert_eq!(time_data.dates.len(), 2);
+    assert_eq!(time_data.dates[&date(2, 27)], Source { file: dir.path().join("2025-02.cli"), line: 1, project: None });
+    assert_eq!(time_data.dates[&date(2, 28)], Source { file: dir.path().join("2025-02.cli"), line: 4, project: None });
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_strict() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("test.cli"), "2025.01.01\n2h = ok\n2x = bad\n2025.01.02\n1h = fine\n")?;
+    let directory = dir.path().to_str().unwrap();
+
+    let time_data = TimeData::load(directory, &DateSelector::new(), &Selection::default().load_options(None))?;
+    assert_eq!(time_data.diagnostics.len(), 1);
+
+    let strict = Selection { strict: true, ..Selection::default() };
+    let err = TimeData::load(directory, &DateSelector::new(), &strict.load_options(None)).unwrap_err();
+    assert_eq!(err.to_string(), "1 problem found in the timesheets, stopping in strict mode");
+
+    // Without problems in the selected dates, strict loading succeeds.
+    std::fs::write(dir.path().join("clinvoice.toml"), "[parse]\nstrict = true\n")?;
+    let config = Config::new(None, Some(directory))?;
+    let options = Selection::default().load_options(Some(&config));
+    assert!(options.strict);
+    assert!(TimeData::load(directory, &DateSelector::from_dates(&["2025.01.02".to_string()])?, &options).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_overlapping_ranges() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("test.cli"), "\
+2025.01.01
+9-12 = code review
+11:30-13 = meeting
+13-14 = lunch
+22-24 = deployment
+8:00-23:00 = on call
+")?;
+    let time_data = TimeData::load(dir.path().to_str().unwrap(), &DateSelector::new(), &LoadOptions::default())?;
+    let overlaps: Vec<_> = time_data.diagnostics.iter().map(|d| (d.line, d.message.as_str(), d.text.as_str())).collect();
+    let file = dir.path().join("test.cli").display().to_string();
+    assert_eq!(overlaps.len(), 5);
+    assert_eq!(overlaps[0], (2, format!("Overlaps 'on call' at {}:6 by 3h:", file).as_str(), "09:00-12:00 = code review"));
+    assert!(overlaps.contains(&(3, format!("Overlaps 'code review' at {}:2 by 30m:", file).as_str(), "11:30-13:00 = meeting")));
+    assert!(overlaps.contains(&(5, format!("Overlaps 'on call' at {}:6 by 1h:", file).as_str(), "22:00-24:00 = deployment")));
+    assert!(!overlaps.iter().any(|(_, message, _)| message.contains("'meeting'")));
+    Ok(())
+}
+
+#[test]
+fn test_time_data_load_duplicate_dates() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    std::fs::write(dir.path().join("a.cli"), "2025.01.01\n2h = a\n2025.01.02\n1h = b\n2025.01.02\n1h = c\n")?;
+    std::fs::write(dir.path().join("b.cli"), "2025.01.02\n1h = again\n")?;
+    let directory = dir.path().to_str().unwrap();
+    let load = |duplicate_dates| TimeData::load(directory, &DateSelector::new(), &LoadOptions { duplicate_dates, ..LoadOptions::default() });
+
+    let merged = load(DuplicateDates::Merge)?;
+    assert!(merged.diagnostics.is_empty());
+    assert_eq!(merged.entries[&NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()].len(), 3);
+
+    let warned = load(DuplicateDates::Warn)?;
+    assert_eq!(warned.entries[&NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()].len(), 3);
+    assert_eq!(warned.diagnostics.len(), 1);
+    let diagnostic = &warned.diagnostics[0];
+    assert_eq!((diagnostic.file.file_name().unwrap().to_str(), diagnostic.line), (Some("b.cli"), 1));
+    assert!(diagnostic.message.contains("a.cli:3, "), "{}", diagnostic.message);
+    assert_eq!(diagnostic.text, "2025.01.02");
+
+    let err = load(DuplicateDates::Error).unwrap_err();
+    assert!(err.to_string().starts_with("1 date is written in several files"));
+    assert!(DuplicateDates::parse("twice").is_err());
+
+    // Only the commands showing or billing the entries stop, others report the dates.
+    std::fs::write(dir.path().join("clinvoice.toml"), "[parse]\nduplicate_dates = \"error\"\n")?;
+    let config = Config::new(None, Some(directory))?;
+    assert_eq!(LoadOptions::from_config(Some(&config)).duplicate_dates, DuplicateDates::Warn);
+    assert_eq!(Selection::default().load_options(Some(&config)).duplicate_dates, DuplicateDates::Warn);
+    assert_eq!(Selection::default().checked_load_options(Some(&config)).duplicate_dates, DuplicateDates::Error);
+    Ok(())
+}
+
+#[test]
+fn test_timesheet_files_recursive() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    for path in ["top.cli", "2024/05.cli", "2024/06.cli", "2024/old/01.cli", "2023/12.cli", ".clinvoice/x.cli", "archive/2020.cli"] {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, "2024.05.01\n1h = work\n")?;
+    }
+    std::fs::write(dir.path().join(".clinvoiceignore"), "2023/\n")?;
+    let directory = dir.path().to_str().unwrap();
+    let files = |depth| -> Result<Vec<String>, std::io::Error> {
+        Ok(timesheet_files(directory, &LoadOptions { depth, ..LoadOptions::default() })?.iter()
+            .map(|path| path.strip_prefix(dir.path()).unwrap().display().to_string())
+            .collect())
+    };
+
+    assert_eq!(files(0)?, vec!["top.cli"]);
+    assert_eq!(files(1)?, vec!["2024/05.cli", "2024/06.cli", "top.cli"]);
+    assert_eq!(files(usize::MAX)?, vec!["2024/05.cli", "2024/06.cli", "2024/old/01.cli", "top.cli"]);
+
+    std::fs::write(dir.path().join("clinvoice.toml"), "[data]\nrecursive = true\nmax_depth = 1\n")?;
+    let config = Config::new(None, Some(directory))?;
+    assert_eq!(LoadOptions::from_config(Some(&config)).depth, 1);
+    Ok(())
+}
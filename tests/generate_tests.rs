@@ -1,5 +1,6 @@
 //use clinvoice::config::Config;
 //use clinvoice::data::DateSelector;
+use clinvoice::data::Selection;
 use clinvoice::generate;
 //use clinvoice::parse::parse_date_arg;
 use std::collections::HashMap;
@@ -24,6 +25,24 @@ fn create_test_env(
     Ok(temp_dir)
 }
 
+// Helper function to generate an invoice with one generator, for the given dates
+fn run(
+    output_option: Option<String>,
+    generator_option: &Option<String>,
+    sequence_option: &Option<u32>,
+    directory_option: &Option<String>,
+    config_file: &Option<String>,
+    dates: &[String],
+) {
+    let options = generate::GenerateOptions {
+        output: output_option,
+        generators: generator_option.iter().cloned().collect(),
+        sequence: *sequence_option,
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, directory_option, config_file, &Selection { dates: dates.to_vec(), ..Selection::default() })
+}
+
 #[test]
 fn test_generate_basic_invoice() -> Result<(), Box<dyn std::error::Error>> {
     let mut cli_contents = HashMap::new();
@@ -61,7 +80,7 @@ Total amount: {{ total_amount }}
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(output_path.to_str().unwrap().to_string()),
         &Some("txt".to_string()),
         &None,
@@ -141,7 +160,7 @@ Total amount: {{ total_amount }}
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(output_path.to_str().unwrap().to_string()),
         &Some("txt".to_string()),
         &None,
@@ -208,7 +227,7 @@ Total amount: {{ total_amount }}
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(output_path.to_str().unwrap().to_string()),
         &Some("txt".to_string()),
         &None,
@@ -272,7 +291,7 @@ output = "custom_invoice.txt"
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(default_output_path.to_str().unwrap().to_string()),
         &None, // Use default generator
         &None,
@@ -287,7 +306,7 @@ output = "custom_invoice.txt"
 
     // Test custom generator
     let custom_output_path = temp_dir.path().join("custom_invoice.txt");
-    generate::run(
+    run(
         Some(custom_output_path.to_str().unwrap().to_string()),
         &Some("custom".to_string()), // Use custom generator
         &None,
@@ -313,7 +332,7 @@ hourly_rate = "invalid"
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
     let result = std::panic::catch_unwind(|| {
-        generate::run(
+        run(
             None,
             &None,
             &None,
@@ -369,7 +388,7 @@ Day 1: {{ days.0.hours }} {{ days.0.description }}
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(output_path.to_str().unwrap().to_string()),
         &Some("txt".to_string()),
         &None,
@@ -391,6 +410,322 @@ Day 1: {{ days.0.hours }} {{ days.0.description }}
     Ok(())
 }
 
+#[test]
+fn test_generate_tag_totals() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+3h = code review #backend
+2h = call #Backend #support.
+$40 = hosting #ops
+2025.01.02
+1h = planning
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = r#"
+Tags: {{ tags | join(sep=",") }}
+{% for total in tag_totals %}{{ total.tag }}: {{ total.hours }} {{ total.amount }}
+{% endfor %}"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    println!("{}", generated_content);
+
+    assert!(generated_content.contains("Tags: backend,ops,support"));
+    assert!(generated_content.contains("backend: 5 500"));
+    assert!(generated_content.contains("ops: 0 40"));
+    assert!(generated_content.contains("support: 2 200"));
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_nonbillable_hours() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+3h = code review
+~1.5h = internal meeting
+2025.01.02
+~2h = training
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = r#"
+Worked: {{ total_hours_worked }}
+Not billed: {{ total_hours_nonbillable }}
+Amount: {{ total_amount }}
+{% for day in days %}{{ day.date }} {{ day.hours }} {{ day.description }}
+{% endfor %}"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    println!("{}", generated_content);
+
+    assert!(generated_content.contains("Worked: 3\n"));
+    assert!(generated_content.contains("Not billed: 3.5\n"));
+    assert!(generated_content.contains("Amount: 300\n"));
+    assert!(generated_content.contains("2025-01-01 3 code review\n"));
+    assert!(!generated_content.contains("internal meeting"));
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_day_intervals() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+9-12:30, 13:00-14:00 = code review
+1h = planning
+22-24 = deployment
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = r#"
+{% for day in days %}{{ day.date }} {{ day.hours }}
+{% for span in day.intervals %}  {{ span.start }}-{{ span.end }} {{ span.hours }} {{ span.description }}
+{% endfor %}{% endfor %}"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    println!("{}", generated_content);
+
+    assert!(generated_content.contains(concat!(
+        "2025-01-01 7.5\n",
+        "  09:00-12:30 3.5 code review\n",
+        "  13:00-14:00 1 code review\n",
+        "  22:00-24:00 2 deployment\n",
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_minute_durations() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2012.02.01
+20m = standup
+2012.02.02
+20m = standup
+2012.02.03
+20m = standup
+10m = email
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 60.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = "{{ total_hours_counted | round(precision=4) }} {{ total_amount | round(precision=2) }}\n";
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &["2012.02".to_string()],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    assert_eq!(generated_content.trim(), "1.1667 70");
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_units() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+2h = setup
+3 x $50 = licenses
+$20 = hosting
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = r#"
+{% for day in days %}{{ day.date }} {{ day.cost }}
+{% for line in day.units %}  {{ line.quantity }} x {{ line.unit_price }} = {{ line.amount }} {{ line.description }}
+{% endfor %}{% endfor %}fees {{ total_fixed_fees }}
+"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    println!("{}", generated_content);
+
+    assert!(generated_content.contains("2025-01-01 200\n  3 x 50 = 150 licenses\n"));
+    assert!(generated_content.contains("fees 170"));
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_projects() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+1h = planning
+# clinvoice-project: acme
+3h = code review
+$40 = hosting
+2025.01.02
+2h = call @globex
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let template_content = r#"
+{% for project in projects %}[{{ project.name }}] {{ project.hours }} {{ project.amount }}
+{% for entry in project.entries %}  {{ entry.date }} {{ entry.description }} {{ entry.amount }}
+{% endfor %}{% endfor %}"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    println!("{}", generated_content);
+
+    assert!(generated_content.contains("[] 1 100\n  2025-01-01 planning 100"));
+    assert!(generated_content.contains("[acme] 3 340\n  2025-01-01 code review 300\n  2025-01-01 hosting 40"));
+    assert!(generated_content.contains("[globex] 2 200\n  2025-01-02 call @globex 200"));
+
+    Ok(())
+}
+
 #[test]
 fn test_cap_hours_per_invoice() -> Result<(), Box<dyn std::error::Error>> {
     let mut cli_contents = HashMap::new();
@@ -431,7 +766,7 @@ Total amount: {{ total_amount }}
     let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
     let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
 
-    generate::run(
+    run(
         Some(output_path.to_str().unwrap().to_string()),
         &Some("txt".to_string()),
         &None,
@@ -451,4 +786,896 @@ Total amount: {{ total_amount }}
     assert!(generated_content.contains("Overage: 4 -400")); // 16 - 12 = 4 hours overage, 4 * 100 = 400 discount
 
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_generate_webhook_notification() -> Result<(), Box<dyn std::error::Error>> {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("Failed to start test server");
+    let port = server.server_addr().to_ip().unwrap().port();
+    let receiver = std::thread::spawn(move || {
+        let mut request = server.recv().unwrap();
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body).unwrap();
+        let token = request.headers().iter()
+            .find(|h| h.field.equiv("X-Token"))
+            .map(|h| h.value.to_string());
+        request.respond(tiny_http::Response::empty(204)).unwrap();
+        (body, token)
+    });
+
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = format!(r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{{{ sequence }}}}.txt"
+
+[notify.webhook]
+url = "http://127.0.0.1:{}/hook"
+headers = {{ X-Token = "secret" }}
+"#, port);
+    let temp_dir = create_test_env(&cli_contents, &config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "Total: {{ total_amount }}")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    run(None, &Some("txt".to_string()), &None, &directory_option, &config_file_option, &[]);
+
+    let (body, token) = receiver.join().unwrap();
+    let payload: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(token.as_deref(), Some("secret"));
+    assert_eq!(payload["sequence"], 1);
+    assert_eq!(payload["total_hours"], 8.0);
+    assert_eq!(payload["total_amount"], 800.0);
+    assert_eq!(payload["period_start"], "2025-01-01");
+    assert!(payload["output"].as_str().unwrap().ends_with("invoice-1.txt"));
+    Ok(())
+}
+
+#[test]
+fn test_generate_webdav_upload() -> Result<(), Box<dyn std::error::Error>> {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("Failed to start test server");
+    let port = server.server_addr().to_ip().unwrap().port();
+    let receiver = std::thread::spawn(move || {
+        let mut request = server.recv().unwrap();
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body).unwrap();
+        let result = (request.method().to_string(), request.url().to_string(), body);
+        request.respond(tiny_http::Response::empty(201)).unwrap();
+        result
+    });
+
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = format!(r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{{{ sequence }}}}.txt"
+upload = {{ kind = "webdav", url = "http://127.0.0.1:{}/dav/", path_template = "acme/{{{{ sequence }}}}.txt" }}
+"#, port);
+    let temp_dir = create_test_env(&cli_contents, &config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "Total: {{ total_amount }}")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    run(None, &Some("txt".to_string()), &None, &directory_option, &config_file_option, &[]);
+
+    let (method, url, body) = receiver.join().unwrap();
+    assert_eq!(method, "PUT");
+    assert_eq!(url, "/dav/acme/1.txt");
+    assert_eq!(body, "Total: 800");
+    Ok(())
+}
+
+#[test]
+fn test_generate_read_only() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+8h = Development
+"#,
+    );
+    let config_content = r#"
+read_only = true
+
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+build = "touch built"
+"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "Total amount: {{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    assert!(!temp_dir.path().join("invoice.txt").exists());
+    assert!(!temp_dir.path().join(".index").exists());
+    assert!(!temp_dir.path().join("built").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_translated_labels() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[invoice]
+language = "fr"
+
+[i18n.fr]
+total = "Total TTC"
+
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"),
+        "{{ language }} {{ label_invoice }} {{ 'hours' | t }} {{ 'total' | t }}\n")?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &Some("txt".to_string()),
+        &None,
+        &directory_option,
+        &config_file_option,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    assert_eq!(generated_content.trim(), "fr Facture Heures Total TTC");
+    Ok(())
+}
+
+#[test]
+fn test_generate_build_argv_and_shell() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[build]
+shell = ["bash", "-c"]
+
+[contract]
+hourly_rate = 100.0
+
+[generator.argv]
+template = "template.txt"
+output = "invoice-argv.txt"
+build = ["cp", "{{output}}", "{{directory}}/argv with space.txt"]
+
+[generator.shell]
+template = "template.txt"
+output = "invoice-shell.txt"
+build = "[[ -n \"$BASH_VERSION\" ]] && cp {{output}} {{directory}}/shell.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    for generator in ["argv", "shell"] {
+        let options = generate::GenerateOptions {
+            generators: vec![generator.to_string()],
+            ..generate::GenerateOptions::default()
+        };
+        generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    }
+
+    assert!(temp_dir.path().join("argv with space.txt").exists());
+    assert!(temp_dir.path().join("shell.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn test_build_highlight_from_config() -> Result<(), Box<dyn std::error::Error>> {
+    let config_content = r#"
+[build]
+negative_words = ["Overfull"]
+context_lines = 0
+
+[generator.typst]
+negative_patterns = ['^error(\[\w+\])?:']
+tail_lines = 4
+"#;
+    let temp_dir = create_test_env(&HashMap::new(), config_content)?;
+    let config = clinvoice::config::Config::new(
+        Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap()), None)?;
+
+    let default = generate::BuildHighlight::default();
+    assert!(default.is_negative("LaTeX Warning: Reference undefined"));
+
+    let latex = generate::BuildHighlight::from_config(&config, "generator.latex");
+    assert!(latex.is_negative("overfull \\hbox in paragraph"));
+    assert!(!latex.is_negative("LaTeX Warning: Reference undefined"));
+    assert_eq!((latex.context_lines, latex.tail_lines), (0, 2));
+
+    let typst = generate::BuildHighlight::from_config(&config, "generator.typst");
+    assert!(typst.is_negative("error: unknown variable"));
+    assert!(typst.is_negative("error[E42]: bad"));
+    assert!(!typst.is_negative("compiled with 0 errors"));
+    assert_eq!((typst.context_lines, typst.tail_lines), (0, 4));
+    Ok(())
+}
+
+#[test]
+fn test_generate_build_log() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+build = "echo one; echo two >&2; echo three"
+build_log = "build-{{ sequence }}.log"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    run(None, &Some("txt".to_string()), &None, &directory_option, &config_file_option, &[]);
+
+    let log = std::fs::read_to_string(temp_dir.path().join("build-1.log"))?;
+    let mut lines: Vec<&str> = log.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["one", "three", "two"]);
+    Ok(())
+}
+
+#[test]
+fn test_generate_multiple_generators() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+build = "cp {{output}} {{output}}.built"
+
+[generator.md]
+template = "template.txt"
+output = "invoice-{{ sequence }}.md"
+build = "cp {{output}} {{output}}.built"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ generator }} {{ sequence }} {{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string(), "md".to_string()],
+        jobs: 2,
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice-1.txt.built"))?.trim(), "txt 1 800");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice-1.md.built"))?.trim(), "md 1 800");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join(".index"))?.trim(), "1");
+    Ok(())
+}
+
+#[test]
+fn test_generate_open_with_viewer() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+viewer = "sh -c 'cp \"$0\" \"$0.opened\"'"
+
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+build = "cp {{output}} {{directory}}/invoice.pdf"
+open = "invoice.pdf"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        open: true,
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    // the viewer is not waited for
+    let opened = temp_dir.path().join("invoice.pdf.opened");
+    for _ in 0..50 {
+        if opened.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(opened.exists());
+    Ok(())
+}
+
+#[test]
+fn test_generate_latest_symlink_and_copy() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n2025.02.01\n4h = More\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.link]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+latest = "invoice-latest.txt"
+
+[generator.copy]
+template = "template.txt"
+output = "copy-{{ sequence }}.txt"
+latest = "copy-latest.txt"
+latest_mode = "copy"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ sequence }} {{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    for month in ["2025.01", "2025.02"] {
+        let options = generate::GenerateOptions {
+            generators: vec!["link".to_string(), "copy".to_string()],
+            ..generate::GenerateOptions::default()
+        };
+        generate::generate(&options, &directory_option, &config_file_option, &Selection { dates: vec![month.to_string()], ..Selection::default() });
+    }
+
+    let link = temp_dir.path().join("invoice-latest.txt");
+    assert_eq!(std::fs::read_to_string(&link)?.trim(), "2 400");
+    #[cfg(unix)]
+    assert_eq!(std::fs::read_link(&link)?, std::path::PathBuf::from("invoice-2.txt"));
+
+    let copy = temp_dir.path().join("copy-latest.txt");
+    assert!(!copy.symlink_metadata()?.file_type().is_symlink());
+    assert_eq!(std::fs::read_to_string(&copy)?.trim(), "2 400");
+    Ok(())
+}
+
+#[test]
+fn test_generate_backs_up_overwritten_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    let backups = || std::fs::read_dir(temp_dir.path()).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("invoice.txt.") && name.ends_with(".bak"))
+        .collect::<Vec<_>>();
+
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    assert!(backups().is_empty(), "identical output should not be backed up");
+
+    std::fs::write(temp_dir.path().join("timesheet.cli"), "2025.01.01\n4h = Development\n")?;
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    let backups = backups();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join(&backups[0]))?.trim(), "800");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice.txt"))?.trim(), "400");
+    Ok(())
+}
+
+#[test]
+fn test_generate_backs_up_build_outputs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+build = "cp {{ output }} {{ output }}.out"
+open = "invoice.txt.out"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    let backups = || std::fs::read_dir(temp_dir.path()).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("invoice.txt.out.") && name.ends_with(".bak"))
+        .collect::<Vec<_>>();
+
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    assert!(backups().is_empty(), "the build output of an unchanged invoice should not be backed up");
+
+    std::fs::write(temp_dir.path().join("timesheet.cli"), "2025.01.01\n4h = Development\n")?;
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    let backups = backups();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join(&backups[0]))?.trim(), "800");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice.txt.out"))?.trim(), "400");
+    Ok(())
+}
+
+#[test]
+fn test_generate_records_files_in_the_data_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory = temp_dir.path().to_str().unwrap();
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions { generators: vec!["txt".to_string()], ..generate::GenerateOptions::default() };
+    generate::generate(&options, &Some(directory.to_string()), &config_file_option, &Selection::default());
+
+    // Relative to the data directory, whatever the current directory of `generate` was.
+    assert_eq!(clinvoice::diff::load(directory, 1)?.files, vec!["invoice-1.txt"]);
+    Ok(())
+}
+
+#[test]
+fn test_generate_computed_context() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n2025.01.02\n2h = Review\n");
+    let config_content = r#"
+[client]
+company = "Acme"
+
+[context]
+a_label = "{{ b_company }} ({{ days | length }} days)"
+b_company = "{{ client_company | upper }}"
+amount_label = "{{ total_amount | decimal(precision=2) }}"
+
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+
+[generator.txt.context]
+amount_label = "EUR {{ total_amount | decimal(precision=2) }}"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ a_label }} {{ amount_label }}\n")?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    run(None, &Some("txt".to_string()), &None, &directory_option, &config_file_option, &[]);
+
+    assert_eq!(std::fs::read_to_string(&output_path)?.trim(), "ACME (2 days) EUR 1000.00");
+    Ok(())
+}
+
+#[test]
+fn test_generate_day_toggles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+8h = Development
+* a note
+0h = Nothing billed
+2025.01.02
+* only a note
+2025.01.03
+$0 = Free consult
+$50 = Hosting
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.full]
+template = "template.txt"
+output = "full.txt"
+
+[generator.terse]
+template = "template.txt"
+output = "terse.txt"
+skip_zero_days = true
+include_notes = false
+hide_zero_cost_lines = true
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"),
+        "{% for day in days %}{{ day.index }} {{ day.date }} {{ day.description }}\n{% endfor %}{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["full".to_string(), "terse".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("full.txt"))?.trim(), "\
+1 2025-01-01 Development; a note; Nothing billed
+2 2025-01-02 only a note
+3 2025-01-03 Free consult; Hosting
+850");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("terse.txt"))?.trim(), "\
+1 2025-01-01 Development
+2 2025-01-03 Hosting
+850");
+    Ok(())
+}
+
+#[test]
+fn test_generate_outputs_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.a]
+template = "template.txt"
+output = "-"
+
+[generator.b]
+template = "template.txt"
+output = "-"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["a".to_string(), "b".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    assert!(!temp_dir.path().join("-").exists(), "an output of - should go to stdout, not to a file");
+    Ok(())
+}
+
+#[test]
+fn test_generate_day_notes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n* a note\n2025.01.02\n* only a note\n");
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+include_notes = false
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"),
+        "{% for day in days %}{{ day.date }} {{ day.description }}{% for note in day.notes %} [{{ note }}]{% endfor %}\n{% endfor %}")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    // The notes are kept apart from the descriptions, even when they are left out of them.
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice.txt"))?.trim(), "\
+2025-01-01 Development [a note]
+2025-01-02  [only a note]");
+    Ok(())
+}
+
+#[test]
+fn test_generate_tag_filter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+4h = Development #clientA
+2h = Support #clientB
+2025.01.02
+3h = Meeting #clientb
+$20 = Hosting #clientA, monthly
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"),
+        "{% for day in days %}{{ day.date }} {{ day.hours }}\n{% endfor %}{{ total_amount }}\n")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    for tag in ["clientA", "#clientB"] {
+        let options = generate::GenerateOptions {
+            generators: vec!["txt".to_string()],
+            tags: vec![tag.to_string()],
+            ..generate::GenerateOptions::default()
+        };
+        generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+    }
+
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice-1.txt"))?.trim(), "\
+2025-01-01 4
+2025-01-02 0
+420");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice-2.txt"))?.trim(), "\
+2025-01-01 2
+2025-01-02 3
+500");
+    let index = std::fs::read_to_string(temp_dir.path().join(".index"))?;
+    assert!(index.contains("#clientA"));
+    assert!(index.contains("#clientB"));
+    Ok(())
+}
+
+#[test]
+fn test_generate_months() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.30
+4h = Development
+2025.01.31
+2h = Review
+$50 = Hosting
+2025.02.03
+3h = Support
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"),
+        "{% for month in months %}{{ month.label }}:{% for day in month.days %} {{ day.index }}{% endfor %} {{ month.hours }} {{ month.amount }}\n{% endfor %}")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("invoice.txt"))?.trim(), "\
+2025-01: 1 2 6 650
+2025-02: 3 3 300");
+    Ok(())
+}
+
+#[test]
+fn test_generate_xdg_index() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert("timesheet.cli", "2025.01.01\n8h = Development\n");
+    let config_content = r#"
+[index]
+location = "xdg"
+project = "acme"
+
+[generator.txt]
+template = "template.txt"
+output = "invoice-{{ sequence }}.txt"
+"#;
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), "{{ total_hours }}\n")?;
+    let data_home = tempfile::tempdir()?;
+    std::env::set_var("XDG_DATA_HOME", data_home.path());
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection { dates: vec!["2025.01".to_string()], ..Selection::default() });
+
+    assert!(temp_dir.path().join("invoice-1.txt").exists());
+    assert!(!temp_dir.path().join(".index").exists());
+    let index = std::fs::read_to_string(data_home.path().join("clinvoice").join("acme").join(".index"))?;
+    assert_eq!(index, "1 2025.01\n");
+    Ok(())
+}
+
+#[test]
+fn test_generate_emit_days() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+    10h = Development & review
+2025.01.02
+    2h = Design, "phase 2"
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+cap_hours_per_day = 8
+
+[generator.tex]
+template = "template.tex"
+output = "invoice.tex"
+escape = "latex"
+"#;
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.tex"), "{% for day in days %}{{ day.description }}\n{% endfor %}")?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    let options = generate::GenerateOptions {
+        generators: vec!["tex".to_string()],
+        emit_days: true,
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    assert!(std::fs::read_to_string(temp_dir.path().join("invoice.tex"))?.contains(r"Development \& review"));
+    let csv = std::fs::read_to_string(temp_dir.path().join("invoice.days.csv"))?;
+    assert_eq!(csv, "date,hours,cost,description,adjustments\n\
+        2025-01-01,8,800.00,\"Development & review (10 worked, 8 billed)\",capped from 10 to 8 hours a day (contract.cap_hours_per_day)\n\
+        2025-01-02,2,200.00,\"Design, \"\"phase 2\"\"\",\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_footnote_notes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli_contents = HashMap::new();
+    cli_contents.insert(
+        "timesheet.cli",
+        r#"
+2025.01.01
+    4h = Development
+    - waiting on the staging server
+    - see ticket #12
+2025.01.02
+    2h = Review
+2025.01.03
+    3h = Deploy
+    - rolled back once
+"#,
+    );
+    let config_content = r#"
+[contract]
+hourly_rate = 100.0
+
+[generator.txt]
+template = "template.txt"
+output = "invoice.txt"
+footnote_notes = true
+"#;
+    let template_content = "{% for day in days %}{{ day.date }} {{ day.description }}{% for n in day.footnote_refs %} [{{ n }}]{% endfor %}\n{% endfor %}\
+        {% for note in footnotes %}[{{ note.number }}] {{ note.date }} (day {{ note.day }}): {{ note.text }}\n{% endfor %}";
+
+    let temp_dir = create_test_env(&cli_contents, config_content)?;
+    std::fs::write(temp_dir.path().join("template.txt"), template_content)?;
+
+    let directory_option = Some(temp_dir.path().to_str().unwrap().to_string());
+    let config_file_option = Some(temp_dir.path().join("clinvoice.toml").to_str().unwrap().to_string());
+
+    let options = generate::GenerateOptions {
+        generators: vec!["txt".to_string()],
+        ..generate::GenerateOptions::default()
+    };
+    generate::generate(&options, &directory_option, &config_file_option, &Selection::default());
+
+    let invoice = std::fs::read_to_string(temp_dir.path().join("invoice.txt"))?;
+    assert_eq!(invoice, "2025-01-01 Development [1] [2]\n\
+        2025-01-02 Review\n\
+        2025-01-03 Deploy [3]\n\
+        [1] 2025-01-01 (day 1): waiting on the staging server\n\
+        [2] 2025-01-01 (day 1): see ticket #12\n\
+        [3] 2025-01-03 (day 3): rolled back once\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_from_init_files() -> Result<(), Box<dyn std::error::Error>> {
+    use clinvoice::init::{files, Answers};
+
+    let temp_dir = tempdir()?;
+    let today = chrono::NaiveDate::from_ymd_opt(2025, 3, 14).unwrap();
+    let answers = Answers { company: "Acme".to_string(), hourly_rate: 90.0, currency: "eur".to_string() };
+    for (name, content) in files(&answers, today) {
+        std::fs::write(temp_dir.path().join(name), content)?;
+    }
+    std::fs::write(temp_dir.path().join("extra.cli"), "2025.03.14\n3h = wrote docs\n")?;
+
+    let output_path = temp_dir.path().join("invoice.txt");
+    run(
+        Some(output_path.to_str().unwrap().to_string()),
+        &None,
+        &None,
+        &Some(temp_dir.path().to_str().unwrap().to_string()),
+        &None,
+        &[],
+    );
+
+    let generated_content = std::fs::read_to_string(&output_path)?;
+    assert!(generated_content.contains("    Acme\n"));
+    assert!(generated_content.contains("| wrote docs "));
+    assert!(generated_content.contains("Total:         270.00 EUR"));
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use clinvoice::index::Index;
+use clinvoice::config::Config;
+use clinvoice::index::{index_file_path, Index};
 use tempfile::TempDir;
 use std::fs;
 use std::path::PathBuf;
@@ -33,6 +34,39 @@ fn test_index_new_fails_if_file_cannot_be_created() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_index_create_makes_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let index_file_path = temp_dir.path().join("clinvoice").join("acme").join(".index");
+    let mut index = Index::create(&index_file_path)?;
+    index.add_sequence(1, &["2025.01".to_string()]);
+    index.save()?;
+    assert_eq!(fs::read_to_string(&index_file_path)?, "1 2025.01\n");
+    Ok(())
+}
+
+#[test]
+fn test_index_file_path_xdg_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let data_home = tempfile::tempdir()?;
+    std::env::set_var("XDG_DATA_HOME", data_home.path());
+    let projects = tempfile::tempdir()?;
+    let mut paths = Vec::new();
+    for name in ["acme", "globex"] {
+        let directory = projects.path().join(name);
+        fs::create_dir_all(directory.join("archive"))?;
+        fs::write(directory.join("clinvoice.toml"), "[index]\nlocation = \"xdg\"\n")?;
+        let config = Config::new(None, directory.to_str())?;
+        let archive = directory.join("archive");
+        paths.push(index_file_path(Some(&config), archive.to_str().unwrap()));
+        assert_eq!(index_file_path(Some(&config), directory.to_str().unwrap()),
+            data_home.path().join("clinvoice").join(name).join(".index"));
+    }
+    // The archives of different projects keep their own index.
+    assert_eq!(paths[0], data_home.path().join("clinvoice").join("acme").join("archive").join(".index"));
+    assert_ne!(paths[0], paths[1]);
+    Ok(())
+}
+
 #[test]
 fn test_index_find_sequence_new_sequence() -> Result<(), Box<dyn std::error::Error>> {
     let (_temp_dir, index_file_path) = setup_test_env();
@@ -108,3 +142,86 @@ fn test_index_save_and_reload() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(reloaded_index.find_sequence(&dates2), 6);
     Ok(())
 }
+
+#[test]
+fn test_index_open_read_only() -> Result<(), Box<dyn std::error::Error>> {
+    let (_temp_dir, index_file_path) = setup_test_env();
+    let mut index = Index::open_read_only(&index_file_path)?;
+    assert!(!index_file_path.exists());
+    assert_eq!(index.find_sequence(&["2023.09.01".to_string()]), 1);
+    assert!(index.save().is_err());
+    assert!(!index_file_path.exists());
+
+    fs::write(&index_file_path, "3 2023.09.01\n")?;
+    let mut index = Index::open_read_only(&index_file_path)?;
+    assert_eq!(index.find_sequence(&["2023.09.01".to_string()]), 3);
+    Ok(())
+}
+
+#[test]
+fn test_index_reserve() -> Result<(), Box<dyn std::error::Error>> {
+    let (_temp_dir, index_file_path) = setup_test_env();
+    fs::write(&index_file_path, "1 2023.10\n")?;
+    let mut index = Index::new(&index_file_path)?;
+    assert_eq!(index.reserve(2), vec![2, 3]);
+    index.save()?;
+    drop(index);
+    assert_eq!(fs::read_to_string(&index_file_path)?, "1 2023.10\n2 state=reserved\n3 state=reserved\n");
+
+    let mut index = Index::new(&index_file_path)?;
+    assert_eq!(index.state(1), Some("issued"));
+    assert_eq!(index.state(2), Some("reserved"));
+    assert_eq!(index.state(4), None);
+    // Reserved sequences are skipped when allocating, even for an empty selection.
+    assert_eq!(index.find_sequence(&[]), 4);
+    // Using a reserved sequence explicitly issues it.
+    index.add_sequence(3, &["2023.11".to_string()]);
+    assert_eq!(index.state(3), Some("issued"));
+    Ok(())
+}
+
+#[test]
+fn test_index_void() -> Result<(), Box<dyn std::error::Error>> {
+    let (_temp_dir, index_file_path) = setup_test_env();
+    fs::write(&index_file_path, "1 2023.10
+2 2023.11
+")?;
+
+    let mut index = Index::new(&index_file_path)?;
+    assert!(index.void(1));
+    assert!(!index.void(3));
+    assert_eq!(index.state(1), Some("voided"));
+    // The voided sequence keeps its dates, but the same dates get a new number.
+    assert_eq!(index.find_sequence(&["2023.10".to_string()]), 3);
+    index.save()?;
+    assert_eq!(fs::read_to_string(&index_file_path)?, "1 2023.10 state=voided\n2 2023.11\n3 2023.10\n");
+    Ok(())
+}
+
+#[test]
+fn test_index_recovers_stale_files() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp_dir, index_file_path) = setup_test_env();
+    fs::write(&index_file_path, "1 2023.10\n")?;
+    // Left behind by a process that crashed while saving.
+    let temp_path = temp_dir.path().join(".index.tmp");
+    let lock_path = temp_dir.path().join(".index.lock");
+    fs::write(&temp_path, "1 2023.10\n2 2023")?;
+    fs::write(&lock_path, "4194304\n")?;
+
+    let mut index = Index::new(&index_file_path)?;
+    assert!(!temp_path.exists());
+    assert_eq!(fs::read_to_string(&lock_path)?.trim(), std::process::id().to_string());
+    assert_eq!(index.find_sequence(&["2023.10".to_string()]), 1);
+    drop(index);
+    assert!(!lock_path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_index_lock_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    let (_temp_dir, index_file_path) = setup_test_env();
+    let _held = Index::new(&index_file_path)?;
+    let result = Index::with_timeout(&index_file_path, std::time::Duration::from_millis(200));
+    assert_eq!(result.err().map(|e| e.kind()), Some(std::io::ErrorKind::TimedOut));
+    Ok(())
+}
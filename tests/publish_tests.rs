@@ -0,0 +1,31 @@
+use clinvoice::data::Selection;
+use clinvoice::publish;
+use tempfile::tempdir;
+
+#[test]
+fn test_publish_dashboard() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = tempdir()?;
+    std::fs::write(data_dir.path().join("2025-01.cli"), "2025.01.06\n8h = Development\n\n2025.01.07\n4h = Review\n")?;
+    std::fs::write(data_dir.path().join("2025-02.cli"), "2025.02.03\n2h = Support\n")?;
+    std::fs::write(data_dir.path().join(".index"), "1 2025.01\n")?;
+    std::fs::write(data_dir.path().join("clinvoice.toml"), "[contract]\nhourly_rate = 100.0\n[publish]\ntitle = \"ACME hours\"\n")?;
+
+    let output_dir = tempdir()?;
+    let output = output_dir.path().join("dashboard");
+    publish::run(
+        output.to_str().unwrap(),
+        &Some(data_dir.path().to_str().unwrap().to_string()),
+        &None,
+        &Selection::default(),
+    );
+
+    let html = std::fs::read_to_string(output.join("index.html"))?;
+    assert!(html.contains("<title>ACME hours</title>"));
+    assert!(html.contains("2025.01: 12 hours"));
+    assert!(html.contains("1200.00"));
+
+    let data: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output.join("data.json"))?)?;
+    assert_eq!(data["months"].as_array().unwrap().len(), 2);
+    assert_eq!(data["invoices"][0]["amount"], 1200.0);
+    Ok(())
+}